@@ -3,6 +3,18 @@
  */
 
 declare module '@danielhaim/packr' {
+	/**
+	 * Endpoint a build summary is POSTed to
+	 */
+	export interface PackrWebhook {
+		/** URL to POST to; may use [env:NAME] */
+		url: string;
+		/** Extra request headers; values may use [env:NAME] */
+		headers?: Record<string, string>;
+		/** Only report failed builds */
+		only_failures?: boolean;
+	}
+
 	/**
 	 * Configuration options for Packr
 	 */
@@ -10,13 +22,13 @@ declare module '@danielhaim/packr' {
 		/** Path to SCSS input file */
 		scss_input?: string;
 
-		/** Path to SCSS output file */
+		/** Path to SCSS output file; may use [name], [hash] and [ext] */
 		scss_output?: string;
 
 		/** Path to JavaScript input file */
 		js_input?: string;
 
-		/** Path to JavaScript output file */
+		/** Path to JavaScript output file; may use [name], [hash] and [ext] */
 		js_output?: string;
 
 		/** Optional path for an alternate CSS output */
@@ -25,6 +37,17 @@ declare module '@danielhaim/packr' {
 		/** Optional path for an alternate JavaScript output */
 		js_destination?: string;
 
+		/** More stylesheets and/or scripts, each built with the rest of the config */
+		bundles?: Array<{
+			scss_input?: string;
+			scss_output?: string;
+			js_input?: string;
+			js_output?: string;
+		}>;
+
+		/** Entries built at the same time, at most one per CPU (default: CPU count) */
+		jobs?: number;
+
 		/** Whether to minify output (default: true) */
 		minify?: boolean;
 
@@ -40,11 +63,114 @@ declare module '@danielhaim/packr' {
 		/** Enable watch mode (default: false) */
 		watch?: boolean;
 
+		/** Build even when `skip_unchanged` finds no changed inputs (default: false) */
+		force?: boolean;
+
+		/** Remove generated files from the output directories instead of building (default: false) */
+		clean?: boolean;
+
+		/** With `clean`, wipe the build cache instead of the outputs (default: false) */
+		cleanCache?: boolean;
+
+		/** Fill the build cache without writing outputs (default: false) */
+		warm?: boolean;
+
+		/** Export the build cache to this `.tar.gz` file instead of building */
+		cacheExport?: string;
+
+		/** Replace the build cache with this exported `.tar.gz` file instead of building */
+		cacheImport?: string;
+
+		/** Report the minified bundle's composition instead of building (default: false) */
+		analyze?: boolean;
+
+		/** With `analyze`, also write a self-contained HTML treemap to this file */
+		analyzeHtml?: string;
+
+		/** Write a Chrome tracing profile of the build to this file */
+		profile?: string;
+
+		/** Write a JUnit XML report of the build steps and diagnostics to this file */
+		junit?: string;
+
+		/** Write the Sass, CSS, ESLint and esbuild diagnostics as a GitLab Code Quality report to this file */
+		codequality?: string;
+
+		/** Print the build result and its Sass, CSS, ESLint and esbuild diagnostics as JSON on stdout (default: false) */
+		json?: boolean;
+
+		/** Stream build events as NDJSON: `true` for stdout, or a file, FIFO or `unix:<socket>` path */
+		eventsNdjson?: boolean | string;
+
+		/** Send the build to a running `packr daemon`, building locally if none is listening (default: false) */
+		daemon?: boolean;
+
+		/** Run the build daemon, serving build requests until stopped (default: false) */
+		runDaemon?: boolean;
+
+		/** Stop a running build daemon (default: false) */
+		stopDaemon?: boolean;
+
+		/** Serve live Sass, esbuild and ESLint diagnostics to an editor over stdio instead of building (default: false) */
+		lsp?: boolean;
+
+		/** In a workspace, build only the projects matching these names or glob patterns */
+		project?: string | string[];
+
+		/** In a workspace, build only the projects affected by changes since this git ref */
+		changedSince?: string;
+
 		/** Enable verbose logging (default: false) */
 		verbose?: boolean;
 
-		/** Generate source maps (default: false) */
-		sourcemap?: boolean;
+		/** Generate source maps, optionally with a mode (default: false) */
+		sourcemap?: boolean | 'linked' | 'external' | 'inline' | 'both';
+
+		/** `sourceRoot` of every source map, whose sources are then listed relative to the config directory (default: sources relative to the map) */
+		sourcemap_root?: string;
+
+		/** Upload the source maps of full builds to Sentry or another endpoint */
+		sourcemap_upload?: {
+			/** sentry, http, or plugin for Rust plugins (default: "sentry") */
+			provider?: 'sentry' | 'http' | 'plugin';
+			/** Release the files are tagged with; may use [env:NAME], and nothing is uploaded when it's empty */
+			release: string;
+			/** Sentry server (default: "https://sentry.io"), or the endpoint of the http provider */
+			url?: string;
+			/** Sentry organization slug */
+			org?: string;
+			/** Sentry project slug */
+			project?: string;
+			/** Sentry auth token (default: "[env:SENTRY_AUTH_TOKEN]") */
+			auth_token?: string;
+			/** Extra request headers; values may use [env:NAME] */
+			headers?: Record<string, string>;
+			/** Prefix of the uploaded file names (default: "~/") */
+			url_prefix?: string;
+			/** Delete the maps and their sourceMappingURL comments once uploaded (default: false) */
+			strip?: boolean;
+		};
+
+		/** Upload the outputs to a bucket or server after a build */
+		deploy?: {
+			/** s3 (aws CLI), gcs (gsutil), rsync, or sftp */
+			provider: 's3' | 'gcs' | 'rsync' | 'sftp';
+			/** s3://bucket/prefix, gs://bucket/prefix, [user@]host:/path for sftp, or any rsync destination */
+			target: string;
+			/** Directory whose files are uploaded (default: the directory of js_output) */
+			dir?: string;
+			/** Cache-Control of files with hashed and unchanging names */
+			cache_control?: {
+				/** default: "public, max-age=31536000, immutable" */
+				hashed?: string;
+				/** default: "public, max-age=300, must-revalidate" */
+				default?: string;
+			};
+			/** CloudFront distribution id (s3) or URL map (gcs) to invalidate the unhashed files on */
+			invalidate?: string;
+			/** Deploy after every full build, not only with --deploy (default: false) */
+			on_build?: boolean;
+		};
 
 		/** Output format: iife, cjs, or esm (default: "iife") */
 		format?: 'iife' | 'cjs' | 'esm';
@@ -55,6 +181,302 @@ declare module '@danielhaim/packr' {
 		/** Path to custom ESLint config file */
 		eslint_config?: string;
 
+		/** Fail when ESLint reports more warnings than this in total (default: no limit) */
+		eslint_max_warnings?: number;
+
+		/** Fail only when a file has more ESLint warnings than recorded in .packr-eslint-baseline.json (default: false) */
+		eslint_ratchet?: boolean;
+
+		/** Loader for `.wasm` imports */
+		wasm_loader?: 'file' | 'binary' | 'base64' | 'dataurl';
+
+		/** URL prefix for emitted assets */
+		public_path?: string;
+
+		/** Preserve function and class names in the minified build (default: false) */
+		keep_names?: boolean;
+
+		/** Regex of property names to mangle in the minified build */
+		mangle_props?: string;
+
+		/** Regex of property names exempt from mangling */
+		reserve_props?: string;
+
+		/** Environment variables to inline into the bundle */
+		env_inject?: string[];
+
+		/** Target for an additional `*.legacy.js` bundle (e.g. "es2015") */
+		legacy_target?: string;
+
+		/** Inject polyfills the legacy target needs (default: false) */
+		polyfills?: boolean;
+
+		/** Enable code splitting, requires ESM format (default: false) */
+		splitting?: boolean;
+
+		/** Entry filename pattern, e.g. "[name].[hash]" */
+		entry_names?: string;
+
+		/** Chunk filename pattern when splitting (default: "chunks/[name]-[hash]") */
+		chunk_names?: string;
+
+		/** Filename pattern for emitted assets */
+		asset_names?: string;
+
+		/** Inline imported images and fonts up to this many bytes as data URLs */
+		asset_inline_limit?: number;
+
+		/** Force tree shaking on or off */
+		tree_shaking?: boolean;
+
+		/** Ignore side-effect annotations (default: false) */
+		ignore_annotations?: boolean;
+
+		/** Per-package sideEffects override */
+		side_effects_overrides?: Record<string, boolean>;
+
+		/** esbuild plugin modules, by name or with options */
+		esbuild_plugins?: Array<string | { module: string; options?: Record<string, unknown> }>;
+
+		/** Sandboxed WebAssembly plugins, relative to the config directory */
+		wasm_plugins?: string[];
+
+		/** Custom package.json `exports` conditions, e.g. ["development"] */
+		conditions?: string[];
+
+		/** package.json fields tried when resolving packages, e.g. ["browser", "module", "main"] */
+		main_fields?: string[];
+
+		/** Write THIRD-PARTY-LICENSES.txt/.json for bundled packages next to the bundle (default: false) */
+		license_report?: boolean;
+
+		/** Warn when a package or file is bundled more than once, with the imports responsible (default: false) */
+		detect_duplicates?: boolean;
+
+		/** Fail the build on warnings, for every category or per category (default: false) */
+		strict_warnings?: boolean | {
+			/** `@warn` messages from the Sass compile */
+			sass?: boolean;
+			/** Rules and selectors lightningcss doesn't recognize */
+			css?: boolean;
+			/** ESLint warnings */
+			eslint?: boolean;
+		};
+
+		/** Run `npm audit` before bundling (default: false) */
+		audit?: boolean;
+
+		/** Lowest advisory severity that counts against the build (default: "high") */
+		audit_level?: 'info' | 'low' | 'moderate' | 'high' | 'critical';
+
+		/** Fail the build on vulnerabilities at or above `audit_level`, instead of warning (default: true) */
+		audit_fail?: boolean;
+
+		/** Runtime used for esbuild and ESLint (default: detected from the project) */
+		runtime?: 'node' | 'bun' | 'deno';
+
+		/** Record tool versions in `.packr.lock` and warn, or fail with "error", when they drift (default: off) */
+		tool_lock?: 'warn' | 'error';
+
+		/** Build cache directory for compiled SCSS, ESLint results and esbuild metafiles, inside the project or absolute (default: ".packr/cache") */
+		cache_dir?: string;
+
+		/** Skip the build when no input changed since the last successful build (default: false) */
+		skip_unchanged?: boolean;
+
+		/** Pin output timestamps and strip absolute paths from source maps, for byte-identical builds (default: false) */
+		reproducible?: boolean;
+
+		/** Parse the emitted CSS and JavaScript again after the build and fail on errors (default: false) */
+		verify?: boolean;
+
+		/** Octal permissions for every file the build writes, e.g. "0644" (default: left to the umask) */
+		output_mode?: string;
+
+		/** Group name or id given to every file the build writes (default: unchanged) */
+		output_group?: string;
+
+		/** Retries for output writes that fail with a temporary error, e.g. in a synced folder (default: 3) */
+		io_retries?: number;
+
+		/** What a run does while another one builds the same project (default: "wait") */
+		instance_lock?: 'wait' | 'fail' | 'off';
+
+		/** Seconds an external tool (esbuild, ESLint, npm audit, ...) may run before it is killed (default: no limit) */
+		tool_timeout_secs?: number;
+
+		/** Per-tool timeouts in seconds overriding `tool_timeout_secs`, e.g. `{ eslint: 300 }` */
+		tool_timeouts?: Partial<Record<'esbuild' | 'eslint' | 'npm' | 'node' | 'bun' | 'deno' | 'curl', number>>;
+
+		/** Delete previously generated files from output and destination directories before building (default: false) */
+		clean_output?: boolean;
+
+		/** Glob patterns of files kept by `clean_output`, relative to each directory */
+		clean_keep?: string[];
+
+		/** Allow the build to write outside the config file's directory (default: false) */
+		allow_outside_config_dir?: boolean;
+
+		/** Write WebP and AVIF versions of the PNG and JPEG files `copy` writes */
+		images?: {
+			/** Formats written next to each image (default: ["webp", "avif"]) */
+			formats?: Array<'webp' | 'avif'>;
+			/** Encoder quality from 0 to 100 per format (default: 80 for webp, 60 for avif) */
+			quality?: { webp?: number; avif?: number };
+			/** List each image with its variants in the manifest (default: false) */
+			manifest?: boolean;
+		};
+
+		/** Static files to mirror into output directories after the build */
+		copy?: Array<{
+			/** Source file or directory, relative to the config file */
+			from: string;
+			/** Target directory, relative to the config file */
+			to: string;
+			/** Files to include when `from` is a directory (default: "**\/*") */
+			glob?: string;
+			/** Substitutions applied to copied text files; `replace` may use [version], [timestamp] and [env:NAME] */
+			replace?: Array<{ search: string; replace: string }>;
+		}>;
+
+		/** Size limits checked after the build; sizes are bytes or text such as "250 KB" */
+		budgets?: Array<{
+			/** Glob matched against output paths as configured, relative to the config file */
+			path: string;
+			/** Maximum size of the file */
+			max_raw?: number | string;
+			/** Maximum size of its `.min` variant */
+			max_min?: number | string;
+			/** Maximum gzipped size of the file */
+			max_gzip?: number | string;
+			/** "warn" prints exceeded budgets, "error" also fails the build (default: "warn") */
+			level?: 'warn' | 'error';
+		}>;
+
+		/** Minify SVGs copied by `copy` tasks (default: false) */
+		minify_svg?: boolean;
+
+		/** Minify HTML pages written from `html` templates or copied by `copy` tasks (default: false) */
+		minify_html?: boolean;
+
+		/** Copy woff2 fonts referenced by the CSS and write preload tags for them */
+		fonts?: boolean | {
+			/** Font directory, relative to the CSS output (default: "fonts") */
+			dir?: string;
+			/** URL prefix for fonts in the CSS and preload tags */
+			public_path?: string;
+			/** Preload snippet file, relative to the CSS output (default: "fonts-preload.html") */
+			preload?: string;
+			/** Subset settings per font-family name */
+			subset?: Record<string, {
+				/** CSS unicode-range syntax, e.g. "U+0000-00FF, U+2019" */
+				unicode_range?: string;
+				/** Sample text whose characters are kept */
+				text?: string;
+			}>;
+		};
+
+		/** Rename CSS/JS outputs to name.<contenthash>.ext and write a manifest (default: false) */
+		hash_outputs?: boolean;
+
+		/** "filename" renames hashed outputs; "query" keeps names and records ?v=<hash> (default: "filename") */
+		hash_mode?: 'filename' | 'query';
+
+		/** Manifest path (default: manifest.json, or .manifest.json for "rails", next to js_output) */
+		manifest?: string;
+
+		/** Manifest shape: Packr's own, Laravel's mix-manifest.json, or Propshaft's .manifest.json (default: "packr") */
+		manifest_format?: 'packr' | 'mix' | 'rails';
+
+		/** Record sha384 SRI hashes in the manifest and injected HTML tags (default: false) */
+		sri?: boolean;
+
+		/** Write precompressed .gz/.br copies of the CSS/JS outputs (default: []) */
+		compress?: Array<'gzip' | 'brotli'>;
+
+		/** HTML templates to write with the built CSS/JS tags injected */
+		html?: {
+			/** Template files, relative to the config file */
+			templates: string[];
+			/** Directory the processed pages are written to */
+			output: string;
+			/** URL prefix for injected assets, instead of paths relative to the pages */
+			public_path?: string;
+			/** `<link>` hints injected ahead of the stylesheet or at `<!-- packr:hints -->` */
+			resource_hints?: {
+				/** `modulepreload` for chunks the entry statically imports (default: true) */
+				modulepreload?: boolean;
+				/** `preload` for fonts copied by the `fonts` option (default: true) */
+				fonts?: boolean;
+			};
+		};
+
+		/** Generate favicons, app icons and a web manifest from one square PNG */
+		favicons?: {
+			/** Source PNG, 512x512 or larger, relative to the config file */
+			source: string;
+			/** Directory the icons are written to */
+			output: string;
+			/** URL prefix for the icons, instead of relative paths */
+			public_path?: string;
+			/** App name for site.webmanifest */
+			name?: string;
+			/** theme-color meta tag and web manifest colour */
+			theme_color?: string;
+			/** Tag snippet file, relative to `output` (default: "favicons.html") */
+			snippet?: string;
+		};
+
+		/** Write a PHP asset map for enqueueing the build from a WordPress theme */
+		wordpress?: {
+			/** Handle the script and stylesheet are registered under */
+			handle: string;
+			/** PHP file to write, relative to the config file (default: "packr-assets.php") */
+			path?: string;
+			/** Script dependency handles, e.g. ["jquery"] */
+			script_deps?: string[];
+			/** Stylesheet dependency handles */
+			style_deps?: string[];
+		};
+
+		/** Report every build to a webhook */
+		notifications?: {
+			webhook?: PackrWebhook;
+			/** Slack incoming webhook, sent a formatted message */
+			slack?: PackrWebhook;
+			/** Discord webhook, sent a formatted message */
+			discord?: PackrWebhook;
+		};
+
+		/** Report the builds of a daemon or watch session to Prometheus or StatsD */
+		metrics?: {
+			/** Address to serve Prometheus metrics on at /metrics, e.g. "127.0.0.1:9464" */
+			listen?: string;
+			/** StatsD server to push to over UDP, e.g. "127.0.0.1:8125" */
+			statsd?: string;
+			/** Start of every metric name (default: "packr") */
+			prefix?: string;
+		};
+
+		/** Let other tools trigger builds of a daemon or watch session and query their results over HTTP */
+		control?: {
+			/** Address to listen on, e.g. "127.0.0.1:7357"; other than loopback only with a token */
+			listen: string;
+			/** Bearer token every request must carry; may use [env:NAME] */
+			token?: string;
+		};
+
+		/** Make this a workspace config: project names mapped to project directories or config files, or glob patterns matching them; each project is built from its own .packr.json */
+		projects?: Record<string, string> | string[];
+
+		/** Move node_modules code into a separate shared chunk */
+		vendor_chunk?: boolean | {
+			/** Packages to move (default: package.json dependencies) */
+			packages?: string[];
+			/** Minimum package size in bytes to be moved (default: 0) */
+			min_size?: number;
+		};
+
 		/** Uglification options */
 		uglify?: {
 			/** Enable name mangling (default: true) */
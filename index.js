@@ -12,9 +12,31 @@ const { spawn } = require('child_process');
  * @param {string} [options.jsOutput] - Path to output bundled JS
  * @param {string} [options.cssDestination] - Output directory for CSS
  * @param {string} [options.jsDestination] - Output directory for JS
+ * @param {Object[]} [options.bundles] - More stylesheet/script entries ({ scss_input, scss_output, js_input, js_output })
+ * @param {number} [options.jobs] - Entries built at the same time, at most one per CPU
  * @param {boolean} [options.minify=true] - Minify output
  * @param {string} [options.target='es2020'] - JavaScript target
  * @param {boolean} [options.watch=false] - Enable watch mode
+ * @param {boolean} [options.force=false] - Rebuild even when inputs are unchanged
+ * @param {boolean} [options.clean=false] - Remove generated files instead of building
+ * @param {boolean} [options.cleanCache=false] - With `clean`, wipe the build cache instead
+ * @param {boolean} [options.warm=false] - Fill the build cache without writing outputs
+ * @param {string} [options.cacheExport] - Export the build cache to this .tar.gz file
+ * @param {string} [options.cacheImport] - Replace the build cache with this .tar.gz file
+ * @param {boolean} [options.analyze=false] - Report the minified bundle's composition instead of building
+ * @param {string} [options.analyzeHtml] - With `analyze`, also write an HTML treemap to this file
+ * @param {string} [options.profile] - Write a Chrome tracing profile of the build to this file
+ * @param {string} [options.junit] - Write a JUnit XML report of the build steps and diagnostics to this file
+ * @param {string} [options.codequality] - Write the diagnostics as a GitLab Code Quality report to this file
+ * @param {boolean} [options.json=false] - Print the build result and its diagnostics as JSON on stdout
+ * @param {boolean|string} [options.eventsNdjson] - Stream build events as NDJSON: `true` for stdout, or a file, FIFO or `unix:` socket path
+ * @param {boolean} [options.reproducible=false] - Build byte-identical outputs with pinned timestamps
+ * @param {boolean} [options.daemon=false] - Hand the build to a running `packr daemon`
+ * @param {boolean} [options.runDaemon=false] - Run the build daemon instead of building
+ * @param {boolean} [options.stopDaemon=false] - Stop a running build daemon instead of building
+ * @param {boolean} [options.lsp=false] - Serve live diagnostics to an editor over stdio instead of building
+ * @param {string|string[]} [options.project] - In a workspace, build only the projects matching these names or glob patterns
+ * @param {string} [options.changedSince] - In a workspace, build only the projects affected by changes since this git ref
  * @param {boolean} [options.verbose=false] - Enable verbose logging
  * @param {boolean} [options.sourcemap=true] - Generate source maps
  * @param {string} [options.format='iife'] - JavaScript output format (iife, cjs, esm)
@@ -171,6 +193,11 @@ function loadEnvFiles() {
 	}
 }
 
+// * `packr lsp` speaks the Language Server Protocol on stdout, so the log goes to stderr
+if (require.main === module && process.argv[2] === 'lsp') {
+	console.log = console.error;
+}
+
 loadEnvFiles();
 
 function packr(options = {}) {
@@ -200,6 +227,18 @@ function packr(options = {}) {
 			}
 		}
 
+		// * A workspace config only lists projects, which the binary builds from their own configs
+		if (configFromFile.projects) {
+			const args = ['--config', path.resolve(options.config)];
+			if (options.reproducible) {
+				args.push('--reproducible');
+			}
+			if (options.deploy === true) {
+				args.push('--deploy');
+			}
+			return runBinary(args, options);
+		}
+
 		// * Construct user config from options or file
 		const userConfig = {
 			scss_input: process.env.PACKR_SCSS_INPUT || options.scssInput || configFromFile.scss_input,
@@ -208,6 +247,8 @@ function packr(options = {}) {
 			js_output: process.env.PACKR_JS_OUTPUT || options.jsOutput || configFromFile.js_output,
 			css_destination: process.env.PACKR_CSS_DESTINATION || options.cssDestination || configFromFile.css_destination,
 			js_destination: process.env.PACKR_JS_DESTINATION || options.jsDestination || configFromFile.js_destination,
+			bundles: options.bundles || configFromFile.bundles || [],
+			jobs: process.env.PACKR_JOBS ? Number(process.env.PACKR_JOBS) : (options.jobs || configFromFile.jobs),
 			minify: process.env.PACKR_MINIFY === 'true' || (
 				process.env.PACKR_MINIFY !== 'false' && (
 					options.minify !== undefined ? options.minify :
@@ -255,9 +296,84 @@ function packr(options = {}) {
 					configFromFile.sourcemap !== undefined ? configFromFile.sourcemap : false
 				)
 			),
+			sourcemap_root: process.env.PACKR_SOURCEMAP_ROOT || options.sourcemapRoot || configFromFile.sourcemap_root,
+			sourcemap_upload: options.sourcemapUpload || configFromFile.sourcemap_upload,
+			deploy: options.deploy || configFromFile.deploy,
 			format: process.env.PACKR_FORMAT || options.format || configFromFile.format || 'iife',
 			eslint: process.env.PACKR_ESLINT === 'true' || configFromFile.eslint || false,
-			eslint_config: process.env.PACKR_ESLINT_CONFIG || configFromFile.eslint_config
+			eslint_config: process.env.PACKR_ESLINT_CONFIG || configFromFile.eslint_config,
+			eslint_max_warnings: process.env.PACKR_ESLINT_MAX_WARNINGS ? Number(process.env.PACKR_ESLINT_MAX_WARNINGS) : (
+				options.eslintMaxWarnings !== undefined ? options.eslintMaxWarnings : configFromFile.eslint_max_warnings
+			),
+			eslint_ratchet: process.env.PACKR_ESLINT_RATCHET === 'true' || options.eslintRatchet || configFromFile.eslint_ratchet || false,
+			wasm_loader: process.env.PACKR_WASM_LOADER || options.wasmLoader || configFromFile.wasm_loader,
+			public_path: process.env.PACKR_PUBLIC_PATH || options.publicPath || configFromFile.public_path,
+			keep_names: process.env.PACKR_KEEP_NAMES === 'true' || options.keepNames || configFromFile.keep_names || false,
+			mangle_props: process.env.PACKR_MANGLE_PROPS || options.mangleProps || configFromFile.mangle_props,
+			reserve_props: process.env.PACKR_RESERVE_PROPS || options.reserveProps || configFromFile.reserve_props,
+			env_inject: options.envInject || configFromFile.env_inject || [],
+			legacy_target: process.env.PACKR_LEGACY_TARGET || options.legacyTarget || configFromFile.legacy_target,
+			polyfills: process.env.PACKR_POLYFILLS === 'true' || options.polyfills || configFromFile.polyfills || false,
+			splitting: process.env.PACKR_SPLITTING === 'true' || options.splitting || configFromFile.splitting || false,
+			vendor_chunk: options.vendorChunk || configFromFile.vendor_chunk,
+			entry_names: process.env.PACKR_ENTRY_NAMES || options.entryNames || configFromFile.entry_names,
+			chunk_names: process.env.PACKR_CHUNK_NAMES || options.chunkNames || configFromFile.chunk_names,
+			asset_names: process.env.PACKR_ASSET_NAMES || options.assetNames || configFromFile.asset_names,
+			asset_inline_limit: process.env.PACKR_ASSET_INLINE_LIMIT ? Number(process.env.PACKR_ASSET_INLINE_LIMIT) : (
+				options.assetInlineLimit !== undefined ? options.assetInlineLimit : configFromFile.asset_inline_limit
+			),
+			tree_shaking: process.env.PACKR_TREE_SHAKING ? process.env.PACKR_TREE_SHAKING === 'true' : (
+				options.treeShaking !== undefined ? options.treeShaking : configFromFile.tree_shaking
+			),
+			ignore_annotations: process.env.PACKR_IGNORE_ANNOTATIONS === 'true' || options.ignoreAnnotations || configFromFile.ignore_annotations || false,
+			side_effects_overrides: options.sideEffectsOverrides || configFromFile.side_effects_overrides || {},
+			esbuild_plugins: options.esbuildPlugins || configFromFile.esbuild_plugins || [],
+			wasm_plugins: options.wasmPlugins || configFromFile.wasm_plugins || [],
+			conditions: process.env.PACKR_CONDITIONS ? process.env.PACKR_CONDITIONS.split(',') : (options.conditions || configFromFile.conditions || []),
+			main_fields: process.env.PACKR_MAIN_FIELDS ? process.env.PACKR_MAIN_FIELDS.split(',') : (options.mainFields || configFromFile.main_fields || []),
+			license_report: process.env.PACKR_LICENSE_REPORT === 'true' || options.licenseReport || configFromFile.license_report || false,
+			detect_duplicates: process.env.PACKR_DETECT_DUPLICATES === 'true' || options.detectDuplicates || configFromFile.detect_duplicates || false,
+			strict_warnings: options.strictWarnings || configFromFile.strict_warnings || false,
+			audit: process.env.PACKR_AUDIT === 'true' || options.audit || configFromFile.audit || false,
+			audit_level: process.env.PACKR_AUDIT_LEVEL || options.auditLevel || configFromFile.audit_level || 'high',
+			audit_fail: process.env.PACKR_AUDIT_FAIL ? process.env.PACKR_AUDIT_FAIL === 'true' : (
+				options.auditFail !== undefined ? options.auditFail : (configFromFile.audit_fail !== undefined ? configFromFile.audit_fail : true)
+			),
+			runtime: process.env.PACKR_RUNTIME || options.runtime || configFromFile.runtime,
+			tool_lock: process.env.PACKR_TOOL_LOCK || options.toolLock || configFromFile.tool_lock,
+			cache_dir: process.env.PACKR_CACHE_DIR || options.cacheDir || configFromFile.cache_dir || '.packr/cache',
+			skip_unchanged: process.env.PACKR_SKIP_UNCHANGED === 'true' || options.skipUnchanged || configFromFile.skip_unchanged || false,
+			reproducible: process.env.PACKR_REPRODUCIBLE === 'true' || options.reproducible || configFromFile.reproducible || false,
+			verify: process.env.PACKR_VERIFY === 'true' || options.verify || configFromFile.verify || false,
+			output_mode: process.env.PACKR_OUTPUT_MODE || options.outputMode || configFromFile.output_mode,
+			output_group: process.env.PACKR_OUTPUT_GROUP || options.outputGroup || configFromFile.output_group,
+			io_retries: process.env.PACKR_IO_RETRIES ? Number(process.env.PACKR_IO_RETRIES) : (
+				options.ioRetries !== undefined ? options.ioRetries : (configFromFile.io_retries !== undefined ? configFromFile.io_retries : 3)
+			),
+			instance_lock: process.env.PACKR_INSTANCE_LOCK || options.instanceLock || configFromFile.instance_lock || 'wait',
+			tool_timeout_secs: process.env.PACKR_TOOL_TIMEOUT_SECS ? Number(process.env.PACKR_TOOL_TIMEOUT_SECS) : (options.toolTimeoutSecs || configFromFile.tool_timeout_secs),
+			tool_timeouts: options.toolTimeouts || configFromFile.tool_timeouts || {},
+			clean_output: process.env.PACKR_CLEAN_OUTPUT === 'true' || options.cleanOutput || configFromFile.clean_output || false,
+			clean_keep: options.cleanKeep || configFromFile.clean_keep || [],
+			allow_outside_config_dir: process.env.PACKR_ALLOW_OUTSIDE_CONFIG_DIR === 'true' || options.allowOutsideConfigDir || configFromFile.allow_outside_config_dir || false,
+			copy: options.copy || configFromFile.copy || [],
+			budgets: options.budgets || configFromFile.budgets || [],
+			minify_svg: process.env.PACKR_MINIFY_SVG === 'true' || options.minifySvg || configFromFile.minify_svg || false,
+			minify_html: process.env.PACKR_MINIFY_HTML === 'true' || options.minifyHtml || configFromFile.minify_html || false,
+			fonts: options.fonts || configFromFile.fonts,
+			hash_outputs: process.env.PACKR_HASH_OUTPUTS === 'true' || options.hashOutputs || configFromFile.hash_outputs || false,
+			hash_mode: process.env.PACKR_HASH_MODE || options.hashMode || configFromFile.hash_mode || 'filename',
+			manifest: process.env.PACKR_MANIFEST || options.manifest || configFromFile.manifest,
+			manifest_format: process.env.PACKR_MANIFEST_FORMAT || options.manifestFormat || configFromFile.manifest_format || 'packr',
+			sri: process.env.PACKR_SRI === 'true' || options.sri || configFromFile.sri || false,
+			compress: process.env.PACKR_COMPRESS ? process.env.PACKR_COMPRESS.split(',') : (options.compress || configFromFile.compress || []),
+			html: options.html || configFromFile.html,
+			favicons: options.favicons || configFromFile.favicons,
+			images: options.images || configFromFile.images,
+			wordpress: options.wordpress || configFromFile.wordpress,
+			notifications: options.notifications || configFromFile.notifications,
+			metrics: options.metrics || configFromFile.metrics,
+			control: options.control || configFromFile.control
 		};
 
 		console.log('User config:', userConfig);
@@ -278,7 +394,10 @@ function packr(options = {}) {
 			js_input: resolveSafe(userConfig.js_input, configDir),
 			js_output: resolveSafe(userConfig.js_output, configDir),
 			css_destination: userConfig.css_destination ? resolveSafe(userConfig.css_destination, configDir) : undefined,
-			js_destination: userConfig.js_destination ? resolveSafe(userConfig.js_destination, configDir) : undefined
+			js_destination: userConfig.js_destination ? resolveSafe(userConfig.js_destination, configDir) : undefined,
+			bundles: userConfig.bundles.map(bundle => Object.fromEntries(
+				Object.entries(bundle).map(([key, value]) => [key, resolveSafe(value, configDir)])
+			))
 		};
 
 		console.log('Final config with absolute paths:', config);
@@ -301,6 +420,8 @@ function packr(options = {}) {
 			scss_output: normalized_scss_output,
 			js_input: config.js_input,
 			js_output: normalized_js_output,
+			bundles: config.bundles,
+			jobs: config.jobs,
 			minify: config.minify,
 			minify_js: config.minify_js,
 			minify_css: config.minify_css,
@@ -308,50 +429,180 @@ function packr(options = {}) {
 			target: config.target,
 			verbose: config.verbose,
 			sourcemap: config.sourcemap,
-			format: config.format
+			sourcemap_root: config.sourcemap_root,
+			sourcemap_upload: config.sourcemap_upload,
+			deploy: config.deploy,
+			eslint_max_warnings: config.eslint_max_warnings,
+			eslint_ratchet: config.eslint_ratchet,
+			format: config.format,
+			wasm_loader: config.wasm_loader,
+			public_path: config.public_path,
+			keep_names: config.keep_names,
+			mangle_props: config.mangle_props,
+			reserve_props: config.reserve_props,
+			env_inject: config.env_inject,
+			legacy_target: config.legacy_target,
+			polyfills: config.polyfills,
+			splitting: config.splitting,
+			vendor_chunk: config.vendor_chunk,
+			entry_names: config.entry_names,
+			chunk_names: config.chunk_names,
+			asset_names: config.asset_names,
+			asset_inline_limit: config.asset_inline_limit,
+			tree_shaking: config.tree_shaking,
+			ignore_annotations: config.ignore_annotations,
+			side_effects_overrides: config.side_effects_overrides,
+			esbuild_plugins: config.esbuild_plugins,
+			wasm_plugins: config.wasm_plugins,
+			conditions: config.conditions,
+			main_fields: config.main_fields,
+			license_report: config.license_report,
+			detect_duplicates: config.detect_duplicates,
+			strict_warnings: config.strict_warnings,
+			audit: config.audit,
+			audit_level: config.audit_level,
+			audit_fail: config.audit_fail,
+			runtime: config.runtime,
+			tool_lock: config.tool_lock,
+			cache_dir: config.cache_dir,
+			skip_unchanged: config.skip_unchanged,
+			reproducible: config.reproducible,
+			verify: config.verify,
+			output_mode: config.output_mode,
+			output_group: config.output_group,
+			io_retries: config.io_retries,
+			instance_lock: config.instance_lock,
+			tool_timeout_secs: config.tool_timeout_secs,
+			tool_timeouts: config.tool_timeouts,
+			clean_output: config.clean_output,
+			clean_keep: config.clean_keep,
+			allow_outside_config_dir: config.allow_outside_config_dir,
+			copy: config.copy,
+			budgets: config.budgets,
+			minify_svg: config.minify_svg,
+			minify_html: config.minify_html,
+			fonts: config.fonts,
+			hash_outputs: config.hash_outputs,
+			hash_mode: config.hash_mode,
+			manifest: config.manifest ? resolveSafe(config.manifest, configDir) : undefined,
+			manifest_format: config.manifest_format,
+			sri: config.sri,
+			compress: config.compress,
+			html: config.html,
+			favicons: config.favicons,
+			images: config.images,
+			wordpress: config.wordpress,
+			notifications: config.notifications,
+			metrics: config.metrics,
+			control: config.control
 		}, null, 2));
 
 
-		// * Get the path to the binary
-		const binaryPath = path.join(__dirname, 'bin', process.platform === 'win32' ? 'packr.exe' : 'packr');
+		return runBinary([configPath], options);
+	} catch (err) {
+		ErrorHandler.handleError(err, 'Packr Error');
+	}
+}
 
-		// * Check if the binary exists
-		if (!fs.existsSync(binaryPath)) {
-			return Promise.reject(new Error(`Binary not found at ${binaryPath}. Please run 'npm run build' first.`));
-		}
+/**
+ * Run the Packr binary with `args` plus the flags `options` ask for
+ * @param {string[]} args - Leading arguments, such as the config path
+ * @param {Object} options - Options passed to packr
+ * @returns {Promise<void>}
+ */
+function runBinary(args, options) {
+	// * Get the path to the binary
+	const binaryPath = path.join(__dirname, 'bin', process.platform === 'win32' ? 'packr.exe' : 'packr');
 
-		// * Prepare arguments
-		const args = [configPath];
-		if (options.watch) {
-			args.push('--watch');
+	// * Check if the binary exists
+	if (!fs.existsSync(binaryPath)) {
+		return Promise.reject(new Error(`Binary not found at ${binaryPath}. Please run 'npm run build' first.`));
+	}
+
+	// * Prepare arguments
+	if (options.watch) {
+		args.push('--watch');
+	}
+	if (options.force) {
+		args.push('--force');
+	}
+	if (options.clean) {
+		args.push('clean');
+		if (options.cleanCache) {
+			args.push('--cache');
 		}
+	}
+	if (options.warm) {
+		args.push('warm');
+	}
+	if (options.cacheExport) {
+		args.push('cache', 'export', options.cacheExport);
+	} else if (options.cacheImport) {
+		args.push('cache', 'import', options.cacheImport);
+	}
+	if (options.analyze) {
+		args.push('analyze');
+		if (options.analyzeHtml) {
+			args.push('--html', options.analyzeHtml);
+		}
+	}
+	if (options.profile) {
+		args.push('--profile', options.profile);
+	}
+	if (options.junit) {
+		args.push('--junit', options.junit);
+	}
+	if (options.codequality) {
+		args.push('--codequality', options.codequality);
+	}
+	if (options.json) {
+		args.push('--json');
+	}
+	if (options.eventsNdjson) {
+		args.push(options.eventsNdjson === true ? '--events-ndjson' : `--events-ndjson=${options.eventsNdjson}`);
+	}
+	if (options.daemon) {
+		args.push('--daemon');
+	}
+	for (const project of [].concat(options.project || [])) {
+		args.push('--project', project);
+	}
+	if (options.changedSince) {
+		args.push('--changed-since', options.changedSince);
+	}
+	if (options.lsp) {
+		args.push('lsp');
+	}
+	if (options.runDaemon || options.stopDaemon) {
+		args.push('daemon');
+		if (options.stopDaemon) {
+			args.push('--stop');
+		}
+	}
 
-		// * Return a promise that resolves when the process exits
-		return new Promise((resolve, reject) => {
-			const packrProcess = spawn(binaryPath, args, {
-				stdio: 'inherit',
-				shell: process.platform === 'win32',
-				env: {
-					...process.env,
-					NODE_ENV: process.env.NODE_ENV || 'production'
-				}
-			});
-
-			packrProcess.on('close', (code) => {
-				if (code === 0) {
-					resolve();
-				} else {
-					reject(new Error(`Packr process exited with code ${code}`));
-				}
-			});
-
-			packrProcess.on('error', (err) => {
-				reject(err);
-			});
+	// * Return a promise that resolves when the process exits
+	return new Promise((resolve, reject) => {
+		const packrProcess = spawn(binaryPath, args, {
+			stdio: 'inherit',
+			shell: process.platform === 'win32',
+			env: {
+				...process.env,
+				NODE_ENV: process.env.NODE_ENV || 'production'
+			}
 		});
-	} catch (err) {
-		ErrorHandler.handleError(err, 'Packr Error');
-	}
+
+		packrProcess.on('close', (code) => {
+			if (code === 0) {
+				resolve();
+			} else {
+				reject(new Error(`Packr process exited with code ${code}`));
+			}
+		});
+
+		packrProcess.on('error', (err) => {
+			reject(err);
+		});
+	});
 }
 
 /**
@@ -366,12 +617,36 @@ function watch(options = {}) {
 // * CLI entry
 if (require.main === module) {
 	const args = process.argv.slice(2);
+	const clean = args[0] === 'clean' && Boolean(args.shift());
+	const runDaemon = !clean && args[0] === 'daemon' && Boolean(args.shift());
+	const lsp = !clean && !runDaemon && args[0] === 'lsp' && Boolean(args.shift());
+	const warm = !clean && !runDaemon && args[0] === 'warm' && Boolean(args.shift());
+	const [, cacheAction, cacheFile] = args[0] === 'cache' ? args.splice(0, 3) : [];
+	const analyze = args[0] === 'analyze' && Boolean(args.shift());
+	const htmlIndex = args.indexOf('--html');
+	const analyzeHtml = analyze && htmlIndex !== -1 ? args.splice(htmlIndex, 2)[1] : undefined;
 	let configPath = args[0] 
 	  || (fs.existsSync(path.join(process.cwd(), 'packr.json'))
 	        ? path.join(process.cwd(), 'packr.json')
 	        : path.join(process.cwd(), '.packr.json'));
 
 	const watchMode = args.includes('--watch');
+	const force = args.includes('--force');
+	const daemon = args.includes('--daemon');
+	const reproducible = args.includes('--reproducible');
+	const deploy = args.includes('--deploy');
+	const profileIndex = args.indexOf('--profile');
+	const profile = profileIndex !== -1 ? args[profileIndex + 1] : undefined;
+	const junitIndex = args.indexOf('--junit');
+	const junit = junitIndex !== -1 ? args[junitIndex + 1] : undefined;
+	const codequalityIndex = args.indexOf('--codequality');
+	const codequality = codequalityIndex !== -1 ? args[codequalityIndex + 1] : undefined;
+	const project = args.filter((arg, i) => args[i - 1] === '--project');
+	const changedSinceIndex = args.indexOf('--changed-since');
+	const changedSince = changedSinceIndex !== -1 ? args[changedSinceIndex + 1] : undefined;
+	const json = args.includes('--json');
+	const eventsArg = args.find((arg) => arg === '--events-ndjson' || arg.startsWith('--events-ndjson='));
+	const eventsNdjson = eventsArg && (eventsArg.includes('=') ? eventsArg.slice(eventsArg.indexOf('=') + 1) : true);
 
 	if (fs.existsSync(configPath) && fs.statSync(configPath).isDirectory()) {
 		configPath = path.join(configPath, '.packr.json');
@@ -384,8 +659,21 @@ if (require.main === module) {
 
 	try {
 	    const config = JSON.parse(fs.readFileSync(configPath, 'utf8'));
+	    if (config.projects) {
+	        return packr({ config: configPath, force, reproducible, deploy, profile, junit, codequality, json, eventsNdjson, project, changedSince }).catch((err) => {
+	            console.error(err);
+	            process.exit(1);
+	        });
+	    }
+	    if (deploy && !config.deploy) {
+	        console.error('--deploy needs a deploy section in the config');
+	        process.exit(1);
+	    }
+	    if (deploy) {
+	        config.deploy = { ...config.deploy, on_build: true };
+	    }
 
-	    return packr({ ...config, watch: watchMode }).catch((err) => {
+	    return packr({ ...config, watch: watchMode, force, clean, cleanCache: args.includes('--cache'), warm, analyze, analyzeHtml, reproducible, cacheExport: cacheAction === 'export' ? cacheFile : undefined, cacheImport: cacheAction === 'import' ? cacheFile : undefined, profile, junit, codequality, json, eventsNdjson, daemon, lsp, runDaemon: runDaemon && !args.includes('--stop'), stopDaemon: runDaemon && args.includes('--stop') }).catch((err) => {
 	        console.error(err);
 	        process.exit(1);
 	    });
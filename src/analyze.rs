@@ -0,0 +1,285 @@
+// * ! ==================================================
+// * ! Bundle composition report for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::retry;
+use crate::sizes::size;
+use colored::*;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// * Modules listed per output in the console summary
+const TOP_MODULES: usize = 10;
+
+// * Self-contained treemap page; `__PACKR_DATA__` is replaced with the module tree
+const REPORT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Packr bundle report</title>
+<style>
+  * { box-sizing: border-box; }
+  body { margin: 0; font: 13px/1.4 system-ui, sans-serif; color: #1d1d1f; background: #f5f5f7; display: flex; flex-direction: column; height: 100vh; }
+  header { padding: 12px 16px; display: flex; gap: 16px; align-items: baseline; flex-wrap: wrap; }
+  h1 { font-size: 16px; margin: 0; }
+  nav a { color: #0066cc; cursor: pointer; text-decoration: none; }
+  nav a:hover { text-decoration: underline; }
+  nav span { color: #86868b; margin: 0 6px; }
+  #total { color: #86868b; }
+  #map { position: relative; flex: 1; margin: 0 16px 16px; }
+  .cell { position: absolute; overflow: hidden; border: 1px solid rgba(255, 255, 255, 0.9); border-radius: 2px; padding: 2px 4px; color: #fff; cursor: pointer; }
+  .cell.group { padding-top: 16px; }
+  .cell .label { position: absolute; top: 1px; left: 4px; right: 4px; white-space: nowrap; overflow: hidden; text-overflow: ellipsis; font-size: 11px; pointer-events: none; text-shadow: 0 1px 1px rgba(0, 0, 0, 0.3); }
+  .cell:hover { filter: brightness(1.1); }
+  #tip { position: fixed; pointer-events: none; background: rgba(29, 29, 31, 0.92); color: #fff; padding: 6px 8px; border-radius: 4px; font-size: 12px; display: none; max-width: 420px; word-break: break-all; }
+</style>
+</head>
+<body>
+<header>
+  <h1>Bundle composition</h1>
+  <nav id="crumbs"></nav>
+  <span id="total"></span>
+</header>
+<div id="map"></div>
+<div id="tip"></div>
+<script>
+const data = __PACKR_DATA__;
+
+function total(node) {
+  if (node.children) node.size = node.children.reduce((sum, child) => sum + total(child), 0);
+  return node.size;
+}
+total(data);
+
+function format(bytes) {
+  return bytes < 1024 ? bytes + ' B' : (bytes / 1024).toFixed(1) + ' KB';
+}
+
+function hue(name) {
+  let hash = 0;
+  for (const c of name) hash = (hash * 31 + c.charCodeAt(0)) % 360;
+  return hash;
+}
+
+// Squarified treemap layout: rows are filled while they keep the cells close to square
+function worst(row, side, scale) {
+  const areas = row.map((node) => node.size * scale);
+  const sum = areas.reduce((a, b) => a + b, 0);
+  const max = Math.max(...areas);
+  const min = Math.min(...areas);
+  return Math.max((side * side * max) / (sum * sum), (sum * sum) / (side * side * min));
+}
+
+function place(row, rect, scale, cells) {
+  const sum = row.reduce((a, node) => a + node.size * scale, 0);
+  if (rect.w >= rect.h) {
+    const width = sum / rect.h;
+    let y = rect.y;
+    for (const node of row) {
+      const height = (node.size * scale) / width;
+      cells.push({ node, x: rect.x, y, w: width, h: height });
+      y += height;
+    }
+    return { x: rect.x + width, y: rect.y, w: rect.w - width, h: rect.h };
+  }
+  const height = sum / rect.w;
+  let x = rect.x;
+  for (const node of row) {
+    const width = (node.size * scale) / height;
+    cells.push({ node, x, y: rect.y, w: width, h: height });
+    x += width;
+  }
+  return { x: rect.x, y: rect.y + height, w: rect.w, h: rect.h - height };
+}
+
+function layout(children, rect) {
+  const nodes = children.filter((node) => node.size > 0).sort((a, b) => b.size - a.size);
+  const sum = nodes.reduce((a, node) => a + node.size, 0);
+  const cells = [];
+  if (!sum || rect.w <= 0 || rect.h <= 0) return cells;
+  const scale = (rect.w * rect.h) / sum;
+  let row = [];
+  let i = 0;
+  while (i < nodes.length) {
+    const side = Math.min(rect.w, rect.h);
+    const next = row.concat([nodes[i]]);
+    if (!row.length || worst(row, side, scale) >= worst(next, side, scale)) {
+      row = next;
+      i++;
+    } else {
+      rect = place(row, rect, scale, cells);
+      row = [];
+    }
+  }
+  if (row.length) place(row, rect, scale, cells);
+  return cells;
+}
+
+const map = document.getElementById('map');
+const tip = document.getElementById('tip');
+const stack = [data];
+
+function render() {
+  const current = stack[stack.length - 1];
+  map.innerHTML = '';
+  draw(current, { x: 0, y: 0, w: map.clientWidth, h: map.clientHeight }, 0, current.path || '');
+
+  const crumbs = document.getElementById('crumbs');
+  crumbs.innerHTML = '';
+  stack.forEach((node, i) => {
+    if (i) crumbs.appendChild(Object.assign(document.createElement('span'), { textContent: '/' }));
+    const link = Object.assign(document.createElement('a'), { textContent: node.name });
+    link.onclick = () => { stack.length = i + 1; render(); };
+    crumbs.appendChild(link);
+  });
+  document.getElementById('total').textContent = format(current.size);
+}
+
+function draw(parent, rect, depth, prefix) {
+  for (const cell of layout(parent.children || [], rect)) {
+    const node = cell.node;
+    const path = prefix ? prefix + '/' + node.name : node.name;
+    const group = node.children && cell.w > 40 && cell.h > 40 && depth < 3;
+    const el = document.createElement('div');
+    el.className = 'cell' + (group ? ' group' : '');
+    Object.assign(el.style, {
+      left: cell.x + 'px', top: cell.y + 'px', width: cell.w + 'px', height: cell.h + 'px',
+      background: `hsl(${hue(depth ? path.split('/')[0] + '/' + path.split('/')[1] : node.name)}, 55%, ${42 + depth * 8}%)`,
+    });
+    if (cell.w > 30 && cell.h > 14) {
+      el.appendChild(Object.assign(document.createElement('div'), { className: 'label', textContent: node.name + ' ' + format(node.size) }));
+    }
+    el.onmousemove = (event) => {
+      event.stopPropagation();
+      tip.style.display = 'block';
+      tip.style.left = Math.min(event.clientX + 12, innerWidth - 430) + 'px';
+      tip.style.top = event.clientY + 12 + 'px';
+      const share = ((node.size / stack[0].size) * 100).toFixed(1);
+      tip.textContent = `${path} — ${format(node.size)} (${share}%)`;
+    };
+    el.onclick = (event) => {
+      event.stopPropagation();
+      if (node.children) {
+        node.path = path;
+        stack.push(node);
+        render();
+      }
+    };
+    map.appendChild(el);
+    if (group) draw(node, { x: cell.x + 2, y: cell.y + 16, w: cell.w - 4, h: cell.h - 18 }, depth + 1, path);
+  }
+}
+
+map.onmouseleave = () => { tip.style.display = 'none'; };
+addEventListener('resize', render);
+render();
+</script>
+</body>
+</html>
+"#;
+
+// * Directory tree of the modules in one output, sized by the bytes they contribute
+#[derive(Debug, Default)]
+struct Node {
+    size: u64,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert(&mut self, path: &str, bytes: u64) {
+        let mut node = self;
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            node = node.children.entry(part.to_string()).or_default();
+        }
+        node.size += bytes;
+    }
+
+    // Directories with a single subdirectory are merged, e.g. `node_modules/lodash-es`
+    fn to_json(&self, name: &str) -> serde_json::Value {
+        if self.children.is_empty() {
+            return serde_json::json!({ "name": name, "size": self.size });
+        }
+        if let (1, Some((child_name, child))) =
+            (self.children.len(), self.children.first_key_value())
+        {
+            if self.size == 0 && !child.children.is_empty() {
+                return child.to_json(&format!("{}/{}", name, child_name));
+            }
+        }
+        let children: Vec<serde_json::Value> = self
+            .children
+            .iter()
+            .map(|(child_name, child)| child.to_json(child_name))
+            .collect();
+        serde_json::json!({ "name": name, "children": children })
+    }
+}
+
+// * Print the largest modules of each output and, with `html`, write the treemap report
+pub fn analyze(metafile: &Path, html: Option<&Path>) -> Result<(), String> {
+    let content = fs::read_to_string(metafile)
+        .map_err(|e| format!("Failed to read esbuild metafile: {}", e))?;
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse esbuild metafile: {}", e))?;
+
+    let mut outputs = Vec::new();
+    for (output, details) in json["outputs"].as_object().into_iter().flatten() {
+        if output.ends_with(".map") {
+            continue;
+        }
+        let mut inputs: Vec<(&str, u64)> = details["inputs"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(input, info)| Some((input.as_str(), info["bytesInOutput"].as_u64()?)))
+            .collect();
+        inputs.sort_by_key(|&(_, bytes)| Reverse(bytes));
+
+        log_line(format!(
+            "{} {} {} from {} module(s)",
+            "Analyze".cyan().bold(),
+            output,
+            size(details["bytes"].as_u64().unwrap_or(0)),
+            inputs.len()
+        ));
+        for (input, bytes) in inputs.iter().take(TOP_MODULES) {
+            log_line(format!("  {:>10}  {}", size(*bytes), input));
+        }
+
+        let mut tree = Node::default();
+        for (input, bytes) in &inputs {
+            // Plugin namespaces such as `packr-side-effects:` are not part of the path
+            let path = input.rsplit_once(':').map_or(*input, |(_, path)| path);
+            tree.insert(path, *bytes);
+        }
+        let children: Vec<serde_json::Value> = tree
+            .children
+            .iter()
+            .map(|(name, child)| child.to_json(name))
+            .collect();
+        outputs.push(serde_json::json!({ "name": output, "children": children }));
+    }
+
+    let Some(html) = html else {
+        return Ok(());
+    };
+    let data = serde_json::json!({ "name": "bundle", "children": outputs });
+    // Keeps module paths from closing the script element
+    let data = data.to_string().replace("</", "<\\/");
+    let report = REPORT_TEMPLATE.replace("__PACKR_DATA__", &data);
+    if let Some(parent) = html.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    retry::write(html, report).map_err(|e| format!("Failed to write {}: {}", html.display(), e))?;
+    log_line(format!(
+        "{} report written to {}",
+        "Analyze".green().bold(),
+        html.display()
+    ));
+    Ok(())
+}
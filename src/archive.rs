@@ -0,0 +1,111 @@
+// * ! ==================================================
+// * ! Gzipped tar archives for Packr
+// * ! ==================================================
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+
+const BLOCK: usize = 512;
+
+// * NUL-terminated text field of a tar header
+fn field(header: &[u8], range: std::ops::Range<usize>) -> String {
+    let bytes = &header[range];
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// * Regular files of a gzipped tar archive as `(path, contents)`, in archive order
+pub fn unpack(tarball: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive = Vec::new();
+    GzDecoder::new(tarball)
+        .read_to_end(&mut archive)
+        .map_err(|e| format!("Failed to decompress archive: {}", e))?;
+
+    let mut files = Vec::new();
+    let mut offset = 0;
+    while offset + BLOCK <= archive.len() {
+        let header = &archive[offset..offset + BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        // ustar splits long paths into a prefix and a name
+        let path = match field(header, 345..500) {
+            prefix if prefix.is_empty() => field(header, 0..100),
+            prefix => format!("{}/{}", prefix, field(header, 0..100)),
+        };
+        let size = usize::from_str_radix(&field(header, 124..136), 8)
+            .map_err(|_| "Invalid tar header in archive".to_string())?;
+
+        let start = offset + BLOCK;
+        // Directories, links and the like carry no contents worth keeping
+        if matches!(header[156], b'0' | 0) {
+            let contents = archive
+                .get(start..start + size)
+                .ok_or_else(|| "Truncated archive".to_string())?;
+            files.push((path, contents.to_vec()));
+        }
+        offset = start + size.div_ceil(BLOCK) * BLOCK;
+    }
+
+    Ok(files)
+}
+
+// * ustar header for a regular file, splitting paths over 100 bytes into prefix and name
+fn header(path: &str, size: usize) -> Result<[u8; BLOCK], String> {
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        path.char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| (&path[..i], &path[i + 1..]))
+            .next()
+            .ok_or_else(|| format!("Path too long for archive: {}", path))?
+    };
+
+    let mut header = [0u8; BLOCK];
+    let mut put = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    put(0, name.as_bytes());
+    put(100, b"0000644\0");
+    put(108, b"0000000\0");
+    put(116, b"0000000\0");
+    put(124, format!("{:011o}\0", size).as_bytes());
+    // A fixed timestamp keeps archives of the same files identical
+    put(136, b"00000000000\0");
+    put(148, b"        ");
+    put(156, b"0");
+    put(257, b"ustar\0");
+    put(263, b"00");
+    put(345, prefix.as_bytes());
+
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+// * Gzipped tar archive of `(path, contents)` files
+pub fn pack(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let mut write = |bytes: &[u8]| {
+        encoder
+            .write_all(bytes)
+            .map_err(|e| format!("Failed to compress archive: {}", e))
+    };
+
+    for (path, contents) in files {
+        write(&header(path, contents.len())?)?;
+        write(contents)?;
+        write(&vec![
+            0;
+            contents.len().next_multiple_of(BLOCK) - contents.len()
+        ])?;
+    }
+    write(&[0; BLOCK * 2])?;
+
+    encoder
+        .finish()
+        .map_err(|e| format!("Failed to compress archive: {}", e))
+}
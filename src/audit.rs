@@ -0,0 +1,153 @@
+// * ! ==================================================
+// * ! Dependency audit step for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::children;
+use crate::runtime;
+use colored::*;
+use std::path::Path;
+
+// * npm advisory severities, lowest first
+pub const AUDIT_LEVELS: [&str; 5] = ["info", "low", "moderate", "high", "critical"];
+
+fn severity_rank(severity: &str) -> usize {
+    AUDIT_LEVELS
+        .iter()
+        .position(|level| *level == severity)
+        .unwrap_or(0)
+}
+
+// * A vulnerable package reported by `npm audit`
+struct Vulnerability {
+    name: String,
+    severity: String,
+    range: String,
+    fix_available: bool,
+}
+
+// * Read the `vulnerabilities` map of an `npm audit --json` report
+fn parse_report(json: &serde_json::Value) -> Vec<Vulnerability> {
+    let mut vulnerabilities: Vec<Vulnerability> = json
+        .get("vulnerabilities")
+        .and_then(|v| v.as_object())
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|(name, entry)| Vulnerability {
+                    name: name.clone(),
+                    severity: entry
+                        .get("severity")
+                        .and_then(|s| s.as_str())
+                        .unwrap_or("info")
+                        .to_string(),
+                    range: entry
+                        .get("range")
+                        .and_then(|r| r.as_str())
+                        .unwrap_or("*")
+                        .to_string(),
+                    fix_available: entry
+                        .get("fixAvailable")
+                        .is_some_and(|f| !matches!(f, serde_json::Value::Bool(false))),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    vulnerabilities.sort_by(|a, b| {
+        severity_rank(&b.severity)
+            .cmp(&severity_rank(&a.severity))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    vulnerabilities
+}
+
+// * Run `npm audit` in the project and gate the build on the configured severity
+pub fn run_audit(config: &Config, config_dir: &Path) -> Result<(), String> {
+    log_line(format!("{} npm audit", "Running".blue().bold()));
+
+    if !config_dir.join("package-lock.json").exists() {
+        return Err("npm audit requires a package-lock.json in the config directory".to_string());
+    }
+
+    let output = children::output(
+        "npm",
+        runtime::program("npm")
+            .arg("audit")
+            .arg("--json")
+            .current_dir(config_dir),
+    )
+    .map_err(|e| format!("Failed to run npm audit: {}", e))?;
+
+    // npm exits non-zero whenever anything is found, so the report decides the outcome
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: serde_json::Value = serde_json::from_str(&stdout).map_err(|_| {
+        format!(
+            "Failed to parse npm audit output: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    })?;
+
+    if let Some(error) = json.get("error") {
+        let summary = error
+            .get("summary")
+            .and_then(|s| s.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("npm audit failed: {}", summary));
+    }
+
+    let threshold = severity_rank(&config.audit_level);
+    let vulnerabilities = parse_report(&json);
+    let blocking = vulnerabilities
+        .iter()
+        .filter(|v| severity_rank(&v.severity) >= threshold)
+        .count();
+
+    if vulnerabilities.is_empty() {
+        log_line(format!(
+            "{} no known vulnerabilities",
+            "Audit".green().bold()
+        ));
+        return Ok(());
+    }
+
+    log_line("\nnpm audit Summary:");
+    log_line("==================");
+    for v in &vulnerabilities {
+        let severity = match severity_rank(&v.severity) {
+            rank if rank >= severity_rank("high") => v.severity.red().bold(),
+            rank if rank >= severity_rank("moderate") => v.severity.yellow().bold(),
+            _ => v.severity.normal(),
+        };
+        log_line(format!(
+            "  {} {} {}{}",
+            severity,
+            v.name,
+            v.range.dimmed(),
+            if v.fix_available {
+                " (fix available)"
+            } else {
+                ""
+            }
+        ));
+    }
+    log_line(format!(
+        "\nTotal vulnerable packages: {} ({} at or above \"{}\")",
+        vulnerabilities.len(),
+        blocking,
+        config.audit_level
+    ));
+
+    if blocking > 0 && config.audit_fail {
+        return Err(format!(
+            "{} package(s) with {} or higher severity vulnerabilities",
+            blocking, config.audit_level
+        ));
+    }
+
+    log_line(format!(
+        "{} vulnerabilities found (see summary above)",
+        "Audit".yellow().bold()
+    ));
+    Ok(())
+}
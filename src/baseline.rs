@@ -0,0 +1,92 @@
+// * ! ==================================================
+// * ! ESLint warning baseline for Packr
+// * ! ==================================================
+
+use crate::retry;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// * Warning counts per file, relative to the config directory; committed with the project
+pub const BASELINE_FILE: &str = ".packr-eslint-baseline.json";
+
+// * ESLint warnings per file, keyed like the baseline
+pub type Counts = BTreeMap<String, usize>;
+
+fn read(config_dir: &Path) -> Result<Option<Counts>, String> {
+    let path = config_dir.join(BASELINE_FILE);
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+fn write(config_dir: &Path, counts: &Counts) -> Result<(), String> {
+    let path = config_dir.join(BASELINE_FILE);
+    let json = serde_json::to_string_pretty(counts)
+        .map_err(|e| format!("Failed to serialize {}: {}", BASELINE_FILE, e))?;
+    retry::write(&path, json + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// * Fail when a file has more warnings than the baseline allows, a file missing from it none.
+// * Otherwise the baseline is lowered to the counts of this lint, so fixed warnings can't
+// * come back. Without a baseline, this lint's counts become it. Returns the trend to report.
+pub fn ratchet(config_dir: &Path, counts: &Counts) -> Result<Option<String>, String> {
+    let total: usize = counts.values().sum();
+    let Some(baseline) = read(config_dir)? else {
+        write(config_dir, &nonzero(counts.clone()))?;
+        return Ok(Some(format!(
+            "{} warning(s) recorded in {}",
+            total, BASELINE_FILE
+        )));
+    };
+
+    let increased: Vec<String> = counts
+        .iter()
+        .filter_map(|(file, &count)| {
+            let allowed = baseline.get(file).copied().unwrap_or(0);
+            (count > allowed).then(|| format!("{} {} (baseline {})", file, count, allowed))
+        })
+        .collect();
+    if !increased.is_empty() {
+        return Err(format!(
+            "ESLint warnings increased: {}; fix the new warnings, as {} only allows fewer",
+            increased.join(", "),
+            BASELINE_FILE
+        ));
+    }
+
+    // Files this lint didn't cover keep their entries
+    let mut lowered = baseline.clone();
+    lowered.extend(counts.iter().map(|(file, &count)| (file.clone(), count)));
+    let lowered = nonzero(lowered);
+    let previous: usize = counts
+        .keys()
+        .map(|file| baseline.get(file).copied().unwrap_or(0))
+        .sum();
+    if lowered != baseline {
+        write(config_dir, &lowered)?;
+    }
+    Ok(if total < previous {
+        Some(format!(
+            "{} warning(s), {} fewer than {}",
+            total,
+            previous - total,
+            BASELINE_FILE
+        ))
+    } else if total > 0 {
+        Some(format!(
+            "{} warning(s), none new since {}",
+            total, BASELINE_FILE
+        ))
+    } else {
+        None
+    })
+}
+
+fn nonzero(counts: Counts) -> Counts {
+    counts.into_iter().filter(|&(_, count)| count > 0).collect()
+}
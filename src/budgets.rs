@@ -0,0 +1,190 @@
+// * ! ==================================================
+// * ! Output size budgets for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, suffixed_path};
+use crate::manifest::relative_to;
+use crate::sizes::size;
+use colored::*;
+use flate2::write::GzEncoder;
+use glob::Pattern;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// * Supported values for a budget's `level`
+pub const LEVELS: [&str; 2] = ["warn", "error"];
+
+// * A `budgets` entry: limits for the outputs whose configured path matches `path`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Budget {
+    pub path: String,
+    // Size of the file itself
+    #[serde(default)]
+    pub max_raw: Option<Size>,
+    // Size of its `.min` variant
+    #[serde(default)]
+    pub max_min: Option<Size>,
+    // Gzipped size of the file
+    #[serde(default)]
+    pub max_gzip: Option<Size>,
+    #[serde(default = "default_level")]
+    pub level: String,
+}
+
+// * A size limit, either in bytes or as text such as `"250 KB"`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Size {
+    Bytes(u64),
+    Text(String),
+}
+
+impl Size {
+    pub fn bytes(&self) -> Result<u64, String> {
+        let text = match self {
+            Size::Bytes(bytes) => return Ok(*bytes),
+            Size::Text(text) => text.trim(),
+        };
+        let split = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("\"{}\" is not a size", text))?;
+        let factor = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1.0,
+            "KB" => 1024.0,
+            "MB" => 1024.0 * 1024.0,
+            _ => {
+                return Err(format!(
+                    "\"{}\" has an unknown unit (use B, KB or MB)",
+                    text
+                ))
+            }
+        };
+        Ok((number * factor).round() as u64)
+    }
+}
+
+fn default_level() -> String {
+    "warn".to_string()
+}
+
+// * Check the `budgets` entries, describing the first problem found
+pub fn validate(budgets: &[Budget]) -> Result<(), String> {
+    for budget in budgets {
+        Pattern::new(&budget.path)
+            .map_err(|e| format!("\"{}\" is not a valid glob: {}", budget.path, e))?;
+        if !LEVELS.contains(&budget.level.as_str()) {
+            return Err(format!(
+                "\"{}\" level \"{}\" (expected one of: {})",
+                budget.path,
+                budget.level,
+                LEVELS.join(", ")
+            ));
+        }
+        let limits = [&budget.max_raw, &budget.max_min, &budget.max_gzip];
+        if limits.iter().all(|limit| limit.is_none()) {
+            return Err(format!(
+                "\"{}\" needs at least one of max_raw, max_min or max_gzip",
+                budget.path
+            ));
+        }
+        for limit in limits.into_iter().flatten() {
+            limit.bytes()?;
+        }
+    }
+    Ok(())
+}
+
+fn gzip_size(path: &Path) -> Result<u64, String> {
+    let content =
+        fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder
+        .write_all(&content)
+        .map_err(|e| format!("Failed to gzip {}: {}", path.display(), e))?;
+    let compressed = encoder
+        .finish()
+        .map_err(|e| format!("Failed to gzip {}: {}", path.display(), e))?;
+    Ok(compressed.len() as u64)
+}
+
+fn file_size(path: &Path) -> Result<u64, String> {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+// * Compare the outputs against their budgets; exceeding an `error` budget fails the build
+pub fn check(
+    budgets: &[Budget],
+    config_dir: &Path,
+    outputs: &[(PathBuf, PathBuf)],
+) -> Result<(), String> {
+    let mut failed = 0;
+
+    for budget in budgets {
+        let pattern = Pattern::new(&budget.path)
+            .map_err(|e| format!("Invalid budget path {}: {}", budget.path, e))?;
+        let matched: Vec<&(PathBuf, PathBuf)> = outputs
+            .iter()
+            .filter(|(logical, _)| pattern.matches(&relative_to(logical, config_dir)))
+            .collect();
+        if matched.is_empty() {
+            log_line(format!(
+                "{} {} matched no outputs",
+                "Budget".yellow().bold(),
+                budget.path
+            ));
+            continue;
+        }
+
+        for (logical, actual) in matched {
+            let name = relative_to(logical, config_dir);
+            let min = suffixed_path(logical, ".min");
+            let min_file = outputs
+                .iter()
+                .find(|(other, _)| *other == min)
+                .map(|(_, file)| file);
+
+            let mut measured = Vec::new();
+            if let Some(ref limit) = budget.max_raw {
+                measured.push(("raw", file_size(actual)?, limit.bytes()?));
+            }
+            if let (Some(ref limit), Some(min_file)) = (&budget.max_min, min_file) {
+                measured.push(("min", file_size(min_file)?, limit.bytes()?));
+            }
+            if let Some(ref limit) = budget.max_gzip {
+                measured.push(("gzip", gzip_size(actual)?, limit.bytes()?));
+            }
+
+            for (kind, actual_size, limit) in measured {
+                if actual_size <= limit {
+                    continue;
+                }
+                let label = if budget.level == "error" {
+                    failed += 1;
+                    "Budget".red().bold()
+                } else {
+                    "Budget".yellow().bold()
+                };
+                log_line(format!(
+                    "{} {} {} {} exceeds {}",
+                    label,
+                    name,
+                    kind,
+                    size(actual_size),
+                    size(limit)
+                ));
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} size budget(s) exceeded", failed));
+    }
+    Ok(())
+}
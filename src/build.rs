@@ -2,16 +2,62 @@
 // * ! Build script for Packr
 // * ! ==================================================
 
+use crate::audit;
+use crate::baseline;
+use crate::budgets::{self, Budget};
+use crate::bundle::{self, Bundle};
+use crate::cache;
+use crate::children;
+use crate::compress;
+use crate::control::{self, ControlApi};
+use crate::copy::{expand_env, CopyTask};
+use crate::deploy::{self, Deploy};
+use crate::diagnostics::{self, EsbuildSummary, Severity};
+use crate::duplicates;
+use crate::error::PackrError;
+use crate::esbuild;
+use crate::events::{self, Event};
+use crate::favicons::Favicons;
+use crate::fonts::{self, Fonts};
+use crate::html::Html;
+use crate::images::{self, Images};
+use crate::inline;
+use crate::instance;
+use crate::licenses;
+use crate::lock;
+use crate::manifest::{self, Manifest};
+use crate::metrics::MetricsExport;
+use crate::notify::{Notifications, Webhook};
+use crate::permissions;
+use crate::plugin;
+use crate::polyfill;
+use crate::profile;
+use crate::retry;
+use crate::runtime::{self, arg_path, PackageManager, Runtime};
+use crate::shim;
+use crate::sourcemap_upload::{self, SourcemapUpload};
+use crate::vendor::{self, VendorChunk};
+use crate::wasm_plugin;
+use crate::wordpress::WordPress;
+use base64::Engine;
 use colored::*;
+use lightningcss::error::PrinterError;
+use lightningcss::printer::Printer;
 use lightningcss::stylesheet::{ParserOptions, PrinterOptions, StyleSheet};
-use std::collections::HashMap;
+use lightningcss::traits::ToCss;
+use parcel_sourcemap::SourceMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::env;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
-// * Default configuration structure loaded from packr.json
-#[derive(Debug, serde::Deserialize)]
+/// Build configuration, as read from `.packr.json` by [`load_config`]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Config {
     pub scss_input: String,
     pub scss_output: String,
@@ -21,22 +67,314 @@ pub struct Config {
     pub css_destination: Option<String>,
     #[serde(default)]
     pub js_destination: Option<String>,
+    #[serde(default)]
+    pub bundles: Vec<Bundle>,
+    #[serde(default)]
+    pub jobs: Option<usize>,
     #[serde(default = "default_minify")]
     pub minify: bool,
     #[serde(default = "default_target")]
     pub target: String,
     #[serde(default = "default_verbose")]
     pub verbose: bool,
-    #[serde(default = "default_sourcemap")]
-    pub sourcemap: bool,
+    #[serde(
+        default = "default_sourcemap",
+        deserialize_with = "deserialize_sourcemap"
+    )]
+    pub sourcemap: SourcemapMode,
     #[serde(default = "default_format")]
     pub format: String,
     #[serde(default = "default_eslint")]
     pub eslint: bool,
     #[serde(default)]
     pub eslint_config: Option<String>,
+    #[serde(default)]
+    pub eslint_max_warnings: Option<usize>,
+    #[serde(default)]
+    pub eslint_ratchet: bool,
+    #[serde(default)]
+    pub wasm_loader: Option<String>,
+    #[serde(default)]
+    pub public_path: Option<String>,
+    #[serde(default)]
+    pub keep_names: bool,
+    #[serde(default)]
+    pub mangle_props: Option<String>,
+    #[serde(default)]
+    pub reserve_props: Option<String>,
+    #[serde(default)]
+    pub env_inject: Vec<String>,
+    #[serde(default)]
+    pub legacy_target: Option<String>,
+    #[serde(default)]
+    pub polyfills: bool,
+    #[serde(default)]
+    pub splitting: bool,
+    #[serde(default, deserialize_with = "vendor::deserialize_vendor_chunk")]
+    pub vendor_chunk: Option<VendorChunk>,
+    #[serde(default)]
+    pub entry_names: Option<String>,
+    #[serde(default)]
+    pub chunk_names: Option<String>,
+    #[serde(default)]
+    pub asset_names: Option<String>,
+    #[serde(default)]
+    pub asset_inline_limit: Option<u64>,
+    #[serde(default)]
+    pub tree_shaking: Option<bool>,
+    #[serde(default)]
+    pub ignore_annotations: bool,
+    #[serde(default)]
+    pub side_effects_overrides: HashMap<String, bool>,
+    #[serde(default)]
+    pub esbuild_plugins: Vec<shim::PluginSpec>,
+    #[serde(default)]
+    pub wasm_plugins: Vec<String>,
+    #[serde(default)]
+    pub conditions: Vec<String>,
+    #[serde(default)]
+    pub main_fields: Vec<String>,
+    #[serde(default)]
+    pub license_report: bool,
+    #[serde(default)]
+    pub detect_duplicates: bool,
+    #[serde(default, deserialize_with = "deserialize_strict_warnings")]
+    pub strict_warnings: StrictWarnings,
+    #[serde(default)]
+    pub audit: bool,
+    #[serde(default = "default_audit_level")]
+    pub audit_level: String,
+    #[serde(default = "default_audit_fail")]
+    pub audit_fail: bool,
+    #[serde(default)]
+    pub runtime: Option<String>,
+    #[serde(default)]
+    pub tool_lock: Option<String>,
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+    #[serde(default)]
+    pub skip_unchanged: bool,
+    #[serde(default)]
+    pub reproducible: bool,
+    #[serde(default)]
+    pub verify: bool,
+    #[serde(default)]
+    pub sourcemap_root: Option<String>,
+    #[serde(default)]
+    pub sourcemap_upload: Option<SourcemapUpload>,
+    #[serde(default)]
+    pub deploy: Option<Deploy>,
+    #[serde(default)]
+    pub metrics: Option<MetricsExport>,
+    #[serde(default)]
+    pub control: Option<ControlApi>,
+    #[serde(default)]
+    pub output_mode: Option<String>,
+    #[serde(default)]
+    pub output_group: Option<String>,
+    #[serde(default = "default_io_retries")]
+    pub io_retries: u32,
+    #[serde(default)]
+    pub tool_timeout_secs: Option<u64>,
+    #[serde(default = "default_instance_lock")]
+    pub instance_lock: String,
+    #[serde(default)]
+    pub tool_timeouts: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub clean_output: bool,
+    #[serde(default)]
+    pub clean_keep: Vec<String>,
+    #[serde(default)]
+    pub allow_outside_config_dir: bool,
+    #[serde(default)]
+    pub images: Option<Images>,
+    #[serde(default)]
+    pub copy: Vec<CopyTask>,
+    #[serde(default)]
+    pub budgets: Vec<Budget>,
+    #[serde(default)]
+    pub minify_svg: bool,
+    #[serde(default)]
+    pub minify_html: bool,
+    #[serde(default, deserialize_with = "fonts::deserialize_fonts")]
+    pub fonts: Option<Fonts>,
+    #[serde(default)]
+    pub hash_outputs: bool,
+    #[serde(default = "default_hash_mode")]
+    pub hash_mode: String,
+    #[serde(default)]
+    pub manifest: Option<String>,
+    #[serde(default = "default_manifest_format")]
+    pub manifest_format: String,
+    #[serde(default)]
+    pub sri: bool,
+    #[serde(default)]
+    pub compress: Vec<String>,
+    #[serde(default)]
+    pub html: Option<Html>,
+    #[serde(default)]
+    pub favicons: Option<Favicons>,
+    #[serde(default)]
+    pub wordpress: Option<WordPress>,
+    #[serde(default)]
+    pub notifications: Notifications,
+    // File name pattern from a `[hash]` in `scss_output`, e.g. `[name].[hash]`
+    #[serde(skip)]
+    pub css_hash_names: Option<String>,
+}
+
+/// Source map output style, shared by the CSS and JS pipelines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourcemapMode {
+    None,
+    Linked,
+    External,
+    Inline,
+    Both,
+}
+
+impl SourcemapMode {
+    // * Parse a mode from a config or environment value (`true` means linked)
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "false" | "none" => Some(Self::None),
+            "true" | "linked" => Some(Self::Linked),
+            "external" => Some(Self::External),
+            "inline" => Some(Self::Inline),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        self != Self::None
+    }
+
+    // * Whether a separate `.map` file is written next to the output
+    pub fn writes_file(self) -> bool {
+        matches!(self, Self::Linked | Self::External | Self::Both)
+    }
+
+    // * Argument passed to esbuild's `--sourcemap` flag
+    fn esbuild_arg(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Linked => Some("--sourcemap=linked"),
+            Self::External => Some("--sourcemap=external"),
+            Self::Inline => Some("--sourcemap=inline"),
+            Self::Both => Some("--sourcemap=both"),
+        }
+    }
+}
+
+// * Written back the way the config spells it: `false`, or the name of the mode
+impl serde::Serialize for SourcemapMode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::None => serializer.serialize_bool(false),
+            Self::Linked => serializer.serialize_str("linked"),
+            Self::External => serializer.serialize_str("external"),
+            Self::Inline => serializer.serialize_str("inline"),
+            Self::Both => serializer.serialize_str("both"),
+        }
+    }
+}
+
+// * Accept either a boolean or one of the named modes for `sourcemap`
+fn deserialize_sourcemap<'de, D>(deserializer: D) -> Result<SourcemapMode, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Bool(bool),
+        Mode(String),
+    }
+
+    match <Value as serde::Deserialize>::deserialize(deserializer)? {
+        Value::Bool(true) => Ok(SourcemapMode::Linked),
+        Value::Bool(false) => Ok(SourcemapMode::None),
+        Value::Mode(mode) => SourcemapMode::parse(&mode).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid sourcemap mode \"{}\" (expected true, false, inline, external, both, or linked)",
+                mode
+            ))
+        }),
+    }
+}
+
+/// Warning categories that fail the build instead of only being reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StrictWarnings {
+    // `@warn` messages from the Sass compile
+    #[serde(default)]
+    pub sass: bool,
+    // Rules and selectors lightningcss doesn't recognize
+    #[serde(default)]
+    pub css: bool,
+    // ESLint warnings
+    #[serde(default)]
+    pub eslint: bool,
+}
+
+impl StrictWarnings {
+    const CATEGORIES: [&'static str; 3] = ["sass", "css", "eslint"];
+
+    fn all(enabled: bool) -> Self {
+        Self {
+            sass: enabled,
+            css: enabled,
+            eslint: enabled,
+        }
+    }
+
+    // * Parse an environment value: `true`, `false`, or a list of categories such as `sass,css`
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "true" => return Some(Self::all(true)),
+            "false" => return Some(Self::all(false)),
+            _ => {}
+        }
+        let mut strict = Self::default();
+        for category in split_list(value) {
+            match category.as_str() {
+                "sass" => strict.sass = true,
+                "css" => strict.css = true,
+                "eslint" => strict.eslint = true,
+                _ => return None,
+            }
+        }
+        Some(strict)
+    }
+}
+
+// * Accept either a boolean for every category or an object of categories for `strict_warnings`
+fn deserialize_strict_warnings<'de, D>(deserializer: D) -> Result<StrictWarnings, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Bool(bool),
+        Categories(StrictWarnings),
+    }
+
+    match <Value as serde::Deserialize>::deserialize(deserializer) {
+        Ok(Value::Bool(enabled)) => Ok(StrictWarnings::all(enabled)),
+        Ok(Value::Categories(strict)) => Ok(strict),
+        Err(_) => Err(serde::de::Error::custom(format!(
+            "invalid strict_warnings (expected true, false, or an object with {})",
+            StrictWarnings::CATEGORIES.join(", ")
+        ))),
+    }
 }
 
+// * esbuild loaders accepted for `.wasm` imports
+const WASM_LOADERS: [&str; 4] = ["file", "binary", "base64", "dataurl"];
+
 // * Error handling utilities
 struct ErrorContext {
     context: String,
@@ -75,19 +413,135 @@ where
     result.map_err(|e| format!("{}: {}", context, e))
 }
 
-// * Load and parse packr configuration JSON
-pub fn load_config(config_path: &str) -> Result<(Config, PathBuf), String> {
+// * File name of an output path, e.g. `app.css` in `dist/app.css`
+fn file_name(path: &Path) -> Result<&std::ffi::OsStr, String> {
+    path.file_name()
+        .ok_or_else(|| format!("Output path has no file name: {}", path.display()))
+}
+
+// * Why an output path can't be written as a file, e.g. `dist/` naming only a directory
+pub(crate) fn invalid_output_path(path: &str) -> Option<&'static str> {
+    if path.trim().is_empty() {
+        return Some("is empty");
+    }
+    if path.ends_with('/') || path.ends_with('\\') || Path::new(path).file_name().is_none() {
+        return Some("names a directory; add a file name, e.g. dist/app.css");
+    }
+    None
+}
+
+// * Split a comma-separated environment value into trimmed, non-empty items
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// * Expand `[name]` and `[ext]` in an output path, and split off a `[hash]` in its file name:
+// * `dist/[name].[hash].[ext]` becomes `dist/app.js` plus the file name pattern `[name].[hash]`
+fn resolve_output_template(
+    output: &str,
+    input: &str,
+    ext: &str,
+) -> Result<(String, Option<String>), String> {
+    let name = Path::new(input)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let output = output.replace("[name]", &name).replace("[ext]", ext);
+    if !output.contains("[hash]") {
+        return Ok((output, None));
+    }
+
+    let invalid = |details: &str| {
+        ErrorContext::new("Invalid output template")
+            .with_details(&format!("{}: {}", output, details))
+            .format()
+    };
+
+    let path = Path::new(&output);
+    let file_name = path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !file_name.contains("[hash]") || file_name.matches("[hash]").count() > 1 {
+        return Err(invalid("[hash] may only appear once, in the file name"));
+    }
+
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains("[hash]") => (stem, Some(extension)),
+        _ => (file_name.as_str(), None),
+    };
+
+    // Drop the placeholder together with one separator next to it
+    let logical_stem = [
+        ".[hash]", "-[hash]", "_[hash]", "[hash].", "[hash]-", "[hash]_", "[hash]",
+    ]
+    .iter()
+    .find(|token| stem.contains(*token))
+    .map(|token| stem.replacen(token, "", 1))
+    .unwrap_or_default();
+    if logical_stem.is_empty() || !stem.contains(&logical_stem) {
+        return Err(invalid(
+            "the file name needs a fixed part before or after [hash]",
+        ));
+    }
+
+    let logical_name = match extension {
+        Some(extension) => format!("{}.{}", logical_stem, extension),
+        None => logical_stem.clone(),
+    };
+    let logical = path.with_file_name(logical_name);
+    Ok((
+        logical.to_string_lossy().to_string(),
+        Some(stem.replacen(&logical_stem, "[name]", 1)),
+    ))
+}
+
+/// Load and validate the config file at `config_path`, with `PACKR_*` environment overrides
+/// applied. Returns the config and the directory its paths are relative to.
+///
+/// Loading also sets the config up for the whole process, for every build that follows: its
+/// `wasm_plugins` are loaded, registered [`PackrPlugin`](crate::PackrPlugin)s get to adjust
+/// it, and its `io_retries` and tool timeouts apply to every write and tool run from then on.
+pub fn load_config(config_path: &str) -> Result<(Config, PathBuf), PackrError> {
     log_info("Loading config", &format!("from: {}", config_path));
+    let (mut config, config_dir) = read_config(config_path)?;
+
+    // * WebAssembly plugins run in every build of this config, after the registered ones
+    wasm_plugin::configure(&config.wasm_plugins, &config_dir).map_err(|e| {
+        PackrError::Config(
+            ErrorContext::new("Invalid wasm_plugins")
+                .with_details(&e)
+                .format(),
+        )
+    })?;
 
-    let config_str = handle_error(
-        fs::read_to_string(config_path),
-        "Failed to read config file",
-    )?;
+    // * Plugins registered with `plugin::register` see the final config and may adjust it
+    plugin::config_resolved(&mut config, &config_dir).map_err(PackrError::Config)?;
 
-    let mut config: Config = handle_error(
-        serde_json::from_str(&config_str),
-        "Failed to parse config file",
-    )?;
+    // * Every write after this point retries as configured, whichever command runs
+    retry::configure(config.io_retries);
+    children::configure(config.tool_timeout_secs, &config.tool_timeouts);
+
+    log_info("Config loaded", &format!("{:?}", config));
+    Ok((config, config_dir))
+}
+
+// * Read and validate the config file at `config_path` like `load_config`, without setting
+// * anything up: no plugin runs and nothing changes for the rest of the process
+pub fn read_config(config_path: &str) -> Result<(Config, PathBuf), PackrError> {
+    let config_str = fs::read_to_string(config_path)
+        .map_err(|e| PackrError::io("Failed to read config file", config_path, e))?;
+
+    let mut config: Config =
+        serde_json::from_str(&config_str).map_err(|source| PackrError::ConfigParse {
+            path: PathBuf::from(config_path),
+            source,
+        })?;
 
     // Override config with environment variables if they exist
     if let Ok(val) = env::var("PACKR_MINIFY") {
@@ -103,7 +557,7 @@ pub fn load_config(config_path: &str) -> Result<(Config, PathBuf), String> {
     }
 
     if let Ok(val) = env::var("PACKR_SOURCEMAP") {
-        config.sourcemap = val == "true";
+        config.sourcemap = SourcemapMode::parse(&val).unwrap_or(SourcemapMode::None);
     }
 
     if let Ok(val) = env::var("PACKR_FORMAT") {
@@ -118,272 +572,1620 @@ pub fn load_config(config_path: &str) -> Result<(Config, PathBuf), String> {
         config.eslint_config = Some(val);
     }
 
-    let config_dir = Path::new(config_path)
-        .parent()
-        .ok_or_else(|| ErrorContext::new("Failed to get config directory").format())?
-        .to_path_buf();
-
-    log_info("Config loaded", &format!("{:?}", config));
-    Ok((config, config_dir))
-}
+    if let Ok(val) = env::var("PACKR_ESLINT_MAX_WARNINGS") {
+        config.eslint_max_warnings = Some(val.trim().parse().map_err(|_| {
+            PackrError::Config(
+                ErrorContext::new("Invalid PACKR_ESLINT_MAX_WARNINGS")
+                    .with_details(&format!("\"{}\" is not a number of warnings", val))
+                    .format(),
+            )
+        })?);
+    }
 
-// * Helper function to resolve paths
-fn resolve_path(base: &Path, path: &str) -> PathBuf {
-    base.join(path)
-}
+    if let Ok(val) = env::var("PACKR_ESLINT_RATCHET") {
+        config.eslint_ratchet = val == "true";
+    }
 
-// * Logging helper functions
-fn log_info(context: &str, message: &str) {
-    println!("{} {}", context.blue().bold(), message);
-}
+    if let Ok(val) = env::var("PACKR_WASM_LOADER") {
+        config.wasm_loader = Some(val);
+    }
 
-fn log_success(context: &str, message: &str) {
-    println!("{} {}", context.green().bold(), message);
-}
+    if let Ok(val) = env::var("PACKR_PUBLIC_PATH") {
+        config.public_path = Some(val);
+    }
 
-fn log_error(context: &str, message: &str) {
-    eprintln!("{} {}", context.red().bold(), message);
-}
+    if let Ok(val) = env::var("PACKR_KEEP_NAMES") {
+        config.keep_names = val == "true";
+    }
 
-fn log_warning(context: &str, message: &str) {
-    println!("{} {}", context.yellow().bold(), message);
-}
+    if let Ok(val) = env::var("PACKR_MANGLE_PROPS") {
+        config.mangle_props = Some(val);
+    }
 
-// * Structure to track ESLint warnings across builds
-#[derive(Default)]
-struct ESLintSummary {
-    warnings: HashMap<String, Vec<String>>,
-}
+    if let Ok(val) = env::var("PACKR_RESERVE_PROPS") {
+        config.reserve_props = Some(val);
+    }
 
-impl ESLintSummary {
-    fn add_warning(&mut self, file: String, warning: String) {
-        self.warnings.entry(file).or_default().push(warning);
+    if let Ok(val) = env::var("PACKR_LEGACY_TARGET") {
+        config.legacy_target = Some(val);
     }
 
-    fn display(&self) {
-        if self.warnings.is_empty() {
-            return;
-        }
+    if let Ok(val) = env::var("PACKR_POLYFILLS") {
+        config.polyfills = val == "true";
+    }
 
-        println!("\nESLint Warning Summary:");
-        println!("=====================");
+    if let Ok(val) = env::var("PACKR_SPLITTING") {
+        config.splitting = val == "true";
+    }
 
-        for (file, warnings) in &self.warnings {
-            println!("\nFile: {}", file);
-            println!("Warnings:");
-            for warning in warnings {
-                println!("  {}", warning);
-            }
-        }
-        println!("\nTotal files with warnings: {}", self.warnings.len());
-        println!(
-            "Total warnings: {}",
-            self.warnings.values().map(|w| w.len()).sum::<usize>()
-        );
+    if let Ok(val) = env::var("PACKR_ENTRY_NAMES") {
+        config.entry_names = Some(val);
     }
-}
 
-// * Compile SCSS using `grass`, optionally minify with `lightningcss`
-pub fn build_styles(config: &Config, config_dir: &Path) -> Result<(), String> {
-    log_info("Building styles", &format!("from: {}", config.scss_input));
+    if let Ok(val) = env::var("PACKR_CHUNK_NAMES") {
+        config.chunk_names = Some(val);
+    }
 
-    let input = resolve_path(config_dir, &config.scss_input);
-    let output = config_dir.join(&config.scss_output);
+    if let Ok(val) = env::var("PACKR_ASSET_NAMES") {
+        config.asset_names = Some(val);
+    }
 
-    if !input.exists() {
-        return Err(ErrorContext::new("SCSS input file not found")
-            .with_details(&format!("{}", input.display()))
-            .format());
+    if let Ok(val) = env::var("PACKR_ASSET_INLINE_LIMIT") {
+        config.asset_inline_limit = Some(val.trim().parse().map_err(|_| {
+            PackrError::Config(
+                ErrorContext::new("Invalid PACKR_ASSET_INLINE_LIMIT")
+                    .with_details(&format!("\"{}\" is not a size in bytes", val))
+                    .format(),
+            )
+        })?);
     }
 
-    let css = handle_error(
-        grass::from_path(&input, &grass::Options::default()),
-        "SCSS compilation failed",
-    )?;
+    if let Ok(val) = env::var("PACKR_TREE_SHAKING") {
+        config.tree_shaking = Some(val == "true");
+    }
 
-    let parser_options = ParserOptions {
-        filename: input.to_string_lossy().to_string(),
-        ..Default::default()
-    };
+    if let Ok(val) = env::var("PACKR_IGNORE_ANNOTATIONS") {
+        config.ignore_annotations = val == "true";
+    }
 
-    let sheet = handle_error(
-        StyleSheet::parse(&css, parser_options),
-        "CSS parsing failed",
-    )?;
+    if let Ok(val) = env::var("PACKR_CONDITIONS") {
+        config.conditions = split_list(&val);
+    }
 
-    if let Some(parent) = output.parent() {
-        handle_error(
-            fs::create_dir_all(parent),
-            "Failed to create output directory",
-        )?;
+    if let Ok(val) = env::var("PACKR_MAIN_FIELDS") {
+        config.main_fields = split_list(&val);
     }
 
-    // Generate non-minified version
-    let printer_options = PrinterOptions {
-        minify: false,
-        ..Default::default()
-    };
-    let result = sheet.to_css(printer_options).map_err(|e| {
-        let error_msg = format!("CSS print error: {e}");
-        log_error("Error", &error_msg);
-        error_msg
-    })?;
+    if let Ok(val) = env::var("PACKR_LICENSE_REPORT") {
+        config.license_report = val == "true";
+    }
 
-    fs::write(&output, &result.code).map_err(|e| {
-        let error_msg = format!("Failed to write CSS: {e}");
-        log_error("Error", &error_msg);
-        error_msg
-    })?;
+    if let Ok(val) = env::var("PACKR_DETECT_DUPLICATES") {
+        config.detect_duplicates = val == "true";
+    }
 
-    if config.sourcemap {
-        let map_path = output.with_extension("css.map");
-        let map_content = format!(
-            "{{\"version\":3,\"file\":\"{}\",\"sources\":[\"{}\"],\"names\":[],\"mappings\":\"\"}}",
-            output.file_name().unwrap().to_string_lossy(),
-            input.file_name().unwrap().to_string_lossy()
-        );
-        fs::write(&map_path, map_content).map_err(|e| {
-            let error_msg = format!("Failed to write CSS sourcemap: {e}");
-            log_error("Error", &error_msg);
-            error_msg
+    if let Ok(val) = env::var("PACKR_STRICT_WARNINGS") {
+        config.strict_warnings = StrictWarnings::parse(&val).ok_or_else(|| {
+            PackrError::Config(
+                ErrorContext::new("Invalid PACKR_STRICT_WARNINGS")
+                    .with_details(&format!(
+                        "\"{}\" (expected true, false, or a list of: {})",
+                        val,
+                        StrictWarnings::CATEGORIES.join(", ")
+                    ))
+                    .format(),
+            )
         })?;
     }
 
-    let min_output = if config.minify {
-        let min_path = output.with_file_name(format!(
-            "{}.min{}",
-            output.file_stem().unwrap().to_string_lossy(),
-            output
-                .extension()
-                .map(|ext| format!(".{}", ext.to_string_lossy()))
-                .unwrap_or_default()
-        ));
+    if let Ok(val) = env::var("PACKR_AUDIT") {
+        config.audit = val == "true";
+    }
 
-        let printer_options = PrinterOptions {
-            minify: true,
-            ..Default::default()
-        };
-        let result = sheet.to_css(printer_options).map_err(|e| {
-            let error_msg = format!("CSS print error: {e}");
-            log_error("Error", &error_msg);
-            error_msg
-        })?;
+    if let Ok(val) = env::var("PACKR_AUDIT_LEVEL") {
+        config.audit_level = val;
+    }
 
-        fs::write(&min_path, &result.code).map_err(|e| {
-            let error_msg = format!("Failed to write minified CSS: {e}");
-            log_error("Error", &error_msg);
-            error_msg
-        })?;
+    if let Ok(val) = env::var("PACKR_AUDIT_FAIL") {
+        config.audit_fail = val == "true";
+    }
 
-        if config.sourcemap {
-            let map_path = min_path.with_extension("css.map");
-            let map_content = format!(
-                "{{\"version\":3,\"file\":\"{}\",\"sources\":[\"{}\"],\"names\":[],\"mappings\":\"\"}}",
-                min_path.file_name().unwrap().to_string_lossy(),
-                input.file_name().unwrap().to_string_lossy()
-            );
-            fs::write(&map_path, map_content).map_err(|e| {
-                let error_msg = format!("Failed to write minified CSS sourcemap: {e}");
-                log_error("Error", &error_msg);
-                error_msg
-            })?;
-        }
+    if let Ok(val) = env::var("PACKR_RUNTIME") {
+        config.runtime = Some(val);
+    }
 
-        Some(min_path)
-    } else {
-        None
-    };
+    if let Ok(val) = env::var("PACKR_TOOL_LOCK") {
+        config.tool_lock = Some(val).filter(|mode| mode != "off");
+    }
 
-    if config.verbose {
-        log_success("CSS", &format!("written to: {}", output.display()));
-        if let Some(ref min_path) = min_output {
-            log_success(
-                "CSS",
-                &format!("minified version written to: {}", min_path.display()),
-            );
-        }
+    if let Ok(val) = env::var("PACKR_CACHE_DIR") {
+        config.cache_dir = val;
     }
 
-    // * Copy result to alternate destination if defined
-    if let Some(dest) = &config.css_destination {
-        let dest_dir = config_dir.join(dest);
-        let dest_path = dest_dir.join(Path::new(&config.scss_output).file_name().unwrap());
-        let dest_min_path = if config.minify {
-            Some(
-                dest_dir.join(Path::new(&config.scss_output).with_file_name(format!(
-                        "{}.min{}",
-                        Path::new(&config.scss_output)
-                            .file_stem()
-                            .unwrap()
-                            .to_string_lossy(),
-                        Path::new(&config.scss_output)
-                            .extension()
-                            .map(|ext| format!(".{}", ext.to_string_lossy()))
-                            .unwrap_or_default()
-                    ))),
+    if let Ok(val) = env::var("PACKR_SKIP_UNCHANGED") {
+        config.skip_unchanged = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_REPRODUCIBLE") {
+        config.reproducible = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_VERIFY") {
+        config.verify = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_SOURCEMAP_ROOT") {
+        config.sourcemap_root = Some(val);
+    }
+
+    if let Ok(val) = env::var("PACKR_RELEASE") {
+        if let Some(ref mut upload) = config.sourcemap_upload {
+            upload.release = val;
+        }
+    }
+
+    if let Ok(val) = env::var("PACKR_DEPLOY") {
+        if let Some(ref mut deploy) = config.deploy {
+            deploy.on_build = val == "true";
+        }
+    }
+
+    if let Ok(val) = env::var("PACKR_DEPLOY_TARGET") {
+        if let Some(ref mut deploy) = config.deploy {
+            deploy.target = val;
+        }
+    }
+
+    if let Ok(val) = env::var("PACKR_METRICS_LISTEN") {
+        config.metrics.get_or_insert_with(Default::default).listen = Some(val);
+    }
+
+    if let Ok(val) = env::var("PACKR_STATSD") {
+        config.metrics.get_or_insert_with(Default::default).statsd = Some(val);
+    }
+
+    if let Ok(val) = env::var("PACKR_CONTROL_LISTEN") {
+        config.control.get_or_insert_with(Default::default).listen = val;
+    }
+
+    if let Ok(val) = env::var("PACKR_CONTROL_TOKEN") {
+        if let Some(ref mut control) = config.control {
+            control.token = Some(val);
+        }
+    }
+
+    if let Ok(val) = env::var("PACKR_OUTPUT_MODE") {
+        config.output_mode = Some(val);
+    }
+
+    if let Ok(val) = env::var("PACKR_OUTPUT_GROUP") {
+        config.output_group = Some(val);
+    }
+
+    if let Ok(val) = env::var("PACKR_IO_RETRIES") {
+        config.io_retries = val.trim().parse().map_err(|_| {
+            PackrError::Config(
+                ErrorContext::new("Invalid PACKR_IO_RETRIES")
+                    .with_details(&format!("\"{}\" is not a number of retries", val))
+                    .format(),
+            )
+        })?;
+    }
+
+    if let Ok(val) = env::var("PACKR_TOOL_TIMEOUT_SECS") {
+        config.tool_timeout_secs = Some(val.trim().parse().map_err(|_| {
+            PackrError::Config(
+                ErrorContext::new("Invalid PACKR_TOOL_TIMEOUT_SECS")
+                    .with_details(&format!("\"{}\" is not a number of seconds", val))
+                    .format(),
+            )
+        })?);
+    }
+
+    if let Ok(val) = env::var("PACKR_INSTANCE_LOCK") {
+        config.instance_lock = val;
+    }
+
+    if let Ok(val) = env::var("PACKR_CLEAN_OUTPUT") {
+        config.clean_output = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_ALLOW_OUTSIDE_CONFIG_DIR") {
+        config.allow_outside_config_dir = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_MINIFY_SVG") {
+        config.minify_svg = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_MINIFY_HTML") {
+        config.minify_html = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_HASH_OUTPUTS") {
+        config.hash_outputs = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_HASH_MODE") {
+        config.hash_mode = val;
+    }
+
+    if let Ok(val) = env::var("PACKR_MANIFEST") {
+        config.manifest = Some(val);
+    }
+
+    if let Ok(val) = env::var("PACKR_MANIFEST_FORMAT") {
+        config.manifest_format = val;
+    }
+
+    if let Ok(val) = env::var("PACKR_SRI") {
+        config.sri = val == "true";
+    }
+
+    if let Ok(val) = env::var("PACKR_COMPRESS") {
+        config.compress = split_list(&val);
+    }
+
+    if let Ok(val) = env::var("PACKR_JOBS") {
+        config.jobs = Some(val.trim().parse().map_err(|_| {
+            PackrError::Config(
+                ErrorContext::new("Invalid PACKR_JOBS")
+                    .with_details(&format!("\"{}\" is not a number of jobs", val))
+                    .format(),
             )
+        })?);
+    }
+
+    if config.jobs == Some(0) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid jobs")
+                .with_details("at least one entry has to be built at a time")
+                .format(),
+        ));
+    }
+
+    // * A webhook URL is often a secret, so it can come from the environment alone
+    for (var, webhook) in [
+        ("PACKR_WEBHOOK_URL", &mut config.notifications.webhook),
+        ("PACKR_SLACK_WEBHOOK_URL", &mut config.notifications.slack),
+        (
+            "PACKR_DISCORD_WEBHOOK_URL",
+            &mut config.notifications.discord,
+        ),
+    ] {
+        if let Ok(val) = env::var(var) {
+            match webhook {
+                Some(ref mut webhook) => webhook.url = val,
+                None => {
+                    *webhook = Some(Webhook {
+                        url: val,
+                        ..Webhook::default()
+                    })
+                }
+            }
+        }
+    }
+
+    // * Outputs must name files, so their minified variants and destination copies can be named
+    for (field, path) in [
+        ("scss_output", &config.scss_output),
+        ("js_output", &config.js_output),
+    ] {
+        if let Some(reason) = invalid_output_path(path) {
+            return Err(PackrError::Config(
+                ErrorContext::new(&format!("Invalid {}", field))
+                    .with_details(&format!("\"{}\" {}", path, reason))
+                    .format(),
+            ));
+        }
+    }
+
+    // * Resolve `[name]`, `[ext]` and `[hash]` placeholders in the output paths
+    let (scss_output, css_hash_names) =
+        resolve_output_template(&config.scss_output, &config.scss_input, "css")
+            .map_err(PackrError::Config)?;
+    config.scss_output = scss_output;
+    config.css_hash_names = css_hash_names;
+
+    let (js_output, js_hash_names) =
+        resolve_output_template(&config.js_output, &config.js_input, "js")
+            .map_err(PackrError::Config)?;
+    config.js_output = js_output;
+    if let Some(pattern) = js_hash_names {
+        if config.entry_names.is_some() {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid js_output")
+                    .with_details("use either [hash] in js_output or entry_names, not both")
+                    .format(),
+            ));
+        }
+        // esbuild fills in the hash; its `[name]` is the output's file stem
+        config.entry_names = Some(pattern);
+    }
+
+    // * Bundles are complete entries, with their output paths resolved like the main ones
+    bundle::validate(&config.bundles).map_err(|e| {
+        PackrError::Config(
+            ErrorContext::new("Invalid bundles")
+                .with_details(&e)
+                .format(),
+        )
+    })?;
+    for bundle in &mut config.bundles {
+        if let (Some(input), Some(output)) = (&bundle.scss_input, &mut bundle.scss_output) {
+            let (resolved, hash_names) =
+                resolve_output_template(output, input, "css").map_err(PackrError::Config)?;
+            *output = resolved;
+            bundle.css_hash_names = hash_names;
+        }
+        if let (Some(input), Some(output)) = (&bundle.js_input, &mut bundle.js_output) {
+            let (resolved, hash_names) =
+                resolve_output_template(output, input, "js").map_err(PackrError::Config)?;
+            *output = resolved;
+            bundle.js_hash_names = hash_names;
+        }
+    }
+
+    // Absolute paths let CI runners share a cache; relative ones stay inside the project
+    let cache_path = Path::new(&config.cache_dir);
+    if (!cache_path.is_absolute()
+        && cache_path
+            .components()
+            .any(|c| matches!(c, std::path::Component::ParentDir)))
+        || !cache_path
+            .components()
+            .any(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid cache_dir")
+                .with_details(&format!(
+                    "\"{}\" must be an absolute path or a subdirectory of the config directory",
+                    config.cache_dir
+                ))
+                .format(),
+        ));
+    }
+
+    if config.splitting && config.format != "esm" {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid splitting configuration")
+                .with_details("code splitting requires \"format\": \"esm\"")
+                .format(),
+        ));
+    }
+
+    if config.vendor_chunk.is_some() && !config.splitting {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid vendor_chunk configuration")
+                .with_details("vendor_chunk requires \"splitting\": true")
+                .format(),
+        ));
+    }
+
+    for name in &config.env_inject {
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid env_inject entry")
+                    .with_details(&format!("\"{}\" is not a valid variable name", name))
+                    .format(),
+            ));
+        }
+    }
+
+    if let Some(ref loader) = config.wasm_loader {
+        if !WASM_LOADERS.contains(&loader.as_str()) {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid wasm_loader")
+                    .with_details(&format!(
+                        "\"{}\" (expected one of: {})",
+                        loader,
+                        WASM_LOADERS.join(", ")
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    if let Some(ref settings) = config.images {
+        if let Err(e) = images::validate(settings) {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid images")
+                    .with_details(&e)
+                    .format(),
+            ));
+        }
+    }
+
+    if !audit::AUDIT_LEVELS.contains(&config.audit_level.as_str()) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid audit_level")
+                .with_details(&format!(
+                    "\"{}\" (expected one of: {})",
+                    config.audit_level,
+                    audit::AUDIT_LEVELS.join(", ")
+                ))
+                .format(),
+        ));
+    }
+
+    if let Some(ref name) = config.runtime {
+        if !runtime::RUNTIMES.contains(&name.as_str()) {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid runtime")
+                    .with_details(&format!(
+                        "\"{}\" (expected one of: {})",
+                        name,
+                        runtime::RUNTIMES.join(", ")
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    if let Some(ref mode) = config.output_mode {
+        if permissions::parse_mode(mode).is_none() {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid output_mode")
+                    .with_details(&format!(
+                        "\"{}\" (expected octal permissions such as \"0644\")",
+                        mode
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    if let Some(ref group) = config.output_group {
+        if cfg!(unix) && permissions::group_id(group).is_none() {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid output_group")
+                    .with_details(&format!(
+                        "\"{}\" is neither a group id nor a group listed in /etc/group",
+                        group
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    if config.tool_timeout_secs == Some(0) || config.tool_timeouts.values().any(|&secs| secs == 0) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid tool timeout")
+                .with_details("timeouts are in seconds and must be at least 1; leave a tool out to not limit it")
+                .format(),
+        ));
+    }
+
+    if let Some(tool) = config
+        .tool_timeouts
+        .keys()
+        .find(|tool| !children::TOOLS.contains(&tool.as_str()))
+    {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid tool_timeouts")
+                .with_details(&format!(
+                    "unknown tool \"{}\" (expected one of: {})",
+                    tool,
+                    children::TOOLS.join(", ")
+                ))
+                .format(),
+        ));
+    }
+
+    if !instance::MODES.contains(&config.instance_lock.as_str()) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid instance_lock")
+                .with_details(&format!(
+                    "\"{}\" (expected one of: {})",
+                    config.instance_lock,
+                    instance::MODES.join(", ")
+                ))
+                .format(),
+        ));
+    }
+
+    if let Some(ref upload) = config.sourcemap_upload {
+        let invalid = |details: String| {
+            PackrError::Config(
+                ErrorContext::new("Invalid sourcemap_upload")
+                    .with_details(&details)
+                    .format(),
+            )
+        };
+        if !sourcemap_upload::PROVIDERS.contains(&upload.provider.as_str()) {
+            return Err(invalid(format!(
+                "provider \"{}\" (expected one of: {})",
+                upload.provider,
+                sourcemap_upload::PROVIDERS.join(", ")
+            )));
+        }
+        // Inline maps are part of the code, so there is nothing to upload on its own
+        if !config.sourcemap.writes_file() {
+            return Err(invalid(
+                "sourcemap must write map files (true, linked, external or both)".to_string(),
+            ));
+        }
+        if upload.provider == "sentry" && (upload.org.is_none() || upload.project.is_none()) {
+            return Err(invalid(
+                "the sentry provider needs org and project".to_string(),
+            ));
+        }
+        if upload.provider == "http" && upload.url.is_none() {
+            return Err(invalid("the http provider needs a url".to_string()));
+        }
+        if let Some(ref url) = upload.url {
+            let expanded = expand_env(url);
+            if !expanded.starts_with("https://") && !expanded.starts_with("http://") {
+                return Err(invalid(format!("url \"{}\" must be an http(s) URL", url)));
+            }
+        }
+    }
+
+    if let Some(ref deploy) = config.deploy {
+        let invalid = |details: String| {
+            PackrError::Config(
+                ErrorContext::new("Invalid deploy")
+                    .with_details(&details)
+                    .format(),
+            )
+        };
+        if !deploy::PROVIDERS.contains(&deploy.provider.as_str()) {
+            return Err(invalid(format!(
+                "provider \"{}\" (expected one of: {})",
+                deploy.provider,
+                deploy::PROVIDERS.join(", ")
+            )));
+        }
+        let scheme = match deploy.provider.as_str() {
+            "s3" => Some("s3://"),
+            "gcs" => Some("gs://"),
+            _ => None,
+        };
+        match scheme {
+            Some(scheme) if !deploy.target.starts_with(scheme) => {
+                return Err(invalid(format!(
+                    "target \"{}\" must start with {}",
+                    deploy.target, scheme
+                )));
+            }
+            None if deploy.provider == "sftp" && !deploy.target.contains(':') => {
+                return Err(invalid(format!(
+                    "target \"{}\" must be [user@]host:/path",
+                    deploy.target
+                )));
+            }
+            _ => {}
+        }
+        if deploy.invalidate.is_some() && scheme.is_none() {
+            return Err(invalid(
+                "invalidate needs the s3 or gcs provider".to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref metrics) = config.metrics {
+        let valid_prefix = metrics
+            .prefix
+            .starts_with(|c: char| c.is_ascii_alphabetic())
+            && metrics
+                .prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_prefix {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid metrics")
+                    .with_details(&format!(
+                        "prefix \"{}\" must be a letter followed by letters, digits and underscores",
+                        metrics.prefix
+                    ))
+                    .format(),
+            ));
+        }
+        for (key, address) in [("listen", &metrics.listen), ("statsd", &metrics.statsd)] {
+            let Some(address) = address else {
+                continue;
+            };
+            let port = address
+                .rsplit_once(':')
+                .map(|(_, port)| port.parse::<u16>());
+            if !matches!(port, Some(Ok(_))) {
+                return Err(PackrError::Config(
+                    ErrorContext::new("Invalid metrics")
+                        .with_details(&format!("{} \"{}\" must be host:port", key, address))
+                        .format(),
+                ));
+            }
+        }
+    }
+
+    if let Some(ref api) = config.control {
+        let port = api
+            .listen
+            .rsplit_once(':')
+            .map(|(_, port)| port.parse::<u16>());
+        let details = if !matches!(port, Some(Ok(_))) {
+            Some(format!("listen \"{}\" must be host:port", api.listen))
+        } else if api.token.is_none() && !control::is_loopback(&api.listen) {
+            Some(format!(
+                "listen \"{}\" is reachable from other machines, which needs a token",
+                api.listen
+            ))
         } else {
             None
         };
+        if let Some(details) = details {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid control")
+                    .with_details(&details)
+                    .format(),
+            ));
+        }
+    }
+
+    for (key, webhook) in config.notifications.webhooks() {
+        let url = expand_env(&webhook.url);
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(PackrError::Config(
+                ErrorContext::new(&format!("Invalid notifications.{}", key))
+                    .with_details(&format!("url \"{}\" must be an http(s) URL", webhook.url))
+                    .format(),
+            ));
+        }
+    }
+
+    if let Some(ref mode) = config.tool_lock {
+        if !lock::MODES.contains(&mode.as_str()) {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid tool_lock")
+                    .with_details(&format!(
+                        "\"{}\" (expected one of: {})",
+                        mode,
+                        lock::MODES.join(", ")
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    if !manifest::HASH_MODES.contains(&config.hash_mode.as_str()) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid hash_mode")
+                .with_details(&format!(
+                    "\"{}\" (expected one of: {})",
+                    config.hash_mode,
+                    manifest::HASH_MODES.join(", ")
+                ))
+                .format(),
+        ));
+    }
+
+    if !manifest::MANIFEST_FORMATS.contains(&config.manifest_format.as_str()) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid manifest_format")
+                .with_details(&format!(
+                    "\"{}\" (expected one of: {})",
+                    config.manifest_format,
+                    manifest::MANIFEST_FORMATS.join(", ")
+                ))
+                .format(),
+        ));
+    }
+
+    if config
+        .copy
+        .iter()
+        .any(|task| task.replace.iter().any(|r| r.search.is_empty()))
+    {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid copy replacement")
+                .with_details("\"search\" must not be empty")
+                .format(),
+        ));
+    }
+
+    if let Err(e) = budgets::validate(&config.budgets) {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid budgets")
+                .with_details(&e)
+                .format(),
+        ));
+    }
+
+    for format in &config.compress {
+        if !compress::FORMATS.contains(&format.as_str()) {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid compress format")
+                    .with_details(&format!(
+                        "\"{}\" (expected one of: {})",
+                        format,
+                        compress::FORMATS.join(", ")
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    if config
+        .html
+        .as_ref()
+        .is_some_and(|html| html.templates.is_empty())
+    {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid html configuration")
+                .with_details("\"templates\" must list at least one template")
+                .format(),
+        ));
+    }
+
+    let config_dir = Path::new(config_path)
+        .parent()
+        .ok_or_else(|| {
+            PackrError::Config(ErrorContext::new("Failed to get config directory").format())
+        })?
+        .to_path_buf();
+
+    // * Catch outputs that would overwrite the sources or each other, or rebuild forever in
+    // * watch mode
+    if let Some(reason) = output_collision(&config, &config_dir)
+        .or_else(|| bundle::duplicate_output(&config, &config_dir))
+    {
+        return Err(PackrError::Config(
+            ErrorContext::new("Invalid output paths")
+                .with_details(&reason)
+                .format(),
+        ));
+    }
+
+    // * Nothing is written outside the project unless the config explicitly allows it
+    if !config.allow_outside_config_dir {
+        if let Some(reason) = outside_config_dir(&config, &config_dir) {
+            return Err(PackrError::Config(
+                ErrorContext::new("Invalid output path")
+                    .with_details(&format!(
+                        "{} (set allow_outside_config_dir to write there)",
+                        reason
+                    ))
+                    .format(),
+            ));
+        }
+    }
+
+    Ok((config, config_dir))
+}
+
+// * Helper function to resolve paths
+fn resolve_path(base: &Path, path: &str) -> PathBuf {
+    base.join(path)
+}
+
+// * `path` with `.` and `..` resolved lexically, as outputs may not exist yet
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// * `path` with the symlinks of its deepest existing ancestor resolved, so a link can't lead a
+// * path that looks contained out of the project
+fn resolve_existing(path: &Path) -> PathBuf {
+    let path = normalize_path(path);
+    let mut existing = path.as_path();
+    let mut rest = Vec::new();
+    loop {
+        let probe = if existing.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            existing
+        };
+        if let Ok(canonical) = probe.canonicalize() {
+            return rest
+                .iter()
+                .rev()
+                .fold(canonical, |path, part| path.join(part));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return path,
+        }
+    }
+}
+
+// * Whether `path` resolves inside the config directory, following symlinks like
+// * `outside_config_dir` does
+pub fn within_config_dir(config_dir: &Path, path: &Path) -> bool {
+    resolve_existing(path).starts_with(resolve_existing(config_dir))
+}
+
+// * Why the first configured path the build writes to that resolves outside the config
+// * directory does, if one does. Symlinked output directories are written through, so one
+// * inside the project that links out of it counts as outside.
+fn outside_config_dir(config: &Config, config_dir: &Path) -> Option<String> {
+    let mut written = bundle::outputs(config);
+    let optional = [
+        ("css_destination", &config.css_destination),
+        ("js_destination", &config.js_destination),
+        ("manifest", &config.manifest),
+    ];
+    for (field, path) in optional {
+        if let Some(path) = path {
+            written.push((field.to_string(), path));
+        }
+    }
+    for (i, task) in config.copy.iter().enumerate() {
+        written.push((format!("copy[{}].to", i), &task.to));
+    }
+    if let Some(ref html) = config.html {
+        written.push(("html.output".to_string(), &html.output));
+    }
+    if let Some(ref favicons) = config.favicons {
+        written.push(("favicons.output".to_string(), &favicons.output));
+    }
+    if let Some(ref wordpress) = config.wordpress {
+        written.push(("wordpress.path".to_string(), &wordpress.path));
+    }
+
+    written.into_iter().find_map(|(field, path)| {
+        if within_config_dir(config_dir, &config_dir.join(path)) {
+            return None;
+        }
+        let resolved = resolve_existing(&config_dir.join(path));
+        if normalize_path(&config_dir.join(path)).starts_with(normalize_path(config_dir)) {
+            return Some(format!(
+                "{} \"{}\" leads through a symlink to {}, outside the config directory",
+                field,
+                path,
+                resolved.display()
+            ));
+        }
+        Some(format!(
+            "{} \"{}\" is outside the config directory",
+            field, path
+        ))
+    })
+}
+
+// * Why the outputs or destinations would land on the inputs, if they do. Symlinks are
+// * resolved on both sides, so e.g. a `dist` linked to `src` is caught.
+fn output_collision(config: &Config, config_dir: &Path) -> Option<String> {
+    let resolve = |path: &str| resolve_existing(&config_dir.join(path));
+    let inputs: Vec<(String, PathBuf)> = bundle::inputs(config)
+        .into_iter()
+        .map(|(name, input)| (name, resolve(input)))
+        .collect();
+
+    // The minified and legacy bundles are written next to each output
+    for (name, output) in bundle::outputs(config) {
+        let output = resolve(output);
+        let written = [
+            suffixed_path(&output, ".min"),
+            suffixed_path(&output, ".legacy"),
+            output,
+        ];
+        if let Some((input_name, input)) = inputs.iter().find(|(_, input)| written.contains(input))
+        {
+            return Some(format!(
+                "{} would overwrite {} ({})",
+                name,
+                input_name,
+                input.display()
+            ));
+        }
+    }
+
+    for (name, destination) in [
+        ("css_destination", &config.css_destination),
+        ("js_destination", &config.js_destination),
+    ] {
+        let Some(destination) = destination else {
+            continue;
+        };
+        let dir = resolve(destination);
+        if let Some((input_name, _)) = inputs.iter().find(|(_, input)| input.starts_with(&dir)) {
+            return Some(format!(
+                "{} \"{}\" points at the source tree containing {}",
+                name, destination, input_name
+            ));
+        }
+        // Copies inside an input's directory would land among the sources too
+        if let Some((input_name, _)) = inputs
+            .iter()
+            .find(|(_, input)| input.parent().is_some_and(|parent| dir.starts_with(parent)))
+        {
+            return Some(format!(
+                "{} \"{}\" is inside the directory of {}",
+                name, destination, input_name
+            ));
+        }
+    }
+
+    None
+}
+
+thread_local! {
+    // Bundle built on this thread, so concurrent builds can be told apart in the log
+    static LOG_SCOPE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// * Prefix the log lines of the current thread with `[name]`, or stop prefixing them
+pub fn set_log_scope(name: Option<&str>) {
+    LOG_SCOPE.with(|scope| *scope.borrow_mut() = name.map(str::to_string));
+}
+
+fn log_prefix() -> String {
+    LOG_SCOPE
+        .with(|scope| scope.borrow().clone())
+        .map(|name| format!("{} ", format!("[{}]", name).dimmed()))
+        .unwrap_or_default()
+}
+
+// * Send the progress lines below to stderr, for modes whose stdout carries a protocol
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn log_to_stderr() {
+    LOG_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn logs_to_stderr() -> bool {
+    LOG_TO_STDERR.load(Ordering::Relaxed)
+}
+
+// * `println!` for progress and report lines, following `log_to_stderr`
+pub fn log_line(line: impl std::fmt::Display) {
+    if logs_to_stderr() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// * Logging helper functions
+fn log_info(context: &str, message: &str) {
+    log_line(format!(
+        "{}{} {}",
+        log_prefix(),
+        context.blue().bold(),
+        message
+    ));
+}
+
+fn log_success(context: &str, message: &str) {
+    log_line(format!(
+        "{}{} {}",
+        log_prefix(),
+        context.green().bold(),
+        message
+    ));
+}
+
+fn log_error(context: &str, message: &str) {
+    eprintln!("{}{} {}", log_prefix(), context.red().bold(), message);
+}
+
+fn log_warning(context: &str, message: &str) {
+    log_line(format!(
+        "{}{} {}",
+        log_prefix(),
+        context.yellow().bold(),
+        message
+    ));
+}
+
+// * Structure to track ESLint warnings across builds
+#[derive(Default)]
+struct ESLintSummary {
+    // Sorted by file, so the summary reads the same on every run
+    warnings: BTreeMap<String, Vec<String>>,
+}
+
+impl ESLintSummary {
+    fn add_warning(&mut self, file: String, warning: String) {
+        self.warnings.entry(file).or_default().push(warning);
+    }
+
+    fn display(&self) {
+        if self.warnings.is_empty() {
+            return;
+        }
+
+        log_line("\nESLint Warning Summary:");
+        log_line("=====================");
+
+        for (file, warnings) in &self.warnings {
+            log_line(format!("\nFile: {}", file));
+            log_line("Warnings:");
+            for warning in warnings {
+                log_line(format!("  {}", warning));
+            }
+        }
+        log_line(format!(
+            "\nTotal files with warnings: {}",
+            self.warnings.len()
+        ));
+        log_line(format!(
+            "Total warnings: {}",
+            self.warnings.values().map(|w| w.len()).sum::<usize>()
+        ));
+    }
+}
+
+// * Print the warnings of one category; with `strict_warnings` set for it they fail the build
+fn report_warnings(category: &str, warnings: &[String], strict: bool) -> Result<(), String> {
+    for warning in warnings {
+        log_warning(category, warning);
+        events::emit(|| Event::diagnostic_from_text(Severity::Warning, category, warning));
+    }
+    if strict && !warnings.is_empty() {
+        return Err(format!(
+            "{} {} warning(s) with strict_warnings enabled",
+            warnings.len(),
+            category
+        ));
+    }
+    Ok(())
+}
+
+/// Compile `scss_input` with grass and write the CSS (and its minified variant) through
+/// lightningcss, recording the outputs in `manifest`
+pub fn build_styles(
+    config: &Config,
+    config_dir: &Path,
+    manifest: &mut Manifest,
+) -> Result<(), PackrError> {
+    let _span = profile::span("styles", "build styles");
+    log_info("Building styles", &format!("from: {}", config.scss_input));
+
+    let input = resolve_path(config_dir, &config.scss_input);
+    let output = config_dir.join(&config.scss_output);
+
+    if !input.exists() {
+        return Err(PackrError::Scss(
+            ErrorContext::new("SCSS input file not found")
+                .with_details(&format!("{}", input.display()))
+                .format(),
+        ));
+    }
+
+    plugin::before_styles(config, config_dir).map_err(PackrError::Scss)?;
+
+    let cache_dir = cache::dir(config, config_dir);
+    let compiled = {
+        let _span = profile::span("styles", "grass compile");
+        cache::compile_scss(&cache_dir, &input).map_err(|e| {
+            events::emit(|| Event::diagnostic_from_text(Severity::Error, "Sass", &e));
+            PackrError::Scss(e)
+        })?
+    };
+    if compiled.cached {
+        log_info("SCSS", "no partial changed, reusing the cached CSS");
+    }
+    report_warnings("Sass", &compiled.warnings, config.strict_warnings.sass)
+        .map_err(PackrError::Scss)?;
+    let css = plugin::transform_css(compiled.css, &input).map_err(PackrError::Css)?;
+
+    let output_dir = output.parent().unwrap_or(config_dir).to_path_buf();
+    let (css, copied_fonts) = match config.fonts {
+        Some(ref settings) => {
+            let search_dirs = [input.parent().unwrap_or(config_dir), config_dir];
+            let processed = fonts::process(settings, &css, &search_dirs, &output_dir)
+                .map_err(PackrError::Css)?;
+            // Large design-system stylesheets shouldn't be held twice
+            drop(css);
+            processed
+        }
+        None => (css, Vec::new()),
+    };
+
+    // * lightningcss prints the same files for the same CSS and settings, so an unchanged
+    // * stylesheet is put back from the cache instead of being parsed and printed again
+    let printed_files = printed_css_files(config, &output);
+    let key = cache::printed_css_key(
+        &[
+            &input.to_string_lossy(),
+            &output.to_string_lossy(),
+            &config.minify.to_string(),
+            &format!("{:?}", config.sourcemap),
+        ],
+        &css,
+    );
+    match cache::printed_css(&cache_dir, &output, &key, printed_files.len()) {
+        Some(printed) => {
+            report_warnings("CSS", &printed.warnings, config.strict_warnings.css)
+                .map_err(PackrError::Css)?;
+            printed
+                .restore(&printed_files)
+                .map_err(|e| PackrError::io("Failed to restore cached CSS", &output, e))?;
+            log_info(
+                "CSS",
+                "unchanged since the last build, reusing the printed output",
+            );
+        }
+        None => {
+            let warnings = print_css(config, &input, &output, &css)?;
+            cache::store_printed_css(&cache_dir, &output, &key, &printed_files, &warnings);
+        }
+    }
+    let min_output = config.minify.then(|| suffixed_path(&output, ".min"));
+
+    // A `[hash]` in scss_output always renames; otherwise `hash_outputs` decides
+    let mut hash = |path: &Path| match config.css_hash_names {
+        Some(ref pattern) => manifest.hash_as(path, pattern),
+        None => manifest.hash(path),
+    };
+    let output = hash(&output).map_err(PackrError::Css)?;
+    let min_output = min_output
+        .map(|min_path| hash(&min_path))
+        .transpose()
+        .map_err(PackrError::Css)?;
+
+    if let Some(ref settings) = config.fonts {
+        let preload_path =
+            fonts::write_preload(settings, &output_dir, &copied_fonts).map_err(PackrError::Css)?;
+        if config.verbose {
+            log_success(
+                "Fonts",
+                &format!(
+                    "{} copied, preload tags written to: {}",
+                    copied_fonts.len(),
+                    preload_path.display()
+                ),
+            );
+        }
+    }
+
+    if config.verbose {
+        log_success("CSS", &format!("written to: {}", output.display()));
+        if let Some(ref min_path) = min_output {
+            log_success(
+                "CSS",
+                &format!("minified version written to: {}", min_path.display()),
+            );
+        }
+    }
+
+    // * Copy result to alternate destination if defined
+    if let Some(dest) = &config.css_destination {
+        let dest_dir = config_dir.join(dest);
+        let dest_path = dest_dir.join(file_name(&output).map_err(PackrError::Config)?);
+        let dest_min_path = min_output
+            .as_ref()
+            .map(|min_path| file_name(min_path).map(|name| dest_dir.join(name)))
+            .transpose()
+            .map_err(PackrError::Config)?;
 
         // Create destination directory
-        handle_error(
-            fs::create_dir_all(&dest_dir),
-            "Failed to create CSS destination folder",
-        )?;
+        fs::create_dir_all(&dest_dir)
+            .map_err(|e| PackrError::io("Failed to create CSS destination folder", &dest_dir, e))?;
 
         // Copy non-minified version
-        fs::write(&dest_path, &result.code).map_err(|e| {
-            let error_msg = format!("Failed to copy CSS to destination: {e}");
-            log_error("Error", &error_msg);
-            error_msg
+        retry::copy(&output, &dest_path).map_err(|e| {
+            let error = PackrError::io("Failed to copy CSS to destination", &dest_path, e);
+            log_error("Error", &error.to_string());
+            error
         })?;
 
-        // Copy minified version if it exists
-        if let Some(ref min_path) = dest_min_path {
-            if let Some(ref source_min) = min_output {
-                if let Ok(min_content) = fs::read(source_min) {
-                    // Create parent directory for minified file if needed
-                    if let Some(parent) = min_path.parent() {
-                        handle_error(
-                            fs::create_dir_all(parent),
-                            "Failed to create minified CSS destination folder",
-                        )?;
-                    }
-                    fs::write(min_path, min_content).map_err(|e| {
-                        let error_msg = format!("Failed to copy minified CSS to destination: {e}");
-                        log_error("Error", &error_msg);
-                        error_msg
+        // Copy minified version if it exists, without reading it back into memory
+        if let (Some(ref min_path), Some(ref source_min)) = (&dest_min_path, &min_output) {
+            if source_min.exists() {
+                retry::copy(source_min, min_path).map_err(|e| {
+                    let error =
+                        PackrError::io("Failed to copy minified CSS to destination", min_path, e);
+                    log_error("Error", &error.to_string());
+                    error
+                })?;
+            }
+        }
+
+        if let Some(ref settings) = config.fonts {
+            fonts::mirror(settings, &output_dir, &dest_dir, &copied_fonts)
+                .map_err(PackrError::Css)?;
+        }
+
+        // Copy the sourcemap of each copied stylesheet, the minified one's included
+        if config.sourcemap.writes_file() {
+            let copied = std::iter::once((output.clone(), dest_path.clone()))
+                .chain(min_output.clone().zip(dest_min_path.clone()));
+            for (source, dest) in copied {
+                let map_path = source.with_extension("css.map");
+                let dest_map_path = dest.with_extension("css.map");
+                if map_path.exists() {
+                    retry::copy(&map_path, &dest_map_path).map_err(|e| {
+                        let error = PackrError::io(
+                            "Failed to copy CSS sourcemap to destination",
+                            &dest_map_path,
+                            e,
+                        );
+                        log_error("Error", &error.to_string());
+                        error
                     })?;
                 }
             }
         }
 
-        if config.sourcemap {
-            let map_path = output.with_extension("css.map");
-            let dest_map_path = dest_path.with_extension("css.map");
-            if let Ok(map_content) = fs::read(&map_path) {
-                fs::write(&dest_map_path, map_content).map_err(|e| {
-                    let error_msg = format!("Failed to copy CSS sourcemap to destination: {e}");
-                    log_error("Error", &error_msg);
-                    error_msg
-                })?;
-            }
-        }
+        if config.verbose {
+            log_success("CSS", &format!("copied to: {}", dest_path.display()));
+            if let Some(ref min_path) = dest_min_path {
+                log_success(
+                    "CSS",
+                    &format!("minified version copied to: {}", min_path.display()),
+                );
+            }
+        }
+    }
+
+    log_success("Styles", "built successfully");
+    Ok(())
+}
+
+// * Files `print_css` writes for the stylesheet at `output`, in a fixed order
+fn printed_css_files(config: &Config, output: &Path) -> Vec<PathBuf> {
+    let mut files = vec![output.to_path_buf()];
+    if config.minify {
+        files.push(suffixed_path(output, ".min"));
+    }
+    if config.sourcemap.writes_file() {
+        let maps: Vec<PathBuf> = files
+            .iter()
+            .map(|file| file.with_extension("css.map"))
+            .collect();
+        files.extend(maps);
+    }
+    files
+}
+
+// * Parse the CSS with lightningcss and print it to `output`, and to its `.min` variant
+// * when minifying, returning the warnings of the parse
+fn print_css(
+    config: &Config,
+    input: &Path,
+    output: &Path,
+    css: &str,
+) -> Result<Vec<String>, PackrError> {
+    let css_warnings = Arc::new(RwLock::new(Vec::new()));
+    let parser_options = ParserOptions {
+        filename: input.to_string_lossy().to_string(),
+        warnings: Some(Arc::clone(&css_warnings)),
+        ..Default::default()
+    };
+
+    let parse_span = profile::span("styles", "lightningcss parse");
+    let sheet = StyleSheet::parse(css, parser_options)
+        .map_err(|e| PackrError::Css(format!("CSS parsing failed: {}", e)))?;
+    drop(parse_span);
+
+    let css_warnings: Vec<String> = css_warnings
+        .read()
+        .map(|warnings| warnings.iter().map(|w| w.to_string()).collect())
+        .unwrap_or_default();
+    report_warnings("CSS", &css_warnings, config.strict_warnings.css).map_err(PackrError::Css)?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| PackrError::io("Failed to create output directory", parent, e))?;
+    }
+
+    // * Both variants are printed straight into their files, one after the other
+    let print_span = profile::span("styles", "lightningcss print");
+    write_css(&sheet, output, input, css, false, config.sourcemap)?;
+    drop(print_span);
+
+    if config.minify {
+        let min_path = suffixed_path(output, ".min");
+        let print_span = profile::span("styles", "lightningcss print (minified)");
+        write_css(&sheet, &min_path, input, css, true, config.sourcemap)?;
+        drop(print_span);
+    }
+
+    Ok(css_warnings)
+}
+
+// * Populate the SCSS, ESLint and esbuild caches without writing any build output,
+// * so CI can prime them in a stage of its own
+pub fn warm_caches(config: &Config, config_dir: &Path) -> Result<(), String> {
+    let cache_dir = cache::dir(config, config_dir);
+
+    // Compiling records the SCSS dependency graph next to the compiled CSS in the cache
+    let scss_input = resolve_path(config_dir, &config.scss_input);
+    if scss_input.exists() {
+        let _span = profile::span("styles", "grass compile");
+        if cache::compile_scss(&cache_dir, &scss_input)?.cached {
+            log_success("Warm", "SCSS dependency graph already cached");
+        } else {
+            log_success("Warm", "SCSS dependency graph cached");
+        }
+    }
+
+    let input = resolve_path(config_dir, &config.js_input);
+    if !input.exists() {
+        return Err(ErrorContext::new("JavaScript input file not found")
+            .with_details(&format!("{}", input.display()))
+            .format());
+    }
+
+    let mut summary = ESLintSummary::default();
+    handle_error(
+        run_eslint(config, config_dir, &input, &mut summary),
+        "ESLint check failed",
+    )?;
+    if config.eslint {
+        log_success("Warm", "ESLint cache written");
+    }
+
+    let metafile = cache_dir.join("metafile.json");
+    write_metafile(config, config_dir, &input, &metafile, false)?;
+    log_success(
+        "Warm",
+        &format!("module graph written to: {}", metafile.display()),
+    );
+
+    Ok(())
+}
+
+// * Have esbuild resolve and parse every module into a metafile, keeping the bundles in memory
+fn write_metafile(
+    config: &Config,
+    config_dir: &Path,
+    input: &Path,
+    metafile: &Path,
+    minify: bool,
+) -> Result<(), String> {
+    let vendor_entry = write_vendor_entry(config, config_dir)?;
+    let mut cmd = esbuild_command(config, config_dir)?;
+    add_entry_args(
+        &mut cmd,
+        config,
+        input,
+        &config_dir.join(&config.js_output),
+        vendor_entry.as_deref(),
+        config.splitting,
+    );
+    cmd.arg("--bundle")
+        .arg(format!("--target={}", config.target))
+        .arg(format!("--format={}", config.format))
+        .args(env_define_args(config, config_dir)?);
+    if minify {
+        cmd.arg("--minify").arg("--legal-comments=none");
+    }
+    add_asset_args(&mut cmd, config);
+
+    if let Some(parent) = metafile.parent() {
+        handle_error(
+            fs::create_dir_all(parent),
+            "Failed to create cache directory",
+        )?;
+    }
+    cmd.arg(format!("--metafile={}", arg_path(metafile)))
+        .arg("--write=false");
+    run_esbuild(&mut cmd, false, "esbuild", &mut EsbuildSummary::default())
+}
+
+// * Metafile of a minified build of `js_input` for size analysis, written without any output
+pub fn analysis_metafile(config: &Config, config_dir: &Path) -> Result<PathBuf, String> {
+    let input = resolve_path(config_dir, &config.js_input);
+    if !input.exists() {
+        return Err(ErrorContext::new("JavaScript input file not found")
+            .with_details(&format!("{}", input.display()))
+            .format());
+    }
+
+    let metafile = cache::dir(config, config_dir).join("metafile.analyze.json");
+    write_metafile(config, config_dir, &input, &metafile, true)?;
+    Ok(metafile)
+}
+
+// * Every source file the script bundle is built from, as esbuild resolves them. Paths are
+// * relative to the working directory, like esbuild's own; virtual modules are left out.
+pub fn script_dependencies(config: &Config, config_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let input = resolve_path(config_dir, &config.js_input);
+    let metafile = cache::dir(config, config_dir).join("metafile.inputs.json");
+    write_metafile(config, config_dir, &input, &metafile, false)?;
+
+    let content = handle_error(
+        fs::read_to_string(&metafile),
+        "Failed to read esbuild metafile",
+    )?;
+    let json: serde_json::Value = handle_error(
+        serde_json::from_str(&content),
+        "Failed to parse esbuild metafile",
+    )?;
+    Ok(json
+        .get("inputs")
+        .and_then(|inputs| inputs.as_object())
+        .into_iter()
+        .flat_map(|inputs| inputs.keys())
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+// * Source map for lightningcss to fill in while printing `css_path`, or `None` when source maps
+// * are off. grass emits no source map of its own, so positions point into the compiled CSS,
+// * which is embedded as a source named after the SCSS entry with a ` (compiled)` suffix, so
+// * devtools don't present it as the SCSS file itself.
+fn css_source_map(
+    css_path: &Path,
+    input: &Path,
+    css: &str,
+    mode: SourcemapMode,
+) -> Option<SourceMap> {
+    if !mode.is_enabled() {
+        return None;
+    }
+    let map_dir = css_path.parent().unwrap_or(Path::new(""));
+    let mut source_map = SourceMap::new("/");
+    source_map.add_source(&format!(
+        "{} (compiled)",
+        manifest::relative_to(input, map_dir)
+    ));
+    source_map.set_source_content(0, css).ok()?;
+    Some(source_map)
+}
+
+// * Emit the sourcemap for a stylesheet according to the configured mode, returning the
+// * `sourceMappingURL` comment to end the CSS with
+fn css_sourcemap_comment(
+    css_path: &Path,
+    source_map: Option<SourceMap>,
+    mode: SourcemapMode,
+) -> Result<String, String> {
+    let Some(mut source_map) = source_map else {
+        return Ok(String::new());
+    };
+
+    let map_path = css_path.with_extension("css.map");
+    let mut map: serde_json::Value = handle_error(
+        source_map.to_json(None),
+        "Failed to serialize CSS sourcemap",
+    )
+    .and_then(|json| handle_error(serde_json::from_str(&json), "Invalid CSS sourcemap"))?;
+    let file = file_name(css_path)?.to_string_lossy();
+    if let Some(fields) = map.as_object_mut() {
+        fields.remove("sourceRoot");
+        fields.insert("file".to_string(), serde_json::Value::from(file.clone()));
+    }
+    let map_content = map.to_string();
+
+    if mode.writes_file() {
+        retry::write(&map_path, &map_content).map_err(|e| {
+            let error_msg = format!("Failed to write CSS sourcemap: {e}");
+            log_error("Error", &error_msg);
+            error_msg
+        })?;
+    }
+
+    let comment = match mode {
+        SourcemapMode::Linked => format!(
+            "\n/*# sourceMappingURL={} */\n",
+            file_name(&map_path)?.to_string_lossy()
+        ),
+        SourcemapMode::Inline | SourcemapMode::Both => format!(
+            "\n/*# sourceMappingURL=data:application/json;base64,{} */\n",
+            base64::engine::general_purpose::STANDARD.encode(&map_content)
+        ),
+        SourcemapMode::External | SourcemapMode::None => String::new(),
+    };
+
+    Ok(comment)
+}
+
+// * `fmt::Write` for lightningcss's printer over a file, keeping the I/O error behind a failed
+// * write, which the printer only reports as a formatting error
+struct CssWriter<'a, W: io::Write> {
+    file: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> std::fmt::Write for CssWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.file.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            std::fmt::Error
+        })
+    }
+}
+
+// * Print `sheet` into `css_path` through a buffered writer rather than into a string first,
+// * so a multi-megabyte stylesheet is never held in memory twice, then end it with the
+// * comment pointing at its source map. This is `StyleSheet::to_css` for a stylesheet parsed
+// * without CSS modules, writing to the file as it goes.
+fn write_css(
+    sheet: &StyleSheet,
+    css_path: &Path,
+    input: &Path,
+    css: &str,
+    minify: bool,
+    mode: SourcemapMode,
+) -> Result<(), PackrError> {
+    // A failure that isn't the file's, which retrying wouldn't fix
+    let mut failure = None;
+    let result = retry::write_with(css_path, |file| {
+        let mut source_map = css_source_map(css_path, input, css, mode);
+        let mut dest = CssWriter { file, error: None };
+        let printed = {
+            let mut printer = Printer::new(
+                &mut dest,
+                PrinterOptions {
+                    minify,
+                    source_map: source_map.as_mut(),
+                    ..Default::default()
+                },
+            );
+            print_stylesheet(sheet, &mut printer)
+        };
+        if let Err(e) = printed {
+            return Err(dest.error.take().unwrap_or_else(|| {
+                let message = format!("CSS print error: {e}");
+                failure = Some(message.clone());
+                io::Error::other(message)
+            }));
+        }
+
+        let comment = css_sourcemap_comment(css_path, source_map, mode).map_err(|e| {
+            failure = Some(e.clone());
+            io::Error::other(e)
+        })?;
+        dest.file.write_all(comment.as_bytes())
+    });
+
+    result.map_err(|e| {
+        let error = match failure.take() {
+            Some(message) => PackrError::Css(message),
+            None if minify => PackrError::io("Failed to write minified CSS", css_path, e),
+            None => PackrError::io("Failed to write CSS", css_path, e),
+        };
+        log_error("Error", &error.to_string());
+        error
+    })
+}
+
+fn print_stylesheet<W: std::fmt::Write>(
+    sheet: &StyleSheet,
+    printer: &mut Printer<W>,
+) -> Result<(), PrinterError> {
+    for comment in &sheet.license_comments {
+        printer.write_str("/*")?;
+        printer.write_str_with_newlines(comment)?;
+        printer.write_str_with_newlines("*/\n")?;
+    }
+    sheet.rules.to_css(printer)?;
+    printer.newline()
+}
+
+// * Resolved ESLint config of the project, which must live inside the config directory
+pub fn eslint_config_path(config: &Config, config_dir: &Path) -> Result<PathBuf, String> {
+    let eslint_config_path = if let Some(ref custom_path) = config.eslint_config {
+        if custom_path.contains("..") || Path::new(custom_path).is_absolute() {
+            return Err("Invalid ESLint config path: potential traversal attempt".to_string());
+        }
+        config_dir.join(custom_path)
+    } else {
+        config_dir.join(".eslintrc.json")
+    };
+
+    let eslint_path = eslint_config_path
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve ESLint config path: {e}"))?;
 
-        if config.verbose {
-            log_success("CSS", &format!("copied to: {}", dest_path.display()));
-            if let Some(ref min_path) = dest_min_path {
-                log_success(
-                    "CSS",
-                    &format!("minified version copied to: {}", min_path.display()),
-                );
-            }
-        }
+    // Both sides canonical, so a config directory reached through a symlink still matches
+    if !eslint_path.starts_with(resolve_existing(config_dir)) {
+        return Err("ESLint config path points outside the allowed config directory".to_string());
     }
-
-    log_success("Styles", "built successfully");
-    Ok(())
+    Ok(eslint_path)
 }
 
 // * Run ESLint on JavaScript files
@@ -397,68 +2199,93 @@ fn run_eslint(
         return Ok(());
     }
 
-    log_info("Running", "ESLint");
-
-    // Validate ESLint config path
-    let eslint_config_path = if let Some(ref custom_path) = config.eslint_config {
-        if custom_path.contains("..") || Path::new(custom_path).is_absolute() {
-            return Err("Invalid ESLint config path: potential traversal attempt".to_string());
-        }
-        config_dir.join(custom_path)
-    } else {
-        config_dir.join(".eslintrc.json")
-    };
+    let eslint_path = eslint_config_path(config, config_dir)
+        .inspect_err(|error_msg| log_error("Error", error_msg))?;
 
-    let eslint_path = eslint_config_path.canonicalize().map_err(|e| {
-        let error_msg = format!("Failed to resolve ESLint config path: {e}");
-        log_error("Error", &error_msg);
-        error_msg
-    })?;
+    // * ESLint is the slowest part of small builds, so a clean lint of the same files is reused
+    let cache_dir = cache::dir(config, config_dir);
+    let lint_hash = cache::lint_hash(&[input, &eslint_path]);
+    if cache::is_lint_clean(&cache_dir, &lint_hash) {
+        log_success(
+            "ESLint",
+            "cached, sources unchanged since the last clean lint",
+        );
+        return Ok(());
+    }
 
-    if !eslint_path.starts_with(config_dir) {
-        let error_msg =
-            "ESLint config path points outside the allowed config directory".to_string();
-        log_error("Error", &error_msg);
-        return Err(error_msg);
+    // * An ESLint release that needs a newer Node fails inside npx with a syntax error, so the
+    // * `engines` requirement is checked up front
+    let runtime = Runtime::resolve(config.runtime.as_deref(), config_dir);
+    if runtime == Runtime::Node {
+        if let Some(error_msg) = runtime::node_requirement("eslint", "ESLint", config_dir) {
+            log_error("Error", &error_msg);
+            return Err(error_msg);
+        }
     }
 
-    let mut cmd = Command::new("npx");
-    cmd.arg("eslint")
-        .arg("--max-warnings=0")
+    log_info("Running", "ESLint");
+
+    let mut cmd = runtime.tool_command("eslint", config_dir)?;
+    cmd.arg("--max-warnings=0")
         .arg("--format=json")
         .arg("--no-eslintrc")
         .arg("-c")
-        .arg(eslint_path.as_os_str())
-        .arg(input.as_os_str());
+        .arg(arg_path(&eslint_path))
+        .arg("--cache")
+        .arg("--cache-location")
+        .arg(cache_dir.join("eslintcache"))
+        .arg(arg_path(input));
 
     if config.verbose {
         log_info("ESLint", "checking JavaScript files");
     }
 
-    let output = cmd.output().map_err(|e| {
+    let eslint_span = profile::span("scripts", "eslint");
+    let output = children::output("eslint", &mut cmd).map_err(|e| {
         let error_msg = format!("Failed to run ESLint: {e}");
         log_error("Error", &error_msg);
         error_msg
     })?;
+    drop(eslint_span);
 
+    // Warnings per linted file, including the clean ones, for `eslint_ratchet`
+    let mut counts = baseline::Counts::new();
+    let project_dir = resolve_existing(config_dir);
     if !output.stdout.is_empty() {
         let json_str = String::from_utf8_lossy(&output.stdout);
         if let Ok(json) = serde_json::from_str::<Vec<serde_json::Value>>(&json_str) {
             for file in json {
                 if let Some(file_path) = file.get("filePath").and_then(|p| p.as_str()) {
+                    let count = counts
+                        .entry(manifest::relative_to(Path::new(file_path), &project_dir))
+                        .or_insert(0);
                     if let Some(messages) = file.get("messages").and_then(|m| m.as_array()) {
                         for message in messages {
-                            if let (Some(rule_id), Some(message), Some(line), Some(column)) = (
+                            if let (Some(rule_id), Some(text), Some(line), Some(column)) = (
                                 message.get("ruleId").and_then(|r| r.as_str()),
                                 message.get("message").and_then(|m| m.as_str()),
                                 message.get("line").and_then(|l| l.as_i64()),
                                 message.get("column").and_then(|c| c.as_i64()),
                             ) {
+                                events::emit(|| Event::Diagnostic {
+                                    // ESLint's severity 2 is an error, 1 a warning
+                                    severity: match message.get("severity").and_then(|s| s.as_i64())
+                                    {
+                                        Some(2) => Severity::Error,
+                                        _ => Severity::Warning,
+                                    },
+                                    source: "ESLint".to_string(),
+                                    message: format!("{} - {}", rule_id, text),
+                                    file: Some(PathBuf::from(file_path)),
+                                    line: usize::try_from(line).ok(),
+                                    column: usize::try_from(column).ok(),
+                                });
                                 let warning = format!(
                                     "Line {}, Column {}: {} - {}",
-                                    line, column, rule_id, message
+                                    line, column, rule_id, text
                                 );
                                 summary.add_warning(file_path.to_string(), warning);
+                                *count += 1;
                             }
                         }
                     }
@@ -475,213 +2302,1088 @@ fn run_eslint(
         }
     }
 
-    if !summary.warnings.is_empty() {
-        log_warning("ESLint", "warnings found (see summary below)");
+    if !summary.warnings.is_empty() && config.strict_warnings.eslint {
+        summary.display();
+        return Err(format!(
+            "{} ESLint warning(s) with strict_warnings enabled",
+            summary.warnings.values().map(|w| w.len()).sum::<usize>()
+        ));
+    }
+
+    let total: usize = counts.values().sum();
+    if let Some(max) = config.eslint_max_warnings {
+        if total > max {
+            summary.display();
+            return Err(format!(
+                "{} ESLint warning(s), more than eslint_max_warnings allows ({})",
+                total, max
+            ));
+        }
+    }
+
+    if config.eslint_ratchet {
+        match baseline::ratchet(config_dir, &counts) {
+            Ok(Some(trend)) => log_success("ESLint", &trend),
+            Ok(None) => {}
+            Err(error_msg) => {
+                summary.display();
+                return Err(error_msg);
+            }
+        }
+    }
+
+    if !summary.warnings.is_empty() {
+        log_warning("ESLint", "warnings found (see summary below)");
+    } else if output.status.success() {
+        cache::store_lint(&cache_dir, &lint_hash)?;
+    }
+
+    if config.verbose {
+        log_success("ESLint", "check passed");
+    }
+
+    Ok(())
+}
+
+/// Lint and bundle `js_input` with esbuild, recording the outputs in `manifest`. With `watch`,
+/// esbuild keeps rebuilding and this only returns once its watcher exits.
+pub fn build_scripts(
+    config: &Config,
+    config_dir: &Path,
+    watch: bool,
+    manifest: &mut Manifest,
+) -> Result<(), PackrError> {
+    let _span = profile::span("scripts", "build scripts");
+    log_info("Building scripts", &format!("from: {}", config.js_input));
+
+    let input = resolve_path(config_dir, &config.js_input);
+    let output = config_dir.join(&config.js_output);
+
+    if !input.exists() {
+        return Err(PackrError::Esbuild(
+            ErrorContext::new("JavaScript input file not found")
+                .with_details(&format!("{}", input.display()))
+                .format(),
+        ));
+    }
+
+    let runtime = Runtime::resolve(config.runtime.as_deref(), config_dir);
+    if config.verbose {
+        log_info("Runtime", runtime.name());
+        if runtime == Runtime::Node {
+            log_info("Package manager", PackageManager::detect(config_dir).name());
+        }
+    }
+
+    // * Flag an esbuild that may not understand Packr's flags before it fails mid-build
+    match runtime
+        .tool_command("esbuild", config_dir)
+        .and_then(esbuild::version)
+    {
+        Ok(version) => {
+            if config.verbose {
+                log_info("esbuild", &version);
+            }
+            if let Some(warning) = esbuild::compatibility(&version) {
+                log_warning("esbuild", &warning);
+            }
+        }
+        Err(e) => log_warning("esbuild", &e),
+    }
+
+    let mut summary = ESLintSummary::default();
+
+    run_eslint(config, config_dir, &input, &mut summary)
+        .map_err(|e| PackrError::Eslint(format!("ESLint check failed: {}", e)))?;
+
+    let env_defines = env_define_args(config, config_dir).map_err(PackrError::Esbuild)?;
+    let mut esbuild_summary = EsbuildSummary::default();
+
+    let vendor_entry = write_vendor_entry(config, config_dir).map_err(PackrError::Esbuild)?;
+
+    // * Set up esbuild CLI call for non-minified version
+    let mut cmd = esbuild_command(config, config_dir).map_err(PackrError::Esbuild)?;
+
+    add_entry_args(
+        &mut cmd,
+        config,
+        &input,
+        &output,
+        vendor_entry.as_deref(),
+        config.splitting,
+    );
+    cmd.arg("--bundle")
+        .arg(format!("--target={}", config.target))
+        .arg("--legal-comments=none");
+
+    // Add format option
+    cmd.arg(format!("--format={}", config.format));
+
+    // Add source map option
+    if let Some(arg) = config.sourcemap.esbuild_arg() {
+        cmd.arg(arg);
+    }
+
+    // Add injected environment variables
+    cmd.args(&env_defines);
+
+    // Add asset loaders and record emitted assets and chunks in a metafile
+    let cache_dir = cache::dir(config, config_dir);
+    let metafile = cache_dir.join("metafile.json");
+    let min_metafile = cache_dir.join("metafile.min.json");
+    let needs_metafile = needs_metafile(config);
+    add_asset_args(&mut cmd, config);
+    // Always written, as `skip_unchanged` hashes the modules it lists
+    fs::create_dir_all(&cache_dir)
+        .map_err(|e| PackrError::io("Failed to create cache directory", &cache_dir, e))?;
+    cmd.arg(format!("--metafile={}", arg_path(&metafile)));
+
+    if watch {
+        cmd.arg("--watch");
+    }
+
+    if config.verbose {
+        log_info(
+            "Running",
+            &format!("esbuild with format: {}", config.format),
+        );
+    }
+
+    run_esbuild(&mut cmd, watch, "esbuild", &mut esbuild_summary).map_err(PackrError::Esbuild)?;
+
+    // Watch rebuilds happen inside esbuild, so they keep every asset as a file
+    if !watch {
+        inline_assets(config, &metafile).map_err(PackrError::Esbuild)?;
+    }
+
+    // Entry names may contain a content hash, so read the real filename back
+    let output = if uses_outdir(config) {
+        metafile_entry_output(&metafile, &input).map_err(PackrError::Esbuild)?
+    } else {
+        output
+    };
+
+    let min_output = if config.minify {
+        let min_path = suffixed_path(&output, ".min");
+
+        let mut cmd = if uses_outdir(config) {
+            // Chunks import each other by name, so split builds are bundled again minified
+            let mut cmd = esbuild_command(config, config_dir).map_err(PackrError::Esbuild)?;
+            add_entry_args(
+                &mut cmd,
+                config,
+                &input,
+                &min_path,
+                vendor_entry.as_deref(),
+                config.splitting,
+            );
+            cmd.arg("--bundle")
+                .arg(format!("--format={}", config.format))
+                .args(&env_defines);
+            add_asset_args(&mut cmd, config);
+            cmd.arg(format!("--metafile={}", arg_path(&min_metafile)));
+            cmd
+        } else {
+            // A single bundle is minified from the output above instead of resolving and
+            // parsing every module again; its source map is followed back to the sources
+            let mut cmd =
+                esbuild_program(config, config_dir, false).map_err(PackrError::Esbuild)?;
+            cmd.arg(arg_path(&output))
+                .arg(format!("--outfile={}", arg_path(&min_path)));
+            cmd
+        };
+        cmd.arg("--minify")
+            .arg("--minify-syntax")
+            .arg("--minify-whitespace")
+            .arg(format!("--target={}", config.target))
+            .arg("--legal-comments=none");
+
+        // Name preservation and property mangling only apply to the minified build
+        if config.keep_names {
+            cmd.arg("--keep-names");
+        }
+
+        if let Some(ref pattern) = config.mangle_props {
+            cmd.arg(format!("--mangle-props={}", pattern));
+        }
+
+        if let Some(ref pattern) = config.reserve_props {
+            cmd.arg(format!("--reserve-props={}", pattern));
+        }
+
+        if let Some(arg) = config.sourcemap.esbuild_arg() {
+            cmd.arg(arg);
+        }
+
+        run_esbuild(
+            &mut cmd,
+            false,
+            "esbuild minification",
+            &mut esbuild_summary,
+        )
+        .map_err(PackrError::Esbuild)?;
+
+        if uses_outdir(config) {
+            inline_assets(config, &min_metafile).map_err(PackrError::Esbuild)?;
+            Some(metafile_entry_output(&min_metafile, &input).map_err(PackrError::Esbuild)?)
+        } else {
+            Some(min_path)
+        }
+    } else {
+        None
+    };
+
+    // The synthetic vendor entry only exists to shape the shared chunk
+    if let Some(ref entry) = vendor_entry {
+        remove_entry_outputs(&metafile, entry).map_err(PackrError::Esbuild)?;
+        if min_output.is_some() {
+            remove_entry_outputs(&min_metafile, entry).map_err(PackrError::Esbuild)?;
+        }
+    }
+
+    let legacy_output = match config.legacy_target {
+        Some(ref legacy_target) => Some(
+            build_legacy_script(
+                config,
+                config_dir,
+                &input,
+                &output,
+                legacy_target,
+                &env_defines,
+                &mut esbuild_summary,
+            )
+            .map_err(PackrError::Esbuild)?,
+        ),
+        None => None,
+    };
+
+    // Entry outputs as esbuild wrote them, before content hashing renames them
+    let written = [Some(output.clone()), min_output.clone()];
+
+    // * Attribution for bundled packages, since `--legal-comments=none` strips it from the code
+    let license_reports = if config.license_report && !watch {
+        // The minified bundle holds the same packages as the unminified one
+        let mut metafiles = vec![metafile.clone()];
+        if legacy_output.is_some() {
+            metafiles.push(cache::dir(config, config_dir).join("metafile.legacy.json"));
+        }
+        licenses::write_report(&metafiles, output.parent().unwrap_or(Path::new(".")))
+            .map_err(PackrError::Esbuild)?
+    } else {
+        Vec::new()
+    };
+
+    // * Several copies of one package are the most common silent bloat
+    if config.detect_duplicates && !watch {
+        duplicates::report(&metafile).map_err(PackrError::Esbuild)?;
+    }
+
+    // * esbuild wrote these itself, so they are reported here rather than as they're written
+    let esbuild_files: BTreeSet<PathBuf> =
+        [Some(&output), min_output.as_ref(), legacy_output.as_ref()]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(script_emitted_files(config, config_dir))
+            .filter(|path| path.is_file())
+            .collect();
+    for path in esbuild_files {
+        events::emit(|| Event::FileWritten { path });
+    }
+
+    let (output, min_output, legacy_output) = if config.entry_names.is_some() {
+        // `entry_names` already decides the names, so only record them
+        let logical = config_dir.join(&config.js_output);
+        manifest
+            .record(&logical, &output)
+            .map_err(PackrError::Esbuild)?;
+        if let Some(ref min_path) = min_output {
+            manifest
+                .record(&suffixed_path(&logical, ".min"), min_path)
+                .map_err(PackrError::Esbuild)?;
+        }
+        if let Some(ref legacy_path) = legacy_output {
+            manifest
+                .record(&suffixed_path(&logical, ".legacy"), legacy_path)
+                .map_err(PackrError::Esbuild)?;
+        }
+        (output, min_output, legacy_output)
+    } else {
+        (
+            manifest.hash(&output).map_err(PackrError::Esbuild)?,
+            min_output
+                .map(|p| manifest.hash(&p))
+                .transpose()
+                .map_err(PackrError::Esbuild)?,
+            legacy_output
+                .map(|p| manifest.hash(&p))
+                .transpose()
+                .map_err(PackrError::Esbuild)?,
+        )
+    };
+
+    if config.verbose {
+        log_success("JavaScript", &format!("written to: {}", output.display()));
+        if let Some(ref min_path) = min_output {
+            log_success(
+                "JavaScript",
+                &format!("minified version written to: {}", min_path.display()),
+            );
+        }
+        if let Some(ref legacy_path) = legacy_output {
+            log_success(
+                "JavaScript",
+                &format!("legacy version written to: {}", legacy_path.display()),
+            );
+        }
+    }
+
+    // * Copy result to alternate destination if defined
+    if let Some(dest) = &config.js_destination {
+        let dest_dir = config_dir.join(dest);
+        let dest_path = dest_dir.join(file_name(&output).map_err(PackrError::Config)?);
+        let dest_min_path = min_output
+            .as_ref()
+            .map(|min_path| file_name(min_path).map(|name| dest_dir.join(name)))
+            .transpose()
+            .map_err(PackrError::Config)?;
+
+        // Create destination directory
+        fs::create_dir_all(&dest_dir)
+            .map_err(|e| PackrError::io("Failed to create JS destination folder", &dest_dir, e))?;
+
+        // Copy non-minified version
+        retry::copy(&output, &dest_path).map_err(|e| {
+            let error = PackrError::io("Failed to copy JS to destination", &dest_path, e);
+            log_error("Error", &error.to_string());
+            error
+        })?;
+
+        // Copy minified version if it exists, without reading it back into memory
+        if let (Some(ref min_path), Some(ref source_min)) = (&dest_min_path, &min_output) {
+            if source_min.exists() {
+                retry::copy(source_min, min_path).map_err(|e| {
+                    let error =
+                        PackrError::io("Failed to copy minified JS to destination", min_path, e);
+                    log_error("Error", &error.to_string());
+                    error
+                })?;
+            }
+        }
+
+        // Copy legacy bundle if it was built
+        if let Some(ref legacy_path) = legacy_output {
+            let dest_legacy = dest_dir.join(file_name(legacy_path).map_err(PackrError::Config)?);
+            retry::copy(legacy_path, &dest_legacy).map_err(|e| {
+                let error =
+                    PackrError::io("Failed to copy legacy JS to destination", &dest_legacy, e);
+                log_error("Error", &error.to_string());
+                error
+            })?;
+
+            if config.sourcemap.writes_file() {
+                let _ = retry::copy(
+                    legacy_path.with_extension("js.map"),
+                    dest_legacy.with_extension("js.map"),
+                );
+            }
+        }
+
+        for report in &license_reports {
+            let dest_report = dest_dir.join(file_name(report).map_err(PackrError::Config)?);
+            retry::copy(report, &dest_report).map_err(|e| {
+                PackrError::io(
+                    "Failed to copy license report to destination",
+                    &dest_report,
+                    e,
+                )
+            })?;
+        }
+
+        // Copy chunks and assets emitted alongside the entry, keeping their layout
+        if needs_metafile {
+            let outdir = output.parent().unwrap_or(Path::new("."));
+            let mut emitted =
+                metafile_relative_outputs(&metafile, outdir).map_err(PackrError::Esbuild)?;
+            if min_output.is_some() && uses_outdir(config) {
+                emitted.extend(
+                    metafile_relative_outputs(&min_metafile, outdir)
+                        .map_err(PackrError::Esbuild)?,
+                );
+            }
+
+            for relative in emitted {
+                let source = outdir.join(&relative);
+                if written.contains(&Some(source.clone())) {
+                    continue;
+                }
+
+                let dest_file = dest_dir.join(&relative);
+                if let Some(parent) = dest_file.parent() {
+                    fs::create_dir_all(parent).map_err(|e| {
+                        PackrError::io("Failed to create JS destination folder", parent, e)
+                    })?;
+                }
+                retry::copy(&source, &dest_file).map_err(|e| {
+                    let error =
+                        PackrError::io("Failed to copy emitted file to destination", &dest_file, e);
+                    log_error("Error", &error.to_string());
+                    error
+                })?;
+
+                if config.verbose {
+                    log_success("JS", &format!("copied to: {}", dest_file.display()));
+                }
+            }
+        }
+
+        if config.sourcemap.writes_file() {
+            let map_path = output.with_extension("js.map");
+            let dest_map_path = dest_path.with_extension("js.map");
+            if map_path.exists() {
+                retry::copy(&map_path, &dest_map_path).map_err(|e| {
+                    let error = PackrError::io(
+                        "Failed to copy JS sourcemap to destination",
+                        &dest_map_path,
+                        e,
+                    );
+                    log_error("Error", &error.to_string());
+                    error
+                })?;
+            }
+        }
+
+        if config.verbose {
+            log_success("JS", &format!("copied to: {}", dest_path.display()));
+            if let Some(ref min_path) = dest_min_path {
+                log_success(
+                    "JS",
+                    &format!("minified version copied to: {}", min_path.display()),
+                );
+            }
+        }
+    }
+
+    if !watch {
+        plugin::after_scripts(config, config_dir, &manifest.files())
+            .map_err(PackrError::Esbuild)?;
+    }
+
+    // Display ESLint and esbuild summaries at the end
+    summary.display();
+    esbuild_summary.display();
+
+    log_success("Scripts", "built successfully");
+    Ok(())
+}
+
+// * Logical JavaScript outputs of a build: the bundle plus its `.min` and `.legacy` variants
+pub fn script_outputs(config: &Config, config_dir: &Path) -> Vec<PathBuf> {
+    let output = config_dir.join(&config.js_output);
+    let mut outputs = vec![output.clone()];
+    if config.minify {
+        outputs.push(suffixed_path(&output, ".min"));
+    }
+    if config.legacy_target.is_some() {
+        outputs.push(suffixed_path(&output, ".legacy"));
+    }
+    outputs
+}
+
+// * `dir/name.ext` becomes `dir/name<suffix>.ext`
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}{}.{}", stem, suffix, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}{}", stem, suffix)),
+    }
+}
+
+// * Name of the synthetic entry used to pull packages into the vendor chunk
+const VENDOR_ENTRY_NAME: &str = "packr-vendor";
+
+// * Whether esbuild writes to an output directory instead of a single `--outfile`
+fn uses_outdir(config: &Config) -> bool {
+    config.splitting || config.entry_names.is_some()
+}
+
+// * Whether esbuild is asked for a metafile, to find emitted files or inspect the bundle
+fn needs_metafile(config: &Config) -> bool {
+    uses_outdir(config)
+        || config.wasm_loader.as_deref() == Some("file")
+        || config.asset_inline_limit.is_some()
+        || config.license_report
+        || config.detect_duplicates
+}
+
+// * Every file esbuild wrote in the last build, including chunks, assets and source maps,
+// * as listed in its metafiles
+pub fn script_emitted_files(config: &Config, config_dir: &Path) -> Vec<PathBuf> {
+    if !needs_metafile(config) {
+        return Vec::new();
+    }
+    let cache_dir = cache::dir(config, config_dir);
+    let mut metafiles = vec![cache_dir.join("metafile.json")];
+    if config.minify && uses_outdir(config) {
+        metafiles.push(cache_dir.join("metafile.min.json"));
+    }
+    metafiles
+        .iter()
+        .filter_map(|metafile| metafile_entries(metafile).ok())
+        .flatten()
+        .map(|(path, _)| path)
+        .collect()
+}
+
+// * Add entry points and output location, switching to `--outdir` when splitting
+// * or when entry names are templated
+fn add_entry_args(
+    cmd: &mut Command,
+    config: &Config,
+    input: &Path,
+    outfile: &Path,
+    vendor_entry: Option<&Path>,
+    splitting: bool,
+) {
+    if !splitting && config.entry_names.is_none() {
+        cmd.arg(arg_path(input))
+            .arg(format!("--outfile={}", arg_path(outfile)));
+        return;
+    }
+
+    // Entry names keep the configured output filename inside the output directory
+    let stem = outfile.file_stem().unwrap_or_default().to_string_lossy();
+    let suffix = if stem.ends_with(".min") { ".min" } else { "" };
+    let outdir = outfile.parent().unwrap_or(Path::new("."));
+
+    cmd.arg(format!("{}={}", stem, arg_path(input)))
+        .arg(format!("--outdir={}", arg_path(outdir)));
+
+    if let Some(ref pattern) = config.entry_names {
+        cmd.arg(format!("--entry-names={}", pattern));
     }
 
-    if config.verbose {
-        log_success("ESLint", "check passed");
+    if !splitting {
+        return;
     }
 
-    Ok(())
+    cmd.arg("--splitting").arg(format!(
+        "--chunk-names={}",
+        config
+            .chunk_names
+            .as_deref()
+            .unwrap_or("chunks/[name]-[hash]")
+    ));
+
+    if let Some(entry) = vendor_entry {
+        cmd.arg(format!(
+            "{}{}={}",
+            VENDOR_ENTRY_NAME,
+            suffix,
+            arg_path(entry)
+        ));
+    }
 }
 
-// * Bundle JavaScript with esbuild CLI, with optional watch mode
-pub fn build_scripts(config: &Config, config_dir: &Path, watch: bool) -> Result<(), String> {
-    log_info("Building scripts", &format!("from: {}", config.js_input));
-
-    let input = resolve_path(config_dir, &config.js_input);
-    let output = config_dir.join(&config.js_output);
+// * Write the synthetic vendor entry when a vendor chunk is configured
+fn write_vendor_entry(config: &Config, config_dir: &Path) -> Result<Option<PathBuf>, String> {
+    let Some(ref settings) = config.vendor_chunk else {
+        return Ok(None);
+    };
 
-    if !input.exists() {
-        return Err(ErrorContext::new("JavaScript input file not found")
-            .with_details(&format!("{}", input.display()))
-            .format());
+    let packages = vendor::vendor_packages(settings, config_dir)?;
+    if packages.is_empty() {
+        log_warning("Vendor", "no packages matched vendor_chunk, skipping");
+        return Ok(None);
     }
 
-    let mut summary = ESLintSummary::default();
-
+    let packr_dir = config_dir.join(".packr");
     handle_error(
-        run_eslint(config, config_dir, &input, &mut summary),
-        "ESLint check failed",
+        fs::create_dir_all(&packr_dir),
+        "Failed to create .packr directory",
+    )?;
+    let entry = packr_dir.join("vendor.js");
+    handle_error(
+        retry::write(&entry, vendor::entry_source(&packages)),
+        "Failed to write vendor entry",
     )?;
 
-    // * Set up esbuild CLI call for non-minified version
-    let mut cmd = Command::new("esbuild");
+    if config.verbose {
+        log_info(
+            "Vendor",
+            &format!("chunk packages: {}", packages.join(", ")),
+        );
+    }
 
-    cmd.arg(input.as_os_str())
-        .arg("--bundle")
-        .arg(format!("--target={}", config.target))
-        .arg(format!("--outfile={}", output.display()))
-        .arg("--legal-comments=none");
+    Ok(Some(entry))
+}
 
-    // Add format option
-    cmd.arg(format!("--format={}", config.format));
+// * Remove the outputs of a synthetic entry, leaving only the shared chunks
+fn remove_entry_outputs(metafile: &Path, entry: &Path) -> Result<(), String> {
+    let entry_output = metafile_entry_output(metafile, entry)?;
+    let _ = fs::remove_file(&entry_output);
+    let _ = fs::remove_file(entry_output.with_extension("js.map"));
+    Ok(())
+}
 
-    // Add source map option
-    if config.sourcemap {
-        cmd.arg("--sourcemap");
+// * Bundle a second `*.legacy.js` for `nomodule` browsers using `legacy_target`
+fn build_legacy_script(
+    config: &Config,
+    config_dir: &Path,
+    input: &Path,
+    output: &Path,
+    legacy_target: &str,
+    env_defines: &[String],
+    esbuild_summary: &mut EsbuildSummary,
+) -> Result<PathBuf, String> {
+    let legacy_path = suffixed_path(output, ".legacy");
+
+    // Legacy browsers load classic scripts, so the format is always iife
+    let mut cmd = esbuild_command(config, config_dir)?;
+    add_entry_args(&mut cmd, config, input, &legacy_path, None, false);
+    cmd.arg("--bundle")
+        .arg(format!("--target={}", legacy_target))
+        .arg("--legal-comments=none")
+        .arg("--format=iife")
+        .args(env_defines);
+
+    if config.minify {
+        cmd.arg("--minify");
+        if config.keep_names {
+            cmd.arg("--keep-names");
+        }
     }
 
-    if watch {
-        cmd.arg("--watch");
+    if let Some(arg) = config.sourcemap.esbuild_arg() {
+        cmd.arg(arg);
+    }
+
+    add_asset_args(&mut cmd, config);
+
+    let cache_dir = cache::dir(config, config_dir);
+    let metafile = cache_dir.join("metafile.legacy.json");
+    if uses_outdir(config) || config.asset_inline_limit.is_some() || config.license_report {
+        handle_error(
+            fs::create_dir_all(&cache_dir),
+            "Failed to create cache directory",
+        )?;
+        cmd.arg(format!("--metafile={}", arg_path(&metafile)));
+    }
+
+    // Inject polyfills for features the legacy target lacks
+    if config.polyfills {
+        let polyfills = polyfill::detect(legacy_target);
+        if !polyfills.is_empty() {
+            let packr_dir = config_dir.join(".packr");
+            handle_error(
+                fs::create_dir_all(&packr_dir),
+                "Failed to create .packr directory",
+            )?;
+            let entry = packr_dir.join("polyfills.js");
+            handle_error(
+                retry::write(&entry, polyfill::entry_source(&polyfills)),
+                "Failed to write polyfill entry",
+            )?;
+            cmd.arg(format!("--inject:{}", arg_path(&entry)));
+
+            if config.verbose {
+                let names: Vec<&str> = polyfills.iter().map(|p| p.name).collect();
+                log_info("Polyfills", &names.join(", "));
+            }
+        }
     }
 
     if config.verbose {
         log_info(
             "Running",
-            &format!("esbuild with format: {}", config.format),
+            &format!("esbuild legacy build with target: {}", legacy_target),
         );
     }
 
-    let status = cmd.status().map_err(|e| {
-        let error_msg = format!("Failed to run esbuild: {e}");
-        log_error("Error", &error_msg);
-        error_msg
-    })?;
+    run_esbuild(&mut cmd, false, "esbuild legacy build", esbuild_summary)?;
+    inline_assets(config, &metafile)?;
 
-    if !status.success() {
-        let error_msg = "esbuild failed".to_string();
-        log_error("Error", &error_msg);
-        return Err(error_msg);
+    if uses_outdir(config) {
+        metafile_entry_output(&metafile, input)
+    } else {
+        Ok(legacy_path)
+    }
+}
+
+// * Build `--define` arguments for the variables allowlisted in `env_inject`,
+// * reading each from the process environment first and `.env` second
+fn env_define_args(config: &Config, config_dir: &Path) -> Result<Vec<String>, String> {
+    if config.env_inject.is_empty() {
+        return Ok(Vec::new());
     }
 
-    let min_output = if config.minify {
-        let min_path = output.with_file_name(format!(
-            "{}.min{}",
-            output.file_stem().unwrap().to_string_lossy(),
-            output
-                .extension()
-                .map(|ext| format!(".{}", ext.to_string_lossy()))
-                .unwrap_or_default()
-        ));
+    let dotenv = read_dotenv(&config_dir.join(".env"))?;
+    let mut args = Vec::new();
 
-        let mut cmd = Command::new("esbuild");
+    for name in &config.env_inject {
+        let value = match env::var(name).ok().or_else(|| dotenv.get(name).cloned()) {
+            Some(value) => value,
+            None => {
+                log_warning(
+                    "Env",
+                    &format!("{} is listed in env_inject but not set, skipping", name),
+                );
+                continue;
+            }
+        };
 
-        cmd.arg(input.as_os_str())
-            .arg("--bundle")
-            .arg("--minify")
-            .arg("--minify-syntax")
-            .arg("--minify-whitespace")
-            .arg(format!("--target={}", config.target))
-            .arg(format!("--outfile={}", min_path.display()))
-            .arg("--legal-comments=none")
-            .arg(format!("--format={}", config.format));
+        let literal = handle_error(
+            serde_json::to_string(&value),
+            "Failed to encode injected variable",
+        )?;
+        args.push(format!("--define:process.env.{}={}", name, literal));
+        args.push(format!("--define:import.meta.env.{}={}", name, literal));
 
-        if config.sourcemap {
-            cmd.arg("--sourcemap");
+        if config.verbose {
+            log_info("Env", &format!("injected {}", name));
         }
+    }
+
+    Ok(args)
+}
+
+// * Parse `KEY=value` lines from a dotenv file, ignoring comments and blanks
+fn read_dotenv(path: &Path) -> Result<HashMap<String, String>, String> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let content = handle_error(fs::read_to_string(path), "Failed to read .env file")?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect())
+}
 
-        let status = cmd.status().map_err(|e| {
-            let error_msg = format!("Failed to run esbuild minification: {e}");
+// * Run an esbuild command, capturing its diagnostics and re-rendering them in
+// * packr's style; watch processes keep esbuild's own streaming output
+fn run_esbuild(
+    cmd: &mut Command,
+    watch: bool,
+    label: &str,
+    summary: &mut EsbuildSummary,
+) -> Result<(), String> {
+    if watch {
+        // esbuild's own progress lines follow Packr's out of a stdout that carries a protocol
+        if logs_to_stderr() {
+            cmd.stdout(std::io::stderr());
+        }
+        let status = children::status(cmd).map_err(|e| {
+            let error_msg = format!("Failed to run {label}: {e}");
             log_error("Error", &error_msg);
             error_msg
         })?;
 
         if !status.success() {
-            let error_msg = "esbuild minification failed".to_string();
+            let error_msg = format!("{label} failed");
             log_error("Error", &error_msg);
             return Err(error_msg);
         }
 
-        Some(min_path)
-    } else {
-        None
-    };
+        return Ok(());
+    }
 
-    if config.verbose {
-        log_success("JavaScript", &format!("written to: {}", output.display()));
-        if let Some(ref min_path) = min_output {
-            log_success(
-                "JavaScript",
-                &format!("minified version written to: {}", min_path.display()),
-            );
+    // The shim prints the messages as JSON; a bare esbuild binary only logs them as text
+    cmd.arg("--color=false")
+        .arg("--log-level=warning")
+        .env("PACKR_ESBUILD_MESSAGES", "1");
+
+    let failed_to_run = |e: std::io::Error| {
+        let error_msg = format!("Failed to run {label}: {e}");
+        log_error("Error", &error_msg);
+        error_msg
+    };
+    let span = profile::span("scripts", label);
+    // * A daemon or watch session builds in the resident esbuild service where it can
+    let (success, parsed) = match shim::service_build(cmd) {
+        Some(reply) => {
+            let reply = reply.map_err(failed_to_run)?;
+            let parsed = diagnostics::parse_esbuild_messages(&reply.messages)?;
+            (reply.success, parsed)
+        }
+        None => {
+            let output = children::output("esbuild", cmd).map_err(failed_to_run)?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let parsed = match stdout
+                .lines()
+                .find_map(|line| line.strip_prefix("packr-messages "))
+            {
+                Some(json) => diagnostics::parse_esbuild_messages(json)?,
+                None => diagnostics::parse_esbuild_log(&stderr),
+            };
+
+            // Output esbuild didn't format as diagnostics (e.g. a crash) is shown as-is
+            if parsed.is_empty() && !output.status.success() && !stderr.trim().is_empty() {
+                eprintln!("{}", stderr.trim_end());
+            }
+            (output.status.success(), parsed)
+        }
+    };
+    drop(span);
+
+    for diagnostic in &parsed {
+        if !summary.diagnostics.contains(diagnostic) {
+            eprintln!("{}", diagnostic.render());
+            events::emit(|| Event::Diagnostic {
+                severity: diagnostic.severity,
+                source: "esbuild".to_string(),
+                message: diagnostic.message.clone(),
+                file: diagnostic.file.as_ref().map(PathBuf::from),
+                line: diagnostic.line,
+                column: diagnostic.column,
+            });
         }
     }
 
-    // * Copy result to alternate destination if defined
-    if let Some(dest) = &config.js_destination {
-        let dest_dir = config_dir.join(dest);
-        let dest_path = dest_dir.join(Path::new(&config.js_output).file_name().unwrap());
-        let dest_min_path = if config.minify {
-            Some(
-                dest_dir.join(Path::new(&config.js_output).with_file_name(format!(
-                        "{}.min{}",
-                        Path::new(&config.js_output)
-                            .file_stem()
-                            .unwrap()
-                            .to_string_lossy(),
-                        Path::new(&config.js_output)
-                            .extension()
-                            .map(|ext| format!(".{}", ext.to_string_lossy()))
-                            .unwrap_or_default()
-                    ))),
-            )
+    let errors = parsed
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    summary.extend(parsed);
+
+    if !success {
+        let error_msg = if errors > 0 {
+            format!("{label} failed with {errors} error(s)")
         } else {
-            None
+            format!("{label} failed")
         };
+        log_error("Error", &error_msg);
+        return Err(error_msg);
+    }
 
-        // Create destination directory
-        handle_error(
-            fs::create_dir_all(&dest_dir),
-            "Failed to create JS destination folder",
-        )?;
+    Ok(())
+}
 
-        // Copy non-minified version
-        fs::copy(&output, &dest_path).map_err(|e| {
-            let error_msg = format!("Failed to copy JS to destination: {e}");
-            log_error("Error", &error_msg);
-            error_msg
-        })?;
+// * The esbuild program: the JS API shim when the build needs plugins the CLI can't express,
+// * or when the project installs the esbuild package, whose API reports diagnostics as data
+// * rather than text; the CLI otherwise. `plugins` is false for passes that only minify.
+fn esbuild_program(config: &Config, config_dir: &Path, plugins: bool) -> Result<Command, String> {
+    let runtime = Runtime::resolve(config.runtime.as_deref(), config_dir);
+    let needs_plugins = plugins
+        && (!config.side_effects_overrides.is_empty() || !config.esbuild_plugins.is_empty());
+    let api = runtime == Runtime::Node && shim::api_installed(config_dir);
+    if !needs_plugins && !api {
+        return runtime.tool_command("esbuild", config_dir);
+    }
 
-        // Copy minified version if it exists
-        if let Some(ref min_path) = dest_min_path {
-            if let Some(ref source_min) = min_output {
-                if let Ok(min_content) = fs::read(source_min) {
-                    // Create parent directory for minified file if needed
-                    if let Some(parent) = min_path.parent() {
-                        handle_error(
-                            fs::create_dir_all(parent),
-                            "Failed to create minified JS destination folder",
-                        )?;
-                    }
-                    fs::write(min_path, min_content).map_err(|e| {
-                        let error_msg = format!("Failed to copy minified JS to destination: {e}");
-                        log_error("Error", &error_msg);
-                        error_msg
-                    })?;
-                }
-            }
-        }
+    let shim_path = shim::write_shim(config_dir)?;
+    // A config in the working directory has an empty `config_dir`
+    let project_dir = handle_error(
+        Path::new(".").join(config_dir).canonicalize(),
+        "Failed to resolve config directory",
+    )?;
+    let shim_config = if needs_plugins {
+        serde_json::json!({
+            "projectDir": arg_path(&project_dir),
+            "sideEffects": config.side_effects_overrides,
+            "plugins": config.esbuild_plugins,
+        })
+    } else {
+        serde_json::json!({ "projectDir": arg_path(&project_dir) })
+    };
 
-        if config.sourcemap {
-            let map_path = output.with_extension("js.map");
-            let dest_map_path = dest_path.with_extension("js.map");
-            if let Ok(map_content) = fs::read(&map_path) {
-                fs::write(&dest_map_path, map_content).map_err(|e| {
-                    let error_msg = format!("Failed to copy JS sourcemap to destination: {e}");
-                    log_error("Error", &error_msg);
-                    error_msg
-                })?;
-            }
-        }
+    if config.verbose && needs_plugins && !config.esbuild_plugins.is_empty() {
+        let names: Vec<&str> = config
+            .esbuild_plugins
+            .iter()
+            .map(|p| p.module.as_str())
+            .collect();
+        log_info("Plugins", &names.join(", "));
+    }
 
-        if config.verbose {
-            log_success("JS", &format!("copied to: {}", dest_path.display()));
-            if let Some(ref min_path) = dest_min_path {
-                log_success(
-                    "JS",
-                    &format!("minified version copied to: {}", min_path.display()),
-                );
+    let mut cmd = runtime.script_command(&shim_path);
+    cmd.env("PACKR_ESBUILD_SHIM", shim_config.to_string());
+    Ok(cmd)
+}
+
+// * Create the esbuild command for a bundling pass, with the resolution options of the config
+fn esbuild_command(config: &Config, config_dir: &Path) -> Result<Command, String> {
+    let mut cmd = esbuild_program(config, config_dir, true)?;
+
+    if let Some(tree_shaking) = config.tree_shaking {
+        cmd.arg(format!("--tree-shaking={}", tree_shaking));
+    }
+
+    if config.ignore_annotations {
+        cmd.arg("--ignore-annotations");
+    }
+
+    if !config.conditions.is_empty() {
+        cmd.arg(format!("--conditions={}", config.conditions.join(",")));
+    }
+
+    if !config.main_fields.is_empty() {
+        cmd.arg(format!("--main-fields={}", config.main_fields.join(",")));
+    }
+
+    Ok(cmd)
+}
+
+// * Add asset loader and naming arguments to an esbuild command
+fn add_asset_args(cmd: &mut Command, config: &Config) {
+    if let Some(ref loader) = config.wasm_loader {
+        cmd.arg(format!("--loader:.wasm={}", loader));
+    }
+
+    if let Some(ref pattern) = config.asset_names {
+        cmd.arg(format!("--asset-names={}", pattern));
+    }
+
+    // Every asset is emitted as a file first; small ones are inlined afterwards
+    if config.asset_inline_limit.is_some() {
+        cmd.args(inline::loader_args());
+    }
+
+    if let Some(ref public_path) = config.public_path {
+        cmd.arg(format!("--public-path={}", public_path));
+    }
+}
+
+// * Swap imported assets under `asset_inline_limit` for data URLs once esbuild has written them
+fn inline_assets(config: &Config, metafile: &Path) -> Result<(), String> {
+    let Some(limit) = config.asset_inline_limit else {
+        return Ok(());
+    };
+    let inlined = inline::inline_small_assets(metafile, limit)?;
+    if config.verbose && inlined > 0 {
+        log_info(
+            "Assets",
+            &format!("{} inlined as data URLs (<= {} bytes)", inlined, limit),
+        );
+    }
+    Ok(())
+}
+
+// * Read emitted output files and their entry points from an esbuild metafile
+fn metafile_entries(metafile: &Path) -> Result<Vec<(PathBuf, Option<PathBuf>)>, String> {
+    let content = handle_error(
+        fs::read_to_string(metafile),
+        "Failed to read esbuild metafile",
+    )?;
+    let json: serde_json::Value = handle_error(
+        serde_json::from_str(&content),
+        "Failed to parse esbuild metafile",
+    )?;
+
+    Ok(json
+        .get("outputs")
+        .and_then(|o| o.as_object())
+        .map(|outputs| {
+            outputs
+                .iter()
+                .map(|(path, meta)| {
+                    let entry = meta
+                        .get("entryPoint")
+                        .and_then(|e| e.as_str())
+                        .map(PathBuf::from);
+                    (PathBuf::from(path), entry)
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+// * Find the output esbuild wrote for a given entry point
+fn metafile_entry_output(metafile: &Path, entry: &Path) -> Result<PathBuf, String> {
+    let entry = entry.canonicalize().ok();
+
+    metafile_entries(metafile)?
+        .into_iter()
+        .find(|(_, entry_point)| {
+            entry_point
+                .as_ref()
+                .and_then(|p| p.canonicalize().ok())
+                .is_some_and(|p| Some(p) == entry)
+        })
+        .map(|(path, _)| path)
+        .ok_or_else(|| {
+            ErrorContext::new("esbuild metafile has no output for entry")
+                .with_details(&format!("{}", metafile.display()))
+                .format()
+        })
+}
+
+// * Chunks the entry statically imports, directly or through other chunks, for `modulepreload`
+pub fn preload_chunks(config: &Config, config_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !config.splitting {
+        return Ok(Vec::new());
+    }
+
+    // The page loads the minified bundle when minifying
+    let name = if config.minify {
+        "metafile.min.json"
+    } else {
+        "metafile.json"
+    };
+    let metafile = cache::dir(config, config_dir).join(name);
+    // Not written yet when pages are generated ahead of the first watch build
+    if !metafile.exists() {
+        return Ok(Vec::new());
+    }
+    let content = handle_error(
+        fs::read_to_string(&metafile),
+        "Failed to read esbuild metafile",
+    )?;
+    let json: serde_json::Value = handle_error(
+        serde_json::from_str(&content),
+        "Failed to parse esbuild metafile",
+    )?;
+
+    let input = resolve_path(config_dir, &config.js_input);
+    let entry = metafile_entry_output(&metafile, &input)?;
+
+    let mut chunks: Vec<PathBuf> = Vec::new();
+    let mut pending = vec![entry.to_string_lossy().to_string()];
+    while let Some(output) = pending.pop() {
+        let imports = json["outputs"][&output]["imports"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for import in imports {
+            // Dynamic imports load on demand, so only static ones are preloaded
+            if import["kind"] != "import-statement" {
+                continue;
+            }
+            let Some(path) = import["path"].as_str() else {
+                continue;
+            };
+            let chunk = PathBuf::from(path);
+            if !chunks.contains(&chunk) {
+                chunks.push(chunk);
+                pending.push(path.to_string());
             }
         }
     }
 
-    // Display ESLint summary at the end
-    summary.display();
+    Ok(chunks)
+}
 
-    log_success("Scripts", "built successfully");
-    Ok(())
+// * Emitted files that still exist on disk, relative to the output directory
+fn metafile_relative_outputs(metafile: &Path, outdir: &Path) -> Result<Vec<PathBuf>, String> {
+    let outdir = handle_error(outdir.canonicalize(), "Failed to resolve output directory")?;
+
+    Ok(metafile_entries(metafile)?
+        .into_iter()
+        .filter_map(|(path, _)| path.canonicalize().ok())
+        .filter_map(|path| path.strip_prefix(&outdir).ok().map(Path::to_path_buf))
+        .collect())
 }
 
 // * Default values for missing config fields
@@ -705,12 +3407,11 @@ fn default_verbose() -> bool {
     }
 }
 
-fn default_sourcemap() -> bool {
-    if let Ok(val) = env::var("PACKR_SOURCEMAP") {
-        val == "true"
-    } else {
-        false
-    }
+fn default_sourcemap() -> SourcemapMode {
+    env::var("PACKR_SOURCEMAP")
+        .ok()
+        .and_then(|val| SourcemapMode::parse(&val))
+        .unwrap_or(SourcemapMode::None)
 }
 
 fn default_format() -> String {
@@ -724,3 +3425,204 @@ fn default_eslint() -> bool {
         false
     }
 }
+
+fn default_hash_mode() -> String {
+    "filename".to_string()
+}
+
+fn default_manifest_format() -> String {
+    "packr".to_string()
+}
+
+fn default_instance_lock() -> String {
+    "wait".to_string()
+}
+
+fn default_io_retries() -> u32 {
+    retry::DEFAULT_RETRIES
+}
+
+fn default_cache_dir() -> String {
+    ".packr/cache".to_string()
+}
+
+fn default_audit_level() -> String {
+    "high".to_string()
+}
+
+fn default_audit_fail() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("packr-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("project/src")).unwrap();
+        fs::create_dir_all(dir.join("theme")).unwrap();
+        dir
+    }
+
+    fn config_writing(fields: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js"{}}}"#,
+            fields
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn destinations_may_not_hold_or_sit_among_the_sources() {
+        let dir = temp_project("destination-collision");
+        let project = dir.join("project");
+        let reason = |destination: &str| {
+            output_collision(
+                &config_writing(&format!(r#", "css_destination": "{}""#, destination)),
+                &project,
+            )
+        };
+        assert_eq!(
+            reason(".").as_deref(),
+            Some("css_destination \".\" points at the source tree containing scss_input")
+        );
+        assert_eq!(
+            reason("src/css").as_deref(),
+            Some("css_destination \"src/css\" is inside the directory of scss_input")
+        );
+        assert_eq!(reason("public/css"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reading_a_config_loads_none_of_its_plugins() {
+        let dir = temp_project("read-config");
+        let config_path = dir.join("project/.packr.json");
+        fs::write(
+            &config_path,
+            r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js",
+                "wasm_plugins": ["plugins/missing.wasm"]}"#,
+        )
+        .unwrap();
+        let config_path = config_path.to_string_lossy();
+
+        let (config, _) = read_config(&config_path).unwrap();
+        assert_eq!(config.wasm_plugins, ["plugins/missing.wasm"]);
+        let error = load_config(&config_path).unwrap_err().to_string();
+        assert!(error.contains("Invalid wasm_plugins"), "{error}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_that_dont_exist_yet_are_placed_by_their_parents() {
+        let dir = temp_project("within-config-dir");
+        let project = dir.join("project");
+        assert!(within_config_dir(
+            &project,
+            &project.join("dist/css/main.css")
+        ));
+        assert!(within_config_dir(&project, &project.join("src/../dist")));
+        assert!(!within_config_dir(&project, &project.join("../theme/css")));
+        assert!(!within_config_dir(
+            &project,
+            &project.join("../project-other")
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn outputs_outside_the_config_dir_are_named() {
+        let dir = temp_project("outside-config-dir");
+        let project = dir.join("project");
+        assert_eq!(outside_config_dir(&config_writing(""), &project), None);
+
+        let config = config_writing(r#", "css_destination": "../theme/css""#);
+        assert_eq!(
+            outside_config_dir(&config, &project).as_deref(),
+            Some("css_destination \"../theme/css\" is outside the config directory")
+        );
+
+        let config = config_writing(r#", "copy": [{"from": "src", "to": "/srv/www"}]"#);
+        assert!(outside_config_dir(&config, &project)
+            .unwrap()
+            .starts_with("copy[0].to \"/srv/www\""));
+
+        let config = config_writing(
+            r#", "bundles": [{"scss_input": "src/admin.scss", "scss_output": "../theme/admin.css"}]"#,
+        );
+        assert!(outside_config_dir(&config, &project)
+            .unwrap()
+            .starts_with("bundles[0].scss_output"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_config_dir_count_as_outside() {
+        let dir = temp_project("symlinked-output");
+        let project = dir.join("project");
+        std::os::unix::fs::symlink(dir.join("theme"), project.join("dist")).unwrap();
+
+        assert!(!within_config_dir(&project, &project.join("dist/main.css")));
+        let reason = outside_config_dir(&config_writing(""), &project).unwrap();
+        assert!(
+            reason.starts_with("scss_output \"dist/main.css\" leads through a symlink to"),
+            "{reason}"
+        );
+        assert!(reason.ends_with("outside the config directory"), "{reason}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // * What `StyleSheet::to_css` prints, with the same source map comment as `write_css`
+    fn printed_in_memory(css: &str, path: &Path, input: &Path, minify: bool) -> String {
+        let sheet = StyleSheet::parse(css, ParserOptions::default()).unwrap();
+        let mut source_map = css_source_map(path, input, css, SourcemapMode::Linked);
+        let code = sheet
+            .to_css(PrinterOptions {
+                minify,
+                source_map: source_map.as_mut(),
+                ..Default::default()
+            })
+            .unwrap()
+            .code;
+        code + &css_sourcemap_comment(path, source_map, SourcemapMode::Linked).unwrap()
+    }
+
+    #[test]
+    fn css_source_maps_name_the_compiled_css_apart_from_the_scss() {
+        let path = Path::new("/project/dist/main.css");
+        let input = Path::new("/project/src/main.scss");
+        let source_map =
+            css_source_map(path, input, "a { color: red; }\n", SourcemapMode::Linked).unwrap();
+        assert_eq!(source_map.get_sources(), &["../src/main.scss (compiled)"]);
+        assert_eq!(source_map.get_sources_content(), &["a { color: red; }\n"]);
+    }
+
+    #[test]
+    fn streamed_css_matches_the_in_memory_printer() {
+        let dir = env::temp_dir().join(format!("packr-write-css-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("main.scss");
+        let css = "/*! License\n * line two */\n.a { color: red; }\n@media (min-width: 600px) {\n  .b > .c { margin: 0 auto; }\n}\n";
+
+        for minify in [false, true] {
+            let path = dir.join(if minify { "main.min.css" } else { "main.css" });
+            let expected = printed_in_memory(css, &path, &input, minify);
+            let expected_map = fs::read_to_string(path.with_extension("css.map")).unwrap();
+
+            let sheet = StyleSheet::parse(css, ParserOptions::default()).unwrap();
+            write_css(&sheet, &path, &input, css, minify, SourcemapMode::Linked).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+            assert_eq!(
+                fs::read_to_string(path.with_extension("css.map")).unwrap(),
+                expected_map
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
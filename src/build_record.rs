@@ -0,0 +1,189 @@
+// * ! ==================================================
+// * ! Build records for Packr
+// * ! ==================================================
+
+use crate::build::Config;
+use crate::cache;
+use crate::diagnostics::Severity;
+use crate::error::PackrError;
+use crate::events::{self, Event, Subscription};
+use crate::lock;
+use crate::manifest::relative_to;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// * Record of the last build, next to the other state Packr keeps in the project
+const RECORD_FILE: &str = ".packr/build.json";
+
+// * Version of the record's layout, raised when a field changes meaning or goes away
+const RECORD_VERSION: u32 = 1;
+
+#[derive(Default)]
+struct Results {
+    phases: Vec<serde_json::Value>,
+    files: BTreeSet<PathBuf>,
+    warnings: Vec<serde_json::Value>,
+    errors: Vec<serde_json::Value>,
+}
+
+impl Results {
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::PhaseFinished {
+                category,
+                name,
+                duration,
+            } => self.phases.push(serde_json::json!({
+                "category": category,
+                "name": name,
+                "duration_ms": duration.as_millis() as u64,
+            })),
+            Event::FileWritten { path } => {
+                self.files.insert(path.clone());
+            }
+            Event::Diagnostic {
+                severity,
+                source,
+                message,
+                file,
+                line,
+                column,
+            } => {
+                let diagnostic = serde_json::json!({
+                    "source": source,
+                    "message": message,
+                    "file": file,
+                    "line": line,
+                    "column": column,
+                });
+                match severity {
+                    Severity::Warning => self.warnings.push(diagnostic),
+                    Severity::Error => self.errors.push(diagnostic),
+                }
+            }
+            Event::Error { message } => {
+                self.errors.push(serde_json::json!({ "message": message }));
+            }
+            _ => {}
+        }
+    }
+}
+
+// * Collects what a full build did, for `write`
+pub struct Recorder {
+    started: Instant,
+    started_at: u64,
+    results: Arc<Mutex<Results>>,
+    _subscription: Subscription,
+}
+
+pub fn record() -> Recorder {
+    let results = Arc::new(Mutex::new(Results::default()));
+    let recorder = results.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        if let Ok(mut results) = recorder.lock() {
+            results.record(event);
+        }
+    }));
+    Recorder {
+        started: Instant::now(),
+        started_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        results,
+        _subscription: subscription,
+    }
+}
+
+// * Size and SHA-256 of each existing file, keyed by its path relative to `base`
+fn hashed_files(
+    files: impl IntoIterator<Item = PathBuf>,
+    base: &Path,
+) -> BTreeMap<String, serde_json::Value> {
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let path = file.canonicalize().ok()?;
+            let content = fs::read(&path).ok()?;
+            Some((
+                relative_to(&path, base),
+                serde_json::json!({
+                    "bytes": content.len(),
+                    "sha256": cache::digest(&content),
+                }),
+            ))
+        })
+        .collect()
+}
+
+impl Recorder {
+    // * Write `.packr/build.json` for a build that ended with `result`: the resolved config, the
+    // * hashes of everything it read, the files it wrote with their sizes and hashes, the tool
+    // * versions, the time spent per phase, and the warnings and errors. Failed builds get a
+    // * record too; builds skipped by `skip_unchanged` keep the record of the build they skipped.
+    pub fn write(
+        self,
+        config: &Config,
+        config_dir: &Path,
+        config_path: &Path,
+        result: &Result<bool, PackrError>,
+    ) -> Result<(), String> {
+        if matches!(result, Ok(false)) {
+            return Ok(());
+        }
+        let duration = self.started.elapsed().as_millis() as u64;
+        let results = match self.results.lock() {
+            Ok(mut results) => std::mem::take(&mut *results),
+            Err(_) => return Err("Build results are unavailable".to_string()),
+        };
+
+        let project_dir = Path::new(".")
+            .join(config_dir)
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve {}: {}", config_dir.display(), e))?;
+        let inputs: BTreeMap<String, String> = hashed_files(
+            cache::input_files(config, config_dir, config_path),
+            &project_dir,
+        )
+        .into_iter()
+        .filter_map(|(path, file)| Some((path, file["sha256"].as_str()?.to_string())))
+        .collect();
+        // Source maps removed after upload were written, but aren't part of the outputs
+        let outputs = hashed_files(results.files, &project_dir);
+        let config_file = config_path
+            .canonicalize()
+            .map(|path| relative_to(&path, &project_dir))
+            .unwrap_or_else(|_| config_path.display().to_string());
+
+        let record = serde_json::json!({
+            "version": RECORD_VERSION,
+            "packr_version": env!("CARGO_PKG_VERSION"),
+            "status": if result.is_ok() { "success" } else { "failure" },
+            "error_code": result.as_ref().err().map(PackrError::code),
+            "started_at": self.started_at,
+            "duration_ms": duration,
+            "config_file": config_file,
+            "config": config,
+            "input_hash": cache::input_hash(config, config_dir, config_path),
+            "inputs": inputs,
+            "outputs": outputs,
+            "tools": lock::current(config, config_dir),
+            "phases": results.phases,
+            "warnings": results.warnings,
+            "errors": results.errors,
+        });
+
+        let path = config_dir.join(RECORD_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(&record)
+            .map_err(|e| format!("Failed to serialize the build record: {}", e))?;
+        fs::write(&path, json + "\n")
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
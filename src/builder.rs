@@ -0,0 +1,101 @@
+// * ! ==================================================
+// * ! Programmatic build API for Packr
+// * ! ==================================================
+
+use crate::build::{load_config, Config};
+use crate::error::PackrError;
+use crate::events::{self, Event, Handler};
+use crate::plugin::{self, PackrPlugin};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Configures and runs one build from Rust, streaming [`Event`]s as it goes:
+///
+/// ```no_run
+/// use packr::{Event, PackrBuilder};
+///
+/// let built = PackrBuilder::from_file(".packr.json")?
+///     .force(true)
+///     .on_event(|event| match event {
+///         Event::FileWritten { path } => println!("wrote {}", path.display()),
+///         Event::Diagnostic { source, message, .. } => println!("{source}: {message}"),
+///         _ => {}
+///     })
+///     .build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct PackrBuilder {
+    config: Config,
+    config_dir: PathBuf,
+    config_path: PathBuf,
+    force: bool,
+    handler: Option<Handler>,
+    plugins: Vec<Arc<dyn PackrPlugin>>,
+}
+
+impl PackrBuilder {
+    /// A build of `config`, with its paths relative to the current directory
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            config_dir: PathBuf::new(),
+            config_path: PathBuf::from(".packr.json"),
+            force: false,
+            handler: None,
+            plugins: Vec::new(),
+        }
+    }
+
+    /// A build of the config file at `config_path`, loaded as the CLI loads it
+    pub fn from_file(config_path: impl AsRef<Path>) -> Result<Self, PackrError> {
+        let config_path = config_path.as_ref();
+        let (config, config_dir) = load_config(&config_path.to_string_lossy())?;
+        Ok(Self {
+            config_dir,
+            config_path: config_path.to_path_buf(),
+            ..Self::new(config)
+        })
+    }
+
+    /// Resolve the config's paths relative to `dir` instead of the current directory
+    pub fn config_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = dir.into();
+        self
+    }
+
+    /// Build even when `skip_unchanged` finds no input changed, like `--force`
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Call `handler` with every event of the build, from whichever thread it happens on
+    pub fn on_event(mut self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Run `plugin` in this build, after any registered with [`plugin::register`]
+    pub fn plugin(mut self, plugin: impl PackrPlugin + 'static) -> Self {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    /// Run the build; `Ok(false)` when it was skipped because no input changed. Events go to
+    /// this build's handler only while it runs, so run one build at a time per process.
+    pub fn build(mut self) -> Result<bool, PackrError> {
+        // The config was resolved before these plugins were known
+        for own in &self.plugins {
+            own.config_resolved(&mut self.config, &self.config_dir)
+                .map_err(|e| PackrError::context(&format!("Plugin {} failed", own.name()), e))?;
+        }
+        let _subscription = self.handler.map(events::subscribe);
+        let _plugins = plugin::scoped(self.plugins);
+        crate::run_build(
+            &self.config,
+            &self.config_dir,
+            &self.config_path,
+            self.force,
+        )
+    }
+}
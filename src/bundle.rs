@@ -0,0 +1,301 @@
+// * ! ==================================================
+// * ! Additional entries and their scheduling for Packr
+// * ! ==================================================
+
+use crate::build::{invalid_output_path, normalize_path, Config};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::thread;
+
+// * A `bundles` entry: a stylesheet and/or a script built like `scss_input` and `js_input`
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct Bundle {
+    #[serde(default)]
+    pub scss_input: Option<String>,
+    #[serde(default)]
+    pub scss_output: Option<String>,
+    #[serde(default)]
+    pub js_input: Option<String>,
+    #[serde(default)]
+    pub js_output: Option<String>,
+    // File name patterns from a `[hash]` in the outputs, as for the main entries
+    #[serde(skip)]
+    pub css_hash_names: Option<String>,
+    #[serde(skip)]
+    pub js_hash_names: Option<String>,
+}
+
+// * What a task builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Styles,
+    Scripts,
+}
+
+// * One entry to build: the main stylesheet or script, or one half of a bundle, with the
+// * config it builds from, the name its log lines are prefixed with and the context its
+// * failure is reported in, e.g. `Styles failed (admin.css)`
+pub struct Task<'a> {
+    pub name: String,
+    // Index of the bundle in `bundles`, `None` for the main entries
+    pub bundle: Option<usize>,
+    pub failed: String,
+    pub step: Step,
+    pub config: Cow<'a, Config>,
+}
+
+// * Why the `bundles` entries can't be built, if one is incomplete or names a directory
+pub fn validate(bundles: &[Bundle]) -> Result<(), String> {
+    for (i, bundle) in bundles.iter().enumerate() {
+        let pairs = [
+            ("scss", &bundle.scss_input, &bundle.scss_output),
+            ("js", &bundle.js_input, &bundle.js_output),
+        ];
+        for (kind, input, output) in pairs {
+            if input.is_some() != output.is_some() {
+                return Err(format!(
+                    "bundles[{}] sets only one of {}_input and {}_output",
+                    i, kind, kind
+                ));
+            }
+            if let Some(output) = output {
+                if let Some(reason) = invalid_output_path(output) {
+                    return Err(format!(
+                        "bundles[{}].{}_output \"{}\" {}",
+                        i, kind, output, reason
+                    ));
+                }
+            }
+        }
+        if bundle.scss_input.is_none() && bundle.js_input.is_none() {
+            return Err(format!(
+                "bundles[{}] needs scss_input and scss_output, js_input and js_output, or both",
+                i
+            ));
+        }
+    }
+    Ok(())
+}
+
+// * Every stylesheet and script input with the field it's set in, the main entries first
+pub fn inputs(config: &Config) -> Vec<(String, &str)> {
+    let mut inputs = vec![
+        ("scss_input".to_string(), config.scss_input.as_str()),
+        ("js_input".to_string(), config.js_input.as_str()),
+    ];
+    for (i, bundle) in config.bundles.iter().enumerate() {
+        for (field, input) in [
+            ("scss_input", &bundle.scss_input),
+            ("js_input", &bundle.js_input),
+        ] {
+            if let Some(input) = input {
+                inputs.push((format!("bundles[{}].{}", i, field), input.as_str()));
+            }
+        }
+    }
+    inputs
+}
+
+// * Every stylesheet and script output with the field it's set in, the main entries first
+pub fn outputs(config: &Config) -> Vec<(String, &str)> {
+    let mut outputs = vec![
+        ("scss_output".to_string(), config.scss_output.as_str()),
+        ("js_output".to_string(), config.js_output.as_str()),
+    ];
+    for (i, bundle) in config.bundles.iter().enumerate() {
+        for (field, output) in [
+            ("scss_output", &bundle.scss_output),
+            ("js_output", &bundle.js_output),
+        ] {
+            if let Some(output) = output {
+                outputs.push((format!("bundles[{}].{}", i, field), output.as_str()));
+            }
+        }
+    }
+    outputs
+}
+
+// * The first output written by two entries, if any, which would overwrite each other
+pub fn duplicate_output(config: &Config, config_dir: &Path) -> Option<String> {
+    let mut seen = BTreeSet::new();
+    outputs(config).into_iter().find_map(|(field, output)| {
+        let path = normalize_path(&config_dir.join(output));
+        (!seen.insert(path))
+            .then(|| format!("{} \"{}\" is written by another entry", field, output))
+    })
+}
+
+// * The entries of a build: the main stylesheet and script, then each bundle's. A bundle
+// * builds from the project config with its own input and output, and keeps its esbuild
+// * metafiles and lint state in a cache directory of its own.
+pub fn tasks(config: &Config) -> Vec<Task<'_>> {
+    let mut tasks = vec![
+        Task {
+            name: "styles".to_string(),
+            bundle: None,
+            failed: "Styles failed".to_string(),
+            step: Step::Styles,
+            config: Cow::Borrowed(config),
+        },
+        Task {
+            name: "scripts".to_string(),
+            bundle: None,
+            failed: "Scripts failed".to_string(),
+            step: Step::Scripts,
+            config: Cow::Borrowed(config),
+        },
+    ];
+    for (i, bundle) in config.bundles.iter().enumerate() {
+        if let (Some(input), Some(output)) = (&bundle.scss_input, &bundle.scss_output) {
+            let mut entry = entry_config(config, i);
+            entry.scss_input = input.clone();
+            entry.scss_output = output.clone();
+            entry.css_hash_names = bundle.css_hash_names.clone();
+            let name = task_name(output);
+            tasks.push(Task {
+                failed: format!("Styles failed ({})", name),
+                name,
+                bundle: Some(i),
+                step: Step::Styles,
+                config: Cow::Owned(entry),
+            });
+        }
+        if let (Some(input), Some(output)) = (&bundle.js_input, &bundle.js_output) {
+            let mut entry = entry_config(config, i);
+            entry.js_input = input.clone();
+            entry.js_output = output.clone();
+            if bundle.js_hash_names.is_some() {
+                entry.entry_names = bundle.js_hash_names.clone();
+            }
+            let name = task_name(output);
+            tasks.push(Task {
+                failed: format!("Scripts failed ({})", name),
+                name,
+                bundle: Some(i),
+                step: Step::Scripts,
+                config: Cow::Owned(entry),
+            });
+        }
+    }
+    tasks
+}
+
+// * The project config for bundle `index`, before its input and output are filled in. The
+// * vendor chunk belongs to `js_input` alone, so bundles don't each write one.
+fn entry_config(config: &Config, index: usize) -> Config {
+    let mut entry = config.clone();
+    entry.bundles = Vec::new();
+    entry.vendor_chunk = None;
+    entry.cache_dir = Path::new(&config.cache_dir)
+        .join("bundles")
+        .join(index.to_string())
+        .to_string_lossy()
+        .to_string();
+    entry
+}
+
+// * Log prefix of a bundle: the file name of its output
+fn task_name(output: &str) -> String {
+    Path::new(output)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| output.to_string())
+}
+
+// * Entries built at once: `jobs` if set, but never more than there are CPUs
+pub fn jobs(config: &Config) -> usize {
+    let cpus = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    config.jobs.unwrap_or(cpus).clamp(1, cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(bundles: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js",
+                "bundles": {}}}"#,
+            bundles
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn bundles_become_tasks_with_their_own_cache() {
+        let config = config(
+            r#"[{"scss_input": "src/admin.scss", "scss_output": "dist/admin.css",
+                 "js_input": "src/admin.js", "js_output": "dist/admin.js"},
+                {"scss_input": "src/print.scss", "scss_output": "dist/print.css"}]"#,
+        );
+        let tasks = tasks(&config);
+        let names: Vec<&str> = tasks.iter().map(|task| task.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["styles", "scripts", "admin.css", "admin.js", "print.css"]
+        );
+        let failed: Vec<&str> = tasks.iter().map(|task| task.failed.as_str()).collect();
+        assert_eq!(
+            failed,
+            [
+                "Styles failed",
+                "Scripts failed",
+                "Styles failed (admin.css)",
+                "Scripts failed (admin.js)",
+                "Styles failed (print.css)"
+            ]
+        );
+
+        let admin = &tasks[3];
+        assert_eq!(admin.step, Step::Scripts);
+        assert_eq!(admin.config.js_input, "src/admin.js");
+        assert_eq!(admin.config.scss_input, "src/main.scss");
+        assert!(admin.config.bundles.is_empty());
+        assert_eq!(
+            Path::new(&admin.config.cache_dir),
+            Path::new(&config.cache_dir).join("bundles").join("0")
+        );
+        assert_eq!(
+            Path::new(&tasks[4].config.cache_dir),
+            Path::new(&config.cache_dir).join("bundles").join("1")
+        );
+    }
+
+    #[test]
+    fn incomplete_bundles_are_rejected() {
+        let half = config(r#"[{"scss_input": "src/admin.scss"}]"#);
+        assert!(validate(&half.bundles)
+            .unwrap_err()
+            .contains("only one of scss_input and scss_output"));
+
+        let empty = config("[{}]");
+        assert!(validate(&empty.bundles).is_err());
+
+        let directory = config(r#"[{"js_input": "src/admin.js", "js_output": "dist/"}]"#);
+        assert!(validate(&directory.bundles)
+            .unwrap_err()
+            .contains("bundles[0].js_output"));
+    }
+
+    #[test]
+    fn outputs_shared_between_entries_are_found() {
+        let config =
+            config(r#"[{"scss_input": "src/admin.scss", "scss_output": "./dist/main.css"}]"#);
+        let reason = duplicate_output(&config, Path::new("/project")).unwrap();
+        assert!(reason.starts_with("bundles[0].scss_output"), "{reason}");
+    }
+
+    #[test]
+    fn jobs_never_exceed_the_cpus() {
+        let cpus = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let mut config = config("[]");
+        assert_eq!(jobs(&config), cpus);
+        config.jobs = Some(usize::MAX);
+        assert_eq!(jobs(&config), cpus);
+        config.jobs = Some(1);
+        assert_eq!(jobs(&config), 1);
+    }
+}
@@ -0,0 +1,939 @@
+// * ! ==================================================
+// * ! Persistent build cache for Packr
+// * ! ==================================================
+
+use crate::archive;
+use crate::build::{log_line, Config};
+use crate::bundle::{self, Step};
+use crate::esbuild;
+use crate::retry;
+use codemap::SpanLoc;
+use colored::*;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const STATE_FILE: &str = "build.json";
+const VERSION_FILE: &str = "version.json";
+const LINT_FILE: &str = "lint.json";
+pub const WATCH_FILE: &str = "watch.json";
+pub const SIZES_FILE: &str = "sizes.json";
+pub const IMAGES_FILE: &str = "images.json";
+
+// * Everything Packr keeps in the cache directory; nothing else in it is ever removed
+const ENTRIES: [&str; 15] = [
+    "scss",
+    "css",
+    "bundles",
+    "eslintcache",
+    "metafile.json",
+    "metafile.min.json",
+    "metafile.legacy.json",
+    "metafile.analyze.json",
+    "metafile.inputs.json",
+    STATE_FILE,
+    VERSION_FILE,
+    SIZES_FILE,
+    LINT_FILE,
+    WATCH_FILE,
+    IMAGES_FILE,
+];
+
+// * npm packages whose installed version decides whether cached tool output is still valid
+const TOOL_PACKAGES: [&str; 2] = ["esbuild", "eslint"];
+
+// * Full SHA-256 hex digest, where a short content hash could let a change slip through
+pub fn digest(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// * Cache directory, as configured by `cache_dir` relative to the config file
+pub fn dir(config: &Config, config_dir: &Path) -> PathBuf {
+    config_dir.join(&config.cache_dir)
+}
+
+// * Project files that change what gets bundled without living next to the inputs
+pub const PROJECT_FILES: [&str; 9] = [
+    "package.json",
+    "tsconfig.json",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "bun.lock",
+    "bun.lockb",
+    ".env",
+];
+
+// * Directory names never descended into when hashing
+const SKIPPED_DIRS: [&str; 2] = ["node_modules", "target"];
+
+// * Directories the build writes to, so its own outputs don't count as input changes
+fn output_dirs(config: &Config, config_dir: &Path) -> Vec<PathBuf> {
+    bundle::outputs(config)
+        .into_iter()
+        .filter_map(|(_, output)| config_dir.join(output).parent().map(Path::to_path_buf))
+        .chain(
+            [&config.css_destination, &config.js_destination]
+                .into_iter()
+                .flatten()
+                .map(|dest| config_dir.join(dest)),
+        )
+        .chain(config.copy.iter().map(|task| config_dir.join(&task.to)))
+        .chain(config.html.iter().map(|html| config_dir.join(&html.output)))
+        .chain(
+            config
+                .favicons
+                .iter()
+                .map(|favicons| config_dir.join(&favicons.output)),
+        )
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect()
+}
+
+// * Every file below `path` (or `path` itself), skipping dependencies, hidden entries and output dirs
+fn collect_files(path: &Path, outputs: &[PathBuf], files: &mut Vec<PathBuf>) {
+    let Ok(canonical) = path.canonicalize() else {
+        return;
+    };
+    if canonical.is_file() {
+        files.push(canonical);
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(&canonical) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        // Outputs are only skipped below a root, so sources next to them are still seen
+        if name.starts_with('.')
+            || SKIPPED_DIRS.contains(&name.as_str())
+            || path.canonicalize().is_ok_and(|p| outputs.contains(&p))
+        {
+            continue;
+        }
+        collect_files(&path, outputs, files);
+    }
+}
+
+// * Every file the build reads: source trees and the sources the last build found outside them,
+// * copy sources, templates, the config and lockfiles
+pub fn input_files(config: &Config, config_dir: &Path, config_path: &Path) -> Vec<PathBuf> {
+    let outputs = output_dirs(config, config_dir);
+    let parent = |input: &str| {
+        config_dir
+            .join(input)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| config_dir.to_path_buf())
+    };
+
+    let mut roots: Vec<PathBuf> = bundle::inputs(config)
+        .into_iter()
+        .map(|(_, input)| parent(input))
+        .collect();
+    roots.extend(config.copy.iter().map(|task| config_dir.join(&task.from)));
+    if let Some(ref html) = config.html {
+        roots.extend(html.templates.iter().map(|template| parent(template)));
+    }
+    if let Some(ref favicons) = config.favicons {
+        roots.push(config_dir.join(&favicons.source));
+    }
+    roots.push(config_path.to_path_buf());
+    roots.extend(PROJECT_FILES.iter().map(|name| config_dir.join(name)));
+    // Sources outside the input directories, e.g. partials of a shared package, as the last
+    // build of each entry read them
+    for task in bundle::tasks(config) {
+        let cache_dir = dir(&task.config, config_dir);
+        match task.step {
+            Step::Styles => {
+                let input = config_dir.join(&task.config.scss_input);
+                roots.extend(scss_dependencies(&cache_dir, &input));
+            }
+            Step::Scripts => roots.extend(script_inputs(&cache_dir)),
+        }
+    }
+
+    let mut files = Vec::new();
+    for root in &roots {
+        collect_files(root, &outputs, &mut files);
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+// * Whether the last build recorded what every entry read: the files of each stylesheet's
+// * compile and the modules of each script's metafile
+pub fn dependencies_recorded(config: &Config, config_dir: &Path) -> bool {
+    bundle::tasks(config).iter().all(|task| {
+        let cache_dir = dir(&task.config, config_dir);
+        match task.step {
+            Step::Styles => {
+                let input = config_dir.join(&task.config.scss_input);
+                !scss_dependencies(&cache_dir, &input).is_empty()
+            }
+            Step::Scripts => cache_dir.join("metafile.json").is_file(),
+        }
+    })
+}
+
+// * Hash of everything the build reads: its input files and `PACKR_*` overrides
+pub fn input_hash(config: &Config, config_dir: &Path, config_path: &Path) -> String {
+    let files = input_files(config, config_dir, config_path);
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    for file in &files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(file).unwrap_or_default());
+        hasher.update([0]);
+    }
+
+    // `--deploy` turns deploying on for one build; a build that deployed isn't up to date
+    // for one that didn't, nor the other way around
+    let deploys = config.deploy.as_ref().is_some_and(|deploy| deploy.on_build);
+    hasher.update(format!("deploy={}\0", deploys));
+
+    // Environment overrides change the effective config without touching the file
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| name.starts_with("PACKR_") || config.env_inject.contains(name))
+        .collect();
+    vars.sort();
+    for (name, value) in vars {
+        hasher.update(format!("{}={}\0", name, value));
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// * Whether the last successful build used the same inputs and its outputs are still there
+pub fn is_up_to_date(cache_dir: &Path, hash: &str) -> bool {
+    let path = cache_dir.join(STATE_FILE);
+    let Some(state) = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+    else {
+        return false;
+    };
+
+    state["inputs"] == hash
+        && state["outputs"].as_array().is_some_and(|outputs| {
+            outputs
+                .iter()
+                .all(|output| output.as_str().is_some_and(|p| Path::new(p).exists()))
+        })
+}
+
+// * Record the inputs and outputs of a successful build
+pub fn store(cache_dir: &Path, hash: &str, outputs: &[PathBuf]) -> Result<(), String> {
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+
+    let state = serde_json::json!({
+        "inputs": hash,
+        "outputs": outputs
+            .iter()
+            .map(|output| output.to_string_lossy().to_string())
+            .collect::<Vec<_>>(),
+    });
+    let path = cache_dir.join(STATE_FILE);
+    fs::write(&path, state.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// * Hash of the linted files together with the ESLint config they were checked against
+pub fn lint_hash(files: &[&Path]) -> String {
+    let mut content = Vec::new();
+    for file in files {
+        content.extend_from_slice(file.to_string_lossy().as_bytes());
+        content.push(0);
+        content.extend(fs::read(file).unwrap_or_default());
+        content.push(0);
+    }
+    digest(&content)
+}
+
+// * Whether the same files already passed ESLint without warnings
+pub fn is_lint_clean(cache_dir: &Path, hash: &str) -> bool {
+    fs::read_to_string(cache_dir.join(LINT_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .is_some_and(|state| state["files"] == hash)
+}
+
+// * Record a lint that passed without warnings
+pub fn store_lint(cache_dir: &Path, hash: &str) -> Result<(), String> {
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+    let path = cache_dir.join(LINT_FILE);
+    fs::write(&path, serde_json::json!({ "files": hash }).to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// * Remove Packr's entries from a cache directory, leaving anything else that lives there
+pub fn clear(cache_dir: &Path) -> Result<(), String> {
+    remove_entries(cache_dir, &[])
+}
+
+fn remove_entries(cache_dir: &Path, keep: &[&str]) -> Result<(), String> {
+    for entry in ENTRIES.iter().filter(|entry| !keep.contains(entry)) {
+        let path = cache_dir.join(entry);
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else if path.exists() {
+            fs::remove_file(&path)
+        } else {
+            continue;
+        };
+        result.map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+// * Tool versions and config the cached results were produced with
+fn version_key(config_dir: &Path, config_path: &Path) -> serde_json::Value {
+    let installed = |name: &str| {
+        fs::read_to_string(
+            config_dir
+                .join("node_modules")
+                .join(name)
+                .join("package.json"),
+        )
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|package| package["version"].as_str().map(str::to_string))
+    };
+    let tools: BTreeMap<&str, Option<String>> = TOOL_PACKAGES
+        .iter()
+        .map(|&name| (name, installed(name)))
+        .collect();
+
+    // Environment overrides are part of the effective config
+    let mut config = fs::read(config_path).unwrap_or_default();
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| name.starts_with("PACKR_"))
+        .collect();
+    vars.sort();
+    for (name, value) in vars {
+        config.extend(format!("\0{}={}", name, value).bytes());
+    }
+
+    serde_json::json!({
+        "packr": env!("CARGO_PKG_VERSION"),
+        "esbuild": esbuild::ESBUILD_VERSION,
+        "tools": tools,
+        "config": digest(&config),
+    })
+}
+
+// * Clear the cache when it was written by other tool versions or another config, e.g. one
+// * restored on CI from a different branch, then record the current ones
+pub fn validate(config: &Config, config_dir: &Path, config_path: &Path) -> Result<(), String> {
+    let cache_dir = dir(config, config_dir);
+    let key = version_key(config_dir, config_path);
+    let version_path = cache_dir.join(VERSION_FILE);
+
+    let stored: Option<serde_json::Value> = fs::read_to_string(&version_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    if stored.as_ref() == Some(&key) {
+        return Ok(());
+    }
+
+    if let Some(stored) = stored {
+        let reason = if stored["packr"] != key["packr"] {
+            "Packr version changed"
+        } else if stored["config"] != key["config"] {
+            "config changed"
+        } else {
+            "tool versions changed"
+        };
+        log_line(format!("{} cleared, {}", "Cache".yellow().bold(), reason));
+        // Output sizes describe the previous build, not cached work, so they are compared still
+        remove_entries(&cache_dir, &[SIZES_FILE])?;
+    } else if ENTRIES.iter().any(|entry| cache_dir.join(entry).exists()) {
+        // Unversioned caches are from before versioning or a partial restore
+        remove_entries(&cache_dir, &[SIZES_FILE])?;
+    }
+
+    fs::create_dir_all(&cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+    fs::write(&version_path, key.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", version_path.display(), e))
+}
+
+// * Every file below `path` with its path relative to `root`, using `/` separators
+fn archive_files(
+    root: &Path,
+    path: &Path,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    if path.is_dir() {
+        let entries =
+            fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        for entry in entries.flatten() {
+            archive_files(root, &entry.path(), files)?;
+        }
+    } else if path.is_file() {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let name: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let contents =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        files.push((name.join("/"), contents));
+    }
+    Ok(())
+}
+
+// * Write the cache to a `.tar.gz` file, e.g. to hand it to a later CI job
+pub fn export(config: &Config, config_dir: &Path, archive_path: &Path) -> Result<usize, String> {
+    let cache_dir = dir(config, config_dir);
+    let mut files = Vec::new();
+    for entry in ENTRIES {
+        archive_files(&cache_dir, &cache_dir.join(entry), &mut files)?;
+    }
+    files.sort();
+
+    fs::write(archive_path, archive::pack(&files)?)
+        .map_err(|e| format!("Failed to write {}: {}", archive_path.display(), e))?;
+    Ok(files.len())
+}
+
+// * Replace the cache with the contents of an exported `.tar.gz` file
+pub fn import(config: &Config, config_dir: &Path, archive_path: &Path) -> Result<usize, String> {
+    let content = fs::read(archive_path)
+        .map_err(|e| format!("Failed to read {}: {}", archive_path.display(), e))?;
+    let files = archive::unpack(&content)?;
+
+    // Only known cache entries are restored, and never outside the cache directory
+    for (name, _) in &files {
+        let path = Path::new(name);
+        let inside = path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+        let known = path
+            .components()
+            .next()
+            .is_some_and(|first| ENTRIES.iter().any(|entry| first.as_os_str() == *entry));
+        if !inside || !known {
+            return Err(format!(
+                "{} is not a Packr cache archive: unexpected entry {}",
+                archive_path.display(),
+                name
+            ));
+        }
+    }
+
+    let cache_dir = dir(config, config_dir);
+    clear(&cache_dir)?;
+    for (name, contents) in &files {
+        let path = cache_dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(files.len())
+}
+
+// * Source bytes as UTF-8 text for grass. A byte order mark is dropped, UTF-16 is transcoded,
+// * and text in a legacy encoding such as Windows-1252 is decoded lossily with a warning.
+// * Content that can't be text at all is an error naming the file and the offending byte.
+fn decode_source(path: &Path, bytes: &[u8]) -> io::Result<(String, Option<String>)> {
+    let invalid = |offset: usize, detail: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} can't be decoded: {} at byte offset {}",
+                path.display(),
+                detail,
+                offset
+            ),
+        )
+    };
+
+    let utf16: Option<fn([u8; 2]) -> u16> = match bytes {
+        [0xFF, 0xFE, ..] => Some(u16::from_le_bytes),
+        [0xFE, 0xFF, ..] => Some(u16::from_be_bytes),
+        _ => None,
+    };
+    if let Some(from_bytes) = utf16 {
+        let body = &bytes[2..];
+        if !body.len().is_multiple_of(2) {
+            return Err(invalid(bytes.len() - 1, "truncated UTF-16 character"));
+        }
+        let units = body
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]));
+        let mut text = String::with_capacity(body.len());
+        let mut decoded_units = 0;
+        for c in char::decode_utf16(units) {
+            let c = c.map_err(|_| invalid(2 + decoded_units * 2, "unpaired UTF-16 surrogate"))?;
+            decoded_units += c.len_utf16();
+            text.push(c);
+        }
+        return Ok((text, None));
+    }
+
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    // NUL never appears in a stylesheet in any 8-bit encoding, so the file isn't text
+    if let Some(nul) = bytes.iter().position(|&byte| byte == 0) {
+        return Err(invalid(nul, "binary content (NUL byte)"));
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok((text.to_string(), None)),
+        Err(e) => {
+            let offset = e.valid_up_to();
+            let warning = format!(
+                "{} is not UTF-8 (byte 0x{:02X} at offset {}); decoded lossily with undecodable \
+                 bytes replaced by U+FFFD, re-save it as UTF-8",
+                path.display(),
+                bytes[offset],
+                offset
+            );
+            Ok((String::from_utf8_lossy(bytes).into_owned(), Some(warning)))
+        }
+    }
+}
+
+// * grass file system that records every file read and every path probed during a compile
+#[derive(Debug, Default)]
+struct RecordingFs {
+    reads: RefCell<BTreeMap<PathBuf, String>>,
+    probes: RefCell<BTreeMap<String, bool>>,
+    // Files that had to be decoded lossily
+    warnings: RefCell<Vec<String>>,
+}
+
+impl RecordingFs {
+    fn probe(&self, kind: &str, path: &Path, result: bool) -> bool {
+        self.probes
+            .borrow_mut()
+            .insert(format!("{}:{}", kind, path.display()), result);
+        result
+    }
+}
+
+impl grass::Fs for RecordingFs {
+    fn is_dir(&self, path: &Path) -> bool {
+        self.probe("dir", path, path.is_dir())
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.probe("file", path, path.is_file())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let content = fs::read(path)?;
+        self.reads
+            .borrow_mut()
+            .insert(path.to_path_buf(), digest(&content));
+        let (text, warning) = decode_source(path, &content)?;
+        self.warnings.borrow_mut().extend(warning);
+        Ok(text.into_bytes())
+    }
+}
+
+// * grass logger that keeps `@warn` messages for the build to report
+#[derive(Debug, Default)]
+struct RecordingLogger {
+    warnings: RefCell<Vec<String>>,
+}
+
+impl grass::Logger for RecordingLogger {
+    fn debug(&self, location: SpanLoc, message: &str) {
+        grass::Logger::debug(&grass::StdLogger, location, message);
+    }
+
+    fn warn(&self, location: SpanLoc, message: &str) {
+        self.warnings.borrow_mut().push(format!(
+            "{} ({}:{}:{})",
+            message,
+            location.file.name(),
+            location.begin.line + 1,
+            location.begin.column + 1
+        ));
+    }
+}
+
+// * Whether every recorded probe and file read would still give the same result
+fn dependencies_unchanged(state: &serde_json::Value) -> bool {
+    let probes_match = state["probes"].as_object().is_some_and(|probes| {
+        probes.iter().all(|(key, expected)| {
+            let result = match key.split_once(':') {
+                Some(("dir", path)) => Path::new(path).is_dir(),
+                Some(("file", path)) => Path::new(path).is_file(),
+                _ => return false,
+            };
+            Some(result) == expected.as_bool()
+        })
+    });
+
+    probes_match
+        && state["reads"].as_object().is_some_and(|reads| {
+            reads.iter().all(|(path, hash)| {
+                fs::read(path).is_ok_and(|content| Some(digest(&content).as_str()) == hash.as_str())
+            })
+        })
+}
+
+// * Recorded state and compiled CSS of an SCSS entry in the cache
+fn scss_paths(cache_dir: &Path, input: &Path) -> (PathBuf, PathBuf) {
+    let dir = cache_dir.join("scss");
+    let key = digest(input.to_string_lossy().as_bytes());
+    (
+        dir.join(format!("{}.json", key)),
+        dir.join(format!("{}.css", key)),
+    )
+}
+
+fn read_scss_state(state_path: &Path) -> Option<serde_json::Value> {
+    fs::read_to_string(state_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .filter(|state: &serde_json::Value| state["version"] == env!("CARGO_PKG_VERSION"))
+}
+
+// * Every path the last compile of an SCSS entry read or probed, i.e. its dependency set
+pub fn scss_dependencies(cache_dir: &Path, input: &Path) -> Vec<PathBuf> {
+    let Some(state) = read_scss_state(&scss_paths(cache_dir, input).0) else {
+        return Vec::new();
+    };
+
+    let reads = state["reads"]
+        .as_object()
+        .into_iter()
+        .flat_map(|r| r.keys());
+    let probes = state["probes"]
+        .as_object()
+        .into_iter()
+        .flat_map(|p| p.keys())
+        .filter_map(|key| key.split_once(':').map(|(_, path)| path));
+    let mut paths: Vec<PathBuf> = reads
+        .map(String::as_str)
+        .chain(probes)
+        .map(PathBuf::from)
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// * Every module the last esbuild build of a script read, as listed in its metafile. Paths
+// * are relative to the working directory, like esbuild's own.
+fn script_inputs(cache_dir: &Path) -> Vec<PathBuf> {
+    let Some(metafile) = fs::read_to_string(cache_dir.join("metafile.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+    else {
+        return Vec::new();
+    };
+    metafile["inputs"]
+        .as_object()
+        .into_iter()
+        .flat_map(|inputs| inputs.keys())
+        .map(PathBuf::from)
+        .collect()
+}
+
+// * An SCSS entry compiled to CSS, with the `@warn` messages of the compile
+pub struct CompiledScss {
+    pub css: String,
+    pub warnings: Vec<String>,
+    // Whether the CSS was reused from the cache
+    pub cached: bool,
+}
+
+// * Compiled SCSS entries a long-running process keeps in memory by state file, with the state
+// * they were compiled in, so a rebuild with nothing changed reads neither back from disk;
+// * `None` until `keep_compiled_scss` turns this on
+static KEPT_SCSS: Mutex<Option<BTreeMap<PathBuf, (serde_json::Value, String)>>> = Mutex::new(None);
+
+// * Keep every SCSS entry compiled from now on in memory, for a daemon serving many builds
+pub fn keep_compiled_scss() {
+    let mut kept = KEPT_SCSS.lock().unwrap_or_else(|e| e.into_inner());
+    kept.get_or_insert_with(BTreeMap::new);
+}
+
+fn keep_scss(state_path: &Path, state: &serde_json::Value, css: &str) {
+    let mut kept = KEPT_SCSS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(kept) = kept.as_mut() {
+        kept.insert(state_path.to_path_buf(), (state.clone(), css.to_string()));
+    }
+}
+
+fn kept_scss(state_path: &Path) -> Option<(serde_json::Value, String)> {
+    let kept = KEPT_SCSS.lock().unwrap_or_else(|e| e.into_inner());
+    kept.as_ref()?.get(state_path).cloned()
+}
+
+fn cached_scss(css: String, state: &serde_json::Value) -> CompiledScss {
+    let warnings = state["warnings"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|warning| warning.as_str().map(str::to_string))
+        .collect();
+    CompiledScss {
+        css,
+        warnings,
+        cached: true,
+    }
+}
+
+// * Compile an SCSS entry, reusing the cached CSS (and its warnings) when none of the files
+// * it used changed
+pub fn compile_scss(cache_dir: &Path, input: &Path) -> Result<CompiledScss, String> {
+    let (state_path, css_path) = scss_paths(cache_dir, input);
+    let dir = state_path.parent().unwrap_or(cache_dir).to_path_buf();
+
+    if let Some((state, css)) =
+        kept_scss(&state_path).filter(|(state, _)| dependencies_unchanged(state))
+    {
+        return Ok(cached_scss(css, &state));
+    }
+    let cached = read_scss_state(&state_path)
+        .filter(dependencies_unchanged)
+        .and_then(|state| Some((fs::read_to_string(&css_path).ok()?, state)));
+    if let Some((css, state)) = cached {
+        keep_scss(&state_path, &state, &css);
+        return Ok(cached_scss(css, &state));
+    }
+
+    let recorder = RecordingFs::default();
+    let logger = RecordingLogger::default();
+    let options = grass::Options::default().fs(&recorder).logger(&logger);
+    let css =
+        grass::from_path(input, &options).map_err(|e| format!("SCSS compilation failed: {}", e))?;
+    let mut warnings = recorder.warnings.take();
+    warnings.extend(logger.warnings.into_inner());
+
+    // A failed write only costs the next build a recompile
+    let state = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "reads": recorder.reads.into_inner(),
+        "probes": recorder.probes.into_inner(),
+        "warnings": warnings,
+    });
+    if fs::create_dir_all(&dir).is_ok() && fs::write(&css_path, &css).is_ok() {
+        let _ = fs::write(&state_path, state.to_string());
+    }
+    keep_scss(&state_path, &state, &css);
+
+    Ok(CompiledScss {
+        css,
+        warnings,
+        cached: false,
+    })
+}
+
+// * Digest of the lightningcss input of a stylesheet and the settings it's printed with
+pub fn printed_css_key(settings: &[&str], css: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    for setting in settings {
+        hasher.update([0]);
+        hasher.update(setting);
+    }
+    hasher.update([0]);
+    hasher.update(css);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// * Directory holding the last printed files of the stylesheet written to `output`
+fn printed_css_dir(cache_dir: &Path, output: &Path) -> PathBuf {
+    cache_dir
+        .join("css")
+        .join(digest(output.to_string_lossy().as_bytes()))
+}
+
+// * The files lightningcss printed for a stylesheet (plain and minified CSS, source maps),
+// * with the warnings it reported while parsing
+pub struct PrintedCss {
+    dir: PathBuf,
+    pub warnings: Vec<String>,
+}
+
+impl PrintedCss {
+    // * Put the cached files back in place, in the order they were stored
+    pub fn restore(&self, files: &[PathBuf]) -> io::Result<()> {
+        for (i, file) in files.iter().enumerate() {
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            retry::copy(self.dir.join(i.to_string()), file)?;
+        }
+        Ok(())
+    }
+}
+
+// * What lightningcss printed for `output` last time, if it printed the same CSS with the
+// * same settings into the same number of files
+pub fn printed_css(cache_dir: &Path, output: &Path, key: &str, files: usize) -> Option<PrintedCss> {
+    let dir = printed_css_dir(cache_dir, output);
+    let state: serde_json::Value = fs::read_to_string(dir.join("state.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())?;
+    if state["key"] != key
+        || state["files"] != files
+        || !(0..files).all(|i| dir.join(i.to_string()).is_file())
+    {
+        return None;
+    }
+    let warnings = state["warnings"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|warning| warning.as_str().map(str::to_string))
+        .collect();
+    Some(PrintedCss { dir, warnings })
+}
+
+// * Keep the files just printed for `output`, replacing those of its previous build. A
+// * failed copy only costs the next build a print.
+pub fn store_printed_css(
+    cache_dir: &Path,
+    output: &Path,
+    key: &str,
+    files: &[PathBuf],
+    warnings: &[String],
+) {
+    let dir = printed_css_dir(cache_dir, output);
+    let _ = fs::remove_dir_all(&dir);
+    if fs::create_dir_all(&dir).is_err() {
+        return;
+    }
+    for (i, file) in files.iter().enumerate() {
+        if fs::copy(file, dir.join(i.to_string())).is_err() {
+            let _ = fs::remove_dir_all(&dir);
+            return;
+        }
+    }
+    let state = serde_json::json!({
+        "key": key,
+        "files": files.len(),
+        "warnings": warnings,
+    });
+    let _ = fs::write(dir.join("state.json"), state.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_removes_every_entry_and_nothing_else() {
+        let dir = env::temp_dir().join(format!("packr-cache-clear-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("scss")).unwrap();
+        for entry in ENTRIES.iter().filter(|entry| **entry != "scss") {
+            fs::write(dir.join(entry), "{}").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "kept").unwrap();
+
+        clear(&dir).unwrap();
+
+        assert!(dir.join("notes.txt").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn printed_css_is_restored_only_for_the_same_key() {
+        let dir = env::temp_dir().join(format!("packr-cache-printed-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("dist")).unwrap();
+        let files = [dir.join("dist/main.css"), dir.join("dist/main.min.css")];
+        fs::write(&files[0], "a {\n  color: red;\n}\n").unwrap();
+        fs::write(&files[1], "a{color:red}").unwrap();
+
+        let key = printed_css_key(&["src/main.scss", "true"], "a { color: red; }");
+        let warnings = vec!["Unknown pseudo class".to_string()];
+        store_printed_css(&dir, &files[0], &key, &files, &warnings);
+        for file in &files {
+            fs::remove_file(file).unwrap();
+        }
+
+        let other = printed_css_key(&["src/main.scss", "false"], "a { color: red; }");
+        assert!(printed_css(&dir, &files[0], &other, 2).is_none());
+        assert!(printed_css(&dir, &files[0], &key, 1).is_none());
+
+        let printed = printed_css(&dir, &files[0], &key, 2).unwrap();
+        assert_eq!(printed.warnings, warnings);
+        printed.restore(&files).unwrap();
+        assert_eq!(fs::read_to_string(&files[1]).unwrap(), "a{color:red}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kept_stylesheets_are_reused_until_a_dependency_changes() {
+        let dir = env::temp_dir().join(format!("packr-cache-kept-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let input = dir.join("src/main.scss");
+        fs::write(&input, "a { color: red; }\n").unwrap();
+        keep_compiled_scss();
+
+        assert!(!compile_scss(&dir, &input).unwrap().cached);
+        // Kept in memory, so the cache on disk isn't read
+        fs::remove_dir_all(dir.join("scss")).unwrap();
+        assert!(compile_scss(&dir, &input).unwrap().cached);
+
+        fs::write(&input, "a { color: blue; }\n").unwrap();
+        let compiled = compile_scss(&dir, &input).unwrap();
+        assert!(!compiled.cached);
+        assert!(compiled.css.contains("blue"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn modules_a_script_read_outside_its_directory_are_inputs() {
+        let dir = env::temp_dir().join(format!("packr-cache-inputs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("site/src")).unwrap();
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::create_dir_all(dir.join("site/.packr/cache")).unwrap();
+        let shared = dir.join("shared/format.js");
+        fs::write(&shared, "export const format = String;\n").unwrap();
+        // esbuild lists inputs relative to the working directory
+        let metafile = serde_json::json!({ "inputs": { shared.to_string_lossy(): {} } });
+        fs::write(
+            dir.join("site/.packr/cache/metafile.json"),
+            metafile.to_string(),
+        )
+        .unwrap();
+
+        let config: Config = serde_json::from_str(
+            r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js"}"#,
+        )
+        .unwrap();
+        let site = dir.join("site");
+        let files = input_files(&config, &site, &site.join(".packr.json"));
+        assert!(files.contains(&shared.canonicalize().unwrap()), "{files:?}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+// * ! ==================================================
+// * ! Child process supervision for Packr
+// * ! ==================================================
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// * Tools `tool_timeouts` accepts, named as they are passed to `output`
+pub const TOOLS: [&str; 9] = [
+    "esbuild", "eslint", "npm", "node", "bun", "deno", "curl", "cwebp", "avifenc",
+];
+
+// * How often a child with a timeout is checked for having exited
+const TIMEOUT_POLL: Duration = Duration::from_millis(20);
+
+// * `tool_timeout_secs` and `tool_timeouts` of the loaded config
+struct Timeouts {
+    default: Option<u64>,
+    overrides: BTreeMap<String, u64>,
+}
+
+static TIMEOUTS: RwLock<Timeouts> = RwLock::new(Timeouts {
+    default: None,
+    overrides: BTreeMap::new(),
+});
+
+pub fn configure(default: Option<u64>, overrides: &BTreeMap<String, u64>) {
+    if let Ok(mut timeouts) = TIMEOUTS.write() {
+        timeouts.default = default;
+        timeouts.overrides = overrides.clone();
+    }
+}
+
+// * Seconds `tool` may run for, if it's limited at all
+pub fn timeout(tool: &str) -> Option<u64> {
+    let timeouts = TIMEOUTS.read().ok()?;
+    timeouts.overrides.get(tool).copied().or(timeouts.default)
+}
+
+// * Process ids of the tools currently running, e.g. esbuild's watcher or `npx eslint`
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+// * Removes a child from `RUNNING` once it has been waited for
+struct Tracked(u32);
+
+impl Tracked {
+    fn new(child: &Child) -> Self {
+        if let Ok(mut running) = RUNNING.lock() {
+            running.push(child.id());
+        }
+        Tracked(child.id())
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Ok(mut running) = RUNNING.lock() {
+            running.retain(|&pid| pid != self.0);
+        }
+    }
+}
+
+// * Start a child in its own process group, so a signal can reach everything it starts in
+// * turn, such as the esbuild binary behind npx
+fn spawn(cmd: &mut Command) -> io::Result<Child> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    cmd.spawn()
+}
+
+// * `Command::output` for a run of `tool`, which is terminated along with Packr and killed
+// * once it exceeds its timeout
+pub fn output(tool: &str, cmd: &mut Command) -> io::Result<Output> {
+    output_from(tool, cmd, Stdio::null())
+}
+
+// * `output`, with the child reading `stdin`
+pub fn output_from(tool: &str, cmd: &mut Command, stdin: impl Into<Stdio>) -> io::Result<Output> {
+    let child = spawn(
+        cmd.stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?;
+    let _tracked = Tracked::new(&child);
+    let result = match timeout(tool) {
+        Some(secs) => wait_with_timeout(child, tool, secs),
+        None => child.wait_with_output(),
+    };
+    settle(result)
+}
+
+// * `Child::wait_with_output`, killing the child and everything it started once `secs` pass
+fn wait_with_timeout(mut child: Child, tool: &str, secs: u64) -> io::Result<Output> {
+    // The pipes are drained while waiting, so a chatty child can't block on a full one
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(
+        child
+            .stdout
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+    let stderr = drain(
+        child
+            .stderr
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(secs);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            signals::kill(&mut child);
+            let _ = child.wait();
+            return Err(timed_out(tool, secs));
+        }
+        thread::sleep(TIMEOUT_POLL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+// * The error of a run of `tool` stopped after `secs`
+pub fn timed_out(tool: &str, secs: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!(
+            "{} timed out after {} s and was stopped (raise tool_timeouts.{} or tool_timeout_secs if it needs longer)",
+            tool, secs, tool
+        ),
+    )
+}
+
+// * A long-lived child spoken to over its stdin and stdout, such as the esbuild service. It's
+// * terminated along with Packr, and killed once dropped.
+pub struct Resident {
+    pub child: Child,
+    _tracked: Tracked,
+}
+
+impl Drop for Resident {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// * Start `cmd` as a `Resident`, with piped stdin and stdout; its stderr stays Packr's
+pub fn resident(cmd: &mut Command) -> io::Result<Resident> {
+    let child = spawn(cmd.stdin(Stdio::piped()).stdout(Stdio::piped()))?;
+    let tracked = Tracked::new(&child);
+    Ok(Resident {
+        child,
+        _tracked: tracked,
+    })
+}
+
+// * `Command::status`, for a child that is terminated along with Packr. Its stdin is a pipe
+// * held open until it exits: a child outside the terminal's process group must not read the
+// * terminal, and esbuild's watcher exits on its own once the pipe closes, even when Packr
+// * was killed too abruptly to forward anything
+pub fn status(cmd: &mut Command) -> io::Result<ExitStatus> {
+    let mut child = spawn(cmd.stdin(Stdio::piped()))?;
+    let _tracked = Tracked::new(&child);
+    let _stdin = child.stdin.take();
+    settle(child.wait())
+}
+
+// * A child that exited because Packr is shutting down didn't fail on its own, so rather than
+// * reporting that, its thread waits for the shutdown to finish
+fn settle<T>(result: T) -> T {
+    if signals::shutting_down() {
+        loop {
+            std::thread::park();
+        }
+    }
+    result
+}
+
+#[cfg(unix)]
+mod signals {
+    use super::RUNNING;
+    use std::process::Child;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // * Termination signals forwarded to the children
+    const FORWARDED: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+    // * How long children get to exit before they are killed
+    const GRACE: Duration = Duration::from_secs(2);
+
+    // * How often the supervisor checks for a signal, and for exited children
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    // * Last signal received; the handler only records it, as little else is safe there
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        RECEIVED.store(signal, Ordering::SeqCst);
+    }
+
+    // * Whether any process of the group led by `pid` is still alive
+    fn group_alive(pid: u32) -> bool {
+        // SAFETY: signal 0 only checks that the process group exists
+        unsafe { libc::kill(-(pid as libc::pid_t), 0) == 0 }
+    }
+
+    // * Forward `signal` to every child's process group, wait for them to exit (their waiting
+    // * threads reap them), kill what is left after the grace period, then exit as `signal`
+    fn terminate(signal: libc::c_int) -> ! {
+        let running = RUNNING.lock().map(|r| r.clone()).unwrap_or_default();
+        for &pid in &running {
+            // SAFETY: sends a signal to a process group Packr started
+            unsafe { libc::kill(-(pid as libc::pid_t), signal) };
+        }
+
+        let deadline = Instant::now() + GRACE;
+        while running.iter().any(|&pid| group_alive(pid)) && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        for &pid in running.iter().filter(|&&pid| group_alive(pid)) {
+            // SAFETY: as above
+            unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+        }
+
+        // SAFETY: restores the default action and re-raises, so the exit status shows the signal
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+        std::process::exit(128 + signal);
+    }
+
+    pub fn shutting_down() -> bool {
+        RECEIVED.load(Ordering::SeqCst) != 0
+    }
+
+    // * Kill `child`'s whole process group, e.g. the esbuild binary behind npx as well
+    pub fn kill(child: &mut Child) {
+        // SAFETY: sends a signal to a process group Packr started
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    }
+
+    pub fn install() {
+        for signal in FORWARDED {
+            // SAFETY: the handler only stores to an atomic
+            unsafe { libc::signal(signal, on_signal as *const () as libc::sighandler_t) };
+        }
+        thread::spawn(|| loop {
+            thread::sleep(POLL_INTERVAL);
+            let signal = RECEIVED.load(Ordering::SeqCst);
+            if signal != 0 {
+                terminate(signal);
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+mod signals {
+    use std::mem;
+    use std::process::Child;
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    // * Ctrl-C reaches the children directly and ends Packr by itself
+    pub fn shutting_down() -> bool {
+        false
+    }
+
+    pub fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+
+    // * Put Packr in a job object that kills every process in it once the last handle closes,
+    // * i.e. when Packr exits however it exits. Children join the job when they start. The
+    // * handle is deliberately never closed. Console Ctrl-C already reaches the children.
+    pub fn install() {
+        // SAFETY: plain Win32 calls on a job handle owned here; failures leave things as before
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                return;
+            }
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = mem::zeroed();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let configured = SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const std::ffi::c_void,
+                mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+            if configured != 0 {
+                AssignProcessToJobObject(job, GetCurrentProcess());
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod signals {
+    use std::process::Child;
+
+    pub fn shutting_down() -> bool {
+        false
+    }
+
+    pub fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+
+    pub fn install() {}
+}
+
+// * Make sure the tools Packr starts don't outlive it when it's interrupted or killed, e.g.
+// * by Ctrl-C or a CI timeout. Called once at startup.
+pub fn install() {
+    signals::install();
+}
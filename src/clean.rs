@@ -0,0 +1,153 @@
+// * ! ==================================================
+// * ! Output directory cleaning for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::bundle;
+use crate::cache;
+use colored::*;
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Files kept in every cleaned directory, on top of `clean_keep`
+const ALWAYS_KEEP: [&str; 2] = ["**/.gitignore", "**/.gitkeep"];
+
+// * Output and destination directories written by the build
+fn output_dirs(config: &Config, config_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = bundle::outputs(config)
+        .into_iter()
+        .filter_map(|(_, output)| config_dir.join(output).parent().map(Path::to_path_buf))
+        .chain(
+            [&config.css_destination, &config.js_destination]
+                .into_iter()
+                .flatten()
+                .map(|dest| config_dir.join(dest)),
+        )
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+
+    // Nested directories are cleaned along with their parent
+    let all = dirs.clone();
+    dirs.retain(|dir| {
+        !all.iter()
+            .any(|other| other != dir && dir.starts_with(other))
+    });
+    dirs
+}
+
+// * Why a directory must not be cleaned, if it holds the project or its sources
+fn protected_reason(dir: &Path, config: &Config, config_dir: &Path) -> Option<String> {
+    let dir = dir.canonicalize().ok()?;
+    let root = config_dir.canonicalize().ok()?;
+    if root.starts_with(&dir) {
+        return Some("it contains the config file".to_string());
+    }
+
+    bundle::inputs(config)
+        .into_iter()
+        .filter_map(|(_, input)| config_dir.join(input).canonicalize().ok())
+        .find(|input| input.starts_with(&dir))
+        .map(|input| format!("it contains the input {}", input.display()))
+}
+
+// * Delete files not matching `keep` below `dir`, then any directories left empty
+fn clean_dir(
+    dir: &Path,
+    prefix: &Path,
+    keep: &[Pattern],
+    options: MatchOptions,
+) -> Result<usize, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let relative = prefix.join(entry.file_name());
+        if keep.iter().any(|p| p.matches_path_with(&relative, options)) {
+            continue;
+        }
+
+        // A symlink is removed as a link; following it could empty a directory outside the
+        // output tree
+        let is_link = entry.file_type().is_ok_and(|kind| kind.is_symlink());
+        if path.is_dir() && !is_link {
+            removed += clean_dir(&path, &relative, keep, options)?;
+            // Only succeeds once nothing kept is left inside
+            let _ = fs::remove_dir(&path);
+        } else {
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+// * Remove previously generated files from the output and destination directories
+pub fn clean_outputs(config: &Config, config_dir: &Path) -> Result<(), String> {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let keep = ALWAYS_KEEP
+        .iter()
+        .map(|p| p.to_string())
+        .chain(config.clean_keep.iter().cloned())
+        .map(|p| Pattern::new(&p).map_err(|e| format!("Invalid clean_keep pattern {}: {}", p, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let dirs: Vec<PathBuf> = output_dirs(config, config_dir)
+        .into_iter()
+        .filter(|dir| dir.is_dir())
+        .collect();
+
+    // Check every directory before deleting anything
+    for dir in &dirs {
+        if let Some(reason) = protected_reason(dir, config, config_dir) {
+            return Err(format!(
+                "Refusing to clean {} because {}",
+                dir.display(),
+                reason
+            ));
+        }
+    }
+
+    for dir in &dirs {
+        let removed = clean_dir(dir, Path::new(""), &keep, options)?;
+        log_line(format!(
+            "{} {} file(s) removed from {}",
+            "Cleaned".green().bold(),
+            removed,
+            dir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+// * Delete the build cache: compiled SCSS, ESLint results, esbuild metafiles and build state
+pub fn clean_cache(config: &Config, config_dir: &Path) -> Result<(), String> {
+    let dir = cache::dir(config, config_dir);
+    if !dir.is_dir() {
+        log_line(format!(
+            "{} no cache at {}",
+            "Cleaned".green().bold(),
+            dir.display()
+        ));
+        return Ok(());
+    }
+
+    // The directory may be shared or absolute, so only Packr's own entries are removed
+    cache::clear(&dir)?;
+    let _ = fs::remove_dir(&dir);
+    log_line(format!(
+        "{} cache at {}",
+        "Cleaned".green().bold(),
+        dir.display()
+    ));
+    Ok(())
+}
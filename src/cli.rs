@@ -0,0 +1,513 @@
+// * ! ==================================================
+// * ! Command line interface for Packr
+// * ! ==================================================
+
+use crate::build::{self, build_scripts, load_config, log_line, script_outputs};
+use crate::bundle::{self, Step};
+use crate::events::{self, Event};
+use crate::manifest::Manifest;
+use crate::{
+    analyze, build_entries, cache, children, clean, codequality, control, copy_static_assets,
+    daemon, duplicates, instance, junit, lsp, manifest_path, metrics, ndjson, prepare_build,
+    profile, report, run_build, shim, watch, workspace, write_favicons, write_html, write_manifest,
+    write_wordpress_assets, PackrError,
+};
+use colored::*;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+pub fn main() {
+    // * Entry point for Packr build process
+    // * Handles config loading, watch mode flag, and dispatches style/script builds
+
+    // * Stop the tools Packr starts along with it when it's interrupted or killed
+    children::install();
+
+    let args: Vec<String> = env::args().collect();
+
+    // * Get config path from --config flag or fallback to default
+    // * `--profile <file>` writes a Chrome tracing profile of the build
+    // * `--junit <file>` writes a JUnit XML report of the build steps and diagnostics
+    // * `--codequality <file>` writes the diagnostics as a GitLab Code Quality report
+    // * `--project <name or glob>` (repeatable) and `--changed-since <git ref>` pick workspace projects
+    let mut config_path = ".packr.json";
+    let mut profile_path = None;
+    let mut junit_path = None;
+    let mut codequality_path = None;
+    let mut project_patterns = Vec::new();
+    let mut changed_since = None;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i].as_str() == "--config" && i + 1 < args.len() {
+            config_path = &args[i + 1];
+            i += 2;
+            continue;
+        }
+        if args[i].as_str() == "--profile" && i + 1 < args.len() {
+            profile_path = Some(PathBuf::from(&args[i + 1]));
+            i += 2;
+            continue;
+        }
+        if args[i].as_str() == "--junit" && i + 1 < args.len() {
+            junit_path = Some(PathBuf::from(&args[i + 1]));
+            i += 2;
+            continue;
+        }
+        if args[i].as_str() == "--codequality" && i + 1 < args.len() {
+            codequality_path = Some(PathBuf::from(&args[i + 1]));
+            i += 2;
+            continue;
+        }
+        if args[i].as_str() == "--project" && i + 1 < args.len() {
+            project_patterns.push(args[i + 1].clone());
+            i += 2;
+            continue;
+        }
+        if args[i].as_str() == "--changed-since" && i + 1 < args.len() {
+            changed_since = Some(args[i + 1].clone());
+            i += 2;
+            continue;
+        }
+        i += 1;
+    }
+    if profile_path.is_some() {
+        profile::enable();
+    }
+
+    // * `--events-ndjson[=<file, FIFO or unix:socket>]` streams build events for editors, one
+    // * JSON object per line, to stdout without a target
+    let events_target = args.iter().find_map(|arg| {
+        arg.strip_prefix("--events-ndjson")
+            .and_then(|rest| match rest {
+                "" => Some(None),
+                rest => rest.strip_prefix('=').map(Some),
+            })
+    });
+    // * `--json` prints the result of the build and its diagnostics as JSON on stdout
+    let json = args.iter().any(|arg| arg == "--json");
+
+    // * Progress lines move to stderr, so stdout carries nothing but events or the report
+    let events_to_stdout = events_target.is_some_and(ndjson::is_stdout);
+    if json && events_to_stdout {
+        exit_on_error(Err(
+            "--json and --events-ndjson both write to stdout; give --events-ndjson a file"
+                .to_string(),
+        ));
+    }
+    if json || events_to_stdout {
+        build::log_to_stderr();
+    }
+    let _events = events_target.map(|target| {
+        ndjson::stream(target).unwrap_or_else(|e| {
+            eprintln!("\u{274C} Events failed: {e}");
+            std::process::exit(1);
+        })
+    });
+
+    // * Check if `--watch` flag is present
+    let watch_mode = args.iter().any(|arg| arg == "--watch");
+
+    // * `--force` rebuilds even when the inputs are unchanged
+    let force = args.iter().any(|arg| arg == "--force");
+
+    let reports = Reports {
+        profile: profile_path,
+        junit: junit_path,
+        codequality: codequality_path,
+        json,
+    };
+
+    // * A workspace config lists projects, each built from its own config
+    let projects = match workspace::load(config_path) {
+        Ok(projects) => projects,
+        Err(e) => {
+            exit_on_error(Err(format!("Invalid workspace: {e}")));
+            return;
+        }
+    };
+    if let Some(projects) = projects {
+        let project_only = ["clean", "cache", "analyze", "warm", "daemon", "lsp"];
+        if watch_mode
+            || args
+                .iter()
+                .skip(1)
+                .any(|arg| project_only.contains(&arg.as_str()))
+        {
+            exit_on_error(Err(
+                "A workspace only runs builds; run other commands with a project's --config"
+                    .to_string(),
+            ));
+        }
+        let reproducible = args.iter().any(|arg| arg == "--reproducible");
+        let deploy = args.iter().any(|arg| arg == "--deploy");
+        let workspace_dir = Path::new(config_path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let projects = match workspace::select(
+            projects,
+            &project_patterns,
+            changed_since.as_deref(),
+            workspace_dir,
+        ) {
+            Ok(projects) => projects,
+            Err(e) => {
+                exit_on_error(Err(format!("Project selection failed: {e}")));
+                return;
+            }
+        };
+        if projects.is_empty() {
+            log_line("\u{2705} Build (workspace) skipped, no project changed.");
+            return;
+        }
+        let result = with_reports(&reports, workspace_dir, || {
+            workspace::build(&projects, force, |config| {
+                config.reproducible |= reproducible;
+                // Projects without a deploy target are built without deploying
+                if let Some(ref mut target) = config.deploy {
+                    target.on_build |= deploy;
+                }
+            })
+        });
+        match result {
+            Ok(_) => log_line("\u{2705} Build (workspace) complete."),
+            Err(e) => {
+                eprintln!("\u{274C} {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if !project_patterns.is_empty() || changed_since.is_some() {
+        exit_on_error(Err(
+            "--project and --changed-since pick projects of a workspace config".to_string(),
+        ));
+    }
+
+    // * `packr lsp` speaks the Language Server Protocol on stdout, so nothing else may print there
+    let lsp_mode = args.iter().skip(1).any(|arg| arg == "lsp");
+    if lsp_mode {
+        build::log_to_stderr();
+    }
+
+    // * Load configuration from file
+    let loaded = {
+        let _span = profile::span("config", "load config");
+        load_config(config_path)
+    };
+    let (mut config, config_dir) = match loaded {
+        Ok(result) => result,
+        Err(e) => {
+            let message = format!("Failed to load configuration [{}]: {e}", e.code());
+            exit_on_error(Err(message));
+            return;
+        }
+    };
+
+    // * `--reproducible` makes two builds of the same inputs byte-identical
+    if args.iter().any(|arg| arg == "--reproducible") {
+        config.reproducible = true;
+    }
+
+    // * `--deploy` publishes the outputs of the build to the `deploy` target
+    if args.iter().any(|arg| arg == "--deploy") {
+        match config.deploy {
+            Some(ref mut deploy) => deploy.on_build = true,
+            None => {
+                exit_on_error(Err(
+                    "--deploy needs a deploy section in the config".to_string()
+                ));
+                return;
+            }
+        }
+    }
+
+    // * `packr clean` removes generated files instead of building; `--cache` wipes the cache
+    if args.iter().skip(1).any(|arg| arg == "clean") {
+        let result = if args.iter().any(|arg| arg == "--cache") {
+            clean::clean_cache(&config, &config_dir)
+        } else {
+            clean::clean_outputs(&config, &config_dir)
+        };
+        if let Err(e) = result {
+            eprintln!("\u{274C} Clean failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // * `packr cache export <file>` / `packr cache import <file>` move the cache between machines
+    if let Some(i) = args.iter().skip(1).position(|arg| arg == "cache") {
+        let (action, file) = (args.get(i + 2), args.get(i + 3));
+        let result = match (action.map(String::as_str), file) {
+            (Some("export"), Some(file)) => cache::export(&config, &config_dir, Path::new(file))
+                .map(|count| {
+                    log_line(format!(
+                        "{} {} file(s) to {}",
+                        "Exported".green().bold(),
+                        count,
+                        file
+                    ))
+                }),
+            (Some("import"), Some(file)) => cache::import(&config, &config_dir, Path::new(file))
+                .map(|count| {
+                    log_line(format!(
+                        "{} {} file(s) from {}",
+                        "Imported".green().bold(),
+                        count,
+                        file
+                    ))
+                }),
+            _ => Err(
+                "Usage: packr cache export <file.tar.gz> | packr cache import <file.tar.gz>"
+                    .to_string(),
+            ),
+        };
+        if let Err(e) = result {
+            eprintln!("\u{274C} Cache failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // * `packr analyze [--html <file>]` shows what the minified bundle is made of
+    if args.iter().skip(1).any(|arg| arg == "analyze") {
+        let html = args
+            .iter()
+            .position(|arg| arg == "--html")
+            .and_then(|i| args.get(i + 1))
+            .map(PathBuf::from);
+        let result = build::analysis_metafile(&config, &config_dir).and_then(|metafile| {
+            analyze::analyze(&metafile, html.as_deref())?;
+            duplicates::report(&metafile).map(|_| ())
+        });
+        if let Err(e) = result {
+            eprintln!("\u{274C} Analyze failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // * `packr lsp` serves live Sass, esbuild and ESLint diagnostics to an editor over stdio
+    if lsp_mode {
+        if let Err(e) = lsp::serve(&config, &config_dir) {
+            eprintln!("\u{274C} LSP failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // * `packr warm` primes the build cache without writing outputs
+    if args.iter().skip(1).any(|arg| arg == "warm") {
+        let result = cache::validate(&config, &config_dir, Path::new(config_path))
+            .and_then(|()| build::warm_caches(&config, &config_dir));
+        if let Err(e) = result {
+            eprintln!("\u{274C} Warm failed: {e}");
+            std::process::exit(1);
+        }
+        log_line("\u{2705} Caches warmed.");
+        return;
+    }
+
+    // * `packr daemon` stays resident and serves build requests over a local socket; `--stop` ends it
+    if args.iter().skip(1).any(|arg| arg == "daemon") {
+        let result = if args.iter().any(|arg| arg == "--stop") {
+            daemon::stop(&config, &config_dir)
+        } else {
+            let trigger = control::Trigger::Build(config_path.to_string());
+            metrics::start(config.metrics.as_ref())
+                .map_err(|e| format!("Metrics failed: {e}"))
+                .and_then(|metrics| {
+                    control::start(config.control.as_ref(), trigger)
+                        .map(|control| (metrics, control))
+                        .map_err(|e| format!("Control API failed: {e}"))
+                })
+                .and_then(|_servers| daemon::serve(config_path, &config, &config_dir))
+        };
+        if let Err(e) = result {
+            eprintln!("\u{274C} Daemon failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // * A daemon's build streams no events here, so event streams and reports build locally, as
+    // * do deployments, which the daemon's config doesn't ask for
+    let local = watch_mode
+        || events_target.is_some()
+        || reports.junit.is_some()
+        || reports.codequality.is_some()
+        || reports.json
+        || args.iter().any(|arg| arg == "--deploy");
+
+    // * `--daemon` hands the build to a running daemon, falling back to building here
+    if args.iter().any(|arg| arg == "--daemon") && !local {
+        match daemon::request(&config, &config_dir, force) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => log_line(format!(
+                "{} {}, building locally",
+                "Daemon".yellow().bold(),
+                e
+            )),
+        }
+    }
+
+    // * A build already running in this project may be a daemon's, which then builds this too
+    if !local && config.instance_lock != "off" && instance::is_held(&config_dir) {
+        match daemon::request(&config, &config_dir, force) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(_) => {}
+        }
+    }
+
+    if !watch_mode {
+        let result = with_reports(&reports, &config_dir, || {
+            run_build(&config, &config_dir, Path::new(config_path), force)
+        });
+        match result {
+            Ok(true) => log_line("\u{2705} Build (single) complete."),
+            Ok(false) => log_line("\u{2705} Build (single) up to date."),
+            Err(e) => {
+                eprintln!("\u{274C} {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // * The session writes outputs until it's stopped, so it holds the lock throughout
+    let _lock = instance::acquire(&config, &config_dir, "watch").unwrap_or_else(|e| {
+        eprintln!("\u{274C} Lock failed: {e}");
+        std::process::exit(1);
+    });
+
+    // * Report the session's rebuilds for monitoring
+    let _metrics = metrics::start(config.metrics.as_ref()).unwrap_or_else(|e| {
+        eprintln!("\u{274C} Metrics failed: {e}");
+        std::process::exit(1);
+    });
+
+    // * Let other tools request rebuilds and read their results
+    let _control =
+        control::start(config.control.as_ref(), control::Trigger::Watch).unwrap_or_else(|e| {
+            eprintln!("\u{274C} Control API failed: {e}");
+            std::process::exit(1);
+        });
+
+    // * Watch sessions always build, and only compare the tool versions; the lockfile is
+    // * written by full builds
+    exit_on_error(prepare_build(&config, &config_dir, Path::new(config_path), true).map(drop));
+
+    let mut manifest = Manifest::new(&config, watch_mode, manifest_path(&config, &config_dir));
+
+    // * Bundle scripts rebuild through one resident esbuild service rather than a process each
+    shim::start_service();
+
+    // * Compile SCSS to CSS and build the scripts of bundles; esbuild's watcher below builds
+    // * `js_input` itself
+    let tasks: Vec<_> = bundle::tasks(&config)
+        .into_iter()
+        .filter(|task| task.step == Step::Styles || task.bundle.is_some())
+        .collect();
+    exit_on_error(build_entries(&config, &tasks, &config_dir, &mut manifest));
+
+    // * The esbuild watcher blocks, so static assets are copied ahead of it in watch mode
+    exit_on_error(copy_static_assets(&config, &config_dir, &mut manifest));
+    exit_on_error(write_favicons(&config, &config_dir));
+    exit_on_error(write_html(&config, &config_dir, &manifest));
+    exit_on_error(write_wordpress_assets(&config, &config_dir, &manifest));
+
+    // * esbuild never returns in watch mode, so its (unhashed) outputs are listed up front
+    for output in script_outputs(&config, &config_dir) {
+        exit_on_error(
+            manifest
+                .record(&output, &output)
+                .map_err(|e| format!("Manifest failed: {e}")),
+        );
+    }
+    exit_on_error(write_manifest(&manifest));
+
+    // * Bundle `js_input` in esbuild's watcher, rebuilding the other entries beside it
+    let stop = AtomicBool::new(false);
+    let scripts = thread::scope(|scope| {
+        let path = manifest_path(&config, &config_dir);
+        scope.spawn(|| watch::watch_styles(&config, &config_dir, path, &stop));
+        let result = build_scripts(&config, &config_dir, true, &mut manifest);
+        stop.store(true, Ordering::Relaxed);
+        result
+    });
+    exit_on_error(scripts.map_err(|e| format!("Scripts failed [{}]: {e}", e.code())));
+
+    // * Build complete message
+    log_line("\u{2705} Build (watch) complete.");
+}
+
+// * Reports of a build asked for on the command line
+struct Reports {
+    profile: Option<PathBuf>,
+    junit: Option<PathBuf>,
+    codequality: Option<PathBuf>,
+    json: bool,
+}
+
+// * Run a single build, or the builds of a workspace, and write the reports of what it did
+fn with_reports(
+    reports: &Reports,
+    config_dir: &Path,
+    build: impl FnOnce() -> Result<bool, PackrError>,
+) -> Result<bool, PackrError> {
+    let junit = reports.junit.as_ref().map(|_| junit::record());
+    let codequality = reports.codequality.as_ref().map(|_| codequality::record());
+    let json = reports.json.then(report::record);
+    let result = build();
+    if let (Some(codequality), Some(path)) = (codequality, &reports.codequality) {
+        match codequality.write(path) {
+            Ok(()) => log_line(format!(
+                "{} written to {}",
+                "Code Quality report".green().bold(),
+                path.display()
+            )),
+            Err(e) => eprintln!("\u{274C} Code Quality report failed: {e}"),
+        }
+    }
+    if let (Some(junit), Some(path)) = (junit, &reports.junit) {
+        match junit.write(path, config_dir, &result) {
+            Ok(()) => log_line(format!(
+                "{} written to {}",
+                "JUnit report".green().bold(),
+                path.display()
+            )),
+            Err(e) => eprintln!("\u{274C} JUnit report failed: {e}"),
+        }
+    }
+    // The profile is written for failed builds too, as those are often the slow ones
+    if let Some(ref path) = reports.profile {
+        match profile::write(path) {
+            Ok(()) => log_line(format!(
+                "{} written to {}",
+                "Profile".green().bold(),
+                path.display()
+            )),
+            Err(e) => eprintln!("\u{274C} Profile failed: {e}"),
+        }
+    }
+    if let Some(json) = json {
+        json.print(&result);
+    }
+    result
+}
+
+fn exit_on_error(result: Result<(), impl std::fmt::Display>) {
+    if let Err(e) = result {
+        eprintln!("\u{274C} {e}");
+        events::emit(|| Event::Error {
+            message: e.to_string(),
+        });
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,114 @@
+// * ! ==================================================
+// * ! GitLab Code Quality reports for Packr
+// * ! ==================================================
+
+use crate::cache;
+use crate::diagnostics::Severity;
+use crate::events::{self, Event, Subscription};
+use crate::manifest::relative_to;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// * A located Sass, CSS, ESLint or esbuild message: `(severity, source, message, file, line)`
+type Finding = (Severity, String, String, PathBuf, usize);
+
+// * Collects the diagnostics of a build for `write`
+pub struct Recorder {
+    findings: Arc<Mutex<Vec<Finding>>>,
+    _subscription: Subscription,
+}
+
+pub fn record() -> Recorder {
+    let findings = Arc::new(Mutex::new(Vec::new()));
+    let recorder = findings.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        // GitLab places every issue in a file, so messages without one are left out
+        if let Event::Diagnostic {
+            severity,
+            source,
+            message,
+            file: Some(file),
+            line,
+            ..
+        } = event
+        {
+            if let Ok(mut findings) = recorder.lock() {
+                findings.push((
+                    *severity,
+                    source.clone(),
+                    message.clone(),
+                    file.clone(),
+                    line.unwrap_or(1),
+                ));
+            }
+        }
+    }));
+    Recorder {
+        findings,
+        _subscription: subscription,
+    }
+}
+
+impl Recorder {
+    // * Write the diagnostics of the build as a GitLab Code Quality report to `path`. Paths are
+    // * relative to the working directory, which is the repository root in a GitLab job.
+    pub fn write(self, path: &Path) -> Result<(), String> {
+        let findings = match self.findings.lock() {
+            Ok(mut findings) => std::mem::take(&mut *findings),
+            Err(_) => return Err("Build results are unavailable".to_string()),
+        };
+        let root = Path::new(".")
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve the working directory: {}", e))?;
+
+        // Occurrences of the same issue in a file so far, which keep their fingerprints apart
+        let mut seen: BTreeMap<(String, String, String), usize> = BTreeMap::new();
+        let mut issues = Vec::new();
+        for (severity, source, message, file, line) in findings {
+            let file = file
+                .canonicalize()
+                .map(|file| relative_to(&file, &root))
+                .unwrap_or_else(|_| relative_to(&file, Path::new(".")));
+            // ESLint messages start with the rule that reported them
+            let (check_name, description) = match message.split_once(" - ") {
+                Some((rule, text)) if source == "ESLint" => {
+                    (format!("eslint/{}", rule), text.to_string())
+                }
+                _ => (source.to_lowercase(), message),
+            };
+            let key = (check_name.clone(), file.clone(), description.clone());
+            let occurrence = seen.entry(key).or_default();
+            *occurrence += 1;
+            // The line is left out, so an issue keeps its fingerprint when code above it moves
+            let fingerprint = cache::digest(
+                format!("{}\0{}\0{}\0{}", check_name, file, description, occurrence).as_bytes(),
+            );
+            issues.push(serde_json::json!({
+                "type": "issue",
+                "check_name": check_name,
+                "description": description,
+                "categories": [match severity {
+                    Severity::Error => "Bug Risk",
+                    Severity::Warning => "Style",
+                }],
+                "severity": match severity {
+                    Severity::Error => "major",
+                    Severity::Warning => "minor",
+                },
+                "fingerprint": fingerprint,
+                "location": { "path": file, "lines": { "begin": line } },
+            }));
+        }
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(&issues)
+            .map_err(|e| format!("Failed to serialize the report: {}", e))?;
+        fs::write(path, json + "\n")
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,77 @@
+// * ! ==================================================
+// * ! Precompressed .gz and .br outputs for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::retry;
+use colored::*;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// * Supported values for `compress`
+pub const FORMATS: [&str; 2] = ["gzip", "brotli"];
+
+// * Compressed bytes of `content` in the given format
+fn encode(format: &str, content: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match format {
+        "gzip" => {
+            let mut encoder = GzEncoder::new(&mut out, flate2::Compression::best());
+            encoder
+                .write_all(content)
+                .and_then(|_| encoder.finish().map(|_| ()))
+                .map_err(|e| format!("Failed to gzip: {}", e))?;
+        }
+        _ => {
+            let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 11, 22);
+            writer
+                .write_all(content)
+                .map_err(|e| format!("Failed to brotli-compress: {}", e))?;
+        }
+    }
+    Ok(out)
+}
+
+// * `app.js` becomes `app.js.gz` / `app.js.br`
+pub fn compressed_path(path: &Path, format: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(if format == "gzip" { ".gz" } else { ".br" });
+    PathBuf::from(name)
+}
+
+fn kilobytes(bytes: usize) -> String {
+    format!("{:.1} KB", bytes as f64 / 1024.0)
+}
+
+// * Write every configured compressed variant next to each file and report the sizes
+pub fn compress_files(formats: &[String], files: &[PathBuf]) -> Result<(), String> {
+    if formats.is_empty() {
+        return Ok(());
+    }
+
+    for file in files {
+        let content =
+            fs::read(file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+
+        let mut sizes = Vec::new();
+        for format in formats {
+            let compressed = encode(format, &content)?;
+            let target = compressed_path(file, format);
+            retry::write(&target, &compressed)
+                .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+            sizes.push(format!("{} {}", format, kilobytes(compressed.len())));
+        }
+
+        log_line(format!(
+            "{} {} ({}): {}",
+            "Compressed".green().bold(),
+            file.display(),
+            kilobytes(content.len()),
+            sizes.join(", ")
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+// * ! ==================================================
+// * ! HTTP control API for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::copy::expand_env;
+use crate::daemon;
+use crate::diagnostics::Severity;
+use crate::events::{self, Event, Subscription};
+use crate::watch;
+use colored::*;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// * Where a daemon or watch session takes build requests over HTTP
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct ControlApi {
+    // Address to listen on, e.g. `127.0.0.1:7357`
+    pub listen: String,
+    // Bearer token every request must carry; may use `[env:NAME]`
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+// * The token is a secret, so the logged config only says whether there is one
+impl fmt::Debug for ControlApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ControlApi")
+            .field("listen", &self.listen)
+            .field("token", &self.token.is_some())
+            .finish()
+    }
+}
+
+// * Likewise for the resolved config in the build record
+impl serde::Serialize for ControlApi {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = serde_json::json!({
+            "listen": self.listen,
+            "token": self.token.is_some(),
+        });
+        serde::Serialize::serialize(&value, serializer)
+    }
+}
+
+// * Whether `address` (`host:port`) only accepts connections from this machine
+pub fn is_loopback(address: &str) -> bool {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+// * How long a client may take to send its request
+pub(crate) const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// * What `POST /build` does in the session serving the API
+pub enum Trigger {
+    // Run a full build of the config at this path, as the daemon does
+    Build(String),
+    // Have the watch session rebuild its stylesheets and bundle scripts
+    Watch,
+}
+
+// * The builds of the session, as far as the API reports them
+#[derive(Default)]
+struct State {
+    building: bool,
+    finished: u64,
+    last: Option<serde_json::Value>,
+    // Diagnostics of the running build, then of the last finished one
+    errors: Vec<serde_json::Value>,
+    warnings: Vec<serde_json::Value>,
+    last_errors: Vec<serde_json::Value>,
+    last_warnings: Vec<serde_json::Value>,
+}
+
+impl State {
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::BuildStarted { .. } => {
+                self.building = true;
+                self.errors.clear();
+                self.warnings.clear();
+            }
+            Event::BuildFinished {
+                kind,
+                success,
+                duration,
+            } => {
+                self.building = false;
+                self.finished += 1;
+                self.last = Some(serde_json::json!({
+                    "kind": kind,
+                    "status": if *success { "success" } else { "failure" },
+                    "finished_at": SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs()),
+                    "duration_ms": duration.as_millis() as u64,
+                }));
+                self.last_errors = std::mem::take(&mut self.errors);
+                self.last_warnings = std::mem::take(&mut self.warnings);
+            }
+            Event::Diagnostic {
+                severity,
+                source,
+                message,
+                file,
+                line,
+                column,
+            } => {
+                let diagnostic = serde_json::json!({
+                    "source": source,
+                    "message": message,
+                    "file": file,
+                    "line": line,
+                    "column": column,
+                });
+                match severity {
+                    Severity::Error => self.errors.push(diagnostic),
+                    Severity::Warning => self.warnings.push(diagnostic),
+                }
+            }
+            Event::Error { message } => {
+                self.errors.push(serde_json::json!({ "message": message }));
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Shared {
+    trigger: Trigger,
+    token: Option<String>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+// * Keeps the API's view of the builds up to date; dropping it stops recording
+pub struct Server {
+    _subscription: Subscription,
+}
+
+// * Serve the control API of a daemon or watch session, if the config has one
+pub fn start(api: Option<&ControlApi>, trigger: Trigger) -> Result<Option<Server>, String> {
+    let Some(api) = api else {
+        return Ok(None);
+    };
+    let listener = TcpListener::bind(&api.listen)
+        .map_err(|e| format!("Failed to listen on {}: {}", api.listen, e))?;
+    let shared = Arc::new(Shared {
+        trigger,
+        token: api.token.as_deref().map(expand_env),
+        state: Mutex::new(State::default()),
+        changed: Condvar::new(),
+    });
+
+    let recorder = shared.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        if let Ok(mut state) = recorder.state.lock() {
+            state.record(event);
+        }
+        recorder.changed.notify_all();
+    }));
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Builds take a while, so a status request doesn't wait behind one
+            let shared = shared.clone();
+            thread::spawn(move || {
+                // A client that stops mid-request only loses its own response
+                let _ = serve(stream, &shared);
+            });
+        }
+    });
+    log_line(format!(
+        "{} listening on http://{}",
+        "Control API".cyan().bold(),
+        api.listen
+    ));
+    Ok(Some(Server {
+        _subscription: subscription,
+    }))
+}
+
+// * Run or request a build and wait for its result
+fn build(shared: &Shared, force: bool) -> (&'static str, serde_json::Value) {
+    match shared.trigger {
+        Trigger::Build(ref config_path) => match daemon::build(config_path, force) {
+            Ok(true) => ("200 OK", serde_json::json!({ "status": "success" })),
+            Ok(false) => ("200 OK", serde_json::json!({ "status": "up-to-date" })),
+            Err(e) => (
+                "500 Internal Server Error",
+                serde_json::json!({ "status": "failure", "error": e.to_string(), "code": e.code() }),
+            ),
+        },
+        Trigger::Watch => {
+            let Ok(state) = shared.state.lock() else {
+                return (
+                    "500 Internal Server Error",
+                    serde_json::json!({ "status": "failure", "error": "Build state is unavailable" }),
+                );
+            };
+            let before = state.finished;
+            watch::request_rebuild();
+            let state = shared
+                .changed
+                .wait_while(state, |state| state.finished == before)
+                .unwrap_or_else(|e| e.into_inner());
+            let failed = state
+                .last
+                .as_ref()
+                .is_some_and(|last| last["status"] == "failure");
+            if !failed {
+                return ("200 OK", serde_json::json!({ "status": "success" }));
+            }
+            // The build's own error, rather than one of the diagnostics that led to it
+            let error = state
+                .last_errors
+                .iter()
+                .rev()
+                .find(|error| error.get("source").is_none())
+                .map(|error| error["message"].clone());
+            (
+                "500 Internal Server Error",
+                serde_json::json!({ "status": "failure", "error": error }),
+            )
+        }
+    }
+}
+
+// * Answer one HTTP request: `POST /build`, `GET /status` or `GET /last-errors`
+fn serve(stream: TcpStream, shared: &Shared) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut authorization = None;
+    let mut cross_origin = false;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("origin") {
+                cross_origin = true;
+            }
+        }
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, target) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let authorized = match shared.token {
+        Some(ref token) => authorization.as_deref() == Some(format!("Bearer {}", token).as_str()),
+        None => true,
+    };
+
+    let error = |message: &str| serde_json::json!({ "error": message });
+    let (status, body) = if cross_origin {
+        // Web pages may send requests to localhost too, but only other tools are meant to
+        (
+            "403 Forbidden",
+            error("Requests from web pages are refused"),
+        )
+    } else if !authorized {
+        ("401 Unauthorized", error("Missing or wrong bearer token"))
+    } else {
+        match (method, path) {
+            ("POST", "/build") => {
+                let force = query
+                    .split('&')
+                    .any(|pair| matches!(pair, "force" | "force=1" | "force=true"));
+                build(shared, force)
+            }
+            ("GET", "/status") => {
+                let state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+                let body = serde_json::json!({
+                    "packr_version": env!("CARGO_PKG_VERSION"),
+                    "mode": match shared.trigger {
+                        Trigger::Build(_) => "daemon",
+                        Trigger::Watch => "watch",
+                    },
+                    "state": if state.building { "building" } else { "idle" },
+                    "builds": state.finished,
+                    "last_build": state.last,
+                });
+                ("200 OK", body)
+            }
+            ("GET", "/last-errors") => {
+                let state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+                let body = serde_json::json!({
+                    "errors": state.last_errors,
+                    "warnings": state.last_warnings,
+                });
+                ("200 OK", body)
+            }
+            (_, "/build" | "/status" | "/last-errors") => {
+                ("405 Method Not Allowed", error("Method not allowed"))
+            }
+            _ => ("404 Not Found", error("Not found")),
+        }
+    };
+
+    let body = body.to_string() + "\n";
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    (&stream).write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loopback_addresses_count_as_local() {
+        for address in [
+            "127.0.0.1:7357",
+            "127.8.0.1:80",
+            "localhost:7357",
+            "[::1]:7357",
+        ] {
+            assert!(is_loopback(address), "{address}");
+        }
+        for address in [
+            "0.0.0.0:7357",
+            "192.168.1.20:7357",
+            "[::]:7357",
+            "example.com:80",
+        ] {
+            assert!(!is_loopback(address), "{address}");
+        }
+    }
+}
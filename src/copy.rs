@@ -0,0 +1,243 @@
+// * ! ==================================================
+// * ! Static asset copy tasks for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::html;
+use crate::profile;
+use crate::reproducible;
+use crate::retry;
+use crate::svg;
+use colored::*;
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// * A `copy` entry: files under `from` matching `glob` are mirrored into `to`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CopyTask {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub glob: Option<String>,
+    // Substitutions applied to text files on the way
+    #[serde(default)]
+    pub replace: Vec<Replacement>,
+}
+
+// * Replace every `search` with `replace`, which may use `[version]`, `[timestamp]` and `[env:NAME]`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Replacement {
+    pub search: String,
+    pub replace: String,
+}
+
+// * Values for the `[version]` and `[timestamp]` placeholders, read once per build
+struct TemplateValues {
+    version: String,
+    timestamp: String,
+}
+
+impl TemplateValues {
+    fn read(config: &Config, config_dir: &Path) -> Self {
+        let version = fs::read_to_string(config_dir.join("package.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|package| package["version"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        let timestamp = if config.reproducible {
+            reproducible::source_date_epoch().to_string()
+        } else {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs().to_string())
+                .unwrap_or_default()
+        };
+        Self { version, timestamp }
+    }
+
+    // * Expand the placeholders in a replacement value
+    fn expand(&self, value: &str) -> String {
+        expand_env(
+            &value
+                .replace("[version]", &self.version)
+                .replace("[timestamp]", &self.timestamp),
+        )
+    }
+}
+
+// * Replace every `[env:NAME]` with the value of that variable, or nothing when it's unset
+pub fn expand_env(value: &str) -> String {
+    let mut out = value.to_string();
+    let mut from = 0;
+    while let Some(start) = out[from..].find("[env:").map(|i| from + i) {
+        let Some(len) = out[start..].find(']') else {
+            break;
+        };
+        let value = std::env::var(&out[start + 5..start + len]).unwrap_or_default();
+        out.replace_range(start..=start + len, &value);
+        // Substituted values are not expanded again
+        from = start + value.len();
+    }
+    out
+}
+
+// * Collect files below a directory, as paths relative to it. Symlinked directories are
+// * followed, except into a directory already being walked, which would loop forever.
+fn walk(
+    dir: &Path,
+    prefix: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    ancestors.push(dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()));
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let relative = prefix.join(entry.file_name());
+        if path.is_dir() {
+            let looped = path
+                .canonicalize()
+                .is_ok_and(|target| ancestors.contains(&target));
+            if !looped {
+                walk(&path, &relative, ancestors, files)?;
+            }
+        } else {
+            files.push(relative);
+        }
+    }
+
+    ancestors.pop();
+    Ok(())
+}
+
+// * Every file below `dir`, as paths relative to it
+pub fn files_below(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    walk(dir, Path::new(""), &mut Vec::new(), &mut files)?;
+    Ok(files)
+}
+
+// * Copy a single file, applying replacements and minifying SVG and HTML files when enabled
+fn copy_file(
+    source: &Path,
+    target: &Path,
+    config: &Config,
+    replacements: &[(String, String)],
+) -> Result<(), String> {
+    let minify: Option<fn(&str) -> String> = match source.extension() {
+        Some(ext) if ext == "svg" && config.minify_svg => Some(svg::minify),
+        Some(ext) if (ext == "html" || ext == "htm") && config.minify_html => Some(html::minify),
+        _ => None,
+    };
+
+    if minify.is_some() || !replacements.is_empty() {
+        let content =
+            fs::read(source).map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+
+        // Binary files (not UTF-8, or containing NUL bytes) are copied unchanged
+        if let Ok(mut text) = String::from_utf8(content) {
+            if !text.contains('\0') {
+                for (search, replace) in replacements {
+                    text = text.replace(search.as_str(), replace);
+                }
+                if let Some(minify) = minify {
+                    text = minify(&text);
+                }
+                return retry::write(target, text)
+                    .map_err(|e| format!("Failed to write {}: {}", target.display(), e));
+            }
+        }
+    }
+
+    retry::copy(source, target)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to copy {}: {}", source.display(), e))
+}
+
+// * Copy one task's files, returning the files written
+fn run_task(
+    task: &CopyTask,
+    config_dir: &Path,
+    config: &Config,
+    values: &TemplateValues,
+) -> Result<Vec<PathBuf>, String> {
+    let from = config_dir.join(&task.from);
+    let to = config_dir.join(&task.to);
+    let replacements: Vec<(String, String)> = task
+        .replace
+        .iter()
+        .map(|r| (r.search.clone(), values.expand(&r.replace)))
+        .collect();
+
+    if !from.exists() {
+        return Err(format!("Copy source not found: {}", from.display()));
+    }
+
+    // A single file is copied into `to` as-is
+    if from.is_file() {
+        let name = from
+            .file_name()
+            .ok_or_else(|| format!("Invalid copy source: {}", from.display()))?;
+        fs::create_dir_all(&to).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+        let target = to.join(name);
+        copy_file(&from, &target, config, &replacements)?;
+        return Ok(vec![target]);
+    }
+
+    let pattern = Pattern::new(task.glob.as_deref().unwrap_or("**/*"))
+        .map_err(|e| format!("Invalid copy glob for {}: {}", task.from, e))?;
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+
+    let files = files_below(&from)?;
+
+    let mut copied = Vec::new();
+    for relative in files
+        .iter()
+        .filter(|f| pattern.matches_path_with(f, options))
+    {
+        let target = to.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        copy_file(&from.join(relative), &target, config, &replacements)?;
+        copied.push(target);
+    }
+
+    Ok(copied)
+}
+
+// * Run every configured copy task in order, returning every file written
+pub fn run_copy_tasks(config: &Config, config_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let values = TemplateValues::read(config, config_dir);
+    let mut written = Vec::new();
+    for task in &config.copy {
+        let _span = profile::span("copy", format!("copy {}", task.from));
+        let copied = run_task(task, config_dir, config, &values)?;
+        match copied.len() {
+            0 => log_line(format!(
+                "{} no files in {} matched {}",
+                "Copy".yellow().bold(),
+                task.from,
+                task.glob.as_deref().unwrap_or("**/*")
+            )),
+            copied => log_line(format!(
+                "{} {} file(s) from {} to {}",
+                "Copied".green().bold(),
+                copied,
+                task.from,
+                task.to
+            )),
+        }
+        written.extend(copied);
+    }
+
+    Ok(written)
+}
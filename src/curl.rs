@@ -0,0 +1,98 @@
+// * ! ==================================================
+// * ! HTTP requests for Packr
+// * ! ==================================================
+
+use crate::children;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// * A request sent with curl. The URL, headers and body go through files in the cache rather
+// * than arguments, so tokens don't show up in the process list.
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub url: String,
+    // `Name: value` lines
+    pub headers: Vec<String>,
+    pub json: Option<&'a serde_json::Value>,
+    // Multipart fields: text, or the contents of a file
+    pub form: Vec<(String, Field)>,
+    pub timeout_secs: u64,
+}
+
+pub enum Field {
+    Text(String),
+    File(PathBuf),
+}
+
+// * curl config-file string, quoted so spaces, quotes and backslashes survive
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// * Send `request`, using `name` for its files in `cache_dir`, and return the HTTP status and
+// * response body. Only a request that got no response at all is an error, with curl's message,
+// * which leaves out the URL as it may have come from a secret.
+pub fn send(cache_dir: &Path, name: &str, request: Request) -> Result<(u16, String), String> {
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+    let curlrc_path = cache_dir.join(format!("{}.curlrc", name));
+    let body_path = cache_dir.join(format!("{}.body", name));
+    let response_path = cache_dir.join(format!("{}.response", name));
+
+    let mut curlrc = format!(
+        "request = {}\nurl = {}\noutput = {}\n",
+        quoted(request.method),
+        quoted(&request.url),
+        quoted(&response_path.display().to_string())
+    );
+    for header in &request.headers {
+        curlrc.push_str(&format!("header = {}\n", quoted(header)));
+    }
+    if let Some(json) = request.json {
+        fs::write(&body_path, json.to_string())
+            .map_err(|e| format!("Failed to write {}: {}", body_path.display(), e))?;
+        curlrc.push_str(&format!(
+            "header = \"Content-Type: application/json\"\ndata-binary = {}\n",
+            quoted(&format!("@{}", body_path.display()))
+        ));
+    }
+    for (field, value) in &request.form {
+        // `form-string` keeps a value starting with `@` or `<` from naming a file
+        let line = match value {
+            Field::Text(text) => format!("form-string = {}\n", quoted(&format!("{field}={text}"))),
+            Field::File(path) => {
+                format!(
+                    "form = {}\n",
+                    quoted(&format!("{field}=@{}", path.display()))
+                )
+            }
+        };
+        curlrc.push_str(&line);
+    }
+    fs::write(&curlrc_path, curlrc)
+        .map_err(|e| format!("Failed to write {}: {}", curlrc_path.display(), e))?;
+
+    let output = children::output(
+        "curl",
+        Command::new("curl")
+            .args(["-sS", "-w", "%{http_code}", "--max-time"])
+            .arg(request.timeout_secs.to_string())
+            .arg("-K")
+            .arg(&curlrc_path),
+    );
+    let response = fs::read_to_string(&response_path).unwrap_or_default();
+    for path in [&curlrc_path, &body_path, &response_path] {
+        let _ = fs::remove_file(path);
+    }
+
+    let output = output.map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    let status = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .unwrap_or_default();
+    Ok((status, response))
+}
@@ -0,0 +1,409 @@
+// * ! ==================================================
+// * ! Background build daemon for Packr
+// * ! ==================================================
+
+use crate::build::{load_config, log_line, Config};
+use crate::cache;
+use crate::control::READ_TIMEOUT;
+use crate::error::PackrError;
+use crate::shim;
+use colored::*;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// * Socket file the daemon listens on, inside the cache directory
+const SOCKET_FILE: &str = "daemon.sock";
+
+// * Replies sent back for a build request, one per line
+const REPLY_BUILT: &str = "ok";
+const REPLY_UP_TO_DATE: &str = "up-to-date";
+const REPLY_ERROR: &str = "error: ";
+
+fn socket_path(config: &Config, config_dir: &Path) -> PathBuf {
+    cache::dir(config, config_dir).join(SOCKET_FILE)
+}
+
+// * The config the daemon last built with, with the text it was loaded from
+struct Loaded {
+    text: String,
+    config: Config,
+    config_dir: PathBuf,
+}
+
+// * Held while the daemon builds, as requests arrive on several connections and over the
+// * control API at once
+static LOADED: Mutex<Option<Loaded>> = Mutex::new(None);
+
+// * Build with the config of the last request, loading it again only when the file changed,
+// * so config edits apply without a restart
+pub fn build(config_path: &str, force: bool) -> Result<bool, PackrError> {
+    // A build that panicked left nothing behind that the next one depends on
+    let mut kept = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    let load_failed = |e| PackrError::context("Failed to load configuration", e);
+    let text = fs::read_to_string(config_path)
+        .map_err(|e| load_failed(PackrError::io("Failed to read config file", config_path, e)))?;
+    let loaded = match kept.take() {
+        Some(loaded) if loaded.text == text => kept.insert(loaded),
+        _ => {
+            let (config, config_dir) = load_config(config_path).map_err(load_failed)?;
+            kept.insert(Loaded {
+                text,
+                config,
+                config_dir,
+            })
+        }
+    };
+    crate::run_build(
+        &loaded.config,
+        &loaded.config_dir,
+        Path::new(config_path),
+        force,
+    )
+}
+
+// * Answer one socket request with the result of its build
+fn handle(request: &str, config_path: &str) -> String {
+    let force = match request {
+        "build" => false,
+        "build --force" => true,
+        other => return format!("{}Unknown request: {}", REPLY_ERROR, other),
+    };
+    match build(config_path, force) {
+        Ok(true) => REPLY_BUILT.to_string(),
+        Ok(false) => REPLY_UP_TO_DATE.to_string(),
+        Err(e) => format!("{}{}", REPLY_ERROR, e.to_string().replace('\n', " ")),
+    }
+}
+
+// * The request line a client sent; `None` when it sent none in time
+fn read_request(client: impl Read) -> Option<String> {
+    let mut request = String::new();
+    BufReader::new(client).read_line(&mut request).ok()?;
+    let request = request.trim();
+    (!request.is_empty()).then(|| request.to_string())
+}
+
+// * Answer a client's request; `true` when it asked the daemon to stop
+fn reply(request: &str, mut client: impl Write, config_path: &str) -> bool {
+    let reply = if request == "stop" {
+        REPLY_BUILT.to_string()
+    } else {
+        log_line(format!("{} {}", "Daemon".cyan().bold(), request));
+        handle(request, config_path)
+    };
+    // The client may have gone away; the build result is in the daemon's output anyway
+    let _ = client.write_all(format!("{}\n", reply).as_bytes());
+    request == "stop"
+}
+
+// * Send a request and read the reply line
+fn exchange(mut client: impl Write, reply: impl Read, request: &str) -> Result<String, String> {
+    client
+        .write_all(format!("{}\n", request).as_bytes())
+        .map_err(|e| format!("Failed to send request to the daemon: {}", e))?;
+
+    let mut line = String::new();
+    BufReader::new(reply)
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read the daemon's reply: {}", e))?;
+    match line.trim() {
+        "" => Err("The daemon closed the connection without replying".to_string()),
+        line => Ok(line.to_string()),
+    }
+}
+
+// * Wait for a build still running when the daemon stops
+fn finish_building() {
+    drop(LOADED.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // * Listen for build requests until a `stop` request arrives. Each client is answered on a
+    // * thread of its own, so one that never sends its request doesn't hold up the rest.
+    pub fn serve(config_path: &str, config: &Config, config_dir: &Path) -> Result<(), String> {
+        let path = socket_path(config, config_dir);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(format!(
+                    "A daemon is already listening on {}",
+                    path.display()
+                ));
+            }
+            // Left behind by a daemon that was killed
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+        log_line(format!(
+            "{} listening on {}",
+            "Daemon".cyan().bold(),
+            path.display()
+        ));
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        for stream in listener.incoming() {
+            if stopping.load(Ordering::SeqCst) {
+                break;
+            }
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let (stopping, path) = (stopping.clone(), path.clone());
+            let config_path = config_path.to_string();
+            thread::spawn(move || {
+                let request = stream
+                    .set_read_timeout(Some(READ_TIMEOUT))
+                    .ok()
+                    .and_then(|()| read_request(&stream));
+                let Some(request) = request else {
+                    return;
+                };
+                // Builds may take longer than a request may take to arrive
+                let _ = stream.set_read_timeout(None);
+                if reply(&request, &stream, &config_path) {
+                    stopping.store(true, Ordering::SeqCst);
+                    // Wake the accept loop so it sees the request
+                    let _ = UnixStream::connect(&path);
+                }
+            });
+        }
+
+        finish_building();
+        fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+    }
+
+    // * Send one request to a running daemon and return its reply
+    pub fn send(config: &Config, config_dir: &Path, request: &str) -> Result<String, String> {
+        let path = socket_path(config, config_dir);
+        let stream = UnixStream::connect(&path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound | ErrorKind::ConnectionRefused => {
+                format!("No daemon is listening on {}", path.display())
+            }
+            _ => format!("Failed to connect to {}: {}", path.display(), e),
+        })?;
+        exchange(&stream, &stream, request)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, ErrorKind};
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::{
+        ERROR_ACCESS_DENIED, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+    use windows_sys::Win32::System::IO::CancelIoEx;
+
+    // * How often a client retries while every instance of the pipe is taken
+    const BUSY_ATTEMPTS: u32 = 20;
+    const BUSY_WAIT: Duration = Duration::from_millis(50);
+
+    // * Named pipes live outside the file system, so the pipe is named after the socket path
+    // * of the project, which keeps one daemon per cache directory
+    fn pipe_name(config: &Config, config_dir: &Path) -> String {
+        let path = socket_path(config, config_dir);
+        let digest = cache::digest(path.to_string_lossy().as_bytes());
+        format!(r"\\.\pipe\packr-{}", &digest[..32])
+    }
+
+    // * A new instance of the pipe for the next client; creating the first fails while
+    // * another daemon owns the name
+    fn create(name: &str, first: bool) -> io::Result<File> {
+        let wide: Vec<u16> = OsStr::new(name).encode_wide().chain(Some(0)).collect();
+        let mut mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        // SAFETY: `wide` is a NUL-terminated name that outlives the call
+        let handle = unsafe {
+            CreateNamedPipeW(
+                wide.as_ptr(),
+                mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle was just created and nothing else owns it
+        Ok(unsafe { File::from_raw_handle(handle) })
+    }
+
+    // * Wait for a client on `pipe`; a client may also have connected since it was created
+    fn connect(pipe: &File) -> bool {
+        // SAFETY: the handle stays open for the call, which isn't overlapped
+        let connected =
+            unsafe { ConnectNamedPipe(pipe.as_raw_handle() as HANDLE, ptr::null_mut()) };
+        connected != 0
+            || io::Error::last_os_error().raw_os_error() == Some(ERROR_PIPE_CONNECTED as i32)
+    }
+
+    // * The request of a client, cancelling the read once `READ_TIMEOUT` passes, as reads
+    // * from a pipe can't time out themselves
+    fn read_in_time(client: &File) -> Option<String> {
+        let reading = Arc::new(Mutex::new(true));
+        let watchdog = reading.clone();
+        let handle = client.as_raw_handle() as usize;
+        thread::spawn(move || {
+            thread::sleep(READ_TIMEOUT);
+            let reading = watchdog.lock().unwrap_or_else(|e| e.into_inner());
+            if *reading {
+                // SAFETY: the client thread closes the handle only after it stopped reading,
+                // which it records under this lock
+                unsafe { CancelIoEx(handle as HANDLE, ptr::null()) };
+            }
+        });
+        let request = read_request(client);
+        *reading.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        request
+    }
+
+    // * Listen for build requests on a named pipe until a `stop` request arrives, answering
+    // * each client on a thread of its own
+    pub fn serve(config_path: &str, config: &Config, config_dir: &Path) -> Result<(), String> {
+        let name = pipe_name(config, config_dir);
+        let listen_failed = |e: io::Error| format!("Failed to listen on {}: {}", name, e);
+        let mut pipe = create(&name, true).map_err(|e| {
+            if e.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+                format!("A daemon is already listening on {}", name)
+            } else {
+                listen_failed(e)
+            }
+        })?;
+        log_line(format!("{} listening on {}", "Daemon".cyan().bold(), name));
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        loop {
+            let connected = connect(&pipe);
+            if stopping.load(Ordering::SeqCst) {
+                break;
+            }
+            let client = std::mem::replace(&mut pipe, create(&name, false).map_err(listen_failed)?);
+            if !connected {
+                continue;
+            }
+            let (stopping, name) = (stopping.clone(), name.clone());
+            let config_path = config_path.to_string();
+            thread::spawn(move || {
+                let Some(request) = read_in_time(&client) else {
+                    return;
+                };
+                if reply(&request, &client, &config_path) {
+                    stopping.store(true, Ordering::SeqCst);
+                    // Wake the accept loop so it sees the request
+                    let _ = OpenOptions::new().read(true).write(true).open(&name);
+                }
+            });
+        }
+
+        finish_building();
+        Ok(())
+    }
+
+    // * Send one request to a running daemon and return its reply
+    pub fn send(config: &Config, config_dir: &Path, request: &str) -> Result<String, String> {
+        let name = pipe_name(config, config_dir);
+        let mut attempts = 0;
+        let pipe = loop {
+            match OpenOptions::new().read(true).write(true).open(&name) {
+                Ok(pipe) => break pipe,
+                // Taken until the daemon has created the next instance
+                Err(e)
+                    if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32)
+                        && attempts < BUSY_ATTEMPTS =>
+                {
+                    attempts += 1;
+                    thread::sleep(BUSY_WAIT);
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    return Err(format!("No daemon is listening on {}", name))
+                }
+                Err(e) => return Err(format!("Failed to connect to {}: {}", name, e)),
+            }
+        };
+        exchange(&pipe, &pipe, request)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use super::*;
+
+    const UNSUPPORTED: &str =
+        "The build daemon needs Unix domain sockets or named pipes, which this platform lacks";
+
+    pub fn serve(_config_path: &str, _config: &Config, _config_dir: &Path) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn send(_config: &Config, _config_dir: &Path, _request: &str) -> Result<String, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+// * Serve build requests until stopped, keeping compiled stylesheets in memory and building
+// * scripts in one resident esbuild service between builds
+pub fn serve(config_path: &str, config: &Config, config_dir: &Path) -> Result<(), String> {
+    cache::keep_compiled_scss();
+    shim::start_service();
+    imp::serve(config_path, config, config_dir)
+}
+
+// * Ask a running daemon to build; `Err` when none is reachable, `Ok(false)` when the build failed
+pub fn request(config: &Config, config_dir: &Path, force: bool) -> Result<bool, String> {
+    let request = if force { "build --force" } else { "build" };
+    let reply = imp::send(config, config_dir, request)?;
+
+    if reply == REPLY_BUILT {
+        log_line("\u{2705} Build (daemon) complete.");
+        Ok(true)
+    } else if reply == REPLY_UP_TO_DATE {
+        log_line("\u{2705} Build (daemon) up to date.");
+        Ok(true)
+    } else {
+        let error = reply.strip_prefix(REPLY_ERROR).unwrap_or(&reply);
+        eprintln!("\u{274C} {error}");
+        Ok(false)
+    }
+}
+
+// * Ask a running daemon to shut down
+pub fn stop(config: &Config, config_dir: &Path) -> Result<(), String> {
+    imp::send(config, config_dir, "stop").map(|_| ())
+}
@@ -0,0 +1,451 @@
+// * ! ==================================================
+// * ! Output deployment for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, normalize_path, script_emitted_files, Config};
+use crate::cache;
+use crate::children;
+use crate::copy::files_below;
+use crate::inline::ASSET_TYPES;
+use crate::manifest::{relative_to, Manifest};
+use colored::*;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// * Where outputs can be published to
+pub const PROVIDERS: [&str; 4] = ["s3", "gcs", "rsync", "sftp"];
+
+// * Above this many changed paths, the CDN is told to invalidate everything under the target
+const MAX_INVALIDATION_PATHS: usize = 100;
+
+// * Publish the output directory after a build, e.g. to a bucket behind a CDN
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Deploy {
+    pub provider: String,
+    // `s3://bucket/prefix`, `gs://bucket/prefix`, `[user@]host:/path` for sftp, or any
+    // destination rsync takes
+    pub target: String,
+    // Directory whose files are published, the JavaScript output directory by default
+    #[serde(default)]
+    pub dir: Option<String>,
+    #[serde(default)]
+    pub cache_control: CacheControl,
+    // CloudFront distribution (s3) or URL map (gcs) whose cache is invalidated afterwards
+    #[serde(default)]
+    pub invalidate: Option<String>,
+    // Deploy after every full build rather than only with `--deploy`
+    #[serde(default)]
+    pub on_build: bool,
+}
+
+// * `Cache-Control` of the uploaded files; content-hashed names never change content, so
+// * they can be cached for good
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CacheControl {
+    #[serde(default = "default_hashed")]
+    pub hashed: String,
+    #[serde(default = "default_unhashed")]
+    pub default: String,
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        Self {
+            hashed: default_hashed(),
+            default: default_unhashed(),
+        }
+    }
+}
+
+fn default_hashed() -> String {
+    "public, max-age=31536000, immutable".to_string()
+}
+
+fn default_unhashed() -> String {
+    "public, max-age=300, must-revalidate".to_string()
+}
+
+// * Content types of text files, on top of the asset types esbuild emits
+const TEXT_TYPES: [(&str, &str); 13] = [
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("cjs", "text/javascript; charset=utf-8"),
+    ("map", "application/json"),
+    ("json", "application/json"),
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("xml", "application/xml"),
+    ("webmanifest", "application/manifest+json"),
+    ("wasm", "application/wasm"),
+    ("php", "text/plain; charset=utf-8"),
+];
+
+// * `(Content-Type, Content-Encoding)` of a file; a precompressed `.gz` or `.br` file has the
+// * type of the file it compresses
+fn content_type(path: &Path) -> (&'static str, Option<&'static str>) {
+    let extension = |path: &Path| {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default()
+    };
+    let (path, encoding) = match extension(path).as_str() {
+        "gz" => (path.with_extension(""), Some("gzip")),
+        "br" => (path.with_extension(""), Some("br")),
+        _ => (path.to_path_buf(), None),
+    };
+    let ext = extension(&path);
+    let content_type = TEXT_TYPES
+        .iter()
+        .chain(ASSET_TYPES.iter())
+        .find(|(e, _)| *e == ext)
+        .map_or("application/octet-stream", |(_, mime)| *mime);
+    (content_type, encoding)
+}
+
+// * The file a source map or precompressed copy belongs to, and is as stable as:
+// * `app.js.map.gz` and `app.js.map` both belong to `app.js`
+fn variant_of(path: &Path) -> PathBuf {
+    let name = path.to_string_lossy();
+    let name = [".gz", ".br"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(&name);
+    PathBuf::from(name.strip_suffix(".map").unwrap_or(name))
+}
+
+// * A file to publish
+struct Upload {
+    local: PathBuf,
+    // Path below the target, with forward slashes
+    key: String,
+    hashed: bool,
+}
+
+// * Files whose names carry a content hash: entry outputs renamed by `hash_outputs` or
+// * `entry_names`, and esbuild's chunks and assets when their name patterns have a `[hash]`
+fn hashed_files(config: &Config, config_dir: &Path, manifest: &Manifest) -> BTreeSet<PathBuf> {
+    let key = |path: &Path| normalize_path(&config_dir.join(path));
+    let mut hashed: BTreeSet<PathBuf> = manifest
+        .outputs()
+        .into_iter()
+        .filter(|(logical, actual)| logical != actual)
+        .map(|(_, actual)| key(&actual))
+        .collect();
+
+    let entries: BTreeSet<PathBuf> = manifest
+        .outputs()
+        .into_iter()
+        .flat_map(|(logical, actual)| [key(&logical), key(&actual)])
+        .collect();
+    for file in script_emitted_files(config, config_dir) {
+        let file = key(&file);
+        let ext = file.extension().unwrap_or_default().to_string_lossy();
+        if entries.contains(&file) || ext == "map" {
+            continue;
+        }
+        // esbuild names both `[name]-[hash]` unless configured otherwise
+        let pattern = if ["js", "mjs", "cjs", "css"].contains(&ext.as_ref()) {
+            config.chunk_names.as_deref()
+        } else {
+            config.asset_names.as_deref()
+        };
+        if pattern.unwrap_or("[name]-[hash]").contains("[hash]") {
+            hashed.insert(file);
+        }
+    }
+    hashed
+}
+
+// * Upload the files below `deploy.dir` to the target, with their content type and a cache
+// * lifetime by whether their name is hashed, then invalidate the CDN's copies of the files
+// * whose names don't change between builds. Does nothing unless `--deploy` or `on_build`
+// * asked for it.
+pub fn run(config: &Config, config_dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let Some(ref deploy) = config.deploy else {
+        return Ok(());
+    };
+    if !deploy.on_build {
+        return Ok(());
+    }
+
+    let dir = match deploy.dir {
+        Some(ref dir) => config_dir.join(dir),
+        None => {
+            let output = config_dir.join(&config.js_output);
+            output.parent().unwrap_or(config_dir).to_path_buf()
+        }
+    };
+    let hashed = hashed_files(config, config_dir, manifest);
+    let mut uploads: Vec<Upload> = files_below(&dir)?
+        .into_iter()
+        .map(|relative| {
+            let local = dir.join(&relative);
+            Upload {
+                hashed: hashed.contains(&normalize_path(&variant_of(&local))),
+                key: relative_to(&relative, Path::new("")),
+                local,
+            }
+        })
+        .collect();
+    // Unhashed files such as the manifest name the hashed ones, so they go last
+    uploads.sort_by(|a, b| b.hashed.cmp(&a.hashed).then_with(|| a.key.cmp(&b.key)));
+    if uploads.is_empty() {
+        return Ok(());
+    }
+
+    match deploy.provider.as_str() {
+        "s3" => bucket(deploy, &uploads, s3_command)?,
+        "gcs" => bucket(deploy, &uploads, gcs_command)?,
+        "rsync" => rsync(deploy, &dir)?,
+        _ => sftp(deploy, &uploads, &cache::dir(config, config_dir))?,
+    }
+    log_line(format!(
+        "{} {} file(s) to {}",
+        "Deployed".green().bold(),
+        uploads.len(),
+        deploy.target
+    ));
+
+    if let Some(ref id) = deploy.invalidate {
+        let changed: Vec<&str> = uploads
+            .iter()
+            .filter(|upload| !upload.hashed)
+            .map(|upload| upload.key.as_str())
+            .collect();
+        if !changed.is_empty() {
+            invalidate(deploy, id, &changed)?;
+        }
+    }
+    Ok(())
+}
+
+// * Run a deploy tool, failing with its output when it does
+fn run_tool(tool: &str, cmd: &mut Command, what: &str) -> Result<(), String> {
+    let output = children::output(tool, cmd).map_err(|e| format!("Failed to run {tool}: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let message = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+        return Err(format!("{} failed: {}", what, message.trim()));
+    }
+    Ok(())
+}
+
+// * `s3://bucket/prefix` and `gs://bucket/prefix` with `key` appended
+fn object_url(target: &str, key: &str) -> String {
+    format!("{}/{}", target.trim_end_matches('/'), key)
+}
+
+fn s3_command(upload: &Upload, url: &str, cache_control: &str) -> (&'static str, Command) {
+    let (content_type, encoding) = content_type(&upload.local);
+    let mut cmd = Command::new("aws");
+    cmd.args(["s3", "cp", "--only-show-errors"])
+        .arg(&upload.local)
+        .arg(url)
+        .args([
+            "--content-type",
+            content_type,
+            "--cache-control",
+            cache_control,
+        ]);
+    if let Some(encoding) = encoding {
+        cmd.args(["--content-encoding", encoding]);
+    }
+    ("aws", cmd)
+}
+
+fn gcs_command(upload: &Upload, url: &str, cache_control: &str) -> (&'static str, Command) {
+    let (content_type, encoding) = content_type(&upload.local);
+    let mut cmd = Command::new("gsutil");
+    cmd.arg("-q")
+        .arg("-h")
+        .arg(format!("Content-Type:{}", content_type))
+        .arg("-h")
+        .arg(format!("Cache-Control:{}", cache_control));
+    if let Some(encoding) = encoding {
+        cmd.arg("-h").arg(format!("Content-Encoding:{}", encoding));
+    }
+    cmd.arg("cp").arg(&upload.local).arg(url);
+    ("gsutil", cmd)
+}
+
+// * Upload each file to an object store, one tool run per file so each gets its own headers
+fn bucket(
+    deploy: &Deploy,
+    uploads: &[Upload],
+    command: fn(&Upload, &str, &str) -> (&'static str, Command),
+) -> Result<(), String> {
+    for upload in uploads {
+        let cache_control = if upload.hashed {
+            &deploy.cache_control.hashed
+        } else {
+            &deploy.cache_control.default
+        };
+        let url = object_url(&deploy.target, &upload.key);
+        let (tool, mut cmd) = command(upload, &url, cache_control);
+        run_tool(tool, &mut cmd, &format!("Uploading {}", upload.key))?;
+    }
+    Ok(())
+}
+
+// * Mirror the directory with rsync; the server picks content types and cache headers.
+// * Nothing is deleted on the other end, as pages cached elsewhere may still use old files.
+fn rsync(deploy: &Deploy, dir: &Path) -> Result<(), String> {
+    let mut cmd = Command::new("rsync");
+    cmd.arg("-rtz")
+        .arg(format!("{}/", dir.display()))
+        .arg(format!("{}/", deploy.target.trim_end_matches('/')));
+    run_tool("rsync", &mut cmd, "rsync")
+}
+
+// * sftp batch-file argument, quoted so spaces survive
+fn sftp_quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// * Upload the files with an sftp batch, creating the directories they need
+fn sftp(deploy: &Deploy, uploads: &[Upload], cache_dir: &Path) -> Result<(), String> {
+    let Some((host, remote)) = deploy.target.split_once(':') else {
+        return Err(format!("{} is not [user@]host:/path", deploy.target));
+    };
+    let remote = remote.trim_end_matches('/');
+    let mut dirs = BTreeSet::new();
+    for upload in uploads {
+        let mut dir = Path::new(&upload.key).parent();
+        while let Some(parent) = dir.filter(|d| !d.as_os_str().is_empty()) {
+            dirs.insert(relative_to(parent, Path::new("")));
+            dir = parent.parent();
+        }
+    }
+
+    // `-` lets a directory that already exists pass
+    let mut batch = String::new();
+    for dir in &dirs {
+        batch.push_str(&format!(
+            "-mkdir {}\n",
+            sftp_quoted(&format!("{}/{}", remote, dir))
+        ));
+    }
+    for upload in uploads {
+        batch.push_str(&format!(
+            "put {} {}\n",
+            sftp_quoted(&upload.local.to_string_lossy()),
+            sftp_quoted(&format!("{}/{}", remote, upload.key))
+        ));
+    }
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+    let batch_path = cache_dir.join("deploy.sftp");
+    fs::write(&batch_path, batch)
+        .map_err(|e| format!("Failed to write {}: {}", batch_path.display(), e))?;
+
+    let result = run_tool(
+        "sftp",
+        Command::new("sftp").arg("-b").arg(&batch_path).arg(host),
+        "sftp",
+    );
+    let _ = fs::remove_file(&batch_path);
+    result
+}
+
+// * Ask the CDN in front of the bucket to drop its copies of `keys`
+fn invalidate(deploy: &Deploy, id: &str, keys: &[&str]) -> Result<(), String> {
+    // Object keys are served from the root of the CDN, below the target's prefix
+    let prefix = deploy
+        .target
+        .split_once("://")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map(|(_, prefix)| prefix.trim_matches('/'))
+        .unwrap_or_default();
+    let path = |key: &str| {
+        if prefix.is_empty() {
+            format!("/{}", key)
+        } else {
+            format!("/{}/{}", prefix, key)
+        }
+    };
+    let paths: Vec<String> = if keys.len() > MAX_INVALIDATION_PATHS {
+        vec![path("*")]
+    } else {
+        keys.iter().map(|key| path(key)).collect()
+    };
+
+    if deploy.provider == "s3" {
+        let mut cmd = Command::new("aws");
+        cmd.args([
+            "cloudfront",
+            "create-invalidation",
+            "--distribution-id",
+            id,
+            "--paths",
+        ])
+        .args(&paths);
+        run_tool("aws", &mut cmd, "CloudFront invalidation")?;
+    } else {
+        for path in &paths {
+            let mut cmd = Command::new("gcloud");
+            cmd.args(["compute", "url-maps", "invalidate-cdn-cache", id, "--async"])
+                .arg(format!("--path={}", path));
+            run_tool("gcloud", &mut cmd, "Cloud CDN invalidation")?;
+        }
+    }
+    log_line(format!(
+        "{} {} path(s) on {}",
+        "Invalidated".green().bold(),
+        paths.len(),
+        id
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_type_follows_the_compressed_file() {
+        assert_eq!(
+            content_type(Path::new("dist/app.js")),
+            ("text/javascript; charset=utf-8", None)
+        );
+        assert_eq!(
+            content_type(Path::new("dist/app.CSS.gz")),
+            ("text/css; charset=utf-8", Some("gzip"))
+        );
+        assert_eq!(
+            content_type(Path::new("dist/app.js.map.br")),
+            ("application/json", Some("br"))
+        );
+        assert_eq!(
+            content_type(Path::new("dist/LICENSE")),
+            ("application/octet-stream", None)
+        );
+    }
+
+    #[test]
+    fn variants_belong_to_their_file() {
+        for variant in ["app.js.map.gz", "app.js.map", "app.js.br", "app.js"] {
+            assert_eq!(variant_of(Path::new(variant)), Path::new("app.js"));
+        }
+        // Only one suffix of each kind is stripped
+        assert_eq!(variant_of(Path::new("data.gz.gz")), Path::new("data.gz"));
+        assert_eq!(variant_of(Path::new("a.map.map")), Path::new("a.map"));
+    }
+
+    #[test]
+    fn object_urls_join_with_one_slash() {
+        assert_eq!(object_url("s3://bucket/", "app.js"), "s3://bucket/app.js");
+        assert_eq!(
+            object_url("gs://bucket/prefix", "css/app.css"),
+            "gs://bucket/prefix/css/app.css"
+        );
+    }
+}
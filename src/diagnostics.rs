@@ -0,0 +1,306 @@
+// * ! ==================================================
+// * ! esbuild diagnostics parsing for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use colored::*;
+use std::fs;
+
+/// Severity of a diagnostic reported by esbuild, ESLint, Sass or lightningcss
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// * A single esbuild error or warning with its source location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    // * `file:line:column` when the message has a location
+    pub fn location(&self) -> Option<String> {
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => Some(format!("{}:{}:{}", file, line, column)),
+            (Some(file), _, _) => Some(file.clone()),
+            _ => None,
+        }
+    }
+
+    // * Render the message in packr's log style, with a code frame when possible
+    pub fn render(&self) -> String {
+        let label = match self.severity {
+            Severity::Error => "esbuild error".red().bold(),
+            Severity::Warning => "esbuild warning".yellow().bold(),
+        };
+
+        let mut out = match self.location() {
+            Some(location) => format!("{} {}: {}", label, location, self.message),
+            None => format!("{} {}", label, self.message),
+        };
+
+        if let (Some(file), Some(line), Some(column)) = (&self.file, self.line, self.column) {
+            if let Some(frame) = code_frame(file, line, column) {
+                out.push('\n');
+                out.push_str(&frame);
+            }
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("\n  {} {}", "note:".dimmed(), note));
+        }
+
+        out
+    }
+}
+
+// * The last `file:line:column` in a message from a tool that only gives text, such as
+// * grass's `Undefined variable ... ./src/main.scss:2:10`
+pub fn find_location(text: &str) -> Option<(String, usize, usize)> {
+    text.split_whitespace().rev().find_map(|token| {
+        let token = token
+            .trim_start_matches(['(', '\''])
+            .trim_end_matches([')', ',', ';', '.', '\'']);
+        let mut parts = token.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next().filter(|file| !file.is_empty())?;
+        Some((file.to_string(), line, column))
+    })
+}
+
+// * A `file:line:column: text` note, or just the text when it has no location
+fn esbuild_note(note: &serde_json::Value) -> String {
+    let text = note["text"].as_str().unwrap_or_default();
+    let location = &note["location"];
+    match location["file"].as_str() {
+        Some(file) => format!(
+            "{}:{}:{}: {}",
+            file,
+            location["line"].as_u64().unwrap_or(1),
+            location["column"].as_u64().unwrap_or(0),
+            text
+        ),
+        None => text.to_string(),
+    }
+}
+
+// * One of esbuild's message objects, as its API reports them: `text`, a `location` with a
+// * 1-based `line` and 0-based `column`, and `notes`
+fn esbuild_message(severity: Severity, message: &serde_json::Value) -> Diagnostic {
+    let location = &message["location"];
+    let position = |key: &str| location[key].as_u64().map(|value| value as usize);
+    let mut text = message["text"].as_str().unwrap_or_default().to_string();
+    // Messages raised by plugins are attributed the way esbuild's own log does
+    if let Some(plugin) = message["pluginName"]
+        .as_str()
+        .filter(|name| !name.is_empty())
+    {
+        text = format!("[plugin {}] {}", plugin, text);
+    }
+    Diagnostic {
+        severity,
+        message: text,
+        file: location["file"].as_str().map(str::to_string),
+        line: position("line"),
+        column: position("column"),
+        notes: message["notes"]
+            .as_array()
+            .map(|notes| notes.iter().map(esbuild_note).collect())
+            .unwrap_or_default(),
+    }
+}
+
+// * Parse the `{"errors": [...], "warnings": [...]}` messages of an esbuild build
+pub fn parse_esbuild_messages(json: &str) -> Result<Vec<Diagnostic>, String> {
+    let messages: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse esbuild messages: {}", e))?;
+    let list = |key: &str, severity: Severity| {
+        messages[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(move |message| esbuild_message(severity, message))
+    };
+    Ok(list("errors", Severity::Error)
+        .chain(list("warnings", Severity::Warning))
+        .collect())
+}
+
+// * Split an esbuild header line like `✘ [ERROR] message` into severity and text
+fn parse_header(line: &str) -> Option<(Severity, String)> {
+    let (_, rest) = line.split_once(' ')?;
+    if let Some(message) = rest.strip_prefix("[ERROR] ") {
+        return Some((Severity::Error, message.to_string()));
+    }
+    if let Some(message) = rest.strip_prefix("[WARNING] ") {
+        return Some((Severity::Warning, message.to_string()));
+    }
+    None
+}
+
+// * Parse a `    file:line:column:` location line
+fn parse_location(line: &str) -> Option<(String, usize, usize)> {
+    let trimmed = line.strip_prefix("    ")?.strip_suffix(':')?;
+    if trimmed.starts_with(' ') {
+        return None;
+    }
+
+    let mut parts = trimmed.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line_number = parts.next()?.parse().ok()?;
+    let file = parts.next()?.to_string();
+    Some((file, line_number, column))
+}
+
+// * Parse esbuild's text log output (as printed with `--color=false`), all a bare esbuild
+// * binary reports; the API behind the shim gives messages to `parse_esbuild_messages` instead
+pub fn parse_esbuild_log(log: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut note = String::new();
+
+    let flush_note = |diagnostic: Option<&mut Diagnostic>, note: &mut String| {
+        if let Some(d) = diagnostic {
+            if !note.is_empty() {
+                d.notes.push(std::mem::take(note));
+            }
+        }
+        note.clear();
+    };
+
+    for line in log.lines() {
+        if let Some((severity, message)) = parse_header(line) {
+            flush_note(diagnostics.last_mut(), &mut note);
+            diagnostics.push(Diagnostic {
+                severity,
+                message,
+                file: None,
+                line: None,
+                column: None,
+                notes: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(current) = diagnostics.last_mut() else {
+            continue;
+        };
+
+        if let Some((file, line_number, column)) = parse_location(line) {
+            // Only the first location belongs to the message itself
+            if current.file.is_none() && current.notes.is_empty() && note.is_empty() {
+                current.file = Some(file);
+                current.line = Some(line_number);
+                current.column = Some(column);
+            }
+            continue;
+        }
+
+        // Notes are indented two spaces; deeper lines are esbuild's own code frames
+        if line.starts_with("  ") && !line.starts_with("   ") {
+            if !note.is_empty() {
+                note.push(' ');
+            }
+            note.push_str(line.trim());
+        } else if line.trim().is_empty() {
+            flush_note(Some(current), &mut note);
+        }
+    }
+
+    flush_note(diagnostics.last_mut(), &mut note);
+    diagnostics
+}
+
+// * Build a two-line code frame with a caret under the reported column
+pub fn code_frame(file: &str, line: usize, column: usize) -> Option<String> {
+    let source = fs::read_to_string(file).ok()?;
+    let lines: Vec<&str> = source.lines().collect();
+    let index = line.checked_sub(1)?;
+    let target = lines.get(index)?;
+
+    let width = line.to_string().len();
+    let mut frame = String::new();
+
+    if index > 0 {
+        frame.push_str(&format!(
+            "  {:>width$} | {}\n",
+            line - 1,
+            lines[index - 1],
+            width = width
+        ));
+    }
+
+    frame.push_str(&format!("  {:>width$} | {}\n", line, target, width = width));
+
+    // Keep tabs in the caret line so it lines up with the source
+    let padding: String = target
+        .chars()
+        .take(column)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    frame.push_str(&format!(
+        "  {:>width$} | {}{}",
+        "",
+        padding,
+        "^".red().bold(),
+        width = width
+    ));
+
+    Some(frame)
+}
+
+// * Collected esbuild messages across the builds of one run
+#[derive(Default)]
+pub struct EsbuildSummary {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl EsbuildSummary {
+    // * Add messages, skipping ones already reported by another build variant
+    pub fn extend(&mut self, diagnostics: Vec<Diagnostic>) {
+        for diagnostic in diagnostics {
+            if !self.diagnostics.contains(&diagnostic) {
+                self.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    pub fn display(&self) {
+        let warnings = self.count(Severity::Warning);
+        if warnings == 0 {
+            return;
+        }
+
+        log_line("\nesbuild Warning Summary:");
+        log_line("=======================");
+
+        for diagnostic in self
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+        {
+            log_line(format!(
+                "  {} - {}",
+                diagnostic
+                    .location()
+                    .unwrap_or_else(|| "<unknown>".to_string()),
+                diagnostic.message
+            ));
+        }
+        log_line(format!("\nTotal warnings: {}", warnings));
+    }
+}
@@ -0,0 +1,169 @@
+// * ! ==================================================
+// * ! Duplicate dependency detection for Packr bundles
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::licenses::package_root;
+use colored::*;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Module graph of one esbuild build, as recorded in its metafile
+struct Graph {
+    // Modules that ended up in an output
+    bundled: Vec<String>,
+    // How each module was first reached from an entry point
+    parents: HashMap<String, Option<String>>,
+    // Breadth-first visit order, so earlier modules have shorter chains
+    order: HashMap<String, usize>,
+}
+
+impl Graph {
+    fn read(metafile: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(metafile)
+            .map_err(|e| format!("Failed to read esbuild metafile: {}", e))?;
+        let json: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse esbuild metafile: {}", e))?;
+
+        let mut bundled = Vec::new();
+        let mut queue = VecDeque::new();
+        let mut parents = HashMap::new();
+        for output in json["outputs"]
+            .as_object()
+            .into_iter()
+            .flat_map(|o| o.values())
+        {
+            for input in output["inputs"]
+                .as_object()
+                .into_iter()
+                .flat_map(|i| i.keys())
+            {
+                if !bundled.contains(input) {
+                    bundled.push(input.clone());
+                }
+            }
+            if let Some(entry) = output["entryPoint"].as_str() {
+                if parents.insert(entry.to_string(), None).is_none() {
+                    queue.push_back(entry.to_string());
+                }
+            }
+        }
+
+        let mut order = HashMap::new();
+        while let Some(module) = queue.pop_front() {
+            order.insert(module.clone(), order.len());
+            let imports = json["inputs"][module.as_str()]["imports"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|import| import["path"].as_str());
+            for import in imports {
+                if !parents.contains_key(import) {
+                    parents.insert(import.to_string(), Some(module.clone()));
+                    queue.push_back(import.to_string());
+                }
+            }
+        }
+
+        Ok(Self {
+            bundled,
+            parents,
+            order,
+        })
+    }
+
+    // * Import chain from an entry point to `module`, e.g. `src/main.js → node_modules/a/index.js`
+    fn chain(&self, module: &str) -> String {
+        let mut chain = vec![module.to_string()];
+        let mut current = module;
+        while let Some(Some(parent)) = self.parents.get(current) {
+            // Guards against a malformed graph looping back on itself
+            if chain.contains(parent) {
+                break;
+            }
+            chain.push(parent.clone());
+            current = parent;
+        }
+        chain.reverse();
+        chain.join(" \u{2192} ")
+    }
+
+    // * The bundled module below `root` that is reached first
+    fn first_below(&self, root: &Path) -> Option<&String> {
+        self.bundled
+            .iter()
+            .filter(|module| Path::new(module).starts_with(root))
+            .min_by_key(|module| self.order.get(*module).copied().unwrap_or(usize::MAX))
+    }
+}
+
+fn package_version(root: &Path) -> String {
+    fs::read_to_string(root.join("package.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|package| package["version"].as_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown version".to_string())
+}
+
+// * Warn about packages bundled from several install locations and files bundled under several
+// * paths, with the import chain that pulled in each copy; returns how many were found
+pub fn report(metafile: &Path) -> Result<usize, String> {
+    let graph = Graph::read(metafile)?;
+
+    // Install locations per package name, e.g. `node_modules/a/node_modules/lodash`
+    let mut packages: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for module in &graph.bundled {
+        let Some(root) = package_root(module) else {
+            continue;
+        };
+        let name = root
+            .to_string_lossy()
+            .rsplit_once("node_modules/")
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_default();
+        let roots = packages.entry(name).or_default();
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+
+    let mut found = 0;
+    for (name, roots) in packages.iter().filter(|(_, roots)| roots.len() > 1) {
+        found += 1;
+        log_line(format!(
+            "{} {} is bundled {} times",
+            "Duplicate".yellow().bold(),
+            name,
+            roots.len()
+        ));
+        for root in roots {
+            log_line(format!("  {} ({})", package_version(root), root.display()));
+            if let Some(module) = graph.first_below(root) {
+                log_line(format!("    {}", graph.chain(module)));
+            }
+        }
+    }
+
+    // The same file reached through symlinks or differently spelled paths
+    let mut files: BTreeMap<PathBuf, Vec<&String>> = BTreeMap::new();
+    for module in &graph.bundled {
+        if let Ok(canonical) = Path::new(module).canonicalize() {
+            files.entry(canonical).or_default().push(module);
+        }
+    }
+    for (file, modules) in files.iter().filter(|(_, modules)| modules.len() > 1) {
+        found += 1;
+        log_line(format!(
+            "{} {} is bundled under {} paths",
+            "Duplicate".yellow().bold(),
+            file.display(),
+            modules.len()
+        ));
+        for module in modules {
+            log_line(format!("    {}", graph.chain(module)));
+        }
+    }
+
+    Ok(found)
+}
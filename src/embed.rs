@@ -0,0 +1,113 @@
+// * ! ==================================================
+// * ! Cargo build script integration for Packr
+// * ! ==================================================
+
+//! Build assets from a Cargo build script, so a Rust web server is compiled together with
+//! the CSS and JavaScript it serves:
+//!
+//! ```no_run
+//! // in build.rs's `main`
+//! packr::embed::run_from_build_script("assets/.packr.json")?;
+//! # Ok::<(), packr::PackrError>(())
+//! ```
+
+use crate::build::{load_config, script_dependencies, Config};
+use crate::bundle::{self, Step};
+use crate::cache::{self, PROJECT_FILES};
+use crate::error::PackrError;
+use crate::events::{self, Event};
+use std::collections::BTreeSet;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Run a full build of `config_path` from a `build.rs` and tell Cargo what it depends on: the
+/// config, the lockfiles, every stylesheet and module the bundles were built from, and the
+/// `PACKR_*` variables that were set. Cargo then only runs the build script again when one of
+/// them changes. Sass, CSS, ESLint and esbuild warnings are shown as Cargo warnings.
+pub fn run_from_build_script(config_path: impl AsRef<Path>) -> Result<(), PackrError> {
+    let config_path = config_path.as_ref();
+    println!("cargo:rerun-if-changed={}", config_path.display());
+
+    let (config, config_dir) = load_config(&config_path.to_string_lossy())
+        .map_err(|e| PackrError::context("Failed to load configuration", e))?;
+
+    let _subscription = events::subscribe(Arc::new(|event| {
+        if let Event::Diagnostic {
+            source, message, ..
+        } = event
+        {
+            // A Cargo instruction ends at the line break
+            let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+            println!("cargo:warning={source}: {message}");
+        }
+    }));
+    crate::run_build(&config, &config_dir, config_path, false)?;
+
+    let dependencies = dependencies(&config, &config_dir)
+        .map_err(|e| PackrError::context("Dependencies failed", e))?;
+    for path in dependencies {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    // Overrides change the effective config without touching a file
+    let mut vars: Vec<String> = env::vars()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with("PACKR_") || config.env_inject.contains(name))
+        .collect();
+    vars.sort();
+    for name in vars.iter().map(String::as_str).chain(["SOURCE_DATE_EPOCH"]) {
+        println!("cargo:rerun-if-env-changed={name}");
+    }
+    Ok(())
+}
+
+// * Files and directories a change in which can change the outputs. Packages in
+// * `node_modules` are covered by the lockfiles instead of being listed one by one.
+fn dependencies(config: &Config, config_dir: &Path) -> Result<BTreeSet<PathBuf>, String> {
+    let mut paths = BTreeSet::new();
+    let in_node_modules = |path: &Path| {
+        path.components()
+            .any(|c| c == Component::Normal("node_modules".as_ref()))
+    };
+
+    // The main entries and those of `bundles`, each with the cache its build recorded into
+    for task in bundle::tasks(config) {
+        match task.step {
+            Step::Styles => {
+                let scss_input = config_dir.join(&task.config.scss_input);
+                paths.insert(scss_input.clone());
+                // Paths grass only probed for are dropped below, as they don't exist
+                paths.extend(cache::scss_dependencies(
+                    &cache::dir(&task.config, config_dir),
+                    &scss_input,
+                ));
+            }
+            Step::Scripts => {
+                paths.insert(config_dir.join(&task.config.js_input));
+                paths.extend(script_dependencies(&task.config, config_dir)?);
+            }
+        }
+    }
+
+    if config.eslint {
+        let eslint_config = config.eslint_config.as_deref().unwrap_or(".eslintrc.json");
+        paths.insert(config_dir.join(eslint_config));
+    }
+    paths.extend(config.copy.iter().map(|task| config_dir.join(&task.from)));
+    if let Some(ref html) = config.html {
+        paths.extend(
+            html.templates
+                .iter()
+                .map(|template| config_dir.join(template)),
+        );
+    }
+    if let Some(ref favicons) = config.favicons {
+        paths.insert(config_dir.join(&favicons.source));
+    }
+    paths.extend(PROJECT_FILES.iter().map(|name| config_dir.join(name)));
+
+    // Cargo reruns the script on every build for a path that doesn't exist
+    paths.retain(|path| path.exists() && !in_node_modules(path));
+    Ok(paths)
+}
@@ -0,0 +1,135 @@
+// * ! ==================================================
+// * ! Error types for Packr
+// * ! ==================================================
+
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+// * Any error a build step can fail with: a `PackrError`, or the text of a helper's failure
+pub type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+// * A build failure, tagged with the stage it came from
+#[derive(Debug, thiserror::Error)]
+pub enum PackrError {
+    // The config file is missing, malformed or inconsistent
+    #[error("{0}")]
+    Config(String),
+    // The config file is not valid JSON for a config
+    #[error("Failed to parse config file {}: {source}", path.display())]
+    ConfigParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    // Sass compilation failed
+    #[error("{0}")]
+    Scss(String),
+    // lightningcss or a CSS post-processing step failed
+    #[error("{0}")]
+    Css(String),
+    // ESLint reported errors or could not run
+    #[error("{0}")]
+    Eslint(String),
+    // esbuild or a script post-processing step failed
+    #[error("{0}")]
+    Esbuild(String),
+    // A file could not be read or written
+    #[error("{context} {}: {source}", path.display())]
+    Io {
+        context: String,
+        path: PathBuf,
+        source: io::Error,
+    },
+    // A step of the build failed, e.g. `Styles failed` with the Sass error it failed on
+    #[error("{context}{}: {source}", code_tag(source.as_ref()))]
+    Context { context: String, source: BoxError },
+}
+
+// * ` [E_SCSS]` after a step's context when it failed with a coded error
+fn code_tag(source: &(dyn Error + Send + Sync + 'static)) -> String {
+    source
+        .downcast_ref::<PackrError>()
+        .map(|error| format!(" [{}]", error.code()))
+        .unwrap_or_default()
+}
+
+impl PackrError {
+    pub fn io(context: &str, path: impl Into<PathBuf>, source: io::Error) -> Self {
+        PackrError::Io {
+            context: context.to_string(),
+            path: path.into(),
+            source,
+        }
+    }
+
+    // * `source` as the cause of a failed step, e.g. `PackrError::context("Deploy failed", e)`
+    pub fn context(context: &str, source: impl Into<BoxError>) -> Self {
+        PackrError::Context {
+            context: context.to_string(),
+            source: source.into(),
+        }
+    }
+
+    // * Stable identifier for scripts and tools that branch on the kind of failure; a failed
+    // * step has the code of the error it failed with, or `E_BUILD` when that has none
+    pub fn code(&self) -> &'static str {
+        match self {
+            PackrError::Config(_) | PackrError::ConfigParse { .. } => "E_CONFIG",
+            PackrError::Scss(_) => "E_SCSS",
+            PackrError::Css(_) => "E_CSS",
+            PackrError::Eslint(_) => "E_ESLINT",
+            PackrError::Esbuild(_) => "E_ESBUILD",
+            PackrError::Io { .. } => "E_IO",
+            PackrError::Context { source, .. } => source
+                .downcast_ref::<PackrError>()
+                .map_or("E_BUILD", PackrError::code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_steps_keep_the_code_and_source_they_failed_with() {
+        let error = PackrError::context(
+            "Styles failed",
+            PackrError::Scss("Undefined variable".to_string()),
+        );
+        assert_eq!(error.code(), "E_SCSS");
+        assert_eq!(
+            error.to_string(),
+            "Styles failed [E_SCSS]: Undefined variable"
+        );
+        assert_eq!(
+            error.source().map(ToString::to_string).as_deref(),
+            Some("Undefined variable")
+        );
+    }
+
+    #[test]
+    fn failed_steps_with_text_errors_are_e_build() {
+        let error = PackrError::context("Deploy failed", "rsync exited with 23".to_string());
+        assert_eq!(error.code(), "E_BUILD");
+        assert_eq!(error.to_string(), "Deploy failed: rsync exited with 23");
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn io_and_parse_errors_chain_their_cause() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let error = PackrError::io("Failed to read config file", ".packr.json", missing);
+        assert_eq!(error.code(), "E_IO");
+        let cause = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(cause.kind(), io::ErrorKind::NotFound);
+
+        let source = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let error = PackrError::ConfigParse {
+            path: PathBuf::from(".packr.json"),
+            source,
+        };
+        assert_eq!(error.code(), "E_CONFIG");
+        assert!(error.source().unwrap().is::<serde_json::Error>());
+    }
+}
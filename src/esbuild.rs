@@ -0,0 +1,281 @@
+// * ! ==================================================
+// * ! Managed esbuild binary for Packr
+// * ! ==================================================
+
+use crate::archive;
+use crate::build::log_line;
+use crate::children;
+use base64::Engine;
+use colored::*;
+use sha2::{Digest, Sha512};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// * esbuild version downloaded when none is installed, matching package.json
+pub const ESBUILD_VERSION: &str = "0.25.2";
+
+// * Directory for downloaded binaries, relative to the config file
+const BIN_DIR: &str = ".packr/bin";
+
+const REGISTRY: &str = "https://registry.npmjs.org";
+
+// * `sha512-…` integrity of each platform package of the pinned esbuild, as npm publishes it
+// * (and package-lock.json records it). Downloads are checked against these rather than
+// * against registry metadata, which could be changed along with the tarball.
+const CHECKSUMS: [(&str, &str, &str); 11] = [
+    ("0.25.2", "darwin-arm64", "sha512-MpM6LUVTXAzOvN4KbjzU/q5smzryuoNjlriAIx+06RpecwCkL9JpenNzpKd2YMzLJFOdPqBpuub6eVRP5IgiSA=="),
+    ("0.25.2", "darwin-x64", "sha512-5eRPrTX7wFyuWe8FqEFPG2cU0+butQQVNcT4sVipqjLYQjjh8a8+vUTfgBKM88ObB85ahsnTwF7PSIt6PG+QkA=="),
+    ("0.25.2", "freebsd-arm64", "sha512-mLwm4vXKiQ2UTSX4+ImyiPdiHjiZhIaE9QvC7sw0tZ6HoNMjYAqQpGyui5VRIi5sGd+uWq940gdCbY3VLvsO1w=="),
+    ("0.25.2", "freebsd-x64", "sha512-6qyyn6TjayJSwGpm8J9QYYGQcRgc90nmfdUb0O7pp1s4lTY+9D0H9O02v5JqGApUyiHOtkz6+1hZNvNtEhbwRQ=="),
+    ("0.25.2", "linux-arm", "sha512-UHBRgJcmjJv5oeQF8EpTRZs/1knq6loLxTsjc3nxO9eXAPDLcWW55flrMVc97qFPbmZP31ta1AZVUKQzKTzb0g=="),
+    ("0.25.2", "linux-arm64", "sha512-gq/sjLsOyMT19I8obBISvhoYiZIAaGF8JpeXu1u8yPv8BE5HlWYobmlsfijFIZ9hIVGYkbdFhEqC0NvM4kNO0g=="),
+    ("0.25.2", "linux-ia32", "sha512-bBYCv9obgW2cBP+2ZWfjYTU+f5cxRoGGQ5SeDbYdFCAZpYWrfjjfYwvUpP8MlKbP0nwZ5gyOU/0aUzZ5HWPuvQ=="),
+    ("0.25.2", "linux-x64", "sha512-QInHERlqpTTZ4FRB0fROQWXcYRD64lAoiegezDunLpalZMjcUcld3YzZmVJ2H/Cp0wJRZ8Xtjtj0cEHhYc/uUg=="),
+    ("0.25.2", "win32-arm64", "sha512-7Loyjh+D/Nx/sOTzV8vfbB3GJuHdOQyrOryFdZvPHLf42Tk9ivBU5Aedi7iyX+x6rbn2Mh68T4qq1SDqJBQO5Q=="),
+    ("0.25.2", "win32-ia32", "sha512-WRJgsz9un0nqZJ4MfhabxaD9Ft8KioqU3JMinOTvobbX6MOSUigSBlogP8QB3uxpJDsFS6yN+3FDBdqE5lg9kg=="),
+    ("0.25.2", "win32-x64", "sha512-kM3HKb16VIXZyIeVrM1ygYmZBKybX8N4p754bw390wGO3Tf2j4L2/WYL+4suWujpgf6GBYs3jv7TyUivdd05JA=="),
+];
+
+// * Pinned integrity of a platform package, failing for any version or platform without one
+fn pinned_integrity(version: &str, platform: &str) -> Result<&'static str, String> {
+    CHECKSUMS
+        .iter()
+        .find(|(v, p, _)| *v == version && *p == platform)
+        .map(|(_, _, integrity)| *integrity)
+        .ok_or_else(|| {
+            format!(
+                "No pinned checksum for esbuild {} on {}; install esbuild with npm instead",
+                version, platform
+            )
+        })
+}
+
+// * npm platform package name suffix for this OS and architecture, e.g. `linux-x64`
+fn platform() -> Result<String, String> {
+    let os = match env::consts::OS {
+        "linux" => "linux",
+        "macos" => "darwin",
+        "windows" => "win32",
+        "freebsd" => "freebsd",
+        other => return Err(format!("No esbuild binary is published for {}", other)),
+    };
+    let arch = match env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        "x86" => "ia32",
+        "arm" => "arm",
+        other => return Err(format!("No esbuild binary is published for {}", other)),
+    };
+    Ok(format!("{}-{}", os, arch))
+}
+
+// * Whether a command is found on PATH
+pub fn on_path(name: &str) -> bool {
+    let names = if cfg!(windows) {
+        vec![format!("{}.exe", name), format!("{}.cmd", name)]
+    } else {
+        vec![name.to_string()]
+    };
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| names.iter().any(|n| dir.join(n).is_file()))
+    })
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    let output = children::output(
+        "curl",
+        Command::new("curl").args(["-fsSL", "--retry", "2", url]),
+    )
+    .map_err(|e| format!("Failed to run curl to download {}: {}", url, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to download {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+// * Check a download against a `sha512-…` integrity
+fn verify(tarball: &[u8], integrity: &str) -> Result<(), String> {
+    let expected = integrity
+        .strip_prefix("sha512-")
+        .ok_or_else(|| format!("Unsupported integrity for esbuild: {}", integrity))?;
+    let actual = base64::engine::general_purpose::STANDARD.encode(Sha512::digest(tarball));
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for esbuild {}: expected sha512-{}, got sha512-{}",
+            ESBUILD_VERSION, expected, actual
+        ));
+    }
+    Ok(())
+}
+
+// * Contents of one file from a gzipped tar archive
+fn extract(tarball: &[u8], name: &str) -> Result<Vec<u8>, String> {
+    archive::unpack(tarball)
+        .map_err(|e| format!("Failed to read esbuild package: {}", e))?
+        .into_iter()
+        .find(|(path, _)| path == name)
+        .map(|(_, contents)| contents)
+        .ok_or_else(|| format!("{} not found in esbuild package", name))
+}
+
+// * Path of the pinned esbuild binary, downloading and verifying it on first use
+pub fn ensure(config_dir: &Path) -> Result<PathBuf, String> {
+    let file = if cfg!(windows) {
+        format!("esbuild-{}.exe", ESBUILD_VERSION)
+    } else {
+        format!("esbuild-{}", ESBUILD_VERSION)
+    };
+    let dir = config_dir.join(BIN_DIR);
+    let path = dir.join(&file);
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    let platform = platform()?;
+    let integrity = pinned_integrity(ESBUILD_VERSION, &platform)?;
+    log_line(format!(
+        "{} esbuild {} for {} into {}",
+        "Downloading".cyan().bold(),
+        ESBUILD_VERSION,
+        platform,
+        BIN_DIR
+    ));
+
+    let url = format!(
+        "{}/@esbuild/{}/-/{}-{}.tgz",
+        REGISTRY, platform, platform, ESBUILD_VERSION
+    );
+    let tarball = fetch(&url)?;
+    verify(&tarball, integrity)?;
+    let binary = extract(
+        &tarball,
+        if cfg!(windows) {
+            "package/esbuild.exe"
+        } else {
+            "package/bin/esbuild"
+        },
+    )?;
+
+    // Written under a temporary name so an interrupted download is never picked up
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let partial = dir.join(format!("{}.partial", file));
+    fs::write(&partial, binary)
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&partial, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to make {} executable: {}", partial.display(), e))?;
+    }
+    fs::rename(&partial, &path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(path)
+}
+
+// * Oldest esbuild release whose CLI accepts every flag Packr passes, e.g. `--legal-comments`,
+// * `--sourcemap=linked`, `--mangle-props` and `--tree-shaking`
+pub const MIN_VERSION: &str = "0.17.0";
+
+// * `major.minor.patch` of a version string, ignoring any prerelease suffix
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version
+        .trim()
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map(|part| part.parse::<u32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch))) => Some((major, minor, patch)),
+        _ => None,
+    }
+}
+
+// * Version reported by `esbuild --version` for the given esbuild command
+pub fn version(mut cmd: Command) -> Result<String, String> {
+    let output = children::output("esbuild", cmd.arg("--version"))
+        .map_err(|e| format!("Failed to run esbuild --version: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "esbuild --version failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// * Why an esbuild version may not work with the flags Packr passes, if it may not
+pub fn compatibility(version: &str) -> Option<String> {
+    let Some(parsed) = parse_version(version) else {
+        return Some(format!("unrecognized version \"{}\"", version));
+    };
+    let minimum = parse_version(MIN_VERSION)?;
+    if parsed < minimum {
+        return Some(format!(
+            "{} is older than {}, the oldest version Packr supports; flags such as \
+             --legal-comments, --sourcemap=linked and --tree-shaking may be rejected. \
+             Upgrade with `npm install esbuild@{}`",
+            version, MIN_VERSION, ESBUILD_VERSION
+        ));
+    }
+    if parsed.0 >= 1 {
+        return Some(format!(
+            "{} is newer than the 0.x releases Packr is tested with; if a build fails \
+             with an unknown flag, pin esbuild@{}",
+            version, ESBUILD_VERSION
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_supported_platform_has_a_pinned_checksum() {
+        for os in ["linux", "darwin", "win32"] {
+            for arch in ["x64", "arm64"] {
+                let platform = format!("{}-{}", os, arch);
+                assert!(
+                    pinned_integrity(ESBUILD_VERSION, &platform).is_ok(),
+                    "{platform}"
+                );
+            }
+        }
+        assert!(pinned_integrity(ESBUILD_VERSION, &platform().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn unpinned_versions_and_platforms_are_refused() {
+        assert!(pinned_integrity("0.25.3", "linux-x64").is_err());
+        assert!(pinned_integrity(ESBUILD_VERSION, "sunos-x64").is_err());
+    }
+
+    #[test]
+    fn pinned_esbuild_matches_package_json() {
+        let package: serde_json::Value =
+            serde_json::from_str(include_str!("../package.json")).unwrap();
+        let range = package["dependencies"]["esbuild"]
+            .as_str()
+            .or_else(|| package["devDependencies"]["esbuild"].as_str())
+            .unwrap();
+        assert!(range.ends_with(ESBUILD_VERSION), "{range}");
+    }
+
+    #[test]
+    fn verify_rejects_other_content() {
+        let integrity = format!(
+            "sha512-{}",
+            base64::engine::general_purpose::STANDARD.encode(Sha512::digest(b"esbuild"))
+        );
+        assert!(verify(b"esbuild", &integrity).is_ok());
+        assert!(verify(b"tampered", &integrity).is_err());
+        assert!(verify(b"esbuild", "sha1-abc").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// * ! ==================================================
+// * ! Build events for Packr
+// * ! ==================================================
+
+use crate::diagnostics::{self, Severity};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Something that happened during a build, streamed to the callback given to
+/// [`PackrBuilder::on_event`](crate::PackrBuilder::on_event)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A build started: `build` for a full build, `styles` for a stylesheet rebuild in watch mode
+    BuildStarted { kind: &'static str },
+    /// A build ended, after `duration`; a failed one follows an [`Event::Error`]
+    BuildFinished {
+        kind: &'static str,
+        success: bool,
+        duration: Duration,
+    },
+    /// A phase of the build started, e.g. `build styles` or `esbuild` in the `scripts` category
+    PhaseStarted {
+        category: &'static str,
+        name: String,
+    },
+    /// A phase finished, after `duration`
+    PhaseFinished {
+        category: &'static str,
+        name: String,
+        duration: Duration,
+    },
+    /// A file was written, by Packr or by esbuild
+    FileWritten { path: PathBuf },
+    /// A problem in the sources, reported by `source` (`Sass`, `CSS`, `ESLint` or `esbuild`),
+    /// with its location when the tool gave one
+    Diagnostic {
+        severity: Severity,
+        source: String,
+        message: String,
+        file: Option<PathBuf>,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    /// The build failed
+    Error { message: String },
+}
+
+impl Event {
+    // * A diagnostic from a tool that only reports text, located by the `file:line:column` in it
+    pub fn diagnostic_from_text(severity: Severity, source: &str, message: &str) -> Self {
+        let location = diagnostics::find_location(message);
+        Event::Diagnostic {
+            severity,
+            source: source.to_string(),
+            message: message.to_string(),
+            file: location.as_ref().map(|(file, _, _)| PathBuf::from(file)),
+            line: location.as_ref().map(|&(_, line, _)| line),
+            column: location.as_ref().map(|&(_, _, column)| column),
+        }
+    }
+
+    // * One line of `--events-ndjson` output
+    pub fn to_json(&self) -> serde_json::Value {
+        let severity = |severity: &Severity| match severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self {
+            Event::BuildStarted { kind } => {
+                serde_json::json!({ "event": "build_started", "kind": kind })
+            }
+            Event::BuildFinished {
+                kind,
+                success,
+                duration,
+            } => serde_json::json!({
+                "event": "build_finished",
+                "kind": kind,
+                "success": success,
+                "duration_ms": duration.as_millis() as u64,
+            }),
+            Event::PhaseStarted { category, name } => {
+                serde_json::json!({ "event": "phase_started", "category": category, "name": name })
+            }
+            Event::PhaseFinished {
+                category,
+                name,
+                duration,
+            } => serde_json::json!({
+                "event": "phase_finished",
+                "category": category,
+                "name": name,
+                "duration_ms": duration.as_millis() as u64,
+            }),
+            Event::FileWritten { path } => {
+                serde_json::json!({ "event": "file_written", "path": path })
+            }
+            Event::Diagnostic {
+                severity: level,
+                source,
+                message,
+                file,
+                line,
+                column,
+            } => serde_json::json!({
+                "event": "diagnostic",
+                "severity": severity(level),
+                "source": source,
+                "message": message,
+                "file": file,
+                "line": line,
+                "column": column,
+            }),
+            Event::Error { message } => serde_json::json!({ "event": "error", "message": message }),
+        }
+    }
+}
+
+// * Receives every event of the running build
+pub type Handler = Arc<dyn Fn(&Event) + Send + Sync>;
+
+// * Handlers listening in this process, such as a `PackrBuilder` callback, `--events-ndjson`
+// * and notifications, each with the id of its subscription
+static HANDLERS: RwLock<Vec<(u64, Handler)>> = RwLock::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// * Send the events of this process to `handler` until the returned guard is dropped
+pub fn subscribe(handler: Handler) -> Subscription {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut handlers) = HANDLERS.write() {
+        handlers.push((id, handler));
+    }
+    Subscription { id }
+}
+
+#[must_use]
+pub struct Subscription {
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Ok(mut handlers) = HANDLERS.write() {
+            handlers.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+// * Report an event; `event` is only built when someone is listening
+pub fn emit(event: impl FnOnce() -> Event) {
+    let handlers: Vec<Handler> = match HANDLERS.read() {
+        Ok(handlers) => handlers
+            .iter()
+            .map(|(_, handler)| handler.clone())
+            .collect(),
+        Err(_) => return,
+    };
+    if handlers.is_empty() {
+        return;
+    }
+    let event = event();
+    for handler in handlers {
+        handler(&event);
+    }
+}
+
+// * Report the end of a build of `kind` that started at `start`
+pub fn finished<T, E: std::fmt::Display>(
+    kind: &'static str,
+    start: Instant,
+    result: &Result<T, E>,
+) {
+    if let Err(message) = result {
+        emit(|| Event::Error {
+            message: message.to_string(),
+        });
+    }
+    emit(|| Event::BuildFinished {
+        kind,
+        success: result.is_ok(),
+        duration: start.elapsed(),
+    });
+}
@@ -0,0 +1,196 @@
+// * ! ==================================================
+// * ! Favicon and app icon generation for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::retry;
+use colored::*;
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType, ImageFormat};
+use std::fs;
+use std::path::Path;
+
+// * Sizes packed into favicon.ico
+const ICO_SIZES: [u32; 3] = [16, 32, 48];
+
+// * PNG icons written next to favicon.ico: (file name, size)
+const PNG_ICONS: [(&str, u32); 5] = [
+    ("favicon-16x16.png", 16),
+    ("favicon-32x32.png", 32),
+    ("apple-touch-icon.png", 180),
+    ("android-chrome-192x192.png", 192),
+    ("android-chrome-512x512.png", 512),
+];
+
+// * Smallest source that isn't upscaled for the largest icon
+const MIN_SOURCE_SIZE: u32 = 512;
+
+const WEB_MANIFEST: &str = "site.webmanifest";
+
+// * Favicon task settings
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Favicons {
+    // Square PNG the icons are generated from, relative to the config file
+    pub source: String,
+    // Directory the icons, web manifest and snippet are written to
+    pub output: String,
+    // URL prefix for the icons, instead of paths relative to the page
+    #[serde(default)]
+    pub public_path: Option<String>,
+    // App name for the web manifest
+    #[serde(default)]
+    pub name: Option<String>,
+    // `theme-color` meta tag and web manifest colour
+    #[serde(default)]
+    pub theme_color: Option<String>,
+    // File receiving the `<link>` tags, relative to `output`
+    #[serde(default = "default_snippet")]
+    pub snippet: String,
+}
+
+fn default_snippet() -> String {
+    "favicons.html".to_string()
+}
+
+// * Source image scaled (and center-cropped if not square) to a square icon
+fn scaled(source: &DynamicImage, size: u32) -> DynamicImage {
+    source.resize_to_fill(size, size, FilterType::Lanczos3)
+}
+
+fn write_ico(source: &DynamicImage, path: &Path) -> Result<(), String> {
+    let frames = ICO_SIZES
+        .iter()
+        .map(|&size| {
+            let icon = scaled(source, size).to_rgba8();
+            IcoFrame::as_png(icon.as_raw(), size, size, ExtendedColorType::Rgba8)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to encode favicon.ico: {}", e))?;
+
+    let file =
+        fs::File::create(path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    IcoEncoder::new(file)
+        .encode_images(&frames)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// * `site.webmanifest` listing the Android icons
+fn web_manifest(settings: &Favicons, url: &dyn Fn(&str) -> String) -> String {
+    let icons: Vec<serde_json::Value> = PNG_ICONS
+        .iter()
+        .filter(|(name, _)| name.starts_with("android-chrome"))
+        .map(|(name, size)| {
+            serde_json::json!({
+                "src": url(name),
+                "sizes": format!("{}x{}", size, size),
+                "type": "image/png"
+            })
+        })
+        .collect();
+
+    let mut manifest = serde_json::json!({ "icons": icons, "display": "standalone" });
+    if let Some(ref name) = settings.name {
+        manifest["name"] = name.clone().into();
+        manifest["short_name"] = name.clone().into();
+    }
+    if let Some(ref color) = settings.theme_color {
+        manifest["theme_color"] = color.clone().into();
+        manifest["background_color"] = color.clone().into();
+    }
+
+    serde_json::to_string_pretty(&manifest).unwrap_or_default() + "\n"
+}
+
+// * `<link>`/`<meta>` tags for the generated icons, with `url` mapping file names to URLs
+pub fn tags(settings: &Favicons, url: &dyn Fn(&str) -> String) -> Vec<String> {
+    let mut tags = vec![
+        format!(
+            "<link rel=\"icon\" href=\"{}\" sizes=\"48x48\">",
+            url("favicon.ico")
+        ),
+        format!(
+            "<link rel=\"icon\" type=\"image/png\" sizes=\"32x32\" href=\"{}\">",
+            url("favicon-32x32.png")
+        ),
+        format!(
+            "<link rel=\"icon\" type=\"image/png\" sizes=\"16x16\" href=\"{}\">",
+            url("favicon-16x16.png")
+        ),
+        format!(
+            "<link rel=\"apple-touch-icon\" sizes=\"180x180\" href=\"{}\">",
+            url("apple-touch-icon.png")
+        ),
+        format!("<link rel=\"manifest\" href=\"{}\">", url(WEB_MANIFEST)),
+    ];
+    if let Some(ref color) = settings.theme_color {
+        tags.push(format!("<meta name=\"theme-color\" content=\"{}\">", color));
+    }
+    tags
+}
+
+// * URL of a generated file as referenced from inside the output directory
+pub fn output_url(settings: &Favicons, name: &str) -> String {
+    match settings.public_path {
+        Some(ref prefix) => format!("{}/{}", prefix.trim_end_matches('/'), name),
+        None => name.to_string(),
+    }
+}
+
+// * Generate the icon set, web manifest and tag snippet from the source image
+pub fn generate(settings: &Favicons, config_dir: &Path) -> Result<(), String> {
+    let source_path = config_dir.join(&settings.source);
+    let source = image::open(&source_path).map_err(|e| {
+        format!(
+            "Failed to read favicon source {} (expected a PNG): {}",
+            source_path.display(),
+            e
+        )
+    })?;
+
+    if source.width().min(source.height()) < MIN_SOURCE_SIZE {
+        log_line(format!(
+            "{} {} is {}x{}; use at least {}x{} to avoid upscaled icons",
+            "Favicons".yellow().bold(),
+            settings.source,
+            source.width(),
+            source.height(),
+            MIN_SOURCE_SIZE,
+            MIN_SOURCE_SIZE
+        ));
+    }
+
+    let output = config_dir.join(&settings.output);
+    fs::create_dir_all(&output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+
+    write_ico(&source, &output.join("favicon.ico"))?;
+    for (name, size) in PNG_ICONS {
+        let path = output.join(name);
+        scaled(&source, size)
+            .save_with_format(&path, ImageFormat::Png)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    // The web manifest sits next to the icons, so relative URLs are just the file names
+    let url = |name: &str| output_url(settings, name);
+    let manifest_path = output.join(WEB_MANIFEST);
+    retry::write(&manifest_path, web_manifest(settings, &url))
+        .map_err(|e| format!("Failed to write {}: {}", manifest_path.display(), e))?;
+
+    let snippet_path = output.join(&settings.snippet);
+    let snippet: String = tags(settings, &url)
+        .iter()
+        .map(|tag| format!("{}\n", tag))
+        .collect();
+    retry::write(&snippet_path, snippet)
+        .map_err(|e| format!("Failed to write {}: {}", snippet_path.display(), e))?;
+
+    log_line(format!(
+        "{} {} icons written to {}",
+        "Favicons".green().bold(),
+        PNG_ICONS.len() + 1,
+        settings.output
+    ));
+    Ok(())
+}
@@ -0,0 +1,384 @@
+// * ! ==================================================
+// * ! Font copying and preload hints for Packr
+// * ! ==================================================
+
+use crate::cache;
+use crate::retry;
+use crate::woff2;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Font task settings (`"fonts": true` uses the defaults)
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Fonts {
+    // Directory the fonts are copied to, relative to the CSS output
+    #[serde(default = "default_dir")]
+    pub dir: String,
+    // URL prefix for the fonts in the CSS and preload tags, instead of relative URLs
+    #[serde(default)]
+    pub public_path: Option<String>,
+    // File receiving the `<link rel="preload">` tags, relative to the CSS output
+    #[serde(default = "default_preload")]
+    pub preload: String,
+    // Subset settings per `font-family` name
+    #[serde(default)]
+    pub subset: HashMap<String, FontSubset>,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Self {
+            dir: default_dir(),
+            public_path: None,
+            preload: default_preload(),
+            subset: HashMap::new(),
+        }
+    }
+}
+
+// * Characters kept when subsetting a font family
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FontSubset {
+    // CSS `unicode-range` syntax, e.g. `U+0000-00FF, U+2019`
+    #[serde(default)]
+    pub unicode_range: Option<String>,
+    // Sample text whose characters are kept
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+fn default_dir() -> String {
+    "fonts".to_string()
+}
+
+fn default_preload() -> String {
+    "fonts-preload.html".to_string()
+}
+
+// * Accept either a boolean or a settings object for `fonts`
+pub fn deserialize_fonts<'de, D>(deserializer: D) -> Result<Option<Fonts>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Bool(bool),
+        Settings(Fonts),
+    }
+
+    Ok(
+        match <Value as serde::Deserialize>::deserialize(deserializer)? {
+            Value::Bool(true) => Some(Fonts::default()),
+            Value::Bool(false) => None,
+            Value::Settings(settings) => Some(settings),
+        },
+    )
+}
+
+// * A font referenced by the stylesheet and copied next to it
+pub struct CopiedFont {
+    // Path relative to the CSS output directory
+    pub relative: PathBuf,
+    // URL the CSS and preload tags use for it
+    pub url: String,
+}
+
+// * Whether a `url()` value points at a local font file with one of the given extensions
+fn is_local_font(url: &str, extensions: &[&str]) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    extensions.iter().any(|ext| path.ends_with(ext))
+        && !path.starts_with('/')
+        && !path.contains("://")
+}
+
+// * `font-family` of the `@font-face` rule containing the given position, if any
+fn font_face_family(css: &str, position: usize) -> Option<String> {
+    let open = css[..position].rfind('{')?;
+    if !css[..open].trim_end().ends_with("@font-face") {
+        return None;
+    }
+    let close = open + css[open..].find('}')?;
+    let block = &css[open + 1..close];
+
+    let start = block.find("font-family")? + "font-family".len();
+    let value = block[start..].trim_start().strip_prefix(':')?;
+    let end = value.find(';').unwrap_or(value.len());
+    Some(value[..end].trim().trim_matches(['"', '\'']).to_string())
+}
+
+// * Parse a CSS `unicode-range` list, including `U+4??` wildcards, into code points
+fn parse_unicode_range(value: &str) -> Result<BTreeSet<u32>, String> {
+    let mut points = BTreeSet::new();
+    for item in value.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let invalid = || format!("Invalid unicode_range entry: {}", item);
+        let body = item
+            .strip_prefix("U+")
+            .or_else(|| item.strip_prefix("u+"))
+            .ok_or_else(invalid)?;
+        let hex = |h: &str| u32::from_str_radix(h, 16).map_err(|_| invalid());
+
+        let (start, end) = if let Some((from, to)) = body.split_once('-') {
+            (hex(from)?, hex(to)?)
+        } else if body.contains('?') {
+            (hex(&body.replace('?', "0"))?, hex(&body.replace('?', "F"))?)
+        } else {
+            let point = hex(body)?;
+            (point, point)
+        };
+        points.extend(start..=end.min(0x10FFFF));
+    }
+    Ok(points)
+}
+
+// * Subset an OpenType font to the configured characters and encode it as WOFF2
+fn subset_font(data: &[u8], settings: &FontSubset, family: &str) -> Result<Vec<u8>, String> {
+    let mut points = match settings.unicode_range {
+        Some(ref range) => parse_unicode_range(range)?,
+        None => BTreeSet::new(),
+    };
+    if let Some(ref text) = settings.text {
+        points.extend(text.chars().map(u32::from));
+    }
+    if points.is_empty() {
+        return Err(format!(
+            "Font subset for \"{}\" needs a unicode_range or text",
+            family
+        ));
+    }
+
+    let face = ttf_parser::Face::parse(data, 0).map_err(|e| {
+        format!(
+            "Failed to parse font for \"{}\" (subsetting needs a .ttf or .otf source): {}",
+            family, e
+        )
+    })?;
+
+    // Glyph 0 is the required .notdef glyph
+    let mut glyphs: Vec<u16> = vec![0];
+    glyphs.extend(
+        points
+            .into_iter()
+            .filter_map(char::from_u32)
+            .filter_map(|c| face.glyph_index(c))
+            .map(|id| id.0),
+    );
+    glyphs.sort_unstable();
+    glyphs.dedup();
+
+    let subset = subsetter::subset(data, 0, subsetter::Profile::pdf(&glyphs))
+        .map_err(|e| format!("Failed to subset font for \"{}\": {}", family, e))?;
+    woff2::encode(&subset)
+}
+
+// * Copy woff2 files referenced by `url()` in the compiled CSS and point the CSS at the copies
+pub fn process(
+    settings: &Fonts,
+    css: &str,
+    search_dirs: &[&Path],
+    css_output_dir: &Path,
+) -> Result<(String, Vec<CopiedFont>), String> {
+    let mut out = String::with_capacity(css.len());
+    let mut fonts: Vec<CopiedFont> = Vec::new();
+    let mut copied: HashMap<(PathBuf, Option<String>), String> = HashMap::new();
+    let mut rest = css;
+
+    while let Some(start) = rest.find("url(") {
+        let Some(end) = rest[start..].find(')') else {
+            break;
+        };
+        let position = css.len() - rest.len() + start;
+        let raw = &rest[start + 4..start + end];
+        let url = raw.trim().trim_matches(['"', '\'']);
+
+        out.push_str(&rest[..start]);
+        rest = &rest[start + end + 1..];
+
+        let family = font_face_family(css, position);
+        let subset = family
+            .as_ref()
+            .and_then(|family| settings.subset.get(family).map(|s| (family, s)));
+        let extensions: &[&str] = if subset.is_some() {
+            &[".woff2", ".ttf", ".otf"]
+        } else {
+            &[".woff2"]
+        };
+
+        if !is_local_font(url, extensions) {
+            out.push_str(&format!("url({})", raw));
+            continue;
+        }
+
+        let split = url.find(['?', '#']).unwrap_or(url.len());
+        let (path, suffix) = url.split_at(split);
+        let source = search_dirs
+            .iter()
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.exists())
+            .ok_or_else(|| format!("Font referenced by the CSS not found: {}", path))?;
+        // The same font referenced again, e.g. by another weight's `src`, reuses its copy
+        let key = (
+            source.canonicalize().unwrap_or_else(|_| source.clone()),
+            subset.map(|(family, _)| family.clone()),
+        );
+        let font_url = match copied.get(&key) {
+            Some(url) => url.clone(),
+            None => {
+                let stem = source
+                    .file_stem()
+                    .ok_or_else(|| format!("Invalid font path: {}", path))?
+                    .to_string_lossy();
+                let data = fs::read(&source)
+                    .map_err(|e| format!("Failed to read font {}: {}", source.display(), e))?;
+                let data = match subset {
+                    Some((family, subset_settings)) => subset_font(&data, subset_settings, family)?,
+                    None => data,
+                };
+
+                // Subsets get their own name so they never overwrite a copied original, and
+                // a different font with a name already taken gets its content hash added
+                let extension = if subset.is_some() {
+                    "subset.woff2"
+                } else {
+                    "woff2"
+                };
+                let mut name = format!("{}.{}", stem, extension);
+                if fonts
+                    .iter()
+                    .any(|f| f.relative == Path::new(&settings.dir).join(&name))
+                {
+                    name = format!("{}.{}.{}", stem, &cache::digest(&data)[..8], extension);
+                }
+                let relative = Path::new(&settings.dir).join(&name);
+                let font_url = match settings.public_path {
+                    Some(ref prefix) => format!("{}/{}", prefix.trim_end_matches('/'), name),
+                    None => relative.to_string_lossy().replace('\\', "/"),
+                };
+
+                let target = css_output_dir.join(&relative);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create fonts directory: {}", e))?;
+                }
+                retry::write(&target, data)
+                    .map_err(|e| format!("Failed to write font {}: {}", target.display(), e))?;
+
+                fonts.push(CopiedFont {
+                    relative,
+                    url: font_url.clone(),
+                });
+                copied.insert(key, font_url.clone());
+                font_url
+            }
+        };
+
+        out.push_str(&format!("url(\"{}{}\")", font_url, suffix));
+
+        // The subset is always WOFF2, whatever format the source was declared as
+        if subset.is_some() {
+            let trimmed = rest.trim_start();
+            if let Some(format_end) = trimmed.strip_prefix("format(").and_then(|f| f.find(')')) {
+                out.push_str(" format(\"woff2\")");
+                rest = &trimmed["format(".len() + format_end + 1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    Ok((out, fonts))
+}
+
+// * Render the `<link rel="preload">` tags for the copied fonts
+fn preload_snippet(fonts: &[CopiedFont]) -> String {
+    fonts
+        .iter()
+        .map(|font| {
+            format!(
+                "<link rel=\"preload\" href=\"{}\" as=\"font\" type=\"font/woff2\" crossorigin>\n",
+                font.url
+            )
+        })
+        .collect()
+}
+
+// * Write the preload snippet next to the CSS output
+pub fn write_preload(
+    settings: &Fonts,
+    css_output_dir: &Path,
+    fonts: &[CopiedFont],
+) -> Result<PathBuf, String> {
+    let path = css_output_dir.join(&settings.preload);
+    retry::write(&path, preload_snippet(fonts))
+        .map_err(|e| format!("Failed to write font preload snippet: {}", e))?;
+    Ok(path)
+}
+
+// * Mirror the copied fonts and preload snippet into the CSS destination
+pub fn mirror(
+    settings: &Fonts,
+    css_output_dir: &Path,
+    dest_dir: &Path,
+    fonts: &[CopiedFont],
+) -> Result<(), String> {
+    for relative in fonts
+        .iter()
+        .map(|f| f.relative.as_path())
+        .chain([Path::new(&settings.preload)])
+    {
+        let target = dest_dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create fonts destination folder: {}", e))?;
+        }
+        retry::copy(css_output_dir.join(relative), &target).map_err(|e| {
+            format!(
+                "Failed to copy {} to destination: {}",
+                relative.display(),
+                e
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fonts_sharing_a_file_name_keep_their_own_copies() {
+        let dir = std::env::temp_dir().join(format!("packr-fonts-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/a")).unwrap();
+        fs::create_dir_all(dir.join("src/b")).unwrap();
+        fs::write(dir.join("src/a/Inter.woff2"), "regular").unwrap();
+        fs::write(dir.join("src/b/Inter.woff2"), "bold").unwrap();
+        let css = "@font-face { font-family: A; src: url(a/Inter.woff2); }\n\
+                   @font-face { font-family: B; src: url(b/Inter.woff2); }\n\
+                   @font-face { font-family: C; src: url(a/Inter.woff2?v=2); }\n";
+
+        let out_dir = dir.join("dist");
+        let (out, fonts) = process(
+            &Fonts::default(),
+            css,
+            &[dir.join("src").as_path()],
+            &out_dir,
+        )
+        .unwrap();
+
+        assert_eq!(fonts.len(), 2);
+        assert_ne!(fonts[0].url, fonts[1].url);
+        assert_eq!(
+            fs::read_to_string(out_dir.join(&fonts[0].relative)).unwrap(),
+            "regular"
+        );
+        assert_eq!(
+            fs::read_to_string(out_dir.join(&fonts[1].relative)).unwrap(),
+            "bold"
+        );
+        assert!(out.contains(&format!("url(\"{}?v=2\")", fonts[0].url)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
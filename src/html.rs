@@ -0,0 +1,516 @@
+// * ! ==================================================
+// * ! HTML templates with asset injection for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, preload_chunks, suffixed_path, Config};
+use crate::favicons;
+use crate::manifest::{relative_to, Manifest};
+use crate::retry;
+use crate::svg::tag_end;
+use colored::*;
+use std::fs;
+use std::path::Path;
+
+// * Placeholder replaced with the stylesheet `<link>` tag
+const CSS_PLACEHOLDER: &str = "<!-- packr:css -->";
+
+// * Placeholder replaced with the `<script>` tags
+const JS_PLACEHOLDER: &str = "<!-- packr:js -->";
+
+// * Placeholder replaced with the favicon `<link>` tags
+const FAVICONS_PLACEHOLDER: &str = "<!-- packr:favicons -->";
+
+// * Placeholder replaced with the resource hint `<link>` tags, ahead of the stylesheet otherwise
+const HINTS_PLACEHOLDER: &str = "<!-- packr:hints -->";
+
+// * Directive replaced with the contents of another file
+const INCLUDE_DIRECTIVE: &str = "<!-- @include ";
+
+// * How deep includes may nest before assuming a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// * HTML task settings
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Html {
+    // Template files, relative to the config file
+    pub templates: Vec<String>,
+    // Directory the processed pages are written to
+    pub output: String,
+    // URL prefix for injected assets, instead of paths relative to the pages
+    #[serde(default)]
+    pub public_path: Option<String>,
+    // Which `<link>` hints are injected for the entry's dependencies
+    #[serde(default)]
+    pub resource_hints: ResourceHints,
+}
+
+// * Resource hint settings for HTML templates
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ResourceHints {
+    // `modulepreload` for chunks the entry statically imports
+    #[serde(default = "default_true")]
+    pub modulepreload: bool,
+    // `preload` for the fonts copied next to the stylesheet
+    #[serde(default = "default_true")]
+    pub fonts: bool,
+}
+
+impl Default for ResourceHints {
+    fn default() -> Self {
+        ResourceHints {
+            modulepreload: true,
+            fonts: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// * URL a page in `page_dir` uses to reference the file built for a logical output
+fn asset_url(settings: &Html, manifest: &Manifest, logical: &Path, page_dir: &Path) -> String {
+    let asset = manifest.resolve(logical);
+    let url = match settings.public_path {
+        Some(ref prefix) => format!(
+            "{}/{}",
+            prefix.trim_end_matches('/'),
+            asset.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        None => relative_to(&asset, page_dir),
+    };
+    url + &manifest.query(logical)
+}
+
+// * `integrity` and `crossorigin` attributes for an asset, when SRI hashes were recorded
+fn integrity_attrs(manifest: &Manifest, logical: &Path) -> String {
+    match manifest.integrity(logical) {
+        Some(integrity) => format!(" integrity=\"{}\" crossorigin=\"anonymous\"", integrity),
+        None => String::new(),
+    }
+}
+
+// * Tags for the stylesheet, preferring the minified build
+fn css_tags(
+    settings: &Html,
+    config: &Config,
+    config_dir: &Path,
+    manifest: &Manifest,
+    page_dir: &Path,
+) -> Vec<String> {
+    let mut logical = config_dir.join(&config.scss_output);
+    if config.minify {
+        logical = suffixed_path(&logical, ".min");
+    }
+
+    let href = asset_url(settings, manifest, &logical, page_dir);
+    vec![format!(
+        "<link rel=\"stylesheet\" href=\"{}\"{}>",
+        href,
+        integrity_attrs(manifest, &logical)
+    )]
+}
+
+// * Tags for the script bundle, with a `nomodule` fallback when a legacy build exists
+fn js_tags(
+    settings: &Html,
+    config: &Config,
+    config_dir: &Path,
+    manifest: &Manifest,
+    page_dir: &Path,
+) -> Vec<String> {
+    let logical = config_dir.join(&config.js_output);
+    let modern = if config.minify {
+        suffixed_path(&logical, ".min")
+    } else {
+        logical.clone()
+    };
+    let src = asset_url(settings, manifest, &modern, page_dir);
+    let attrs = integrity_attrs(manifest, &modern);
+
+    match config.legacy_target {
+        Some(_) => {
+            let legacy = suffixed_path(&logical, ".legacy");
+            vec![
+                format!("<script type=\"module\" src=\"{}\"{}></script>", src, attrs),
+                format!(
+                    "<script nomodule src=\"{}\"{}></script>",
+                    asset_url(settings, manifest, &legacy, page_dir),
+                    integrity_attrs(manifest, &legacy)
+                ),
+            ]
+        }
+        None if config.format == "esm" => {
+            vec![format!(
+                "<script type=\"module\" src=\"{}\"{}></script>",
+                src, attrs
+            )]
+        }
+        None => vec![format!("<script src=\"{}\"{}></script>", src, attrs)],
+    }
+}
+
+// * `modulepreload` and font `preload` tags for what the entry needs before it can run
+fn hint_tags(
+    settings: &Html,
+    config: &Config,
+    config_dir: &Path,
+    page_dir: &Path,
+) -> Result<Vec<String>, String> {
+    let mut tags = Vec::new();
+    let page_dir = page_dir
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve {}: {}", page_dir.display(), e))?;
+
+    // Chunk paths come from esbuild's metafile, so compare them as absolute paths
+    if settings.resource_hints.modulepreload {
+        let js_dir = config_dir
+            .join(&config.js_output)
+            .parent()
+            .and_then(|dir| dir.canonicalize().ok());
+        for chunk in preload_chunks(config, config_dir)? {
+            let Ok(chunk) = chunk.canonicalize() else {
+                continue;
+            };
+            let href = match (&settings.public_path, &js_dir) {
+                (Some(prefix), Some(js_dir)) => format!(
+                    "{}/{}",
+                    prefix.trim_end_matches('/'),
+                    relative_to(&chunk, js_dir)
+                ),
+                _ => relative_to(&chunk, &page_dir),
+            };
+            tags.push(format!("<link rel=\"modulepreload\" href=\"{}\">", href));
+        }
+    }
+
+    // The fonts task already wrote its preload tags, with URLs relative to the stylesheet
+    if let (true, Some(fonts)) = (settings.resource_hints.fonts, &config.fonts) {
+        let css_dir = config_dir
+            .join(&config.scss_output)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let snippet = fs::read_to_string(css_dir.join(&fonts.preload)).unwrap_or_default();
+        for tag in snippet.lines().filter(|line| !line.trim().is_empty()) {
+            let rebased = tag
+                .split_once("href=\"")
+                .and_then(|(before, rest)| {
+                    let (url, after) = rest.split_once('"')?;
+                    if fonts.public_path.is_some() || url.starts_with('/') || url.contains("://") {
+                        return None;
+                    }
+                    let font = css_dir.join(url).canonicalize().ok()?;
+                    Some(format!(
+                        "{}href=\"{}\"{}",
+                        before,
+                        relative_to(&font, &page_dir),
+                        after
+                    ))
+                })
+                .unwrap_or_else(|| tag.to_string());
+            tags.push(rebased);
+        }
+    }
+
+    Ok(tags)
+}
+
+// * Replace every occurrence of a placeholder, keeping its indentation for each tag
+fn replace_placeholder(html: &str, placeholder: &str, tags: &[String]) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(placeholder) {
+        let before = &rest[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let indent: String = before[line_start..]
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+
+        out.push_str(before);
+        out.push_str(&tags.join(&format!("\n{}", indent)));
+        rest = &rest[start + placeholder.len()..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+// * Inline `<!-- @include file.html -->` directives, resolved relative to the including file
+fn expand_includes(html: &str, file: &Path, depth: usize) -> Result<String, String> {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(INCLUDE_DIRECTIVE) {
+        let Some(len) = rest[start..].find("-->") else {
+            break;
+        };
+        let target = rest[start + INCLUDE_DIRECTIVE.len()..start + len].trim();
+        let before = &rest[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let indent: String = before[line_start..]
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "Includes nested more than {} levels deep at {} (circular include?)",
+                MAX_INCLUDE_DEPTH,
+                file.display()
+            ));
+        }
+        let partial_path = file.parent().unwrap_or(Path::new("")).join(target);
+        let partial = fs::read_to_string(&partial_path).map_err(|e| {
+            format!(
+                "Failed to read include {} from {}: {}",
+                partial_path.display(),
+                file.display(),
+                e
+            )
+        })?;
+        let partial = expand_includes(&partial, &partial_path, depth + 1)?;
+
+        out.push_str(before);
+        // Later lines of the partial line up with the directive
+        let lines: Vec<String> = partial
+            .trim_end()
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match i {
+                0 => line.to_string(),
+                _ if line.is_empty() => String::new(),
+                _ => format!("{}{}", indent, line),
+            })
+            .collect();
+        out.push_str(&lines.join("\n"));
+        rest = &rest[start + len + 3..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+// * Write every template to the HTML output directory with asset tags injected
+pub fn process_templates(
+    config: &Config,
+    config_dir: &Path,
+    manifest: &Manifest,
+) -> Result<(), String> {
+    let Some(ref settings) = config.html else {
+        return Ok(());
+    };
+
+    let page_dir = config_dir.join(&settings.output);
+    fs::create_dir_all(&page_dir)
+        .map_err(|e| format!("Failed to create {}: {}", page_dir.display(), e))?;
+
+    let css = css_tags(settings, config, config_dir, manifest, &page_dir);
+    let hints = hint_tags(settings, config, config_dir, &page_dir)?;
+    let js = js_tags(settings, config, config_dir, manifest, &page_dir);
+    let icons = match config.favicons {
+        Some(ref favicons) => {
+            let icon_dir = config_dir.join(&favicons.output);
+            favicons::tags(favicons, &|name| match favicons.public_path {
+                Some(_) => favicons::output_url(favicons, name),
+                None => relative_to(&icon_dir.join(name), &page_dir),
+            })
+        }
+        None => Vec::new(),
+    };
+
+    for template in &settings.templates {
+        let source = config_dir.join(template);
+        let html = fs::read_to_string(&source)
+            .map_err(|e| format!("Failed to read template {}: {}", source.display(), e))?;
+        let html = expand_includes(&html, &source, 0)?;
+
+        if !html.contains(CSS_PLACEHOLDER) && !html.contains(JS_PLACEHOLDER) {
+            log_line(format!(
+                "{} {} has no {} or {} placeholder",
+                "HTML".yellow().bold(),
+                template,
+                CSS_PLACEHOLDER,
+                JS_PLACEHOLDER
+            ));
+        }
+
+        let html = if html.contains(HINTS_PLACEHOLDER) {
+            let html = replace_placeholder(&html, HINTS_PLACEHOLDER, &hints);
+            replace_placeholder(&html, CSS_PLACEHOLDER, &css)
+        } else {
+            let tags: Vec<String> = hints.iter().chain(&css).cloned().collect();
+            replace_placeholder(&html, CSS_PLACEHOLDER, &tags)
+        };
+        let html = replace_placeholder(&html, JS_PLACEHOLDER, &js);
+        let mut html = replace_placeholder(&html, FAVICONS_PLACEHOLDER, &icons);
+        if config.minify_html {
+            html = minify(&html);
+        }
+
+        let name = source
+            .file_name()
+            .ok_or_else(|| format!("Invalid template path: {}", template))?;
+        let target = page_dir.join(name);
+        retry::write(&target, html)
+            .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+
+        log_line(format!(
+            "{} {} written to {}",
+            "HTML".green().bold(),
+            template,
+            target.display()
+        ));
+    }
+
+    Ok(())
+}
+
+// * Elements whose content is kept byte for byte
+const RAW_ELEMENTS: [&str; 4] = ["script", "style", "pre", "textarea"];
+
+// * Elements around which whitespace never renders
+const BLOCK_ELEMENTS: [&str; 38] = [
+    "html", "head", "body", "title", "meta", "link", "script", "style", "base", "div", "p",
+    "section", "article", "aside", "header", "footer", "nav", "main", "h1", "h2", "h3", "h4", "h5",
+    "h6", "ul", "ol", "li", "table", "thead", "tbody", "tfoot", "tr", "td", "th", "form", "figure",
+    "hr", "br",
+];
+
+// * Lowercased name of the tag starting at `<`, without a leading `/`
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches(['<', '/'])
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+// * Rewrite a start tag with single spaces between attributes and double-quoted values
+fn normalize_tag(inner: &str) -> String {
+    let self_closing = inner.ends_with('/');
+    let inner = inner.trim_end_matches('/').trim();
+    let name_end = inner
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(inner.len());
+
+    let mut out = format!("<{}", &inner[..name_end]);
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+
+        let Some(after) = rest.strip_prefix('=') else {
+            // Boolean attribute
+            out.push_str(&format!(" {}", key));
+            continue;
+        };
+        let after = after.trim_start();
+        let (value, remaining) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => match after[1..].find(quote) {
+                Some(close) => (&after[1..close + 1], &after[close + 2..]),
+                None => (&after[1..], ""),
+            },
+            _ => {
+                let end = after
+                    .find(|c: char| c.is_whitespace())
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        out.push_str(&format!(" {}=\"{}\"", key, value.replace('"', "&quot;")));
+        rest = remaining.trim_start();
+    }
+
+    out.push_str(if self_closing { "/>" } else { ">" });
+    out
+}
+
+// * Remove comments and insignificant whitespace, and normalize attribute quoting
+pub fn minify(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    // Name of the last tag written, to drop whitespace next to block elements
+    let mut previous_tag = String::new();
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        let start = rest.find('<').unwrap_or(rest.len());
+        let text = &rest[..start];
+        rest = &rest[start..];
+
+        if !text.is_empty() {
+            let block = |name: &str| BLOCK_ELEMENTS.contains(&name);
+            let space_before = !out.is_empty() && !out.ends_with(' ') && !block(&previous_tag);
+            let space_after = !rest.is_empty() && !block(&tag_name(rest));
+            let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            // Keep a single space where the text touched an inline neighbour
+            if collapsed.is_empty() {
+                if space_before && space_after {
+                    out.push(' ');
+                }
+            } else {
+                if space_before && text.starts_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+                out.push_str(&collapsed);
+                if space_after && text.ends_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+            }
+        }
+
+        if rest.is_empty() {
+            break;
+        }
+
+        if let Some(body) = rest.strip_prefix("<!--") {
+            let end = body.find("-->").map_or(body.len(), |end| end + 3);
+            // Conditional comments still change behaviour in old browsers
+            if body.starts_with("[if") || body.starts_with("<![endif]") {
+                out.push_str(&rest[..4 + end]);
+            }
+            rest = &body[end..];
+            continue;
+        }
+
+        let end = tag_end(rest);
+        if end == rest.len() {
+            // Unterminated tag, keep it as written
+            out.push_str(rest);
+            break;
+        }
+        let tag = &rest[..end + 1];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with("<!") || tag.starts_with("<?") {
+            out.push_str(tag);
+            continue;
+        }
+
+        let name = tag_name(tag);
+        if let Some(closing) = tag.strip_prefix("</") {
+            out.push_str(&format!("</{}>", closing.trim_end_matches('>').trim()));
+            previous_tag = name;
+            continue;
+        }
+
+        out.push_str(&normalize_tag(&tag[1..tag.len() - 1]));
+        previous_tag = name.clone();
+
+        // Copy raw content through to the matching closing tag untouched
+        if RAW_ELEMENTS.contains(&name.as_str()) && !tag.ends_with("/>") {
+            let close = format!("</{}", name);
+            let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+
+    out
+}
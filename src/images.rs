@@ -0,0 +1,278 @@
+// * ! ==================================================
+// * ! WebP and AVIF versions of copied images for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::bundle;
+use crate::cache::{self, IMAGES_FILE};
+use crate::children;
+use crate::manifest::Manifest;
+use crate::retry;
+use crate::runtime;
+use colored::*;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// * Supported values for `images.formats`
+pub const FORMATS: [&str; 2] = ["webp", "avif"];
+
+// * Images that get variants, by extension
+const SOURCE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+// * Image task settings: modern formats written next to the images the `copy` tasks write
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Images {
+    // Formats written for each PNG and JPEG
+    #[serde(default = "default_formats")]
+    pub formats: Vec<String>,
+    // Encoder quality from 0 to 100 per format, e.g. `{"webp": 75}`
+    #[serde(default)]
+    pub quality: BTreeMap<String, u8>,
+    // Record each image with its variants in the asset manifest
+    #[serde(default)]
+    pub manifest: bool,
+}
+
+fn default_formats() -> Vec<String> {
+    FORMATS.iter().map(|format| format.to_string()).collect()
+}
+
+// * Quality used for a format `quality` leaves out; AVIF looks as good at a lower setting
+fn default_quality(format: &str) -> u8 {
+    if format == "avif" {
+        60
+    } else {
+        80
+    }
+}
+
+// * A variant to write: `source` encoded as `format` into `target`
+struct Variant<'a> {
+    source: &'a Path,
+    format: &'a str,
+    quality: u8,
+    target: PathBuf,
+    // Source digest, format and quality; an unchanged key means an unchanged variant
+    key: String,
+}
+
+// * Why the `images` settings can't be used, if they name an unknown format or quality
+pub fn validate(images: &Images) -> Result<(), String> {
+    if images.formats.is_empty() {
+        return Err("formats is empty; leave images out to write no variants".to_string());
+    }
+    if let Some(format) = images
+        .formats
+        .iter()
+        .chain(images.quality.keys())
+        .find(|format| !FORMATS.contains(&format.as_str()))
+    {
+        return Err(format!(
+            "unknown format \"{}\" (expected one of: {})",
+            format,
+            FORMATS.join(", ")
+        ));
+    }
+    if let Some((format, quality)) = images.quality.iter().find(|(_, &quality)| quality > 100) {
+        return Err(format!(
+            "quality.{} is {} (expected 0 to 100)",
+            format, quality
+        ));
+    }
+    Ok(())
+}
+
+fn quality(images: &Images, format: &str) -> u8 {
+    images
+        .quality
+        .get(format)
+        .copied()
+        .unwrap_or_else(|| default_quality(format))
+}
+
+// * `hero.png` becomes `hero.png.webp`, so a PNG and a JPEG of the same name don't share one
+pub fn variant_path(image: &Path, format: &str) -> PathBuf {
+    let mut path = image.as_os_str().to_owned();
+    path.push(".");
+    path.push(format);
+    PathBuf::from(path)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        SOURCE_EXTENSIONS.contains(&ext.to_string_lossy().to_ascii_lowercase().as_str())
+    })
+}
+
+// * The encoder writing a variant: cwebp for WebP, avifenc for AVIF
+fn encoder(variant: &Variant) -> (&'static str, Command) {
+    let quality = variant.quality.to_string();
+    if variant.format == "webp" {
+        let mut cmd = runtime::program("cwebp");
+        cmd.args(["-quiet", "-q", &quality])
+            .arg(variant.source)
+            .arg("-o")
+            .arg(&variant.target);
+        ("cwebp", cmd)
+    } else {
+        let mut cmd = runtime::program("avifenc");
+        cmd.args(["-q", &quality])
+            .arg(variant.source)
+            .arg(&variant.target);
+        ("avifenc", cmd)
+    }
+}
+
+fn encode(variant: &Variant) -> Result<(), String> {
+    let (tool, mut cmd) = encoder(variant);
+    let output = children::output(tool, &mut cmd).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!(
+            "{} is not installed; it writes the {} versions of images",
+            tool, variant.format
+        ),
+        _ => format!("Failed to run {}: {}", tool, e),
+    })?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed on {}: {}",
+            tool,
+            variant.source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// * Write the configured variants of the PNG and JPEG files among `files`, and record them
+// * in the manifest when `images.manifest` is set. Variants encoded from the same image at
+// * the same quality by an earlier build are kept as they are.
+pub fn write_variants(
+    config: &Config,
+    config_dir: &Path,
+    files: &[PathBuf],
+    manifest: &mut Manifest,
+) -> Result<(), String> {
+    let Some(ref images) = config.images else {
+        return Ok(());
+    };
+    let sources: BTreeSet<&PathBuf> = files.iter().filter(|file| is_image(file)).collect();
+
+    let mut variants = Vec::new();
+    for source in &sources {
+        let content =
+            fs::read(source).map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+        let digest = cache::digest(&content);
+        for format in &images.formats {
+            let quality = quality(images, format);
+            variants.push(Variant {
+                source,
+                format,
+                quality,
+                target: variant_path(source, format),
+                key: format!("{}:{}:{}", format, quality, digest),
+            });
+        }
+    }
+
+    let cache_dir = cache::dir(config, config_dir);
+    let state_path = cache_dir.join(IMAGES_FILE);
+    let previous: BTreeMap<String, String> = fs::read_to_string(&state_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let changed: Vec<&Variant> = variants
+        .iter()
+        .filter(|variant| {
+            previous.get(variant.target.to_string_lossy().as_ref()) != Some(&variant.key)
+                || !variant.target.is_file()
+        })
+        .collect();
+
+    // Encoders are slow and single-threaded, so several images are encoded at once
+    if !changed.is_empty() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(bundle::jobs(config).min(changed.len()))
+            .build()
+            .map_err(|e| format!("Failed to start the image encoders: {}", e))?;
+        pool.install(|| changed.par_iter().try_for_each(|variant| encode(variant)))?;
+    }
+
+    let state: BTreeMap<String, &str> = variants
+        .iter()
+        .map(|variant| {
+            (
+                variant.target.to_string_lossy().to_string(),
+                variant.key.as_str(),
+            )
+        })
+        .collect();
+    fs::create_dir_all(&cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+    let state = serde_json::to_string(&state)
+        .map_err(|e| format!("Failed to serialize image state: {}", e))?;
+    retry::write(&state_path, state)
+        .map_err(|e| format!("Failed to write {}: {}", state_path.display(), e))?;
+
+    if images.manifest {
+        for source in &sources {
+            let formats = images
+                .formats
+                .iter()
+                .map(|format| (format.clone(), variant_path(source, format)))
+                .collect();
+            manifest.record_variants(source, formats);
+        }
+    }
+
+    log_line(format!(
+        "{} {} variant(s) of {} image(s) written, {} unchanged",
+        "Images".green().bold(),
+        changed.len(),
+        sources.len(),
+        variants.len() - changed.len()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn images(json: &str) -> Images {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn unknown_formats_and_qualities_are_rejected() {
+        assert!(validate(&images("{}")).is_ok());
+        assert!(validate(&images(r#"{"formats": ["jxl"]}"#))
+            .unwrap_err()
+            .contains("unknown format \"jxl\""));
+        assert!(validate(&images(r#"{"quality": {"png": 80}}"#)).is_err());
+        assert!(validate(&images(r#"{"quality": {"webp": 101}}"#))
+            .unwrap_err()
+            .contains("quality.webp is 101"));
+        assert!(validate(&images(r#"{"formats": []}"#)).is_err());
+    }
+
+    #[test]
+    fn qualities_default_per_format() {
+        let settings = images(r#"{"quality": {"webp": 70}}"#);
+        assert_eq!(quality(&settings, "webp"), 70);
+        assert_eq!(quality(&settings, "avif"), 60);
+    }
+
+    #[test]
+    fn variants_keep_the_image_extension() {
+        assert_eq!(
+            variant_path(Path::new("dist/img/hero.png"), "webp"),
+            Path::new("dist/img/hero.png.webp")
+        );
+        assert!(is_image(Path::new("photo.JPG")));
+        assert!(!is_image(Path::new("icon.svg")));
+    }
+}
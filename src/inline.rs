@@ -0,0 +1,224 @@
+// * ! ==================================================
+// * ! Asset inlining for JavaScript imports in Packr
+// * ! ==================================================
+
+use crate::retry;
+use base64::Engine;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Imported asset types emitted as files, and the MIME type used when inlining them
+pub const ASSET_TYPES: [(&str, &str); 14] = [
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+];
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// * `--loader` arguments emitting every asset type as a file, so the build can decide per file
+pub fn loader_args() -> Vec<String> {
+    ASSET_TYPES
+        .iter()
+        .map(|(ext, _)| format!("--loader:.{}=file", ext))
+        .collect()
+}
+
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    ASSET_TYPES
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, mime)| *mime)
+}
+
+// * Byte ranges of the double-quoted string literals ending in `/name` (or equal to `name`)
+fn url_literals(code: &str, name: &str) -> Vec<(usize, usize)> {
+    let needle = format!("{}\"", name);
+    let mut ranges = Vec::new();
+    let mut from = 0;
+
+    while let Some(found) = code[from..].find(&needle) {
+        let name_start = from + found;
+        let end = name_start + needle.len();
+        from = end;
+
+        let Some(open) = code[..name_start].rfind(['"', '\n']) else {
+            continue;
+        };
+        let url = &code[open + 1..name_start];
+        if code[open..].starts_with('"') && (url.is_empty() || url.ends_with('/')) {
+            ranges.push((open, end));
+        }
+    }
+    ranges
+}
+
+fn decode_vlq(segment: &str) -> Option<(i64, usize)> {
+    let mut value: i64 = 0;
+    let mut shift = 0;
+    for (i, c) in segment.bytes().enumerate() {
+        let digit = BASE64_CHARS.iter().position(|&b| b == c)? as i64;
+        value += (digit & 31) << shift;
+        if digit & 32 == 0 {
+            let negative = value & 1 == 1;
+            value >>= 1;
+            return Some((if negative { -value } else { value }, i + 1));
+        }
+        shift += 5;
+    }
+    None
+}
+
+fn encode_vlq(value: i64) -> String {
+    let mut rest = if value < 0 {
+        (-value << 1) | 1
+    } else {
+        value << 1
+    };
+    let mut out = String::new();
+    loop {
+        let mut digit = rest & 31;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 32;
+        }
+        out.push(BASE64_CHARS[digit as usize] as char);
+        if rest == 0 {
+            return out;
+        }
+    }
+}
+
+// * Move the generated columns after `column` on `line` by `delta`, keeping the other fields
+fn shift_mappings(mappings: &str, line: usize, column: i64, delta: i64) -> String {
+    let mut lines: Vec<String> = mappings.split(';').map(str::to_string).collect();
+    let Some(target) = lines.get_mut(line) else {
+        return mappings.to_string();
+    };
+
+    let mut previous = 0;
+    let mut previous_shifted = 0;
+    let mut segments = Vec::new();
+    for segment in target.split(',').filter(|s| !s.is_empty()) {
+        let Some((relative, len)) = decode_vlq(segment) else {
+            return mappings.to_string();
+        };
+        let absolute = previous + relative;
+        let shifted = if absolute > column {
+            absolute + delta
+        } else {
+            absolute
+        };
+        segments.push(format!(
+            "{}{}",
+            encode_vlq(shifted - previous_shifted),
+            &segment[len..]
+        ));
+        previous = absolute;
+        previous_shifted = shifted;
+    }
+
+    *target = segments.join(",");
+    lines.join(";")
+}
+
+// * Replace the URL literals for an inlined asset in a script and its external source map
+fn inline_into(script: &Path, name: &str, data_url: &str) -> Result<(), String> {
+    let mut code = fs::read_to_string(script)
+        .map_err(|e| format!("Failed to read {}: {}", script.display(), e))?;
+    let ranges = url_literals(&code, name);
+    if ranges.is_empty() {
+        return Ok(());
+    }
+
+    let map_path = PathBuf::from(format!("{}.map", script.display()));
+    let mut map: Option<serde_json::Value> = fs::read_to_string(&map_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+
+    let literal = format!("\"{}\"", data_url);
+    // Right to left, so earlier ranges and columns stay valid
+    for &(start, end) in ranges.iter().rev() {
+        if let Some(mappings) = map
+            .as_mut()
+            .and_then(|m| m.get_mut("mappings"))
+            .filter(|m| m.is_string())
+        {
+            let line_start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line = code[..start].matches('\n').count();
+            // Source map columns count UTF-16 code units
+            let column = code[line_start..start].encode_utf16().count() as i64;
+            let delta = literal.len() as i64 - (end - start) as i64;
+            let shifted = shift_mappings(mappings.as_str().unwrap_or(""), line, column, delta);
+            *mappings = shifted.into();
+        }
+        code.replace_range(start..end, &literal);
+    }
+
+    retry::write(script, code)
+        .map_err(|e| format!("Failed to write {}: {}", script.display(), e))?;
+    if let Some(map) = map {
+        retry::write(&map_path, map.to_string())
+            .map_err(|e| format!("Failed to write {}: {}", map_path.display(), e))?;
+    }
+    Ok(())
+}
+
+// * Inline the assets of an esbuild build at or under `limit` bytes as data URLs,
+// * removing their emitted files; returns how many were inlined
+pub fn inline_small_assets(metafile: &Path, limit: u64) -> Result<usize, String> {
+    let content = fs::read_to_string(metafile)
+        .map_err(|e| format!("Failed to read esbuild metafile: {}", e))?;
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse esbuild metafile: {}", e))?;
+    let Some(outputs) = json["outputs"].as_object() else {
+        return Ok(0);
+    };
+
+    let scripts: Vec<PathBuf> = outputs
+        .keys()
+        .filter(|path| path.ends_with(".js") || path.ends_with(".mjs"))
+        .map(PathBuf::from)
+        .collect();
+
+    let mut inlined = 0;
+    for (path, output) in outputs {
+        let asset = PathBuf::from(path);
+        let (Some(mime), Some(bytes)) = (mime_type(&asset), output["bytes"].as_u64()) else {
+            continue;
+        };
+        if bytes > limit {
+            continue;
+        }
+        let data =
+            fs::read(&asset).map_err(|e| format!("Failed to read {}: {}", asset.display(), e))?;
+
+        let name = asset.file_name().unwrap_or_default().to_string_lossy();
+        let data_url = format!(
+            "data:{};base64,{}",
+            mime,
+            base64::engine::general_purpose::STANDARD.encode(&data)
+        );
+        for script in &scripts {
+            inline_into(script, &name, &data_url)?;
+        }
+
+        fs::remove_file(&asset)
+            .map_err(|e| format!("Failed to remove {}: {}", asset.display(), e))?;
+        inlined += 1;
+    }
+
+    Ok(inlined)
+}
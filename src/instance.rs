@@ -0,0 +1,111 @@
+// * ! ==================================================
+// * ! Concurrent run locking for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use colored::*;
+use std::fs::{self, File, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+// * Held by the run writing the outputs, relative to the config directory
+const LOCK_FILE: &str = ".packr/build.lock";
+
+// * Accepted values of `instance_lock`
+pub const MODES: [&str; 3] = ["wait", "fail", "off"];
+
+// * Released when dropped, or by the OS when the process exits however it exits
+pub struct InstanceLock {
+    _file: Option<File>,
+}
+
+fn lock_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(LOCK_FILE)
+}
+
+// * The run holding the lock as it recorded itself, e.g. `packr --watch (pid 4242)`, and
+// * whether it's a watch session
+fn holder(file: &mut File) -> (String, bool) {
+    let mut content = String::new();
+    let _ = file
+        .rewind()
+        .and_then(|()| file.read_to_string(&mut content));
+    match content.split_once(' ') {
+        Some(("watch", pid)) => (format!("packr --watch (pid {})", pid.trim()), true),
+        Some((_, pid)) => (format!("a packr build (pid {})", pid.trim()), false),
+        None => ("another packr run".to_string(), false),
+    }
+}
+
+// * Whether another run holds the lock right now
+pub fn is_held(config_dir: &Path) -> bool {
+    let Ok(file) = File::open(lock_path(config_dir)) else {
+        return false;
+    };
+    match file.try_lock() {
+        Ok(()) => false,
+        Err(TryLockError::WouldBlock) => true,
+        Err(TryLockError::Error(_)) => false,
+    }
+}
+
+// * Take the lock for a run of `kind` (`build` or `watch`) writing the outputs. A concurrent
+// * run is waited for in `wait` mode, unless it's a watch session, which only ends when
+// * stopped; `fail` fails right away; `off` takes no lock.
+pub fn acquire(config: &Config, config_dir: &Path, kind: &str) -> Result<InstanceLock, String> {
+    if config.instance_lock == "off" {
+        return Ok(InstanceLock { _file: None });
+    }
+
+    let path = lock_path(config_dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let (owner, watching) = holder(&mut file);
+            if config.instance_lock == "fail" || watching {
+                return Err(format!(
+                    "{} is already writing the outputs of {}; wait for it or stop it, as both runs would write the same files",
+                    owner,
+                    display_dir(config_dir)
+                ));
+            }
+            log_line(format!(
+                "{} for {} to finish",
+                "Waiting".yellow().bold(),
+                owner
+            ));
+            file.lock()
+                .map_err(|e| format!("Failed to lock {}: {}", path.display(), e))?;
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(format!("Failed to lock {}: {}", path.display(), e));
+        }
+    }
+
+    // Recorded for the runs that find the lock taken
+    let owner = format!("{} {}", kind, std::process::id());
+    let _ = file
+        .set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| file.write_all(owner.as_bytes()));
+    Ok(InstanceLock { _file: Some(file) })
+}
+
+fn display_dir(config_dir: &Path) -> String {
+    match config_dir.as_os_str().is_empty() {
+        true => ".".to_string(),
+        false => config_dir.display().to_string(),
+    }
+}
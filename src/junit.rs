@@ -0,0 +1,275 @@
+// * ! ==================================================
+// * ! JUnit XML reports for Packr
+// * ! ==================================================
+
+use crate::diagnostics::Severity;
+use crate::error::PackrError;
+use crate::events::{self, Event, Subscription};
+use crate::manifest::relative_to;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// * A finished build step: `(category, name, duration)`
+type Step = (&'static str, String, Duration);
+
+// * A Sass, CSS, ESLint or esbuild message: `(severity, line, column, message)`
+type Finding = (Severity, Option<usize>, Option<usize>, String);
+
+#[derive(Default)]
+struct Results {
+    steps: Vec<Step>,
+    // Keyed by source, then file; messages without a file are keyed by an empty path
+    findings: BTreeMap<(String, String), Vec<Finding>>,
+}
+
+// * Collects the steps and diagnostics of a build for `write`
+pub struct Recorder {
+    started: Instant,
+    results: Arc<Mutex<Results>>,
+    _subscription: Subscription,
+}
+
+pub fn record() -> Recorder {
+    let results = Arc::new(Mutex::new(Results::default()));
+    let recorder = results.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        let Ok(mut results) = recorder.lock() else {
+            return;
+        };
+        match event {
+            Event::PhaseFinished {
+                category,
+                name,
+                duration,
+            } => results.steps.push((category, name.clone(), *duration)),
+            Event::Diagnostic {
+                severity,
+                source,
+                message,
+                file,
+                line,
+                column,
+            } => {
+                let file = file
+                    .as_ref()
+                    .map(|file| file.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                results
+                    .findings
+                    .entry((source.clone(), file))
+                    .or_default()
+                    .push((*severity, *line, *column, message.clone()));
+            }
+            _ => {}
+        }
+    }));
+    Recorder {
+        started: Instant::now(),
+        results,
+        _subscription: subscription,
+    }
+}
+
+impl Recorder {
+    // * Write the report of a build that ended with `result` to `path`: one suite with a test
+    // * case per build step and one for the build as a whole, and one suite with a test case
+    // * per file a tool reported on, failing when it reported an error
+    pub fn write(
+        self,
+        path: &Path,
+        config_dir: &Path,
+        result: &Result<bool, PackrError>,
+    ) -> Result<(), String> {
+        let elapsed = self.started.elapsed();
+        let results = match self.results.lock() {
+            Ok(mut results) => std::mem::take(&mut *results),
+            Err(_) => return Err("Build results are unavailable".to_string()),
+        };
+
+        let mut build = Suite::new("build");
+        build.time = elapsed;
+        for (category, name, duration) in &results.steps {
+            let classname = format!("packr.{}", category);
+            build.case(&classname, name, *duration, Outcome::Passed, None);
+        }
+        let outcome = match result {
+            Ok(true) => Outcome::Passed,
+            Ok(false) => Outcome::Skipped("No input changed"),
+            Err(e) => Outcome::Failed(e.code(), e.to_string()),
+        };
+        build.case("packr", "build", elapsed, outcome, None);
+
+        // Tools report absolute paths (ESLint) or ones relative to the working directory
+        let project_dir = Path::new(".")
+            .join(config_dir)
+            .canonicalize()
+            .unwrap_or_else(|_| config_dir.to_path_buf());
+        let mut diagnostics = Suite::new("diagnostics");
+        for ((source, file), findings) in &results.findings {
+            let name = if file.is_empty() {
+                source.clone()
+            } else if Path::new(file).is_absolute() {
+                relative_to(Path::new(file), &project_dir)
+            } else {
+                file.trim_start_matches("./").to_string()
+            };
+            let describe = |(_, line, column, message): &Finding| match (line, column) {
+                (Some(line), Some(column)) => format!("{}:{}:{}: {}", name, line, column, message),
+                (Some(line), None) => format!("{}:{}: {}", name, line, message),
+                _ => message.clone(),
+            };
+            let errors: Vec<String> = findings
+                .iter()
+                .filter(|finding| finding.0 == Severity::Error)
+                .map(describe)
+                .collect();
+            let warnings: Vec<String> = findings
+                .iter()
+                .filter(|finding| finding.0 == Severity::Warning)
+                .map(describe)
+                .collect();
+            let outcome = if errors.is_empty() {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(source, errors.join("\n\n"))
+            };
+            let output = (!warnings.is_empty()).then(|| warnings.join("\n\n"));
+            let classname = format!("packr.{}", source);
+            diagnostics.case(&classname, &name, Duration::ZERO, outcome, output);
+        }
+
+        let suites = [build, diagnostics];
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"packr\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            suites.iter().map(|suite| suite.tests).sum::<usize>(),
+            suites.iter().map(|suite| suite.failures).sum::<usize>(),
+            suites.iter().map(|suite| suite.skipped).sum::<usize>(),
+            elapsed.as_secs_f64()
+        );
+        for suite in &suites {
+            suite.write(&mut xml);
+        }
+        xml.push_str("</testsuites>\n");
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(path, xml).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+// * How a test case ended
+enum Outcome<'a> {
+    Passed,
+    // `(type, text)`, where the first line of the text is the message
+    Failed(&'a str, String),
+    Skipped(&'a str),
+}
+
+// * A `<testsuite>` being assembled
+struct Suite {
+    name: &'static str,
+    tests: usize,
+    failures: usize,
+    skipped: usize,
+    // Steps nest, so this is the duration of the build rather than the sum of theirs
+    time: Duration,
+    cases: String,
+}
+
+impl Suite {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            tests: 0,
+            failures: 0,
+            skipped: 0,
+            time: Duration::ZERO,
+            cases: String::new(),
+        }
+    }
+
+    // * Add a test case; `output` is shown next to it by CI servers
+    fn case(
+        &mut self,
+        classname: &str,
+        name: &str,
+        time: Duration,
+        outcome: Outcome,
+        output: Option<String>,
+    ) {
+        self.tests += 1;
+        let mut body = String::new();
+        match outcome {
+            Outcome::Passed => {}
+            Outcome::Failed(kind, text) => {
+                self.failures += 1;
+                let message = text.lines().next().unwrap_or_default();
+                let _ = writeln!(
+                    body,
+                    "      <failure type=\"{}\" message=\"{}\">{}</failure>",
+                    escape(kind),
+                    escape(message),
+                    escape(&text)
+                );
+            }
+            Outcome::Skipped(message) => {
+                self.skipped += 1;
+                let _ = writeln!(body, "      <skipped message=\"{}\"/>", escape(message));
+            }
+        }
+        if let Some(output) = output {
+            let _ = writeln!(body, "      <system-out>{}</system-out>", escape(&output));
+        }
+
+        let _ = write!(
+            self.cases,
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            escape(classname),
+            escape(name),
+            time.as_secs_f64()
+        );
+        if body.is_empty() {
+            self.cases.push_str("/>\n");
+        } else {
+            let _ = write!(self.cases, ">\n{}    </testcase>\n", body);
+        }
+    }
+
+    fn write(&self, xml: &mut String) {
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\">\n{}  </testsuite>",
+            self.name,
+            self.tests,
+            self.failures,
+            self.skipped,
+            self.time.as_secs_f64(),
+            self.cases
+        );
+    }
+}
+
+// * `text` as XML character data or attribute value. Control characters XML can't hold at
+// * all, like the escape codes of colored tool output, are dropped.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c < ' ' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
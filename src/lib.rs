@@ -0,0 +1,392 @@
+// * ! ==================================================
+// * ! Library interface for Packr
+// * ! ==================================================
+
+//! Packr compiles SCSS with grass and lightningcss and bundles JavaScript with esbuild,
+//! driven by a `.packr.json` config. The `asset-pipeline` binary is a thin wrapper around
+//! this crate, so site generators and deploy tools can run the same builds in-process:
+//!
+//! ```no_run
+//! let (config, config_dir) = packr::load_config(".packr.json")?;
+//! packr::run_build(&config, &config_dir, std::path::Path::new(".packr.json"), false)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`PackrBuilder`] runs the same build and streams [`Event`]s to a callback, for embedders
+//! that render their own progress. [`build_styles`] and [`build_scripts`] run one half of a
+//! build, recording what they write in a [`Manifest`]; [`watch_styles`] keeps the stylesheets
+//! (and the scripts of bundles) rebuilt as their sources change. [`PackrPlugin`]s hook into the
+//! pipeline from Rust, and [`embed::run_from_build_script`] builds the assets of a Rust web app
+//! from its Cargo build script.
+
+mod analyze;
+mod archive;
+mod audit;
+mod baseline;
+mod budgets;
+mod build;
+mod build_record;
+mod builder;
+mod bundle;
+mod cache;
+mod children;
+mod clean;
+#[doc(hidden)]
+pub mod cli;
+mod codequality;
+mod compress;
+mod control;
+mod copy;
+mod curl;
+mod daemon;
+mod deploy;
+mod diagnostics;
+mod duplicates;
+pub mod embed;
+mod error;
+mod esbuild;
+mod events;
+mod favicons;
+mod fonts;
+mod html;
+mod images;
+mod inline;
+mod instance;
+mod junit;
+mod licenses;
+mod lock;
+mod lsp;
+mod manifest;
+mod metrics;
+mod ndjson;
+mod notify;
+mod permissions;
+pub mod plugin;
+mod polyfill;
+mod profile;
+mod prune;
+mod report;
+mod reproducible;
+mod retry;
+mod runtime;
+mod shim;
+mod sizes;
+mod sourcemap_upload;
+mod sourcemaps;
+mod svg;
+mod vendor;
+mod verify;
+mod wasm_plugin;
+mod watch;
+mod woff2;
+mod wordpress;
+mod workspace;
+
+pub use build::{build_scripts, build_styles, load_config, Config, SourcemapMode, StrictWarnings};
+pub use builder::PackrBuilder;
+pub use diagnostics::Severity;
+pub use error::PackrError;
+pub use events::Event;
+pub use manifest::Manifest;
+pub use plugin::PackrPlugin;
+pub use watch::watch_styles;
+
+use bundle::Step;
+use colored::*;
+use rayon::prelude::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// Run one complete build of the project described by `config`: styles and scripts, then
+/// every post-pass the config enables (manifest, copies, HTML, compression, budgets, ...).
+/// Returns `Ok(false)` when `skip_unchanged` found no input changed since the last build;
+/// `force` builds regardless. A failure names the step that failed, with the error it failed
+/// on as its [`source`](std::error::Error::source) and that error's [`code`](PackrError::code).
+pub fn run_build(
+    config: &Config,
+    config_dir: &Path,
+    config_path: &Path,
+    force: bool,
+) -> Result<bool, PackrError> {
+    let start = Instant::now();
+    let recorder = notify::start(config, config_dir);
+    let record = build_record::record();
+    events::emit(|| Event::BuildStarted { kind: "build" });
+    let result = build_project(config, config_dir, config_path, force);
+    events::finished("build", start, &result);
+    // * A build that can't leave its record behind still built its outputs
+    if let Err(e) = record.write(config, config_dir, config_path, &result) {
+        build::log_line(format!("{} {}", "Build record".yellow().bold(), e));
+    }
+    if let Some(recorder) = recorder {
+        recorder.finish(
+            config,
+            config_dir,
+            "build",
+            &result,
+            matches!(result, Ok(true)),
+        );
+    }
+    result
+}
+
+fn build_project(
+    config: &Config,
+    config_dir: &Path,
+    config_path: &Path,
+    force: bool,
+) -> Result<bool, PackrError> {
+    // * Wait for (or fail on) another run writing the same outputs
+    let _lock = instance::acquire(config, config_dir, "build")
+        .map_err(|e| PackrError::context("Lock failed", e))?;
+
+    let started = SystemTime::now();
+    let cache_dir = cache::dir(config, config_dir);
+    let tool_versions = match prepare_build(config, config_dir, config_path, force)? {
+        Prepared::UpToDate => return Ok(false),
+        Prepared::Ready(tool_versions) => tool_versions,
+    };
+
+    let mut manifest = Manifest::new(config, false, manifest_path(config, config_dir));
+
+    // * Compile SCSS to CSS and bundle JavaScript for every entry side by side, as none
+    // * depends on another
+    build_entries(config, &bundle::tasks(config), config_dir, &mut manifest)?;
+
+    // * Make source map paths independent of where the project was built, before the
+    // * maps are uploaded
+    sourcemaps::normalize(config, config_dir, &manifest.files())
+        .map_err(|e| PackrError::context("Source maps failed", e))?;
+
+    // * Hand the source maps to an error tracker, and keep them out of the deployment if asked
+    sourcemap_upload::run(config, config_dir, &mut manifest)
+        .map_err(|e| PackrError::context("Source map upload failed", e))?;
+
+    // * Copied images are recorded with their variants, so they are in place before the manifest
+    copy_static_assets(config, config_dir, &mut manifest)?;
+    write_manifest(&manifest)?;
+    write_favicons(config, config_dir)?;
+    write_html(config, config_dir, &manifest)?;
+    write_wordpress_assets(config, config_dir, &manifest)?;
+
+    // * Parse the emitted CSS and JavaScript again before anything treats them as final
+    if config.verify {
+        verify::verify(config, config_dir, &manifest.files())
+            .map_err(|e| PackrError::context("Verification failed", e))?;
+    }
+
+    // * Precompress the final CSS/JS for servers that serve .gz/.br files directly
+    compress::compress_files(&config.compress, &manifest.files())
+        .map_err(|e| PackrError::context("Compression failed", e))?;
+
+    // * Let a web server running as another user read the outputs; this needs the timestamps
+    // * of this build, so it runs before they are pinned
+    permissions::apply(config, config_dir, started)
+        .map_err(|e| PackrError::context("Permissions failed", e))?;
+
+    // * Pin timestamps and strip machine-specific paths so the outputs can be compared
+    if config.reproducible {
+        reproducible::finalize(&manifest.files())
+            .map_err(|e| PackrError::context("Reproducible build failed", e))?;
+    }
+
+    // * Remove outputs of entries that were renamed or removed since the previous build
+    prune::prune(config, config_dir, &manifest.files())
+        .map_err(|e| PackrError::context("Prune failed", e))?;
+
+    // * Show how the output sizes moved since the previous build
+    sizes::report(&cache_dir, config_dir, &manifest.outputs())
+        .map_err(|e| PackrError::context("Sizes failed", e))?;
+
+    // * Fail (or warn) when an output outgrew its budget
+    budgets::check(&config.budgets, config_dir, &manifest.outputs())
+        .map_err(|e| PackrError::context("Budgets failed", e))?;
+
+    // * Hand the finished outputs to plugins, e.g. to upload them
+    plugin::emit(config, config_dir, &manifest)
+        .map_err(|e| PackrError::context("Emit failed", e))?;
+
+    // * Publish the outputs, e.g. to a bucket behind a CDN
+    deploy::run(config, config_dir, &manifest)
+        .map_err(|e| PackrError::context("Deploy failed", e))?;
+
+    // * Hashed after the build, which recorded the sources it read outside the input
+    // * directories
+    if config.skip_unchanged {
+        let hash = cache::input_hash(config, config_dir, config_path);
+        cache::store(&cache_dir, &hash, &manifest.files())
+            .map_err(|e| PackrError::context("Cache failed", e))?;
+    }
+
+    // * Lock the tool versions of the first successful build
+    if let Some(ref versions) = tool_versions {
+        lock::write(config_dir, versions)
+            .map_err(|e| PackrError::context("Tool lock failed", e))?;
+    }
+
+    Ok(true)
+}
+
+// * Where a build stands once its checks ran: up to date, or ready to build with the tool
+// * versions to lock when it succeeds
+enum Prepared {
+    UpToDate,
+    Ready(Option<lock::Versions>),
+}
+
+// * What every build and watch session runs before building: the cache is checked, then,
+// * unless `skip_unchanged` finds nothing changed and `force` is off, the tool versions are
+// * compared against .packr.lock, dependencies are audited and stale outputs are removed
+fn prepare_build(
+    config: &Config,
+    config_dir: &Path,
+    config_path: &Path,
+    force: bool,
+) -> Result<Prepared, PackrError> {
+    cache::validate(config, config_dir, config_path)
+        .map_err(|e| PackrError::context("Cache failed", e))?;
+
+    // * Skip everything, including the audit, when no input changed since the last build
+    if config.skip_unchanged && !force {
+        let hash = cache::input_hash(config, config_dir, config_path);
+        if cache::is_up_to_date(&cache::dir(config, config_dir), &hash) {
+            return Ok(Prepared::UpToDate);
+        }
+    }
+
+    // * Compare the tool versions against .packr.lock before spending time on the build
+    let tool_versions = match config.tool_lock {
+        Some(ref mode) => {
+            let versions = lock::current(config, config_dir);
+            lock::check(mode, config_dir, &versions)
+                .map_err(|e| PackrError::context("Tool lock failed", e))?;
+            Some(versions)
+        }
+        None => None,
+    };
+
+    // * Check dependencies for known vulnerabilities before bundling
+    if config.audit {
+        audit::run_audit(config, config_dir).map_err(|e| PackrError::context("Audit failed", e))?;
+    }
+
+    // * Remove stale bundles so renamed outputs don't linger in deployments
+    if config.clean_output {
+        clean::clean_outputs(config, config_dir)
+            .map_err(|e| PackrError::context("Clean failed", e))?;
+    }
+    Ok(Prepared::Ready(tool_versions))
+}
+
+// * Build entries on a pool of `bundle::jobs` workers, each recording into a manifest of its
+// * own that's merged in entry order afterwards
+fn build_entries(
+    config: &Config,
+    tasks: &[bundle::Task],
+    config_dir: &Path,
+    manifest: &mut Manifest,
+) -> Result<(), PackrError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(bundle::jobs(config).min(tasks.len()))
+        .thread_name(|i| format!("build-{}", i))
+        .build()
+        .map_err(|e| PackrError::context("Failed to start the build workers", e))?;
+
+    let results: Vec<(Manifest, Result<(), PackrError>)> = pool.install(|| {
+        tasks
+            .par_iter()
+            .map(|task| {
+                let mut entry_manifest = manifest.fork();
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    build::set_log_scope(Some(&task.name));
+                    build_entry(task, config_dir, &mut entry_manifest)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(PackrError::context(
+                        "Build failed",
+                        format!("{} build panicked", task.name),
+                    ))
+                });
+                build::set_log_scope(None);
+                (entry_manifest, result)
+            })
+            .collect()
+    });
+
+    for (entry_manifest, result) in results {
+        result?;
+        manifest.merge(entry_manifest);
+    }
+    Ok(())
+}
+
+// * Build one entry, failing with its context, e.g. `Styles failed (admin.css)`
+fn build_entry(
+    task: &bundle::Task,
+    config_dir: &Path,
+    manifest: &mut Manifest,
+) -> Result<(), PackrError> {
+    match task.step {
+        Step::Styles => build_styles(&task.config, config_dir, manifest),
+        Step::Scripts => build_scripts(&task.config, config_dir, false, manifest),
+    }
+    .map_err(|e| PackrError::context(&task.failed, e))
+}
+
+fn write_manifest(manifest: &Manifest) -> Result<(), PackrError> {
+    manifest
+        .write()
+        .map_err(|e| PackrError::context("Manifest failed", e))
+}
+
+// * Mirror configured static assets into their output directories, then write the
+// * image variants of what was copied
+fn copy_static_assets(
+    config: &Config,
+    config_dir: &Path,
+    manifest: &mut Manifest,
+) -> Result<(), PackrError> {
+    let copied = copy::run_copy_tasks(config, config_dir)
+        .map_err(|e| PackrError::context("Copy failed", e))?;
+    images::write_variants(config, config_dir, &copied, manifest)
+        .map_err(|e| PackrError::context("Images failed", e))
+}
+
+// * Generate the favicon and app icon set from the configured source image
+fn write_favicons(config: &Config, config_dir: &Path) -> Result<(), PackrError> {
+    match config.favicons {
+        Some(ref settings) => favicons::generate(settings, config_dir)
+            .map_err(|e| PackrError::context("Favicons failed", e)),
+        None => Ok(()),
+    }
+}
+
+// * Inject the built asset tags into the configured HTML templates
+fn write_html(config: &Config, config_dir: &Path, manifest: &Manifest) -> Result<(), PackrError> {
+    html::process_templates(config, config_dir, manifest)
+        .map_err(|e| PackrError::context("HTML failed", e))
+}
+
+// * Write the PHP asset map used to enqueue the build from a WordPress theme
+fn write_wordpress_assets(
+    config: &Config,
+    config_dir: &Path,
+    manifest: &Manifest,
+) -> Result<(), PackrError> {
+    wordpress::write_assets(config, config_dir, manifest)
+        .map_err(|e| PackrError::context("WordPress assets failed", e))
+}
+
+/// Where the asset manifest of `config` is written: next to the JavaScript output unless
+/// configured, named after its format
+pub fn manifest_path(config: &Config, config_dir: &Path) -> PathBuf {
+    let name = match config.manifest_format.as_str() {
+        "rails" => ".manifest.json",
+        _ => "manifest.json",
+    };
+    match config.manifest {
+        Some(ref path) => config_dir.join(path),
+        None => config_dir.join(&config.js_output).with_file_name(name),
+    }
+}
@@ -0,0 +1,193 @@
+// * ! ==================================================
+// * ! Third-party license report for Packr bundles
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::retry;
+use colored::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Report file names, written next to the JavaScript output
+const REPORT_TEXT: &str = "THIRD-PARTY-LICENSES.txt";
+const REPORT_JSON: &str = "THIRD-PARTY-LICENSES.json";
+
+// * License file names checked in each package root, in order
+const LICENSE_FILES: [&str; 8] = [
+    "LICENSE",
+    "LICENSE.md",
+    "LICENSE.txt",
+    "LICENCE",
+    "LICENCE.md",
+    "LICENCE.txt",
+    "license",
+    "license.md",
+];
+
+// * A bundled npm package and its license details
+#[derive(Debug, serde::Serialize)]
+struct Package {
+    name: String,
+    version: Option<String>,
+    license: Option<String>,
+    repository: Option<String>,
+    #[serde(skip)]
+    license_text: Option<String>,
+}
+
+// * Root directory of the package an input belongs to, e.g. `node_modules/@scope/name`
+pub fn package_root(input: &str) -> Option<PathBuf> {
+    let (before, after) = input.rsplit_once("node_modules/")?;
+    let mut parts = after.split('/');
+    let first = parts.next()?;
+    let name = if first.starts_with('@') {
+        format!("{}/{}", first, parts.next()?)
+    } else {
+        first.to_string()
+    };
+    Some(PathBuf::from(format!("{}node_modules/{}", before, name)))
+}
+
+// * `license` as SPDX string, or the legacy `{ "type": ... }` / `licenses` array forms
+fn license_field(package: &serde_json::Value) -> Option<String> {
+    match &package["license"] {
+        serde_json::Value::String(license) => return Some(license.clone()),
+        serde_json::Value::Object(license) => {
+            return license.get("type")?.as_str().map(str::to_string)
+        }
+        _ => {}
+    }
+    let types: Vec<&str> = package["licenses"]
+        .as_array()?
+        .iter()
+        .filter_map(|l| l["type"].as_str())
+        .collect();
+    (!types.is_empty()).then(|| format!("({})", types.join(" OR ")))
+}
+
+fn repository_field(package: &serde_json::Value) -> Option<String> {
+    match &package["repository"] {
+        serde_json::Value::String(url) => Some(url.clone()),
+        repository => repository["url"].as_str().map(str::to_string),
+    }
+}
+
+fn read_package(root: &Path) -> Option<Package> {
+    let content = fs::read_to_string(root.join("package.json")).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&content).ok()?;
+
+    Some(Package {
+        name: json["name"].as_str()?.to_string(),
+        version: json["version"].as_str().map(str::to_string),
+        license: license_field(&json),
+        repository: repository_field(&json),
+        license_text: LICENSE_FILES
+            .iter()
+            .find_map(|name| fs::read_to_string(root.join(name)).ok())
+            .map(|text| text.trim_end().to_string()),
+    })
+}
+
+// * Packages with at least one module in the bundle, keyed by name and version
+fn bundled_packages(metafiles: &[PathBuf]) -> Result<BTreeMap<String, Package>, String> {
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for metafile in metafiles.iter().filter(|m| m.exists()) {
+        let content = fs::read_to_string(metafile)
+            .map_err(|e| format!("Failed to read esbuild metafile: {}", e))?;
+        let json: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse esbuild metafile: {}", e))?;
+
+        // Only modules that ended up in an output, not everything esbuild resolved
+        for output in json["outputs"]
+            .as_object()
+            .into_iter()
+            .flat_map(|o| o.values())
+        {
+            for input in output["inputs"]
+                .as_object()
+                .into_iter()
+                .flat_map(|i| i.keys())
+            {
+                if let Some(root) = package_root(input) {
+                    if !roots.contains(&root) {
+                        roots.push(root);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut packages = BTreeMap::new();
+    for root in roots {
+        if let Some(package) = read_package(&root) {
+            let key = format!(
+                "{}@{}",
+                package.name,
+                package.version.as_deref().unwrap_or("")
+            );
+            packages.entry(key).or_insert(package);
+        }
+    }
+    Ok(packages)
+}
+
+fn text_report(packages: &BTreeMap<String, Package>) -> String {
+    let mut out = String::from("Third-party software bundled in this build\n");
+    for package in packages.values() {
+        out.push_str(&format!("\n{}\n", "=".repeat(72)));
+        out.push_str(&format!(
+            "{}{}\n",
+            package.name,
+            package
+                .version
+                .as_ref()
+                .map(|v| format!(" {}", v))
+                .unwrap_or_default()
+        ));
+        out.push_str(&format!(
+            "License: {}\n",
+            package.license.as_deref().unwrap_or("UNKNOWN")
+        ));
+        if let Some(ref repository) = package.repository {
+            out.push_str(&format!("Repository: {}\n", repository));
+        }
+        if let Some(ref text) = package.license_text {
+            out.push_str(&format!("\n{}\n", text));
+        }
+    }
+    out
+}
+
+// * Write the text and JSON license reports for the packages in the given builds
+pub fn write_report(metafiles: &[PathBuf], output_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let packages = bundled_packages(metafiles)?;
+
+    let text_path = output_dir.join(REPORT_TEXT);
+    retry::write(&text_path, text_report(&packages))
+        .map_err(|e| format!("Failed to write {}: {}", text_path.display(), e))?;
+
+    let json: Vec<&Package> = packages.values().collect();
+    let json_path = output_dir.join(REPORT_JSON);
+    let content = serde_json::to_string_pretty(&json)
+        .map_err(|e| format!("Failed to serialize license report: {}", e))?;
+    retry::write(&json_path, content + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", json_path.display(), e))?;
+
+    let unknown = packages.values().filter(|p| p.license.is_none()).count();
+    log_line(format!(
+        "{} {} package(s) listed in {}",
+        "Licenses".green().bold(),
+        packages.len(),
+        text_path.display()
+    ));
+    if unknown > 0 {
+        log_line(format!(
+            "{} {} package(s) declare no license",
+            "Licenses".yellow().bold(),
+            unknown
+        ));
+    }
+
+    Ok(vec![text_path, json_path])
+}
@@ -0,0 +1,129 @@
+// * ! ==================================================
+// * ! Tool version lockfile for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::esbuild;
+use crate::retry;
+use crate::runtime::{self, Runtime};
+use colored::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// * Tool versions of the first successful build, next to the config file so it can be committed
+pub const LOCK_FILE: &str = ".packr.lock";
+
+// * Values accepted by the `tool_lock` option
+pub const MODES: [&str; 2] = ["warn", "error"];
+
+// * Version per tool, e.g. `esbuild` → `0.21.5`
+pub type Versions = BTreeMap<String, String>;
+
+// * Versions of Packr and of the runtime, esbuild and ESLint this build runs. ESLint is only
+// * recorded when installed in `node_modules`, as a copy fetched by npx isn't pinned anyway.
+pub fn current(config: &Config, config_dir: &Path) -> Versions {
+    let mut versions = Versions::new();
+    versions.insert("packr".to_string(), env!("CARGO_PKG_VERSION").to_string());
+
+    let runtime = Runtime::resolve(config.runtime.as_deref(), config_dir);
+    if let Some(version) = runtime.version() {
+        versions.insert(runtime.name().to_string(), version);
+    }
+    if let Ok(version) = runtime
+        .tool_command("esbuild", config_dir)
+        .and_then(esbuild::version)
+    {
+        versions.insert(
+            "esbuild".to_string(),
+            version.trim_start_matches('v').to_string(),
+        );
+    }
+    if config.eslint {
+        let eslint = runtime::package_manifest("eslint", config_dir)
+            .and_then(|manifest| manifest["version"].as_str().map(str::to_string));
+        if let Some(version) = eslint {
+            versions.insert("eslint".to_string(), version);
+        }
+    }
+    versions
+}
+
+fn read(config_dir: &Path) -> Result<Option<Versions>, String> {
+    let path = config_dir.join(LOCK_FILE);
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+// * Compare this build's tool versions against the lockfile. A drift fails the build in
+// * `error` mode and is printed in `warn` mode; tools missing from the lockfile are not drifts.
+pub fn check(mode: &str, config_dir: &Path, versions: &Versions) -> Result<(), String> {
+    let Some(locked) = read(config_dir)? else {
+        return Ok(());
+    };
+    let drifted: Vec<(&String, &String, &String)> = versions
+        .iter()
+        .filter_map(|(tool, version)| {
+            let locked = locked.get(tool)?;
+            (locked != version).then_some((tool, version, locked))
+        })
+        .collect();
+    if drifted.is_empty() {
+        return Ok(());
+    }
+
+    if mode == "error" {
+        let list: Vec<String> = drifted
+            .iter()
+            .map(|(tool, version, locked)| format!("{} {} (locked {})", tool, version, locked))
+            .collect();
+        return Err(format!(
+            "tool versions differ from {}: {}; install the locked versions, or delete {} to lock the current ones",
+            LOCK_FILE,
+            list.join(", "),
+            LOCK_FILE
+        ));
+    }
+    for (tool, version, locked) in drifted {
+        log_line(format!(
+            "{} {} {} found, {} has {}",
+            "Lock".yellow().bold(),
+            tool,
+            version,
+            LOCK_FILE,
+            locked
+        ));
+    }
+    Ok(())
+}
+
+// * Record the versions of a successful build. Locked entries are kept as they are, so a drift
+// * is reported until the lockfile is deleted; tools the build newly runs are added.
+pub fn write(config_dir: &Path, versions: &Versions) -> Result<(), String> {
+    let existing = read(config_dir)?;
+    let mut locked = existing.clone().unwrap_or_default();
+    for (tool, version) in versions {
+        locked
+            .entry(tool.clone())
+            .or_insert_with(|| version.clone());
+    }
+    if existing.as_ref() == Some(&locked) {
+        return Ok(());
+    }
+
+    let path = config_dir.join(LOCK_FILE);
+    let json = serde_json::to_string_pretty(&locked)
+        .map_err(|e| format!("Failed to serialize {}: {}", LOCK_FILE, e))?;
+    retry::write(&path, json + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    log_line(format!(
+        "{} tool versions in {}",
+        "Locked".green().bold(),
+        LOCK_FILE
+    ));
+    Ok(())
+}
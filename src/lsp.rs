@@ -0,0 +1,984 @@
+// * ! ==================================================
+// * ! Editor diagnostics server for Packr
+// * ! ==================================================
+
+use crate::build::{self, Config};
+use crate::bundle;
+use crate::cache;
+use crate::children;
+use crate::diagnostics::{self, Severity};
+use crate::runtime::{arg_path, Runtime};
+use codemap::SpanLoc;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// * Quiet time after an edit before the sources are checked again, so typing doesn't queue a
+// * check per keystroke
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+// * esbuild loaders of the script files the server checks
+const SCRIPT_LOADERS: [(&str, &str); 8] = [
+    ("js", "js"),
+    ("mjs", "js"),
+    ("cjs", "js"),
+    ("jsx", "jsx"),
+    ("ts", "ts"),
+    ("mts", "ts"),
+    ("cts", "ts"),
+    ("tsx", "tsx"),
+];
+
+// * JSON-RPC error for requests the server doesn't implement
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// * A file the editor has open, with its unsaved text
+#[derive(Debug)]
+struct Document {
+    uri: String,
+    text: String,
+}
+
+// * A diagnostic with a zero-based `(line, column)` range, as LSP counts them, with columns in
+// * UTF-16 code units
+struct Finding {
+    severity: Severity,
+    source: &'static str,
+    code: Option<String>,
+    message: String,
+    start: (usize, usize),
+    end: (usize, usize),
+    related: Option<Related>,
+}
+
+// * Where a finding shown on an importing stylesheet was reported: an unopened partial
+struct Related {
+    uri: String,
+    start: (usize, usize),
+    end: (usize, usize),
+    message: String,
+}
+
+// * How a tool counts the columns it reports
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Chars,
+    Bytes,
+}
+
+// * `column` of `line`, counted in `unit`s, as UTF-16 code units; past the end of the line
+// * every column is one unit
+fn utf16_column(line: &str, column: usize, unit: Unit) -> usize {
+    let mut units = 0;
+    let mut counted = 0;
+    for (index, ch) in line.char_indices() {
+        let position = match unit {
+            Unit::Chars => counted,
+            Unit::Bytes => index,
+        };
+        if position >= column {
+            return units;
+        }
+        units += ch.len_utf16();
+        counted += 1;
+    }
+    let length = match unit {
+        Unit::Chars => counted,
+        Unit::Bytes => line.len(),
+    };
+    units + column.saturating_sub(length)
+}
+
+// * Zero-based line `number` of `text`, empty past its end
+fn line_of(text: &str, number: usize) -> &str {
+    text.lines().nth(number).unwrap_or("")
+}
+
+impl Finding {
+    // * A finding at a one-based line and column, spanning a single character
+    fn at(
+        severity: Severity,
+        source: &'static str,
+        message: String,
+        line: Option<usize>,
+        column: Option<usize>,
+    ) -> Self {
+        let start = (
+            line.unwrap_or(1).saturating_sub(1),
+            column.unwrap_or(1).saturating_sub(1),
+        );
+        Finding {
+            severity,
+            source,
+            code: None,
+            message,
+            start,
+            end: (start.0, start.1 + 1),
+            related: None,
+        }
+    }
+
+    // * codemap counts columns in characters of the compiled source
+    fn from_span(severity: Severity, source: &'static str, message: String, loc: &SpanLoc) -> Self {
+        let column = |line: usize, column: usize| {
+            let text = if line < loc.file.num_lines() {
+                loc.file.source_line(line)
+            } else {
+                ""
+            };
+            utf16_column(text, column, Unit::Chars)
+        };
+        let start = (loc.begin.line, column(loc.begin.line, loc.begin.column));
+        let end = (loc.end.line, column(loc.end.line, loc.end.column));
+        Finding {
+            severity,
+            source,
+            code: None,
+            message,
+            start,
+            end: if end > start {
+                end
+            } else {
+                (start.0, start.1 + 1)
+            },
+            related: None,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let range = |start: (usize, usize), end: (usize, usize)| {
+            serde_json::json!({
+                "start": { "line": start.0, "character": start.1 },
+                "end": { "line": end.0, "character": end.1 },
+            })
+        };
+        let mut diagnostic = serde_json::json!({
+            "range": range(self.start, self.end),
+            "severity": match self.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            "source": self.source,
+            "message": self.message,
+        });
+        if let Some(ref code) = self.code {
+            diagnostic["code"] = serde_json::json!(code);
+        }
+        if let Some(ref related) = self.related {
+            diagnostic["relatedInformation"] = serde_json::json!([{
+                "location": { "uri": related.uri, "range": range(related.start, related.end) },
+                "message": related.message,
+            }]);
+        }
+        diagnostic
+    }
+}
+
+// * Read one `Content-Length` framed message; `None` once the editor closed the stream
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(out: &mut impl Write, message: &serde_json::Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+// * Path of a `file://` URI, undoing its percent-encoding
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    // `file:///C:/...` on Windows
+    let encoded = match encoded.as_bytes() {
+        [b'/', _, b':', ..] if cfg!(windows) => &encoded[1..],
+        _ => encoded,
+    };
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+// * `file://` URI of a path, for diagnostics of files the editor didn't name itself
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+// * Files a Sass import of `url` from a stylesheet in `dir` can load, as grass looks them up
+fn import_candidates(dir: &Path, url: &str) -> Vec<PathBuf> {
+    let path = build::normalize_path(&dir.join(url));
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Vec::new();
+    };
+    let name = name.to_string_lossy();
+    let mut candidates = vec![path.clone()];
+    for ext in ["scss", "css"] {
+        candidates.push(parent.join(format!("{}.{}", name, ext)));
+        candidates.push(parent.join(format!("_{}.{}", name, ext)));
+        candidates.push(path.join(format!("index.{}", ext)));
+        candidates.push(path.join(format!("_index.{}", ext)));
+    }
+    candidates
+}
+
+// * Range of the `@use`, `@forward` or `@import` in `text`, the source of `importer`, that
+// * loads `imported`
+fn import_range(
+    text: &str,
+    importer: &Path,
+    imported: &Path,
+) -> Option<((usize, usize), (usize, usize))> {
+    let dir = importer.parent()?;
+    text.lines().enumerate().find_map(|(number, line)| {
+        let rule = line.trim_start();
+        if !["@use", "@forward", "@import"]
+            .iter()
+            .any(|keyword| rule.starts_with(keyword))
+        {
+            return None;
+        }
+        // The quoted URLs are every other piece between quotes
+        let loads = rule
+            .split(['"', '\''])
+            .skip(1)
+            .step_by(2)
+            .any(|url| import_candidates(dir, url).iter().any(|c| c == imported));
+        let indent = line.len() - rule.len();
+        loads.then(|| {
+            (
+                (number, utf16_column(line, indent, Unit::Bytes)),
+                (
+                    number,
+                    utf16_column(line, line.trim_end().len(), Unit::Bytes),
+                ),
+            )
+        })
+    })
+}
+
+// * grass file system that serves the editor's unsaved buffers in place of the files on disk,
+// * and remembers which files a compile read
+#[derive(Debug)]
+struct OverlayFs<'a> {
+    server: &'a Server<'a>,
+    reads: RefCell<BTreeSet<PathBuf>>,
+}
+
+impl grass::Fs for OverlayFs<'_> {
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.server
+            .documents
+            .contains_key(&self.server.absolute(path))
+            || path.is_file()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let absolute = self.server.absolute(path);
+        let content = match self.server.documents.get(&absolute) {
+            Some(document) => document.text.clone().into_bytes(),
+            None => fs::read(path)?,
+        };
+        self.reads.borrow_mut().insert(absolute);
+        Ok(content)
+    }
+}
+
+// * grass logger that keeps `@warn` messages with their locations
+#[derive(Debug, Default)]
+struct WarningLogger {
+    warnings: RefCell<Vec<(SpanLoc, String)>>,
+}
+
+impl grass::Logger for WarningLogger {
+    fn debug(&self, _location: SpanLoc, _message: &str) {}
+
+    fn warn(&self, location: SpanLoc, message: &str) {
+        self.warnings
+            .borrow_mut()
+            .push((location, message.to_string()));
+    }
+}
+
+#[derive(Debug)]
+struct Server<'a> {
+    config: &'a Config,
+    config_dir: &'a Path,
+    cwd: PathBuf,
+    documents: BTreeMap<PathBuf, Document>,
+}
+
+impl<'a> Server<'a> {
+    // * `path` made absolute against the working directory, the form documents are keyed by
+    fn absolute(&self, path: &Path) -> PathBuf {
+        build::normalize_path(&self.cwd.join(path))
+    }
+
+    fn text(&self, path: &Path) -> Option<String> {
+        match self.documents.get(path) {
+            Some(document) => Some(document.text.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    fn uri(&self, path: &Path) -> String {
+        self.documents
+            .get(path)
+            .map(|document| document.uri.clone())
+            .unwrap_or_else(|| path_to_uri(path))
+    }
+
+    // * Diagnostics of `paths`, plus those of every other open stylesheet, as one edit to a
+    // * partial can break or fix the stylesheets that use it
+    fn check(&self, paths: &BTreeSet<PathBuf>) -> BTreeMap<PathBuf, Vec<Finding>> {
+        let mut findings = BTreeMap::new();
+        if paths.iter().any(|path| extension(path) == "scss") {
+            for path in self.documents.keys().filter(|p| extension(p) == "scss") {
+                findings.insert(path.clone(), Vec::new());
+            }
+            for (path, finding) in self.check_styles() {
+                if let Some(found) = findings.get_mut(&path) {
+                    found.push(finding);
+                }
+            }
+        }
+        for path in paths {
+            if let Some((_, loader)) = SCRIPT_LOADERS.iter().find(|(e, _)| *e == extension(path)) {
+                findings.insert(path.clone(), self.check_script(path, loader));
+            }
+        }
+        findings
+    }
+
+    // * Compile `scss_input` and the stylesheets of `bundles` with the open buffers in place,
+    // * then every open stylesheet that isn't a partial and wasn't part of those, on its own
+    fn check_styles(&self) -> Vec<(PathBuf, Finding)> {
+        let mut findings = Vec::new();
+        let mut compiled = BTreeSet::new();
+        let entries: Vec<PathBuf> = bundle::inputs(self.config)
+            .into_iter()
+            .filter(|(field, _)| field.ends_with("scss_input"))
+            .map(|(_, input)| self.absolute(&self.config_dir.join(input)))
+            .collect();
+        let standalone = self.documents.keys().filter(|path| {
+            extension(path) == "scss"
+                && !path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('_'))
+        });
+        for input in entries.iter().chain(standalone) {
+            if compiled.contains(input) || !(self.documents.contains_key(input) || input.is_file())
+            {
+                continue;
+            }
+            let fs = OverlayFs {
+                server: self,
+                reads: RefCell::new(BTreeSet::new()),
+            };
+            let logger = WarningLogger::default();
+            let options = grass::Options::default().fs(&fs).logger(&logger);
+            let mut found = Vec::new();
+            if let Err(e) = grass::from_path(input, &options) {
+                match (*e).kind() {
+                    grass::ErrorKind::ParseError { message, loc, .. } => found.push((
+                        self.absolute(Path::new(loc.file.name())),
+                        Finding::from_span(Severity::Error, "Sass", message, &loc),
+                    )),
+                    other => found.push((
+                        input.clone(),
+                        Finding::at(Severity::Error, "Sass", format!("{:?}", other), None, None),
+                    )),
+                }
+            }
+            for (loc, message) in logger.warnings.into_inner() {
+                found.push((
+                    self.absolute(Path::new(loc.file.name())),
+                    Finding::from_span(Severity::Warning, "Sass", message, &loc),
+                ));
+            }
+            let reads = fs.reads.into_inner();
+            for (path, finding) in found {
+                if self.documents.contains_key(&path) {
+                    findings.push((path, finding));
+                } else {
+                    findings.extend(self.on_importers(&path, &finding, &reads));
+                }
+            }
+            compiled.extend(reads);
+            compiled.insert(input.clone());
+        }
+        findings
+    }
+
+    // * `finding`, reported in a file the editor doesn't have open, shown on each open stylesheet
+    // * of the compile that imports that file, directly or through other unopened partials, at
+    // * the import. Without such an import it goes on every open stylesheet the compile read.
+    fn on_importers(
+        &self,
+        path: &Path,
+        finding: &Finding,
+        reads: &BTreeSet<PathBuf>,
+    ) -> Vec<(PathBuf, Finding)> {
+        let mut targets = Vec::new();
+        let mut seen = BTreeSet::from([path.to_path_buf()]);
+        let mut imported = vec![path.to_path_buf()];
+        while let Some(partial) = imported.pop() {
+            for importer in reads {
+                if seen.contains(importer) {
+                    continue;
+                }
+                let Some(import) = self
+                    .text(importer)
+                    .and_then(|text| import_range(&text, importer, &partial))
+                else {
+                    continue;
+                };
+                seen.insert(importer.clone());
+                if self.documents.contains_key(importer) {
+                    targets.push((importer.clone(), import));
+                } else {
+                    imported.push(importer.clone());
+                }
+            }
+        }
+        if targets.is_empty() {
+            targets = reads
+                .iter()
+                .filter(|read| self.documents.contains_key(*read))
+                .map(|read| (read.clone(), ((0, 0), (0, 1))))
+                .collect();
+        }
+
+        let shown = path
+            .strip_prefix(self.absolute(self.config_dir))
+            .unwrap_or(path);
+        let message = format!(
+            "{}:{}:{}: {}",
+            shown.display(),
+            finding.start.0 + 1,
+            finding.start.1 + 1,
+            finding.message
+        );
+        targets
+            .into_iter()
+            .map(|(importer, (start, end))| {
+                let moved = Finding {
+                    severity: finding.severity,
+                    source: finding.source,
+                    code: finding.code.clone(),
+                    message: message.clone(),
+                    start,
+                    end,
+                    related: Some(Related {
+                        uri: self.uri(path),
+                        start: finding.start,
+                        end: finding.end,
+                        message: finding.message.clone(),
+                    }),
+                };
+                (importer, moved)
+            })
+            .collect()
+    }
+
+    // * Parse a script with esbuild and lint it with ESLint when the build does
+    fn check_script(&self, path: &Path, loader: &str) -> Vec<Finding> {
+        let Some(text) = self.text(path) else {
+            return Vec::new();
+        };
+        let mut findings = Vec::new();
+        if let Err(e) = self.check_with_esbuild(path, loader, &text, &mut findings) {
+            findings.push(Finding::at(Severity::Warning, "esbuild", e, None, None));
+        }
+        if self.config.eslint {
+            if let Err(e) = self.check_with_eslint(path, &text, &mut findings) {
+                findings.push(Finding::at(Severity::Warning, "ESLint", e, None, None));
+            }
+        }
+        findings
+    }
+
+    // * The buffer goes to the tools through a file in the cache, as their output is read
+    // * only after they exit
+    fn stdin_file(&self, text: &str) -> Result<fs::File, String> {
+        let path = cache::dir(self.config, self.config_dir).join("lsp-buffer");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        fs::File::open(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+
+    fn check_with_esbuild(
+        &self,
+        path: &Path,
+        loader: &str,
+        text: &str,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(), String> {
+        let runtime = Runtime::resolve(self.config.runtime.as_deref(), self.config_dir);
+        let mut cmd = runtime.tool_command("esbuild", self.config_dir)?;
+        cmd.arg(format!("--loader={}", loader))
+            .arg(format!("--sourcefile={}", path.display()))
+            .arg("--log-level=warning")
+            .arg("--color=false");
+        let output = children::output_from("esbuild", &mut cmd, self.stdin_file(text)?)
+            .map_err(|e| format!("Failed to run esbuild: {}", e))?;
+        for diagnostic in diagnostics::parse_esbuild_log(&String::from_utf8_lossy(&output.stderr)) {
+            // esbuild counts columns in bytes, from zero
+            let column = diagnostic.column.map(|column| {
+                let line = line_of(text, diagnostic.line.unwrap_or(1).saturating_sub(1));
+                utf16_column(line, column, Unit::Bytes) + 1
+            });
+            findings.push(Finding::at(
+                diagnostic.severity,
+                "esbuild",
+                diagnostic.message,
+                diagnostic.line,
+                column,
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_with_eslint(
+        &self,
+        path: &Path,
+        text: &str,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(), String> {
+        let eslint_path = build::eslint_config_path(self.config, self.config_dir)?;
+        let runtime = Runtime::resolve(self.config.runtime.as_deref(), self.config_dir);
+        let mut cmd = runtime.tool_command("eslint", self.config_dir)?;
+        cmd.arg("--format=json")
+            .arg("--no-eslintrc")
+            .arg("-c")
+            .arg(arg_path(&eslint_path))
+            .arg("--stdin")
+            .arg("--stdin-filename")
+            .arg(arg_path(path));
+        let output = children::output_from("eslint", &mut cmd, self.stdin_file(text)?)
+            .map_err(|e| format!("Failed to run ESLint: {}", e))?;
+        let results: Vec<serde_json::Value> =
+            serde_json::from_slice(&output.stdout).map_err(|_| {
+                format!(
+                    "ESLint failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )
+            })?;
+        let messages = results
+            .iter()
+            .filter_map(|result| result["messages"].as_array())
+            .flatten();
+        for message in messages {
+            // ESLint counts columns in UTF-16 code units already, as JavaScript strings do
+            let number = |key: &str| message[key].as_u64().map(|n| n as usize);
+            let mut finding = Finding::at(
+                // ESLint's severity 2 is an error, 1 a warning
+                match message["severity"].as_i64() {
+                    Some(2) => Severity::Error,
+                    _ => Severity::Warning,
+                },
+                "ESLint",
+                message["message"].as_str().unwrap_or_default().to_string(),
+                number("line"),
+                number("column"),
+            );
+            if let (Some(line), Some(column)) = (number("endLine"), number("endColumn")) {
+                finding.end = (line.saturating_sub(1), column.saturating_sub(1));
+            }
+            finding.code = message["ruleId"].as_str().map(str::to_string);
+            findings.push(finding);
+        }
+        Ok(())
+    }
+
+    fn publish(&self, out: &mut impl Write, path: &Path, findings: &[Finding]) -> io::Result<()> {
+        let diagnostics: Vec<serde_json::Value> = findings.iter().map(Finding::to_json).collect();
+        write_message(
+            out,
+            &serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": self.uri(path), "diagnostics": diagnostics },
+            }),
+        )
+    }
+
+    fn document_path(&self, params: &serde_json::Value) -> Option<PathBuf> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        uri_to_path(uri).map(|path| self.absolute(&path))
+    }
+}
+
+fn respond(
+    out: &mut impl Write,
+    id: &serde_json::Value,
+    result: serde_json::Value,
+) -> io::Result<()> {
+    write_message(
+        out,
+        &serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+    )
+}
+
+/// Answer diagnostics requests from an editor over stdio, speaking the part of the Language
+/// Server Protocol that carries diagnostics. Open buffers are checked as they change, without
+/// building: stylesheets with grass, scripts with esbuild and, when enabled, ESLint.
+pub fn serve(config: &Config, config_dir: &Path) -> Result<(), String> {
+    // The editor reads the protocol from stdout, so a blocking read of stdin happens elsewhere
+    // and edits arriving while a check runs are picked up together
+    let (sender, messages) = mpsc::channel::<serde_json::Value>();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin().lock());
+        while let Ok(Some(body)) = read_message(&mut reader) {
+            // A malformed message is dropped rather than ending the session
+            if let Ok(message) = serde_json::from_slice(&body) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut server = Server {
+        config,
+        config_dir,
+        cwd: env::current_dir()
+            .map_err(|e| format!("Failed to resolve the working directory: {}", e))?,
+        documents: BTreeMap::new(),
+    };
+    let mut out = io::stdout();
+    let write_failed = |e: io::Error| format!("Failed to write to the editor: {}", e);
+    // Documents edited since their last check
+    let mut pending = BTreeSet::new();
+
+    loop {
+        let message = if pending.is_empty() {
+            messages.recv().ok()
+        } else {
+            match messages.recv_timeout(DEBOUNCE) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => {
+                    for (path, findings) in server.check(&std::mem::take(&mut pending)) {
+                        server
+                            .publish(&mut out, &path, &findings)
+                            .map_err(write_failed)?;
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => None,
+            }
+        };
+        // The editor went away without `exit`
+        let Some(message) = message else {
+            return Ok(());
+        };
+
+        let params = &message["params"];
+        let id = &message["id"];
+        match message["method"].as_str() {
+            Some("initialize") => respond(
+                &mut out,
+                id,
+                serde_json::json!({
+                    "capabilities": {
+                        "textDocumentSync": { "openClose": true, "change": 1, "save": true },
+                        "diagnosticProvider": {
+                            "interFileDependencies": true,
+                            "workspaceDiagnostics": false,
+                        },
+                    },
+                    "serverInfo": { "name": "packr", "version": env!("CARGO_PKG_VERSION") },
+                }),
+            )
+            .map_err(write_failed)?,
+            Some("textDocument/didOpen") => {
+                if let (Some(path), Some(uri), Some(text)) = (
+                    server.document_path(params),
+                    params["textDocument"]["uri"].as_str(),
+                    params["textDocument"]["text"].as_str(),
+                ) {
+                    server.documents.insert(
+                        path.clone(),
+                        Document {
+                            uri: uri.to_string(),
+                            text: text.to_string(),
+                        },
+                    );
+                    pending.insert(path);
+                }
+            }
+            Some("textDocument/didChange") => {
+                // Full sync: the last change holds the whole text
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                if let (Some(path), Some(text)) = (server.document_path(params), text) {
+                    if let Some(document) = server.documents.get_mut(&path) {
+                        document.text = text.to_string();
+                        pending.insert(path);
+                    }
+                }
+            }
+            Some("textDocument/didSave") => {
+                if let Some(path) = server.document_path(params) {
+                    pending.insert(path);
+                }
+            }
+            Some("textDocument/didClose") => {
+                if let Some(path) = server.document_path(params) {
+                    pending.remove(&path);
+                    server.publish(&mut out, &path, &[]).map_err(write_failed)?;
+                    server.documents.remove(&path);
+                }
+            }
+            Some("textDocument/diagnostic") => {
+                let items: Vec<serde_json::Value> = server
+                    .document_path(params)
+                    .and_then(|path| server.check(&BTreeSet::from([path.clone()])).remove(&path))
+                    .unwrap_or_default()
+                    .iter()
+                    .map(Finding::to_json)
+                    .collect();
+                respond(
+                    &mut out,
+                    id,
+                    serde_json::json!({ "kind": "full", "items": items }),
+                )
+                .map_err(write_failed)?;
+            }
+            Some("shutdown") => {
+                respond(&mut out, id, serde_json::Value::Null).map_err(write_failed)?
+            }
+            Some("exit") => return Ok(()),
+            // Requests need an answer, notifications like `initialized` don't
+            Some(method) if !id.is_null() => write_message(
+                &mut out,
+                &serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("{} is not supported", method),
+                    },
+                }),
+            )
+            .map_err(write_failed)?,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("packr-lsp-{}-{}", name, std::process::id()));
+        for (file, text) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    fn config() -> Config {
+        serde_json::from_str(
+            r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js"}"#,
+        )
+        .unwrap()
+    }
+
+    fn open(server: &mut Server, path: &Path) {
+        server.documents.insert(
+            path.to_path_buf(),
+            Document {
+                uri: path_to_uri(path),
+                text: fs::read_to_string(path).unwrap(),
+            },
+        );
+    }
+
+    #[test]
+    fn columns_are_counted_in_utf16() {
+        assert_eq!(utf16_column("a😀b", 2, Unit::Chars), 3);
+        assert_eq!(utf16_column("é = 1", 2, Unit::Bytes), 1);
+        assert_eq!(utf16_column("ab", 4, Unit::Chars), 4);
+    }
+
+    #[test]
+    fn sass_errors_point_at_utf16_columns() {
+        let line = ".a { content: \"😀\"; color: $missing; }";
+        let dir = project("utf16", &[("src/main.scss", line)]);
+        let config = config();
+        let mut server = Server {
+            config: &config,
+            config_dir: &dir,
+            cwd: dir.clone(),
+            documents: BTreeMap::new(),
+        };
+        let main = dir.join("src/main.scss");
+        open(&mut server, &main);
+
+        let findings = server.check(&BTreeSet::from([main.clone()]));
+        let finding = &findings[&main][0];
+        let expected = line[..line.find("$missing").unwrap()]
+            .encode_utf16()
+            .count();
+        assert_eq!(finding.start, (0, expected));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_in_unopened_partials_show_on_the_open_importer() {
+        let dir = project(
+            "partials",
+            &[
+                ("src/main.scss", "@use \"a\";\n"),
+                ("src/_a.scss", ".a { color: red; }\n  @import \"b\";\n"),
+                ("src/_b.scss", ".b { color: $missing; }\n"),
+            ],
+        );
+        let config = config();
+        let mut server = Server {
+            config: &config,
+            config_dir: &dir,
+            cwd: dir.clone(),
+            documents: BTreeMap::new(),
+        };
+        let partial = dir.join("src/_a.scss");
+        open(&mut server, &partial);
+
+        let findings = server.check(&BTreeSet::from([partial.clone()]));
+        assert_eq!(findings.len(), 1);
+        let found = &findings[&partial];
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].start, (1, 2));
+        assert!(
+            found[0].message.starts_with("src/_b.scss:1:"),
+            "{}",
+            found[0].message
+        );
+        let related = found[0].related.as_ref().unwrap();
+        assert!(related.uri.ends_with("src/_b.scss"));
+        assert_eq!(related.start.0, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn partials_of_bundle_stylesheets_are_checked() {
+        let dir = project(
+            "bundles",
+            &[
+                ("src/main.scss", ".main { color: red; }\n"),
+                ("src/admin.scss", "@use \"b\";\n"),
+                ("src/_b.scss", ".b { color: $missing; }\n"),
+            ],
+        );
+        let config: Config = serde_json::from_str(
+            r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js",
+                "bundles": [{"scss_input": "src/admin.scss", "scss_output": "dist/admin.css"}]}"#,
+        )
+        .unwrap();
+        let mut server = Server {
+            config: &config,
+            config_dir: &dir,
+            cwd: dir.clone(),
+            documents: BTreeMap::new(),
+        };
+        let partial = dir.join("src/_b.scss");
+        open(&mut server, &partial);
+
+        let findings = server.check(&BTreeSet::from([partial.clone()]));
+        let found = &findings[&partial];
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].start.0, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_uris_are_percent_decoded() {
+        assert_eq!(
+            uri_to_path("file:///home/me/My%20Site/src/_b%C3%A4se.scss"),
+            Some(PathBuf::from("/home/me/My Site/src/_bäse.scss"))
+        );
+        // A `%` not followed by two hex digits is kept as it is
+        assert_eq!(
+            uri_to_path("file:///tmp/100%.scss"),
+            Some(PathBuf::from("/tmp/100%.scss"))
+        );
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+        // Escapes that don't decode to UTF-8 name no path
+        assert_eq!(uri_to_path("file:///tmp/%FF.scss"), None);
+    }
+}
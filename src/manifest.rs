@@ -0,0 +1,417 @@
+// * ! ==================================================
+// * ! Content hashing, SRI and manifest.json for Packr
+// * ! ==================================================
+
+use crate::build::Config;
+use crate::plugin;
+use crate::retry;
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha384};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// * Hex characters of the content hash kept in file names
+const HASH_LENGTH: usize = 8;
+
+// * Supported values for `hash_mode`
+pub const HASH_MODES: [&str; 2] = ["filename", "query"];
+
+// * Supported values for `manifest_format`
+pub const MANIFEST_FORMATS: [&str; 3] = ["packr", "mix", "rails"];
+
+// * A file written for a logical output
+struct Entry {
+    file: PathBuf,
+    // Content hash appended as `?v=` when file names are kept stable
+    version: Option<String>,
+    integrity: Option<String>,
+}
+
+/// Maps logical output paths to the (possibly hashed) files written for them
+pub struct Manifest {
+    enabled: bool,
+    // Keep file names and record `?v=<hash>` versions instead of renaming
+    query: bool,
+    integrity: bool,
+    format: String,
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+    // Copied images with the file written for each of their formats, e.g. `webp`
+    images: BTreeMap<PathBuf, BTreeMap<String, PathBuf>>,
+}
+
+// * Path of `path` relative to `base`, with forward slashes
+pub fn relative_to(path: &Path, base: &Path) -> String {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path
+        .iter()
+        .zip(base.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); base.len() - common];
+    parts.extend(
+        path[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().to_string()),
+    );
+    parts.join("/")
+}
+
+// * `name.ext` becomes `<pattern>.ext`, with `[name]` and `[hash]` filled in
+fn hashed_name(path: &Path, hash: &str, pattern: &str) -> Result<PathBuf, String> {
+    let stem = path
+        .file_stem()
+        .ok_or_else(|| format!("Cannot hash output without a file name: {}", path.display()))?
+        .to_string_lossy();
+    let stem = pattern.replace("[name]", &stem).replace("[hash]", hash);
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}", stem, ext.to_string_lossy()),
+        None => stem,
+    };
+    Ok(path.with_file_name(name))
+}
+
+// * Short hex content hash used in file names and `?v=` versions
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()[..HASH_LENGTH]
+        .to_string()
+}
+
+// * Subresource integrity value for file contents
+fn integrity_of(content: &[u8]) -> String {
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(Sha384::digest(content))
+    )
+}
+
+// * File a previous manifest entry points at, in any entry shape, without a version query
+// * or the leading `/` of Mix paths
+fn previous_file(value: &serde_json::Value) -> Option<&str> {
+    value
+        .as_str()
+        .or_else(|| value.get("file").and_then(|file| file.as_str()))
+        .or_else(|| value.get("digested_path").and_then(|file| file.as_str()))
+        .map(|file| {
+            file.split('?')
+                .next()
+                .unwrap_or(file)
+                .trim_start_matches('/')
+        })
+}
+
+fn map_path(path: &Path) -> PathBuf {
+    let mut map = path.as_os_str().to_owned();
+    map.push(".map");
+    PathBuf::from(map)
+}
+
+impl Manifest {
+    // * Hashing and SRI only apply to single builds, as esbuild keeps rewriting outputs in watch
+    // * mode; without either (and in Packr's own format) nothing is written, but outputs are
+    // * still tracked for HTML injection
+    pub fn new(config: &Config, watch: bool, path: PathBuf) -> Self {
+        Self {
+            enabled: config.hash_outputs && !watch,
+            query: config.hash_mode == "query",
+            integrity: config.sri && !watch,
+            format: config.manifest_format.clone(),
+            path,
+            entries: BTreeMap::new(),
+            images: BTreeMap::new(),
+        }
+    }
+
+    // * Empty manifest with the same settings, for outputs built on another thread
+    pub fn fork(&self) -> Self {
+        Self {
+            enabled: self.enabled,
+            query: self.query,
+            integrity: self.integrity,
+            format: self.format.clone(),
+            path: self.path.clone(),
+            entries: BTreeMap::new(),
+            images: BTreeMap::new(),
+        }
+    }
+
+    // * Take over the outputs recorded in a forked manifest
+    pub fn merge(&mut self, other: Manifest) {
+        self.entries.extend(other.entries);
+        self.images.extend(other.images);
+    }
+
+    // * Rename an output to its content-hashed name and record it, along with its source map
+    pub fn hash(&mut self, path: &Path) -> Result<PathBuf, String> {
+        if !self.enabled {
+            return self.place(path, &plugin::rename_output(path)?);
+        }
+
+        let content =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let hash = content_hash(&content);
+
+        // Query mode keeps the file name and only records its version
+        if self.query {
+            let placed = self.place(path, &plugin::rename_output(path)?)?;
+            if let Some(entry) = self.entries.get_mut(path) {
+                entry.version = Some(hash);
+            }
+            return Ok(placed);
+        }
+
+        // Rails digests are joined with a dash, as in `application-<digest>.js`
+        let pattern = if self.format == "rails" {
+            "[name]-[hash]"
+        } else {
+            "[name].[hash]"
+        };
+        let hashed = plugin::rename_output(&hashed_name(path, &hash, pattern)?)?;
+        self.rename(path, content, &hashed)
+    }
+
+    // * Rename an output following a file name pattern such as `[name]-[hash]`, whether or
+    // * not `hash_outputs` is enabled
+    pub fn hash_as(&mut self, path: &Path, pattern: &str) -> Result<PathBuf, String> {
+        let content =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let hash = content_hash(&content);
+        let hashed = plugin::rename_output(&hashed_name(path, &hash, pattern)?)?;
+        self.rename(path, content, &hashed)
+    }
+
+    // * Record an output under `target`, moving it there unless a plugin kept its name
+    fn place(&mut self, path: &Path, target: &Path) -> Result<PathBuf, String> {
+        if target == path {
+            self.record(path, path)?;
+            return Ok(path.to_path_buf());
+        }
+        let content =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        self.rename(path, content, target)
+    }
+
+    fn rename(
+        &mut self,
+        path: &Path,
+        mut content: Vec<u8>,
+        hashed: &Path,
+    ) -> Result<PathBuf, String> {
+        let hashed = hashed.to_path_buf();
+
+        // Keep the sourceMappingURL comment pointing at the renamed map
+        let map = map_path(path);
+        if map.exists() {
+            let hashed_map = map_path(&hashed);
+            retry::rename(&map, &hashed_map)
+                .map_err(|e| format!("Failed to rename {}: {}", map.display(), e))?;
+
+            let old_name = map.file_name().unwrap_or_default().to_string_lossy();
+            let new_name = hashed_map.file_name().unwrap_or_default().to_string_lossy();
+            let text = String::from_utf8_lossy(&content).replace(
+                &format!("sourceMappingURL={}", old_name),
+                &format!("sourceMappingURL={}", new_name),
+            );
+            content = text.into_bytes();
+
+            retry::write(&hashed, &content)
+                .map_err(|e| format!("Failed to write {}: {}", hashed.display(), e))?;
+            fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        } else {
+            // Unchanged content is moved rather than written out again
+            retry::rename(path, &hashed)
+                .map_err(|e| format!("Failed to rename {}: {}", path.display(), e))?;
+        }
+
+        self.record(path, &hashed)?;
+        Ok(hashed)
+    }
+
+    // * Record an output whose final name was chosen elsewhere (e.g. by `entry_names`)
+    pub fn record(&mut self, logical: &Path, actual: &Path) -> Result<(), String> {
+        let integrity = if self.integrity {
+            let content = fs::read(actual)
+                .map_err(|e| format!("Failed to read {}: {}", actual.display(), e))?;
+            Some(integrity_of(&content))
+        } else {
+            None
+        };
+
+        self.entries.insert(
+            logical.to_path_buf(),
+            Entry {
+                file: actual.to_path_buf(),
+                version: None,
+                integrity,
+            },
+        );
+        Ok(())
+    }
+
+    // * Hash the written files again, after a pass changed them in place
+    pub fn refresh_integrity(&mut self) -> Result<(), String> {
+        if !self.integrity {
+            return Ok(());
+        }
+        for entry in self.entries.values_mut() {
+            let content = fs::read(&entry.file)
+                .map_err(|e| format!("Failed to read {}: {}", entry.file.display(), e))?;
+            entry.integrity = Some(integrity_of(&content));
+        }
+        Ok(())
+    }
+
+    // * Final file written for a logical output, or the logical path if none was recorded
+    pub fn resolve(&self, logical: &Path) -> PathBuf {
+        self.entries
+            .get(logical)
+            .map(|entry| entry.file.clone())
+            .unwrap_or_else(|| logical.to_path_buf())
+    }
+
+    // * Version query for a logical output in query mode (`?v=`, or `?id=` for Mix), or an
+    // * empty string
+    pub fn query(&self, logical: &Path) -> String {
+        let key = if self.format == "mix" { "id" } else { "v" };
+        self.entries
+            .get(logical)
+            .and_then(|entry| entry.version.as_ref())
+            .map(|version| format!("?{}={}", key, version))
+            .unwrap_or_default()
+    }
+
+    // * SRI hash of the file written for a logical output, when `integrity` is enabled
+    pub fn integrity(&self, logical: &Path) -> Option<&str> {
+        self.entries
+            .get(logical)
+            .and_then(|entry| entry.integrity.as_deref())
+    }
+
+    // * Record an image with the variants written for it, by format. Images aren't outputs
+    // * of the build, so they are only listed in manifest.json.
+    pub fn record_variants(&mut self, image: &Path, variants: BTreeMap<String, PathBuf>) {
+        self.images.insert(image.to_path_buf(), variants);
+    }
+
+    // * Every file recorded for this build
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.entries
+            .values()
+            .map(|entry| entry.file.clone())
+            .collect()
+    }
+
+    // * Manifest entries of the images: Packr's format lists the variants under each image,
+    // * while Mix and Rails get an entry of their own for every file, as they look up no others
+    fn image_entries(&self, base: &Path) -> Vec<(String, serde_json::Value)> {
+        let mut entries = Vec::new();
+        for (image, variants) in &self.images {
+            let image = relative_to(image, base);
+            let variants: BTreeMap<&String, String> = variants
+                .iter()
+                .map(|(format, file)| (format, relative_to(file, base)))
+                .collect();
+            if self.format == "packr" {
+                entries.push((
+                    image.clone(),
+                    serde_json::json!({ "file": image, "variants": variants }),
+                ));
+                continue;
+            }
+            for file in std::iter::once(&image).chain(variants.values()) {
+                entries.push(match self.format.as_str() {
+                    "mix" => (
+                        format!("/{}", file),
+                        serde_json::Value::String(format!("/{}", file)),
+                    ),
+                    _ => (
+                        file.clone(),
+                        serde_json::json!({ "digested_path": file, "integrity": null }),
+                    ),
+                });
+            }
+        }
+        entries
+    }
+
+    // * Every logical output with the file written for it
+    pub fn outputs(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.entries
+            .iter()
+            .map(|(logical, entry)| (logical.clone(), entry.file.clone()))
+            .collect()
+    }
+
+    // * Write manifest.json, removing hashed files left over from the previous build
+    pub fn write(&self) -> Result<(), String> {
+        // Laravel's mix() helper and Rails need the manifest even without versioning
+        if !self.enabled && !self.integrity && self.format == "packr" && self.images.is_empty() {
+            return Ok(());
+        }
+        let base = self.path.parent().unwrap_or(Path::new(""));
+        let files: BTreeMap<String, String> = self
+            .entries
+            .iter()
+            .map(|(logical, entry)| (relative_to(logical, base), relative_to(&entry.file, base)))
+            .collect();
+
+        if let Ok(previous) = fs::read_to_string(&self.path) {
+            let previous: BTreeMap<String, serde_json::Value> =
+                serde_json::from_str(&previous).unwrap_or_default();
+            for (logical, value) in &previous {
+                let Some(stale) = previous_file(value) else {
+                    continue;
+                };
+                let logical = logical.trim_start_matches('/');
+                if files.get(logical).map(String::as_str) != Some(stale) && stale != logical {
+                    let _ = fs::remove_file(base.join(stale));
+                    let _ = fs::remove_file(map_path(&base.join(stale)));
+                }
+            }
+        }
+
+        let mut json: BTreeMap<String, serde_json::Value> = self
+            .entries
+            .iter()
+            .map(|(logical, entry)| {
+                let logical_path = relative_to(logical, base);
+                let file = relative_to(&entry.file, base) + &self.query(logical);
+                match (self.format.as_str(), &entry.integrity) {
+                    // Mix paths are absolute from the public directory the manifest sits in
+                    ("mix", _) => (
+                        format!("/{}", logical_path),
+                        serde_json::Value::String(format!("/{}", file)),
+                    ),
+                    // Propshaft's `.manifest.json` entries
+                    ("rails", integrity) => (
+                        logical_path,
+                        serde_json::json!({ "digested_path": file, "integrity": integrity }),
+                    ),
+                    // Entries are plain file names unless integrity hashes are recorded with them
+                    (_, Some(integrity)) => (
+                        logical_path,
+                        serde_json::json!({ "file": file, "integrity": integrity }),
+                    ),
+                    (_, None) => (logical_path, serde_json::Value::String(file)),
+                }
+            })
+            .collect();
+        json.extend(self.image_entries(base));
+
+        let json = serde_json::to_string_pretty(&json)
+            .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        if !base.as_os_str().is_empty() {
+            fs::create_dir_all(base)
+                .map_err(|e| format!("Failed to create manifest directory: {}", e))?;
+        }
+        retry::write(&self.path, json + "\n")
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
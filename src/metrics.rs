@@ -0,0 +1,545 @@
+// * ! ==================================================
+// * ! Metrics of long-running sessions for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::diagnostics::Severity;
+use crate::events::{self, Event, Subscription};
+use colored::*;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// * Where a daemon or watch session reports its builds
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct MetricsExport {
+    // Address to serve Prometheus metrics on at `/metrics`, e.g. `127.0.0.1:9464`
+    #[serde(default)]
+    pub listen: Option<String>,
+    // StatsD server to push to over UDP, e.g. `127.0.0.1:8125`
+    #[serde(default)]
+    pub statsd: Option<String>,
+    // Start of every metric name
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+impl Default for MetricsExport {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            statsd: None,
+            prefix: default_prefix(),
+        }
+    }
+}
+
+fn default_prefix() -> String {
+    "packr".to_string()
+}
+
+// * Upper bounds of the build duration histogram, in seconds
+const BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+// * How long a scrape may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// * Sum and count of durations
+#[derive(Default)]
+struct Timing {
+    seconds: f64,
+    count: u64,
+}
+
+// * A build duration histogram: counts per bucket, not cumulative, plus the total
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    timing: Timing,
+}
+
+// * What the session's builds added up to
+#[derive(Default)]
+struct Stats {
+    // By kind, then whether the build succeeded
+    builds: BTreeMap<(&'static str, bool), u64>,
+    durations: BTreeMap<&'static str, Histogram>,
+    // By category and name
+    phases: BTreeMap<(&'static str, String), Timing>,
+    // By source and severity
+    diagnostics: BTreeMap<(String, &'static str), u64>,
+    // By kind: whether the last build succeeded, and when it ended
+    last: BTreeMap<&'static str, (bool, f64)>,
+}
+
+impl Stats {
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::BuildFinished {
+                kind,
+                success,
+                duration,
+            } => {
+                let seconds = duration.as_secs_f64();
+                *self.builds.entry((kind, *success)).or_default() += 1;
+                let histogram = self.durations.entry(kind).or_default();
+                if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+                    histogram.buckets[bucket] += 1;
+                }
+                histogram.timing.seconds += seconds;
+                histogram.timing.count += 1;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0.0, |d| d.as_secs_f64());
+                self.last.insert(kind, (*success, now));
+            }
+            Event::PhaseFinished {
+                category,
+                name,
+                duration,
+            } => {
+                let timing = self.phases.entry((category, name.clone())).or_default();
+                timing.seconds += duration.as_secs_f64();
+                timing.count += 1;
+            }
+            Event::Diagnostic {
+                severity, source, ..
+            } => {
+                *self
+                    .diagnostics
+                    .entry((source.clone(), severity_name(severity)))
+                    .or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // * The Prometheus text exposition of the stats
+    fn prometheus(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            out.push_str(&format!(
+                "# HELP {prefix}_{name} {help}\n# TYPE {prefix}_{name} {kind}\n"
+            ));
+            for (sample, value) in samples {
+                out.push_str(&format!("{prefix}_{sample} {value}\n"));
+            }
+        };
+
+        family(
+            "info",
+            "gauge",
+            "Version of the running Packr",
+            vec![(
+                format!("info{{version=\"{}\"}}", env!("CARGO_PKG_VERSION")),
+                "1".to_string(),
+            )],
+        );
+        family(
+            "builds_total",
+            "counter",
+            "Builds finished, by kind and result",
+            self.builds
+                .iter()
+                .map(|((kind, success), count)| {
+                    (
+                        format!(
+                            "builds_total{{kind=\"{}\",result=\"{}\"}}",
+                            kind,
+                            result_name(*success)
+                        ),
+                        count.to_string(),
+                    )
+                })
+                .collect(),
+        );
+        family(
+            "build_failures_total",
+            "counter",
+            "Builds that failed, by kind",
+            self.durations
+                .keys()
+                .map(|kind| {
+                    let failures = self.builds.get(&(*kind, false)).copied().unwrap_or(0);
+                    (
+                        format!("build_failures_total{{kind=\"{}\"}}", kind),
+                        failures.to_string(),
+                    )
+                })
+                .collect(),
+        );
+
+        let mut samples = Vec::new();
+        for (kind, histogram) in &self.durations {
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                samples.push((
+                    format!(
+                        "build_duration_seconds_bucket{{kind=\"{}\",le=\"{}\"}}",
+                        kind, bound
+                    ),
+                    cumulative.to_string(),
+                ));
+            }
+            samples.push((
+                format!(
+                    "build_duration_seconds_bucket{{kind=\"{}\",le=\"+Inf\"}}",
+                    kind
+                ),
+                histogram.timing.count.to_string(),
+            ));
+            samples.push((
+                format!("build_duration_seconds_sum{{kind=\"{}\"}}", kind),
+                format!("{:.6}", histogram.timing.seconds),
+            ));
+            samples.push((
+                format!("build_duration_seconds_count{{kind=\"{}\"}}", kind),
+                histogram.timing.count.to_string(),
+            ));
+        }
+        family(
+            "build_duration_seconds",
+            "histogram",
+            "Duration of builds, by kind",
+            samples,
+        );
+
+        let mut samples = Vec::new();
+        for ((category, name), timing) in &self.phases {
+            let labels = format!("category=\"{}\",phase=\"{}\"", category, label(name));
+            samples.push((
+                format!("phase_duration_seconds_sum{{{}}}", labels),
+                format!("{:.6}", timing.seconds),
+            ));
+            samples.push((
+                format!("phase_duration_seconds_count{{{}}}", labels),
+                timing.count.to_string(),
+            ));
+        }
+        family(
+            "phase_duration_seconds",
+            "summary",
+            "Time spent in build phases",
+            samples,
+        );
+
+        family(
+            "diagnostics_total",
+            "counter",
+            "Errors and warnings reported by Sass, CSS, ESLint and esbuild",
+            self.diagnostics
+                .iter()
+                .map(|((source, severity), count)| {
+                    (
+                        format!(
+                            "diagnostics_total{{source=\"{}\",severity=\"{}\"}}",
+                            label(source),
+                            severity
+                        ),
+                        count.to_string(),
+                    )
+                })
+                .collect(),
+        );
+        family(
+            "last_build_success",
+            "gauge",
+            "Whether the last build of a kind succeeded",
+            self.last
+                .iter()
+                .map(|(kind, (success, _))| {
+                    (
+                        format!("last_build_success{{kind=\"{}\"}}", kind),
+                        u8::from(*success).to_string(),
+                    )
+                })
+                .collect(),
+        );
+        family(
+            "last_build_timestamp_seconds",
+            "gauge",
+            "When the last build of a kind finished, in seconds since the epoch",
+            self.last
+                .iter()
+                .map(|(kind, (_, at))| {
+                    (
+                        format!("last_build_timestamp_seconds{{kind=\"{}\"}}", kind),
+                        format!("{:.3}", at),
+                    )
+                })
+                .collect(),
+        );
+        out
+    }
+}
+
+fn severity_name(severity: &Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    }
+}
+
+fn result_name(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+// * `value` as a Prometheus label value
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// * `value` as one dot-separated part of a StatsD metric name
+fn bucket_part(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// * StatsD lines for an event
+fn statsd_lines(prefix: &str, event: &Event) -> Vec<String> {
+    match event {
+        Event::BuildFinished {
+            kind,
+            success,
+            duration,
+        } => vec![
+            format!("{}.builds.{}.{}:1|c", prefix, kind, result_name(*success)),
+            format!(
+                "{}.build_duration.{}:{}|ms",
+                prefix,
+                kind,
+                duration.as_millis()
+            ),
+        ],
+        Event::PhaseFinished {
+            category,
+            name,
+            duration,
+        } => vec![format!(
+            "{}.phase.{}.{}:{}|ms",
+            prefix,
+            category,
+            bucket_part(name),
+            duration.as_millis()
+        )],
+        Event::Diagnostic {
+            severity, source, ..
+        } => vec![format!(
+            "{}.diagnostics.{}.{}:1|c",
+            prefix,
+            bucket_part(source),
+            severity_name(severity)
+        )],
+        _ => Vec::new(),
+    }
+}
+
+// * Keeps the session's metrics up to date; dropping it stops recording
+pub struct Exporter {
+    _subscription: Subscription,
+}
+
+// * Start recording the builds of a daemon or watch session, serving them to Prometheus and
+// * pushing them to StatsD as configured. Unset, or with neither target, nothing is recorded.
+pub fn start(export: Option<&MetricsExport>) -> Result<Option<Exporter>, String> {
+    let Some(export) = export.filter(|e| e.listen.is_some() || e.statsd.is_some()) else {
+        return Ok(None);
+    };
+    let stats = Arc::new(Mutex::new(Stats::default()));
+
+    if let Some(ref listen) = export.listen {
+        let listener = TcpListener::bind(listen)
+            .map_err(|e| format!("Failed to listen on {}: {}", listen, e))?;
+        let stats = stats.clone();
+        let prefix = export.prefix.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A client that stops mid-request only loses its own response
+                let _ = serve(stream, &stats, &prefix);
+            }
+        });
+        log_line(format!(
+            "{} served on http://{}/metrics",
+            "Metrics".cyan().bold(),
+            listen
+        ));
+    }
+
+    let statsd = match export.statsd {
+        Some(ref address) => {
+            let target = address
+                .to_socket_addrs()
+                .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+                .next()
+                .ok_or_else(|| format!("{} has no address", address))?;
+            let local = if target.is_ipv6() {
+                "[::]:0"
+            } else {
+                "0.0.0.0:0"
+            };
+            let socket = UdpSocket::bind(local)
+                .map_err(|e| format!("Failed to open a UDP socket: {}", e))?;
+            log_line(format!(
+                "{} pushed to StatsD at {}",
+                "Metrics".cyan().bold(),
+                address
+            ));
+            Some((socket, target))
+        }
+        None => None,
+    };
+
+    let prefix = export.prefix.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        if let Ok(mut stats) = stats.lock() {
+            stats.record(event);
+        }
+        // StatsD is fire-and-forget: a server that isn't listening loses these and nothing else
+        if let Some((ref socket, target)) = statsd {
+            for line in statsd_lines(&prefix, event) {
+                let _ = socket.send_to(line.as_bytes(), target);
+            }
+        }
+    }));
+    Ok(Some(Exporter {
+        _subscription: subscription,
+    }))
+}
+
+// * Answer one HTTP request: the metrics for `GET /metrics`, 404 for anything else
+fn serve(stream: TcpStream, stats: &Mutex<Stats>, prefix: &str) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers are read so closing the connection doesn't reset it under the client
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, path) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => {
+            let body = match stats.lock() {
+                Ok(stats) => stats.prometheus(prefix),
+                Err(_) => String::new(),
+            };
+            ("200 OK", body)
+        }
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    (&stream).write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(kind: &'static str, success: bool, millis: u64) -> Event {
+        Event::BuildFinished {
+            kind,
+            success,
+            duration: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn exposition_counts_builds_with_cumulative_buckets() {
+        let mut stats = Stats::default();
+        stats.record(&finished("build", true, 300));
+        stats.record(&finished("build", false, 3000));
+        stats.record(&Event::PhaseFinished {
+            category: "styles",
+            name: "compile \"main\"".to_string(),
+            duration: Duration::from_millis(500),
+        });
+        stats.record(&Event::diagnostic_from_text(
+            Severity::Warning,
+            "Sass",
+            "deprecated",
+        ));
+        let text = stats.prometheus("packr");
+
+        for line in [
+            "# TYPE packr_builds_total counter",
+            "packr_builds_total{kind=\"build\",result=\"success\"} 1",
+            "packr_builds_total{kind=\"build\",result=\"failure\"} 1",
+            "packr_build_failures_total{kind=\"build\"} 1",
+            "packr_build_duration_seconds_bucket{kind=\"build\",le=\"0.25\"} 0",
+            "packr_build_duration_seconds_bucket{kind=\"build\",le=\"0.5\"} 1",
+            "packr_build_duration_seconds_bucket{kind=\"build\",le=\"5\"} 2",
+            "packr_build_duration_seconds_bucket{kind=\"build\",le=\"+Inf\"} 2",
+            "packr_build_duration_seconds_sum{kind=\"build\"} 3.300000",
+            "packr_build_duration_seconds_count{kind=\"build\"} 2",
+            "packr_phase_duration_seconds_sum{category=\"styles\",phase=\"compile \\\"main\\\"\"} 0.500000",
+            "packr_diagnostics_total{source=\"Sass\",severity=\"warning\"} 1",
+            "packr_last_build_success{kind=\"build\"} 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "no {line} in:\n{text}");
+        }
+    }
+
+    #[test]
+    fn exposition_without_builds_only_has_the_info() {
+        let text = Stats::default().prometheus("site");
+        let samples: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            samples,
+            [format!(
+                "site_info{{version=\"{}\"}} 1",
+                env!("CARGO_PKG_VERSION")
+            )]
+        );
+    }
+
+    #[test]
+    fn statsd_names_are_sanitized() {
+        let phase = Event::PhaseFinished {
+            category: "scripts",
+            name: "esbuild main.js".to_string(),
+            duration: Duration::from_millis(42),
+        };
+        assert_eq!(
+            statsd_lines("packr", &phase),
+            ["packr.phase.scripts.esbuild_main_js:42|ms"]
+        );
+        assert_eq!(
+            statsd_lines("packr", &finished("styles", false, 7)),
+            [
+                "packr.builds.styles.failure:1|c",
+                "packr.build_duration.styles:7|ms"
+            ]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+// * ! ==================================================
+// * ! NDJSON event stream for Packr
+// * ! ==================================================
+
+use crate::events::{self, Subscription};
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+// * Whether `--events-ndjson` writes to stdout, which then carries nothing else
+pub fn is_stdout(target: Option<&str>) -> bool {
+    matches!(target, None | Some("") | Some("-"))
+}
+
+// * Where `--events-ndjson` sends its lines: stdout without a target, a Unix socket for
+// * `unix:<path>`, and a file or FIFO for any other path
+fn open(target: Option<&str>) -> Result<Box<dyn Write + Send>, String> {
+    let Some(target) = target.filter(|_| !is_stdout(target)) else {
+        return Ok(Box::new(io::stdout()));
+    };
+    match target {
+        #[cfg(unix)]
+        target if target.starts_with("unix:") => {
+            let path = &target["unix:".len()..];
+            std::os::unix::net::UnixStream::connect(path)
+                .map(|stream| Box::new(stream) as Box<dyn Write + Send>)
+                .map_err(|e| format!("Failed to connect to {}: {}", path, e))
+        }
+        // A FIFO blocks here until the reading end is opened, as it should
+        path => File::create(path)
+            .map(|file| Box::new(file) as Box<dyn Write + Send>)
+            .map_err(|e| format!("Failed to open {}: {}", path, e)),
+    }
+}
+
+// * Write every event of this process to `target` as one JSON object per line, until the
+// * returned guard is dropped
+pub fn stream(target: Option<&str>) -> Result<Subscription, String> {
+    let sink = Mutex::new(open(target)?);
+    Ok(events::subscribe(Arc::new(move |event| {
+        let Ok(mut sink) = sink.lock() else {
+            return;
+        };
+        // A reader that went away must not fail the build
+        let _ = writeln!(sink, "{}", event.to_json()).and_then(|()| sink.flush());
+    })))
+}
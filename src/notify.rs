@@ -0,0 +1,436 @@
+// * ! ==================================================
+// * ! Build notifications for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::cache;
+use crate::copy::expand_env;
+use crate::curl::{self, Request};
+use crate::events::{self, Event, Subscription};
+use crate::manifest::relative_to;
+use crate::sizes;
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// * Where to report finished builds
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct Notifications {
+    #[serde(default)]
+    pub webhook: Option<Webhook>,
+    // Incoming webhooks of a chat channel, which get a formatted message instead of the JSON
+    #[serde(default)]
+    pub slack: Option<Webhook>,
+    #[serde(default)]
+    pub discord: Option<Webhook>,
+}
+
+impl Notifications {
+    // * The configured webhooks, each with its key in `notifications`
+    pub fn webhooks(&self) -> impl Iterator<Item = (&'static str, &Webhook)> {
+        [
+            ("webhook", &self.webhook),
+            ("slack", &self.slack),
+            ("discord", &self.discord),
+        ]
+        .into_iter()
+        .filter_map(|(key, webhook)| Some((key, webhook.as_ref()?)))
+    }
+}
+
+// * An endpoint that receives a summary of every build as a POST request
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    // Header values may use `[env:NAME]`, to keep tokens out of the config
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // Stay quiet about builds that succeeded or were skipped
+    #[serde(default)]
+    pub only_failures: bool,
+}
+
+impl Webhook {
+    fn host(&self) -> &str {
+        let host = self.url.split('/').nth(2).unwrap_or_default();
+        host.rsplit('@').next().unwrap_or_default()
+    }
+}
+
+// * Webhook URLs and headers often carry tokens, so the logged config only names the host
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("host", &self.host())
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("only_failures", &self.only_failures)
+            .finish()
+    }
+}
+
+// * Likewise for the resolved config in the build record
+impl serde::Serialize for Webhook {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = serde_json::json!({
+            "host": self.host(),
+            "headers": self.headers.keys().collect::<Vec<_>>(),
+            "only_failures": self.only_failures,
+        });
+        serde::Serialize::serialize(&value, serializer)
+    }
+}
+
+// * How long a webhook may take before the build moves on without it
+const TIMEOUT_SECS: u64 = 10;
+
+// * How much of the errors and size changes a chat message quotes
+const EXCERPT_ERRORS: usize = 3;
+const EXCERPT_ERROR_CHARS: usize = 300;
+const EXCERPT_SIZES: usize = 10;
+
+// * What the events of one build said, for its notification
+#[derive(Default)]
+struct Summary {
+    phases: BTreeMap<String, u64>,
+    files: BTreeSet<PathBuf>,
+    warnings: usize,
+    errors: Vec<serde_json::Value>,
+}
+
+impl Summary {
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::PhaseFinished { name, duration, .. } => {
+                *self.phases.entry(name.clone()).or_default() += duration.as_millis() as u64;
+            }
+            Event::FileWritten { path } => {
+                self.files.insert(path.clone());
+            }
+            Event::Diagnostic {
+                severity: crate::diagnostics::Severity::Warning,
+                ..
+            } => self.warnings += 1,
+            Event::Diagnostic {
+                source,
+                message,
+                file,
+                line,
+                column,
+                ..
+            } => self.errors.push(serde_json::json!({
+                "source": source,
+                "message": message,
+                "file": file,
+                "line": line,
+                "column": column,
+            })),
+            Event::Error { message } => {
+                self.errors.push(serde_json::json!({ "message": message }));
+            }
+            _ => {}
+        }
+    }
+}
+
+// * Collects the events of a build while it runs, when the config asks for notifications
+pub struct Recorder {
+    started: Instant,
+    // Sizes from before the build, for the size changes of chat messages
+    previous_sizes: Option<BTreeMap<String, u64>>,
+    summary: Arc<Mutex<Summary>>,
+    _subscription: Subscription,
+}
+
+pub fn start(config: &Config, config_dir: &Path) -> Option<Recorder> {
+    config.notifications.webhooks().next()?;
+    let summary = Arc::new(Mutex::new(Summary::default()));
+    let recorder = summary.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        if let Ok(mut summary) = recorder.lock() {
+            summary.record(event);
+        }
+    }));
+    Some(Recorder {
+        started: Instant::now(),
+        previous_sizes: sizes::recorded(&cache::dir(config, config_dir)),
+        summary,
+        _subscription: subscription,
+    })
+}
+
+impl Recorder {
+    // * Send the summary of a build of `kind` that ended with `result`. A notification that
+    // * can't be delivered is reported and otherwise ignored, as the build itself is done.
+    pub fn finish<T, E: std::fmt::Display>(
+        self,
+        config: &Config,
+        config_dir: &Path,
+        kind: &str,
+        result: &Result<T, E>,
+        built: bool,
+    ) {
+        let duration = self.started.elapsed().as_millis() as u64;
+        let summary = match self.summary.lock() {
+            Ok(mut summary) => std::mem::take(&mut *summary),
+            Err(_) => return,
+        };
+
+        let status = match result {
+            Err(_) => "failure",
+            Ok(_) if !built => "skipped",
+            Ok(_) => "success",
+        };
+        let sizes: BTreeMap<String, u64> = summary
+            .files
+            .iter()
+            .filter_map(|file| {
+                let bytes = fs::metadata(file).ok()?.len();
+                Some((relative_to(file, config_dir), bytes))
+            })
+            .collect();
+        let project = Path::new(".")
+            .join(config_dir)
+            .canonicalize()
+            .ok()
+            .and_then(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+        let payload = serde_json::json!({
+            "project": project,
+            "kind": kind,
+            "status": status,
+            "duration_ms": duration,
+            "phases": summary.phases,
+            "sizes": sizes,
+            "warnings": summary.warnings,
+            "errors": summary.errors,
+            "packr_version": env!("CARGO_PKG_VERSION"),
+        });
+
+        let cache_dir = cache::dir(config, config_dir);
+        // The build recorded its sizes only if it got that far
+        let size_changes = match (self.previous_sizes, sizes::recorded(&cache_dir)) {
+            (Some(previous), Some(current)) => size_changes(&previous, &current),
+            _ => Vec::new(),
+        };
+        for (key, webhook) in config.notifications.webhooks() {
+            if webhook.only_failures && status != "failure" {
+                continue;
+            }
+            let body = match key {
+                "slack" => slack_message(&payload, &size_changes),
+                "discord" => discord_message(&payload, &size_changes),
+                _ => payload.clone(),
+            };
+            if let Err(e) = post(webhook, &cache_dir, &body) {
+                log_line(format!("{} {}", "Webhook".yellow().bold(), e));
+            }
+        }
+    }
+}
+
+// * `dist/main.css +1.2 KB (20.1 KB)` lines for the outputs whose size changed
+fn size_changes(previous: &BTreeMap<String, u64>, current: &BTreeMap<String, u64>) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (name, &bytes) in current {
+        match previous.get(name) {
+            Some(&before) if before == bytes => {}
+            Some(&before) => changes.push(format!(
+                "{} {} ({})",
+                name,
+                sizes::change(before, bytes),
+                sizes::size(bytes)
+            )),
+            None => changes.push(format!("{} {} (new)", name, sizes::change(0, bytes))),
+        }
+    }
+    for (name, &before) in previous {
+        if !current.contains_key(name) {
+            changes.push(format!("{} {} (removed)", name, sizes::change(before, 0)));
+        }
+    }
+    changes
+}
+
+// * What a chat message says about a build, whichever chat it goes to
+struct Message {
+    title: String,
+    color: u32,
+    errors: Option<String>,
+    sizes: Option<String>,
+    footer: String,
+}
+
+impl Message {
+    fn new(payload: &serde_json::Value, size_changes: &[String]) -> Self {
+        let project = payload["project"].as_str().unwrap_or_default();
+        let what = match payload["kind"].as_str() {
+            Some("styles") => "styles rebuild",
+            _ => "build",
+        };
+        let duration = payload["duration_ms"].as_u64().unwrap_or_default();
+        let duration = if duration < 1000 {
+            format!("{} ms", duration)
+        } else {
+            format!("{:.1} s", duration as f64 / 1000.0)
+        };
+        let (title, color) = match payload["status"].as_str() {
+            Some("failure") => (
+                format!("\u{274C} {} {} failed after {}", project, what, duration),
+                0xE01E5A,
+            ),
+            Some("skipped") => (
+                format!("\u{23ED} {} {} skipped, no input changed", project, what),
+                0x9E9E9E,
+            ),
+            _ => (
+                format!("\u{2705} {} {} succeeded in {}", project, what, duration),
+                0x2EB886,
+            ),
+        };
+
+        let errors = payload["errors"].as_array().cloned().unwrap_or_default();
+        let mut excerpt: Vec<String> = errors
+            .iter()
+            .take(EXCERPT_ERRORS)
+            .map(|error| {
+                let message = error["message"].as_str().unwrap_or_default().trim_end();
+                let message = match (error["file"].as_str(), error["line"].as_u64()) {
+                    (Some(file), Some(line)) => format!("{}:{}: {}", file, line, message),
+                    _ => message.to_string(),
+                };
+                truncate(&message, EXCERPT_ERROR_CHARS)
+            })
+            .collect();
+        if errors.len() > EXCERPT_ERRORS {
+            excerpt.push(format!(
+                "\u{2026} and {} more",
+                errors.len() - EXCERPT_ERRORS
+            ));
+        }
+
+        let mut sizes: Vec<String> = size_changes.iter().take(EXCERPT_SIZES).cloned().collect();
+        if size_changes.len() > EXCERPT_SIZES {
+            sizes.push(format!(
+                "\u{2026} and {} more",
+                size_changes.len() - EXCERPT_SIZES
+            ));
+        }
+
+        let mut footer = format!(
+            "packr {}",
+            payload["packr_version"].as_str().unwrap_or_default()
+        );
+        match payload["warnings"].as_u64() {
+            Some(0) | None => {}
+            Some(1) => footer.push_str(" \u{00B7} 1 warning"),
+            Some(n) => footer.push_str(&format!(" \u{00B7} {} warnings", n)),
+        }
+
+        Self {
+            title,
+            color,
+            errors: (!excerpt.is_empty()).then(|| code_block(&excerpt.join("\n"))),
+            sizes: (!sizes.is_empty()).then(|| code_block(&sizes.join("\n"))),
+            footer,
+        }
+    }
+
+    fn fields(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        [("Errors", &self.errors), ("Size changes", &self.sizes)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_ref()?)))
+    }
+}
+
+// * At most `max` characters of `text`
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}\u{2026}", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+// * `text` as a code block, which both Slack and Discord show verbatim; a zero-width space
+// * keeps a fence in the text from closing it
+fn code_block(text: &str) -> String {
+    format!("```\n{}\n```", text.replace("```", "`\u{200B}``"))
+}
+
+// * A Slack incoming webhook message, with a colored attachment
+fn slack_message(payload: &serde_json::Value, size_changes: &[String]) -> serde_json::Value {
+    let message = Message::new(payload, size_changes);
+    // Slack reads `&`, `<` and `>` as markup
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let fields: Vec<serde_json::Value> = message
+        .fields()
+        .map(|(name, value)| serde_json::json!({ "title": name, "value": escape(value), "short": false }))
+        .collect();
+    serde_json::json!({
+        "text": escape(&message.title),
+        "attachments": [{
+            "color": format!("#{:06X}", message.color),
+            "fallback": escape(&message.title),
+            "fields": fields,
+            "footer": message.footer,
+            "mrkdwn_in": ["fields"],
+        }],
+    })
+}
+
+// * A Discord webhook message, with a colored embed
+fn discord_message(payload: &serde_json::Value, size_changes: &[String]) -> serde_json::Value {
+    let message = Message::new(payload, size_changes);
+    let fields: Vec<serde_json::Value> = message
+        .fields()
+        // Discord rejects field values over 1024 characters
+        .map(|(name, value)| {
+            let value = if value.chars().count() > 1024 {
+                code_block(&truncate(value.trim_matches('`').trim(), 1000))
+            } else {
+                value.clone()
+            };
+            serde_json::json!({ "name": name, "value": value })
+        })
+        .collect();
+    serde_json::json!({
+        "embeds": [{
+            "title": message.title,
+            "color": message.color,
+            "fields": fields,
+            "footer": { "text": message.footer },
+        }],
+    })
+}
+
+// * POST `body` as JSON
+fn post(webhook: &Webhook, cache_dir: &Path, body: &serde_json::Value) -> Result<(), String> {
+    let request = Request {
+        method: "POST",
+        url: expand_env(&webhook.url),
+        headers: webhook
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, expand_env(value)))
+            .collect(),
+        json: Some(body),
+        form: Vec::new(),
+        timeout_secs: TIMEOUT_SECS,
+    };
+    let failed = |e: String| format!("POST to {} failed: {}", webhook.url, e);
+    let (status, response) = curl::send(cache_dir, "webhook", request).map_err(failed)?;
+    if !(200..300).contains(&status) {
+        return Err(failed(format!("HTTP {} {}", status, response.trim())));
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+// * ! ==================================================
+// * ! Output file permissions for Packr
+// * ! ==================================================
+
+use crate::build::Config;
+use crate::bundle;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// * Slack for file systems that store modification times in whole seconds or coarser
+const MTIME_SLACK: Duration = Duration::from_secs(2);
+
+// * Directories never searched for written files, even when an output lives next to them
+const SKIPPED_DIRS: [&str; 2] = ["node_modules", ".git"];
+
+// * Permission bits from an octal string such as `0644`, `644` or `0o644`
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    let digits = mode.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+}
+
+// * Group id for a numeric id, or for a group name listed in /etc/group
+pub fn group_id(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.trim().parse() {
+        return Some(gid);
+    }
+    let groups = fs::read_to_string("/etc/group").ok()?;
+    groups.lines().find_map(|line| {
+        // name:password:gid:members
+        let mut fields = line.split(':');
+        if fields.next()? != group.trim() {
+            return None;
+        }
+        fields.nth(1)?.parse().ok()
+    })
+}
+
+// * Directories the build writes into: output directories and destinations, `copy` targets,
+// * and the directories of the manifest, HTML pages, favicons and WordPress asset map
+fn written_dirs(config: &Config, config_dir: &Path) -> Vec<PathBuf> {
+    let parent = |path: &str| config_dir.join(path).parent().map(Path::to_path_buf);
+
+    let mut dirs: Vec<PathBuf> = bundle::outputs(config)
+        .into_iter()
+        .filter_map(|(_, output)| parent(output))
+        .collect();
+    dirs.extend(
+        [&config.css_destination, &config.js_destination]
+            .into_iter()
+            .flatten()
+            .chain(config.copy.iter().map(|task| &task.to))
+            .chain(config.html.iter().map(|html| &html.output))
+            .chain(config.favicons.iter().map(|favicons| &favicons.output))
+            .map(|dir| config_dir.join(dir)),
+    );
+    dirs.extend(config.manifest.iter().filter_map(|path| parent(path)));
+    dirs.extend(
+        config
+            .wordpress
+            .iter()
+            .filter_map(|wordpress| parent(&wordpress.path)),
+    );
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+// * Files below `dir` modified since `since`, and the directories holding them
+fn collect_written(
+    dir: &Path,
+    since: SystemTime,
+    files: &mut Vec<PathBuf>,
+    dirs: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut found = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+        if kind.is_dir() {
+            let skipped = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with('.') || SKIPPED_DIRS.contains(&name));
+            if !skipped {
+                collect_written(&path, since, files, dirs);
+            }
+        } else if kind.is_file()
+            && entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified >= since)
+        {
+            files.push(path);
+            found = true;
+        }
+    }
+    if found {
+        dirs.push(dir.to_path_buf());
+    }
+}
+
+// * Apply `output_mode` and `output_group` to every file this build wrote, i.e. every file in
+// * the directories it writes into that changed since `started`. Directories holding them get
+// * the same mode plus execute wherever it grants read, e.g. 0755 for 0644.
+pub fn apply(config: &Config, config_dir: &Path, started: SystemTime) -> Result<usize, String> {
+    if config.output_mode.is_none() && config.output_group.is_none() {
+        return Ok(0);
+    }
+    let mode = config.output_mode.as_deref().and_then(parse_mode);
+    let group = config.output_group.as_deref().and_then(group_id);
+
+    let since = started.checked_sub(MTIME_SLACK).unwrap_or(started);
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for dir in written_dirs(config, config_dir) {
+        collect_written(&dir, since, &mut files, &mut dirs);
+    }
+    files.sort();
+    files.dedup();
+    dirs.sort();
+    dirs.dedup();
+
+    for file in &files {
+        set_permissions(file, mode, group)?;
+    }
+    let dir_mode = mode.map(|mode| mode | ((mode & 0o444) >> 2));
+    for dir in &dirs {
+        set_permissions(dir, dir_mode, group)?;
+    }
+    Ok(files.len())
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: Option<u32>, group: Option<u32>) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| {
+            format!(
+                "Failed to set the mode of {} to {:o}: {}",
+                path.display(),
+                mode,
+                e
+            )
+        })?;
+    }
+    if let Some(group) = group {
+        std::os::unix::fs::chown(path, None, Some(group)).map_err(|e| {
+            format!(
+                "Failed to set the group of {} to {}: {}",
+                path.display(),
+                group,
+                e
+            )
+        })?;
+    }
+    Ok(())
+}
+
+// * Windows has no mode bits or groups to set
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _mode: Option<u32>, _group: Option<u32>) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,209 @@
+// * ! ==================================================
+// * ! Rust plugins for Packr
+// * ! ==================================================
+
+use crate::build::Config;
+use crate::manifest::Manifest;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// A Rust extension to the build pipeline, for features that don't belong in the core, such
+/// as custom manifests or uploading the outputs. Every hook does nothing by default; an error
+/// fails the build with the plugin's name in front of it.
+///
+/// ```no_run
+/// use packr::{PackrBuilder, PackrPlugin};
+/// use std::path::Path;
+///
+/// struct Brand;
+///
+/// impl PackrPlugin for Brand {
+///     fn name(&self) -> &str {
+///         "brand"
+///     }
+///
+///     fn transform_css(&self, css: String, _input: &Path) -> Result<String, String> {
+///         Ok(css.replace("BRAND_COLOR", "#0a7d5c"))
+///     }
+/// }
+///
+/// PackrBuilder::from_file(".packr.json")?.plugin(Brand).build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait PackrPlugin: Send + Sync {
+    /// Name shown in errors
+    fn name(&self) -> &str;
+
+    /// The config was loaded and validated, with its `PACKR_*` overrides applied; the plugin
+    /// may still change it
+    fn config_resolved(&self, _config: &mut Config, _config_dir: &Path) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Stylesheets are about to be compiled, in full builds and watch rebuilds alike
+    fn before_styles(&self, _config: &Config, _config_dir: &Path) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The CSS grass compiled from `input`, before lightningcss prints the plain and minified
+    /// outputs from it
+    fn transform_css(&self, css: String, _input: &Path) -> Result<String, String> {
+        Ok(css)
+    }
+
+    /// The file name an entry output gets, after any content hash was added; return it as is
+    /// to keep it. Chunks and assets esbuild names keep their names.
+    fn rename_output(&self, file_name: String) -> Result<String, String> {
+        Ok(file_name)
+    }
+
+    /// esbuild finished and the script `outputs` are in place; not called in watch mode,
+    /// where esbuild rebuilds on its own
+    fn after_scripts(
+        &self,
+        _config: &Config,
+        _config_dir: &Path,
+        _outputs: &[PathBuf],
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Upload the source maps of a full build, when `sourcemap_upload` uses the `plugin`
+    /// provider. `files` pairs the maps and the files they map with the names they're served
+    /// under, `url_prefix` included.
+    fn upload_source_maps(
+        &self,
+        _release: &str,
+        _files: &[(String, PathBuf)],
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// A full build wrote every output, listed in `manifest`, and ran its post-passes
+    fn emit(
+        &self,
+        _config: &Config,
+        _config_dir: &Path,
+        _manifest: &Manifest,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// * Plugins the pipeline calls, in registration order
+static PLUGINS: RwLock<Vec<Arc<dyn PackrPlugin>>> = RwLock::new(Vec::new());
+
+// * Plugins the loaded config declares in `wasm_plugins`, called after the registered ones
+static DECLARED: RwLock<Vec<Arc<dyn PackrPlugin>>> = RwLock::new(Vec::new());
+
+/// Add `plugin` to every build this process runs from here on, including those of the CLI
+/// when it's registered before [`cli::main`](crate::cli::main). Register before loading the
+/// config for its [`config_resolved`](PackrPlugin::config_resolved) hook to run.
+pub fn register(plugin: impl PackrPlugin + 'static) {
+    if let Ok(mut plugins) = PLUGINS.write() {
+        plugins.push(Arc::new(plugin));
+    }
+}
+
+// * Add `plugins` until the returned guard is dropped, for the plugins of one `PackrBuilder`
+pub(crate) fn scoped(plugins: Vec<Arc<dyn PackrPlugin>>) -> Scope {
+    if let Ok(mut registered) = PLUGINS.write() {
+        registered.extend(plugins.iter().cloned());
+    }
+    Scope { plugins }
+}
+
+#[must_use]
+pub(crate) struct Scope {
+    plugins: Vec<Arc<dyn PackrPlugin>>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if let Ok(mut registered) = PLUGINS.write() {
+            registered.retain(|plugin| !self.plugins.iter().any(|own| Arc::ptr_eq(own, plugin)));
+        }
+    }
+}
+
+// * Replace the plugins of the previously loaded config
+pub(crate) fn declare(plugins: Vec<Arc<dyn PackrPlugin>>) {
+    if let Ok(mut declared) = DECLARED.write() {
+        *declared = plugins;
+    }
+}
+
+// * Snapshot of the plugins, so hooks run without holding the locks
+fn plugins() -> Vec<Arc<dyn PackrPlugin>> {
+    [&PLUGINS, &DECLARED]
+        .iter()
+        .flat_map(|plugins| plugins.read().map(|p| p.clone()).unwrap_or_default())
+        .collect()
+}
+
+// * Run `hook` for each plugin in turn, stopping at the first failure
+fn each(mut hook: impl FnMut(&dyn PackrPlugin) -> Result<(), String>) -> Result<(), String> {
+    for plugin in plugins() {
+        hook(plugin.as_ref()).map_err(|e| format!("Plugin {} failed: {}", plugin.name(), e))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn config_resolved(config: &mut Config, config_dir: &Path) -> Result<(), String> {
+    each(|plugin| plugin.config_resolved(config, config_dir))
+}
+
+pub(crate) fn before_styles(config: &Config, config_dir: &Path) -> Result<(), String> {
+    each(|plugin| plugin.before_styles(config, config_dir))
+}
+
+pub(crate) fn transform_css(mut css: String, input: &Path) -> Result<String, String> {
+    for plugin in plugins() {
+        css = plugin
+            .transform_css(css, input)
+            .map_err(|e| format!("Plugin {} failed: {}", plugin.name(), e))?;
+    }
+    Ok(css)
+}
+
+// * `path` with the file name the plugins give it
+pub(crate) fn rename_output(path: &Path) -> Result<PathBuf, String> {
+    let Some(original) = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+    else {
+        return Ok(path.to_path_buf());
+    };
+    let mut name = original.clone();
+    for plugin in plugins() {
+        name = plugin
+            .rename_output(name)
+            .map_err(|e| format!("Plugin {} failed: {}", plugin.name(), e))?;
+        // Outputs stay in their directory
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(format!(
+                "Plugin {} renamed {} to {:?}, which is not a file name",
+                plugin.name(),
+                original,
+                name
+            ));
+        }
+    }
+    Ok(path.with_file_name(name))
+}
+
+pub(crate) fn after_scripts(
+    config: &Config,
+    config_dir: &Path,
+    outputs: &[PathBuf],
+) -> Result<(), String> {
+    each(|plugin| plugin.after_scripts(config, config_dir, outputs))
+}
+
+pub(crate) fn upload_source_maps(release: &str, files: &[(String, PathBuf)]) -> Result<(), String> {
+    each(|plugin| plugin.upload_source_maps(release, files))
+}
+
+pub(crate) fn emit(config: &Config, config_dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    each(|plugin| plugin.emit(config, config_dir, manifest))
+}
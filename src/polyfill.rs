@@ -0,0 +1,277 @@
+// * ! ==================================================
+// * ! Polyfill detection for Packr legacy bundles
+// * ! ==================================================
+
+// * A runtime feature that may need a polyfill on older targets
+struct Feature {
+    name: &'static str,
+    module: &'static str,
+    // ECMAScript edition that introduced it, or None for web platform APIs
+    es: Option<u32>,
+    // First native version per browser as (engine, version); missing engines never support it
+    browsers: &'static [(&'static str, f32)],
+}
+
+// * Feature map checked against the legacy target
+const FEATURES: &[Feature] = &[
+    Feature {
+        name: "Promise",
+        module: "core-js/es/promise",
+        es: Some(2015),
+        browsers: &[
+            ("chrome", 32.0),
+            ("edge", 12.0),
+            ("firefox", 29.0),
+            ("safari", 8.0),
+            ("ios", 8.0),
+            ("opera", 19.0),
+        ],
+    },
+    Feature {
+        name: "Symbol",
+        module: "core-js/es/symbol",
+        es: Some(2015),
+        browsers: &[
+            ("chrome", 38.0),
+            ("edge", 12.0),
+            ("firefox", 36.0),
+            ("safari", 9.0),
+            ("ios", 9.0),
+            ("opera", 25.0),
+        ],
+    },
+    Feature {
+        name: "Map",
+        module: "core-js/es/map",
+        es: Some(2015),
+        browsers: &[
+            ("chrome", 38.0),
+            ("edge", 12.0),
+            ("firefox", 13.0),
+            ("safari", 8.0),
+            ("ios", 8.0),
+            ("opera", 25.0),
+        ],
+    },
+    Feature {
+        name: "Set",
+        module: "core-js/es/set",
+        es: Some(2015),
+        browsers: &[
+            ("chrome", 38.0),
+            ("edge", 12.0),
+            ("firefox", 13.0),
+            ("safari", 8.0),
+            ("ios", 8.0),
+            ("opera", 25.0),
+        ],
+    },
+    Feature {
+        name: "Array.from",
+        module: "core-js/es/array/from",
+        es: Some(2015),
+        browsers: &[
+            ("chrome", 45.0),
+            ("edge", 12.0),
+            ("firefox", 32.0),
+            ("safari", 9.0),
+            ("ios", 9.0),
+            ("opera", 32.0),
+        ],
+    },
+    Feature {
+        name: "Object.assign",
+        module: "core-js/es/object/assign",
+        es: Some(2015),
+        browsers: &[
+            ("chrome", 45.0),
+            ("edge", 12.0),
+            ("firefox", 34.0),
+            ("safari", 9.0),
+            ("ios", 9.0),
+            ("opera", 32.0),
+        ],
+    },
+    Feature {
+        name: "Array.prototype.includes",
+        module: "core-js/es/array/includes",
+        es: Some(2016),
+        browsers: &[
+            ("chrome", 47.0),
+            ("edge", 14.0),
+            ("firefox", 43.0),
+            ("safari", 9.0),
+            ("ios", 9.0),
+            ("opera", 34.0),
+        ],
+    },
+    Feature {
+        name: "Object.entries",
+        module: "core-js/es/object/entries",
+        es: Some(2017),
+        browsers: &[
+            ("chrome", 54.0),
+            ("edge", 14.0),
+            ("firefox", 47.0),
+            ("safari", 10.1),
+            ("ios", 10.3),
+            ("opera", 41.0),
+        ],
+    },
+    Feature {
+        name: "String.prototype.padStart",
+        module: "core-js/es/string/pad-start",
+        es: Some(2017),
+        browsers: &[
+            ("chrome", 57.0),
+            ("edge", 15.0),
+            ("firefox", 48.0),
+            ("safari", 10.0),
+            ("ios", 10.0),
+            ("opera", 44.0),
+        ],
+    },
+    Feature {
+        name: "Promise.prototype.finally",
+        module: "core-js/es/promise/finally",
+        es: Some(2018),
+        browsers: &[
+            ("chrome", 63.0),
+            ("edge", 18.0),
+            ("firefox", 58.0),
+            ("safari", 11.1),
+            ("ios", 11.3),
+            ("opera", 50.0),
+        ],
+    },
+    Feature {
+        name: "Array.prototype.flat",
+        module: "core-js/es/array/flat",
+        es: Some(2019),
+        browsers: &[
+            ("chrome", 69.0),
+            ("edge", 79.0),
+            ("firefox", 62.0),
+            ("safari", 12.0),
+            ("ios", 12.0),
+            ("opera", 56.0),
+        ],
+    },
+    Feature {
+        name: "Object.fromEntries",
+        module: "core-js/es/object/from-entries",
+        es: Some(2019),
+        browsers: &[
+            ("chrome", 73.0),
+            ("edge", 79.0),
+            ("firefox", 63.0),
+            ("safari", 12.1),
+            ("ios", 12.2),
+            ("opera", 60.0),
+        ],
+    },
+    Feature {
+        name: "globalThis",
+        module: "core-js/es/global-this",
+        es: Some(2020),
+        browsers: &[
+            ("chrome", 71.0),
+            ("edge", 79.0),
+            ("firefox", 65.0),
+            ("safari", 12.1),
+            ("ios", 12.2),
+            ("opera", 58.0),
+        ],
+    },
+    Feature {
+        name: "fetch",
+        module: "whatwg-fetch",
+        es: None,
+        browsers: &[
+            ("chrome", 42.0),
+            ("edge", 14.0),
+            ("firefox", 39.0),
+            ("safari", 10.1),
+            ("ios", 10.3),
+            ("opera", 29.0),
+        ],
+    },
+    Feature {
+        name: "URLSearchParams",
+        module: "core-js/web/url-search-params",
+        es: None,
+        browsers: &[
+            ("chrome", 49.0),
+            ("edge", 17.0),
+            ("firefox", 44.0),
+            ("safari", 10.1),
+            ("ios", 10.3),
+            ("opera", 36.0),
+        ],
+    },
+];
+
+// * Oldest ECMAScript edition whose engines are assumed to ship web APIs like fetch
+const WEB_API_BASELINE: u32 = 2017;
+
+// * A polyfill required by the legacy target
+pub struct Polyfill {
+    pub name: &'static str,
+    pub module: &'static str,
+}
+
+// * Split an esbuild target entry like `safari10.1` into engine and version
+fn parse_target(entry: &str) -> Option<(String, f32)> {
+    let entry = entry.trim().to_lowercase();
+    let split = entry.find(|c: char| c.is_ascii_digit())?;
+    let (engine, version) = entry.split_at(split);
+    let version = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+    Some((engine.to_string(), version.parse().ok()?))
+}
+
+// * Whether a single target entry lacks native support for a feature
+fn target_needs(feature: &Feature, engine: &str, version: f32) -> bool {
+    if engine == "es" {
+        let edition = version as u32;
+        return match feature.es {
+            Some(introduced) => edition < introduced,
+            None => edition < WEB_API_BASELINE,
+        };
+    }
+
+    // Node bundles don't go through the nomodule path
+    if engine == "node" {
+        return false;
+    }
+
+    match feature.browsers.iter().find(|(name, _)| *name == engine) {
+        Some((_, since)) => version < *since,
+        None => true,
+    }
+}
+
+// * Collect polyfills needed by any entry of a comma-separated esbuild target
+pub fn detect(target: &str) -> Vec<Polyfill> {
+    let entries: Vec<(String, f32)> = target.split(',').filter_map(parse_target).collect();
+
+    FEATURES
+        .iter()
+        .filter(|feature| {
+            entries
+                .iter()
+                .any(|(engine, version)| target_needs(feature, engine, *version))
+        })
+        .map(|feature| Polyfill {
+            name: feature.name,
+            module: feature.module,
+        })
+        .collect()
+}
+
+// * Render the entry module esbuild injects ahead of the legacy bundle
+pub fn entry_source(polyfills: &[Polyfill]) -> String {
+    polyfills
+        .iter()
+        .map(|p| format!("import \"{}\";\n", p.module))
+        .collect()
+}
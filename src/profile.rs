@@ -0,0 +1,173 @@
+// * ! ==================================================
+// * ! Build profiling for Packr
+// * ! ==================================================
+
+use crate::events;
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+// * A finished span, as a Chrome tracing "complete" event
+#[derive(Debug)]
+struct Event {
+    name: String,
+    category: &'static str,
+    start: u128,
+    duration: u128,
+    thread: u64,
+}
+
+#[derive(Debug)]
+struct Profile {
+    start: Instant,
+    events: Mutex<Vec<Event>>,
+    threads: Mutex<Vec<(u64, String)>>,
+}
+
+static PROFILE: OnceLock<Profile> = OnceLock::new();
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD: Cell<u64> = const { Cell::new(0) };
+}
+
+// * Small stable id for the current thread, registering its name on first use
+fn thread_id(profile: &Profile) -> u64 {
+    THREAD.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT_THREAD.fetch_add(1, Ordering::Relaxed));
+            let name = thread::current()
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("thread {}", id.get()));
+            if let Ok(mut threads) = profile.threads.lock() {
+                threads.push((id.get(), name));
+            }
+        }
+        id.get()
+    })
+}
+
+// * Start recording spans; until this is called, spans cost nothing
+pub fn enable() {
+    let _ = PROFILE.set(Profile {
+        start: Instant::now(),
+        events: Mutex::new(Vec::new()),
+        threads: Mutex::new(Vec::new()),
+    });
+}
+
+// * A timed section of the build, recorded when dropped
+#[must_use]
+pub struct Span {
+    name: String,
+    category: &'static str,
+    start: Option<Instant>,
+    // For the phase events, which are reported whether or not profiling is enabled
+    started: Instant,
+}
+
+// * Time everything until the returned span is dropped
+pub fn span(category: &'static str, name: impl Into<String>) -> Span {
+    let name = name.into();
+    events::emit(|| events::Event::PhaseStarted {
+        category,
+        name: name.clone(),
+    });
+    Span {
+        name,
+        category,
+        start: PROFILE.get().map(|_| Instant::now()),
+        started: Instant::now(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        events::emit(|| events::Event::PhaseFinished {
+            category: self.category,
+            name: self.name.clone(),
+            duration: self.started.elapsed(),
+        });
+        let (Some(profile), Some(start)) = (PROFILE.get(), self.start) else {
+            return;
+        };
+        let event = Event {
+            name: std::mem::take(&mut self.name),
+            category: self.category,
+            start: start.duration_since(profile.start).as_micros(),
+            duration: start.elapsed().as_micros(),
+            thread: thread_id(profile),
+        };
+        if let Ok(mut events) = profile.events.lock() {
+            events.push(event);
+        }
+    }
+}
+
+// * Remove and return the spans the current thread finished so far, as `(name, microseconds)`
+pub fn take_thread_spans() -> Vec<(String, u128)> {
+    let Some(profile) = PROFILE.get() else {
+        return Vec::new();
+    };
+    let thread = thread_id(profile);
+    let Ok(mut events) = profile.events.lock() else {
+        return Vec::new();
+    };
+    let (taken, kept): (Vec<Event>, Vec<Event>) =
+        events.drain(..).partition(|event| event.thread == thread);
+    *events = kept;
+    taken
+        .into_iter()
+        .map(|event| (event.name, event.duration))
+        .collect()
+}
+
+// * Write the recorded spans as a Chrome tracing file, loadable in Perfetto or chrome://tracing
+pub fn write(path: &Path) -> Result<(), String> {
+    let Some(profile) = PROFILE.get() else {
+        return Ok(());
+    };
+    let pid = std::process::id();
+
+    let mut trace_events: Vec<serde_json::Value> = Vec::new();
+    if let Ok(threads) = profile.threads.lock() {
+        trace_events.extend(threads.iter().map(|(tid, name)| {
+            serde_json::json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": pid,
+                "tid": tid,
+                "args": { "name": name },
+            })
+        }));
+    }
+    if let Ok(events) = profile.events.lock() {
+        trace_events.extend(events.iter().map(|event| {
+            serde_json::json!({
+                "name": event.name,
+                "cat": event.category,
+                "ph": "X",
+                "ts": event.start as u64,
+                "dur": event.duration as u64,
+                "pid": pid,
+                "tid": event.thread,
+            })
+        }));
+    }
+
+    let trace = serde_json::json!({
+        "traceEvents": trace_events,
+        "displayTimeUnit": "ms",
+    });
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, trace.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
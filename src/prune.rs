@@ -0,0 +1,182 @@
+// * ! ==================================================
+// * ! Stale output pruning for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, script_emitted_files, within_config_dir, Config};
+use crate::bundle::{self, Step};
+use crate::compress::compressed_path;
+use crate::manifest::relative_to;
+use colored::*;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Files written by the last build, relative to the config directory
+const OUTPUTS_FILE: &str = ".packr/outputs.json";
+
+// * `file` with the source map and precompressed variants the build writes next to it
+fn with_variants(config: &Config, file: &Path) -> Vec<PathBuf> {
+    let mut files = vec![file.to_path_buf()];
+    if config.sourcemap.writes_file() {
+        files.push(PathBuf::from(format!("{}.map", file.display())));
+    }
+    files.extend(
+        config
+            .compress
+            .iter()
+            .map(|format| compressed_path(file, format)),
+    );
+    files
+}
+
+// * Every file this build produced: the entry outputs from `files`, esbuild's chunks and
+// * assets, their variants, and their copies in the destination directories
+pub fn emitted(config: &Config, config_dir: &Path, files: &[PathBuf]) -> BTreeSet<String> {
+    let tasks = bundle::tasks(config);
+    let dir = |output: &str| {
+        config_dir
+            .join(output)
+            .parent()
+            .map_or_else(|| config_dir.to_path_buf(), Path::to_path_buf)
+    };
+    // Output directories of every entry, which destination copies mirror
+    let css_dirs: Vec<PathBuf> = tasks
+        .iter()
+        .filter(|task| task.step == Step::Styles)
+        .map(|task| dir(&task.config.scss_output))
+        .collect();
+    let js_dirs: Vec<PathBuf> = tasks
+        .iter()
+        .filter(|task| task.step == Step::Scripts)
+        .map(|task| dir(&task.config.js_output))
+        .collect();
+
+    let mut emitted = BTreeSet::new();
+    let outputs = files.iter().cloned().chain(
+        tasks
+            .iter()
+            .filter(|task| task.step == Step::Scripts)
+            .flat_map(|task| script_emitted_files(&task.config, config_dir)),
+    );
+    for file in outputs {
+        let (dirs, destination) = if file.extension().is_some_and(|ext| ext == "css") {
+            (&css_dirs, &config.css_destination)
+        } else {
+            (&js_dirs, &config.js_destination)
+        };
+        let copy = destination.as_ref().and_then(|dest| {
+            let relative = dirs.iter().find_map(|dir| file.strip_prefix(dir).ok())?;
+            Some(config_dir.join(dest).join(relative))
+        });
+        for path in std::iter::once(file).chain(copy) {
+            for variant in with_variants(config, &path) {
+                emitted.insert(relative_to(&variant, config_dir));
+            }
+        }
+    }
+    emitted
+}
+
+// * Delete files the previous build wrote that this one no longer produces, e.g. after an
+// * entry was renamed, then record this build's files for the next run
+pub fn prune(config: &Config, config_dir: &Path, files: &[PathBuf]) -> Result<(), String> {
+    let path = config_dir.join(OUTPUTS_FILE);
+    let current = emitted(config, config_dir, files);
+    let previous: BTreeSet<String> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    let inputs: Vec<PathBuf> = bundle::inputs(config)
+        .into_iter()
+        .map(|(_, input)| config_dir.join(input))
+        .collect();
+    for stale in previous.difference(&current) {
+        let file = config_dir.join(stale);
+        // Never an input, even if a hand-edited outputs.json lists one
+        if !file.is_file() || inputs.contains(&file) {
+            continue;
+        }
+        // Nor a file outside the project, unless the build may write there
+        if !config.allow_outside_config_dir && !within_config_dir(config_dir, &file) {
+            log_line(format!(
+                "{} {} (outside the config directory)",
+                "Not pruning".yellow().bold(),
+                stale
+            ));
+            continue;
+        }
+        fs::remove_file(&file)
+            .map_err(|e| format!("Failed to remove {}: {}", file.display(), e))?;
+        log_line(format!("{} {}", "Pruned".green().bold(), stale));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(&current)
+        .map_err(|e| format!("Failed to serialize emitted files: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> Config {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn prunes_stale_outputs_inside_the_config_dir_only() {
+        let root = std::env::temp_dir().join(format!("packr-prune-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("project");
+        fs::create_dir_all(dir.join("dist")).unwrap();
+        fs::create_dir_all(dir.join(".packr")).unwrap();
+        fs::write(dir.join("dist/old.css"), "").unwrap();
+        fs::write(dir.join("dist/main.css"), "").unwrap();
+        fs::write(root.join("elsewhere.txt"), "").unwrap();
+        fs::write(
+            dir.join(OUTPUTS_FILE),
+            r#"["dist/old.css", "dist/main.css", "../elsewhere.txt", "src/main.scss"]"#,
+        )
+        .unwrap();
+
+        let config = config(
+            r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js"}"#,
+        );
+        prune(&config, &dir, &[dir.join("dist/main.css")]).unwrap();
+
+        assert!(!dir.join("dist/old.css").exists());
+        assert!(dir.join("dist/main.css").exists());
+        assert!(root.join("elsewhere.txt").exists());
+        let recorded: BTreeSet<String> =
+            serde_json::from_str(&fs::read_to_string(dir.join(OUTPUTS_FILE)).unwrap()).unwrap();
+        assert!(recorded.contains("dist/main.css"));
+        assert!(!recorded.contains("dist/old.css"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn prunes_outside_the_config_dir_when_allowed() {
+        let root = std::env::temp_dir().join(format!("packr-prune-out-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("project");
+        fs::create_dir_all(dir.join(".packr")).unwrap();
+        fs::write(root.join("old.js"), "").unwrap();
+        fs::write(dir.join(OUTPUTS_FILE), r#"["../old.js"]"#).unwrap();
+
+        let config = config(
+            r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                "js_input": "src/main.js", "js_output": "dist/main.js",
+                "allow_outside_config_dir": true}"#,
+        );
+        prune(&config, &dir, &[]).unwrap();
+
+        assert!(!root.join("old.js").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+// * ! ==================================================
+// * ! JSON build reports for Packr
+// * ! ==================================================
+
+use crate::diagnostics::Severity;
+use crate::error::PackrError;
+use crate::events::{self, Event, Subscription};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(Default)]
+struct Findings {
+    errors: Vec<serde_json::Value>,
+    warnings: Vec<serde_json::Value>,
+}
+
+// * Collects the diagnostics of a build for `print`
+pub struct Recorder {
+    started: Instant,
+    findings: Arc<Mutex<Findings>>,
+    _subscription: Subscription,
+}
+
+pub fn record() -> Recorder {
+    let findings = Arc::new(Mutex::new(Findings::default()));
+    let recorder = findings.clone();
+    let subscription = events::subscribe(Arc::new(move |event| {
+        let Event::Diagnostic {
+            severity,
+            source,
+            message,
+            file,
+            line,
+            column,
+        } = event
+        else {
+            return;
+        };
+        let Ok(mut findings) = recorder.lock() else {
+            return;
+        };
+        let diagnostic = serde_json::json!({
+            "source": source,
+            "message": message,
+            "file": file,
+            "line": line,
+            "column": column,
+        });
+        match severity {
+            Severity::Error => findings.errors.push(diagnostic),
+            Severity::Warning => findings.warnings.push(diagnostic),
+        }
+    }));
+    Recorder {
+        started: Instant::now(),
+        findings,
+        _subscription: subscription,
+    }
+}
+
+impl Recorder {
+    // * Print how a build that ended with `result` went, with every Sass, CSS, ESLint and
+    // * esbuild message it reported, as one JSON document on stdout
+    pub fn print(self, result: &Result<bool, PackrError>) {
+        let findings = match self.findings.lock() {
+            Ok(mut findings) => std::mem::take(&mut *findings),
+            Err(_) => Findings::default(),
+        };
+        let status = match result {
+            Ok(true) => "success",
+            Ok(false) => "up-to-date",
+            Err(_) => "failure",
+        };
+        let error = result.as_ref().err();
+        let report = serde_json::json!({
+            "status": status,
+            "error": error.map(ToString::to_string),
+            "code": error.map(PackrError::code),
+            "duration_ms": self.started.elapsed().as_millis() as u64,
+            "errors": findings.errors,
+            "warnings": findings.warnings,
+        });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap_or_default()
+        );
+    }
+}
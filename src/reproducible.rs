@@ -0,0 +1,46 @@
+// * ! ==================================================
+// * ! Reproducible builds for Packr
+// * ! ==================================================
+
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// * Files written next to an output that belong to it
+const SIBLINGS: [&str; 4] = [".map", ".gz", ".br", ".map.gz"];
+
+// * Build time used in place of the clock: `SOURCE_DATE_EPOCH` when set, the Unix epoch otherwise
+pub fn source_date_epoch() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+// * Pin the modification time of `outputs` and the files written next to them, so two builds
+// * of the same inputs can't be told apart. Source maps are already free of absolute paths,
+// * see `sourcemaps::normalize`.
+pub fn finalize(outputs: &[PathBuf]) -> Result<(), String> {
+    let mtime = UNIX_EPOCH + Duration::from_secs(source_date_epoch());
+
+    for output in outputs {
+        let files = std::iter::once(output.clone()).chain(
+            SIBLINGS
+                .iter()
+                .map(|suffix| PathBuf::from(format!("{}{}", output.display(), suffix))),
+        );
+        for file in files.filter(|file| file.is_file()) {
+            set_mtime(&file, mtime)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_mtime(path: &Path, mtime: SystemTime) -> Result<(), String> {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(mtime))
+        .map_err(|e| format!("Failed to set the timestamp of {}: {}", path.display(), e))
+}
@@ -0,0 +1,142 @@
+// * ! ==================================================
+// * ! Retried file writes for Packr
+// * ! ==================================================
+
+use crate::events::{self, Event};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// * Default for `io_retries`
+pub const DEFAULT_RETRIES: u32 = 3;
+
+// * Wait before the first retry; doubled for every further one
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+// * Retries per operation, from the `io_retries` of the loaded config
+static RETRIES: AtomicU32 = AtomicU32::new(DEFAULT_RETRIES);
+
+pub fn configure(retries: u32) {
+    RETRIES.store(retries, Ordering::Relaxed);
+}
+
+// * Errors a sync client, virus scanner or network share causes for a moment, as opposed to
+// * ones that fail the same way every time
+fn is_transient(error: &io::Error) -> bool {
+    if matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+    ) {
+        return true;
+    }
+    if cfg!(windows) {
+        // Sharing and lock violations, and access denied while another process holds the file
+        return matches!(error.raw_os_error(), Some(5 | 32 | 33));
+    }
+    // ETXTBSY: the file is being executed or mapped
+    error.raw_os_error() == Some(26)
+}
+
+// * Run `operation`, retrying transient errors with exponential backoff
+fn with_retries<T>(mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    with_retries(|| fs::write(path.as_ref(), contents.as_ref()))?;
+    written(path.as_ref());
+    Ok(())
+}
+
+// * Write a file through a buffered writer handed to `contents`, without building it in memory
+// * first. A transient error starts the file over, so `contents` may be called again.
+pub fn write_with(
+    path: impl AsRef<Path>,
+    mut contents: impl FnMut(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    with_retries(|| {
+        let mut writer = io::BufWriter::new(fs::File::create(path.as_ref())?);
+        contents(&mut writer)?;
+        writer.flush()
+    })?;
+    written(path.as_ref());
+    Ok(())
+}
+
+pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+    let bytes = with_retries(|| fs::copy(from.as_ref(), to.as_ref()))?;
+    written(to.as_ref());
+    Ok(bytes)
+}
+
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    with_retries(|| fs::rename(from.as_ref(), to.as_ref()))?;
+    written(to.as_ref());
+    Ok(())
+}
+
+fn written(path: &Path) {
+    events::emit(|| Event::FileWritten {
+        path: path.to_path_buf(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transient_errors_are_retried_until_they_pass() {
+        let mut attempts = 0;
+        let result = with_retries(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(io::Error::from(io::ErrorKind::Interrupted))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn lasting_errors_fail_at_once() {
+        let mut attempts = 0;
+        let result: io::Result<()> = with_retries(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn retries_stop_after_the_configured_count() {
+        let mut attempts = 0;
+        let result: io::Result<()> = with_retries(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(attempts, RETRIES.load(Ordering::Relaxed) + 1);
+    }
+}
@@ -0,0 +1,355 @@
+// * ! ==================================================
+// * ! JavaScript runtime selection for Packr's external tools
+// * ! ==================================================
+
+use crate::children;
+use crate::esbuild;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// * Command for a program on PATH. npm, npx, pnpm, yarn and globally installed CLIs are `.cmd`
+// * shims on Windows, which `Command::new` only finds when given the extension.
+pub fn program(name: &str) -> Command {
+    let on_path = |file: &str| {
+        env::var_os("PATH")
+            .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(file).is_file()))
+    };
+    let shim = format!("{}.cmd", name);
+    if cfg!(windows) && !on_path(&format!("{}.exe", name)) && on_path(&shim) {
+        return Command::new(shim);
+    }
+    Command::new(name)
+}
+
+// * Path as passed to a tool, with forward slashes on Windows too; esbuild copies its arguments
+// * into metafile keys and source maps, which should read the same on every platform. The `\\?\`
+// * prefix of canonicalized Windows paths is dropped, as Node and ESLint can't resolve it.
+pub fn arg_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        let path = path.strip_prefix(r"\\?\").unwrap_or(&path);
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+// * A package's binary in the project's `node_modules/.bin`, if installed
+fn local_bin(package: &str, config_dir: &Path) -> Option<PathBuf> {
+    let bin = if cfg!(windows) {
+        format!("{}.cmd", package)
+    } else {
+        package.to_string()
+    };
+    Some(config_dir.join("node_modules").join(".bin").join(bin)).filter(|path| path.exists())
+}
+
+fn missing(package: &str, runner: &str) -> String {
+    format!(
+        "{} is not installed in node_modules/.bin and {} is not available; install it with `npm install --save-dev {}`",
+        package, runner, package
+    )
+}
+
+// * Package manager of a Node project, detected from its lockfile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+    Bun,
+}
+
+impl PackageManager {
+    pub fn detect(config_dir: &Path) -> Self {
+        let has = |name: &str| config_dir.join(name).exists();
+
+        if has("pnpm-lock.yaml") {
+            Self::Pnpm
+        } else if has("yarn.lock") {
+            Self::Yarn
+        } else if has("bun.lockb") || has("bun.lock") {
+            Self::Bun
+        } else {
+            Self::Npm
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Npm => "npm",
+            Self::Pnpm => "pnpm",
+            Self::Yarn => "yarn",
+            Self::Bun => "bun",
+        }
+    }
+
+    // * Program that runs package binaries for this package manager
+    fn runner_program(self) -> &'static str {
+        match self {
+            Self::Npm => "npx",
+            Self::Pnpm => "pnpm",
+            Self::Yarn => "yarn",
+            Self::Bun => "bunx",
+        }
+    }
+
+    // * Command that runs a package binary through the package manager
+    fn runner(self, package: &str) -> Command {
+        let mut cmd = match self {
+            Self::Npm => program("npx"),
+            Self::Pnpm => {
+                let mut cmd = program("pnpm");
+                cmd.arg("exec");
+                cmd
+            }
+            Self::Yarn => {
+                let mut cmd = program("yarn");
+                cmd.arg("dlx");
+                cmd
+            }
+            Self::Bun => program("bunx"),
+        };
+        cmd.arg(package);
+        cmd
+    }
+}
+
+// * Runtime names accepted by the `runtime` option
+pub const RUNTIMES: [&str; 3] = ["node", "bun", "deno"];
+
+// * JavaScript runtime used to run esbuild, ESLint and the esbuild shim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Node,
+    Bun,
+    Deno,
+}
+
+impl Runtime {
+    // * Use the configured runtime, or pick one from the project's files
+    pub fn resolve(configured: Option<&str>, config_dir: &Path) -> Self {
+        match configured {
+            Some("bun") => Self::Bun,
+            Some("deno") => Self::Deno,
+            Some(_) => Self::Node,
+            None => Self::detect(config_dir),
+        }
+    }
+
+    // * Bun and Deno projects are recognized by their lockfile or config file
+    fn detect(config_dir: &Path) -> Self {
+        let has = |name: &str| config_dir.join(name).exists();
+
+        if has("bun.lockb") || has("bun.lock") {
+            Self::Bun
+        } else if !has("package.json") && (has("deno.json") || has("deno.jsonc")) {
+            Self::Deno
+        } else {
+            Self::Node
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Node => "node",
+            Self::Bun => "bun",
+            Self::Deno => "deno",
+        }
+    }
+
+    // * Command running an npm package's CLI, e.g. `esbuild` or `eslint`. A binary installed in
+    // * `node_modules/.bin` is used whenever the runtime's package runner is missing, as in
+    // * minimal Docker images without npx, so this only fails when neither is available.
+    pub fn tool_command(self, package: &str, config_dir: &Path) -> Result<Command, String> {
+        let local = local_bin(package, config_dir);
+        match self {
+            Self::Node => {
+                // Installed binaries skip the package manager's startup cost
+                if let Some(local) = local {
+                    return Ok(Command::new(local));
+                }
+                if package == "esbuild" {
+                    // A global esbuild on PATH keeps working as before
+                    if esbuild::on_path("esbuild") {
+                        return Ok(program("esbuild"));
+                    }
+                    return esbuild::ensure(config_dir)
+                        .map(Command::new)
+                        .map_err(|e| format!("esbuild is not installed and {}", e));
+                }
+                let manager = PackageManager::detect(config_dir);
+                if !esbuild::on_path(manager.runner_program()) {
+                    return Err(missing(package, manager.runner_program()));
+                }
+                Ok(manager.runner(package))
+            }
+            Self::Bun if esbuild::on_path("bunx") => {
+                let mut cmd = program("bunx");
+                cmd.arg(package);
+                Ok(cmd)
+            }
+            Self::Deno if esbuild::on_path("deno") => {
+                let mut cmd = program("deno");
+                cmd.arg("run").arg("-A").arg(format!("npm:{}", package));
+                Ok(cmd)
+            }
+            Self::Bun | Self::Deno => {
+                let runner = if self == Self::Bun { "bunx" } else { "deno" };
+                local
+                    .map(Command::new)
+                    .ok_or_else(|| missing(package, runner))
+            }
+        }
+    }
+
+    // * Version reported by `<runtime> --version`, e.g. `20.11.1` for Node's `v20.11.1` and
+    // * `1.40.0` for Deno's `deno 1.40.0 (release, x86_64-unknown-linux-gnu)`
+    pub fn version(self) -> Option<String> {
+        let output = children::output(self.name(), program(self.name()).arg("--version")).ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .map(|token| token.trim_start_matches('v'))
+            .find(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+            .map(str::to_string)
+    }
+
+    // * Command running a local script file
+    pub fn script_command(self, script: &Path) -> Command {
+        let mut cmd = match self {
+            Self::Node => program("node"),
+            Self::Bun => program("bun"),
+            Self::Deno => {
+                let mut cmd = program("deno");
+                cmd.arg("run").arg("-A");
+                cmd
+            }
+        };
+        cmd.arg(arg_path(script));
+        cmd
+    }
+}
+
+// * Operators of an npm semver comparator, longest first
+const OPERATORS: [&str; 7] = ["<=", ">=", "<", ">", "^", "~", "="];
+
+// * Parts of a version in a range, with wildcard and missing parts as `None`, e.g. `18`, `18.x`
+// * or `18.2.*`
+fn partial_version(version: &str) -> Option<[Option<u32>; 3]> {
+    let core = version
+        .trim_start_matches(['v', '='])
+        .split(['-', '+'])
+        .next()?;
+    let mut parts = [None; 3];
+    for (index, part) in core.split('.').enumerate() {
+        if index >= 3 {
+            return None;
+        }
+        if matches!(part, "x" | "X" | "*") {
+            break;
+        }
+        parts[index] = Some(part.parse().ok()?);
+    }
+    Some(parts)
+}
+
+// * Smallest version above `version` at `index`, e.g. 19.0.0 for 18.2.1 at the major part
+fn bump(version: [u32; 3], index: usize) -> [u32; 3] {
+    match index {
+        0 => [version[0] + 1, 0, 0],
+        1 => [version[0], version[1] + 1, 0],
+        _ => [version[0], version[1], version[2] + 1],
+    }
+}
+
+// * Whether `version` matches one comparator such as `>=18.18.0`, `^20.9` or `16.x`
+fn matches_comparator(version: [u32; 3], comparator: &str) -> Option<bool> {
+    let (operator, rest) = OPERATORS
+        .iter()
+        .find_map(|op| comparator.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("", comparator));
+    let parts = partial_version(rest)?;
+    let specified = parts.iter().take_while(|part| part.is_some()).count();
+    if specified == 0 {
+        return Some(true);
+    }
+    let low = parts.map(|part| part.unwrap_or(0));
+    let last = specified - 1;
+    let within = |index: usize| version >= low && version < bump(low, index);
+
+    Some(match operator {
+        ">=" => version >= low,
+        ">" => version >= bump(low, last),
+        "<" => version < low,
+        "<=" => version < bump(low, last),
+        // Caret ranges allow changes right of the first non-zero part
+        "^" => within((0..specified).find(|&i| low[i] != 0).unwrap_or(last)),
+        "~" => within(last.min(1)),
+        _ => within(last),
+    })
+}
+
+// * Whether `version` satisfies an npm semver range such as `^18.18.0 || ^20.9.0 || >=21.1.0`.
+// * Syntax this doesn't understand counts as satisfied, so an unusual `engines` field never
+// * blocks a build.
+fn satisfies(version: [u32; 3], range: &str) -> bool {
+    range.split("||").any(|set| {
+        // `>= 18` is the same comparator as `>=18`
+        let mut comparators: Vec<String> = Vec::new();
+        let mut pending = String::new();
+        for token in set.split_whitespace() {
+            pending.push_str(token);
+            if !OPERATORS.contains(&token) {
+                comparators.push(std::mem::take(&mut pending));
+            }
+        }
+
+        // Hyphen ranges, e.g. `16 - 18`
+        if let [low, hyphen, high] = comparators.as_slice() {
+            if hyphen == "-" {
+                comparators = vec![format!(">={}", low), format!("<={}", high)];
+            }
+        }
+        comparators
+            .iter()
+            .all(|comparator| matches_comparator(version, comparator).unwrap_or(true))
+    })
+}
+
+// * package.json of a package installed in the project's `node_modules`
+pub fn package_manifest(package: &str, config_dir: &Path) -> Option<serde_json::Value> {
+    let path = config_dir
+        .join("node_modules")
+        .join(package)
+        .join("package.json");
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+// * Why the installed Node can't run a package from the project's `node_modules`, if the
+// * `engines.node` range in its package.json rules the Node version out. A package run through
+// * npx without a local install is not checked, as its version isn't known until it's fetched.
+pub fn node_requirement(package: &str, label: &str, config_dir: &Path) -> Option<String> {
+    let manifest = package_manifest(package, config_dir)?;
+    let range = manifest["engines"]["node"].as_str()?;
+
+    let node = Runtime::Node.version()?;
+    let (major, minor, patch) = esbuild::parse_version(&node)?;
+    if satisfies([major, minor, patch], range) {
+        return None;
+    }
+
+    Some(format!(
+        "Node {} found, {} {} needs Node {}; upgrade Node or install a release of {} that supports Node {}",
+        node,
+        label,
+        manifest["version"].as_str().unwrap_or_default(),
+        range,
+        package,
+        major
+    ))
+}
@@ -0,0 +1,510 @@
+// * ! ==================================================
+// * ! esbuild JS API shim for Packr
+// * ! ==================================================
+
+use crate::children::{self, Resident};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// * Node script that accepts esbuild CLI-style arguments and runs the same build
+// * through esbuild's JS API, so plugins can take part in it
+const SHIM_SOURCE: &str = r#"// Generated by packr. Do not edit.
+const fs = require('fs');
+const path = require('path');
+
+let esbuild;
+try {
+	esbuild = require('esbuild');
+} catch (err) {
+	console.error('packr: the esbuild package must be installed in the project to use plugins or side-effect overrides');
+	process.exit(1);
+}
+
+const shim = JSON.parse(process.env.PACKR_ESBUILD_SHIM || '{}');
+
+// Flags taking `--flag:key=value` and collected into an object
+const MAP_FLAGS = new Set(['define', 'loader', 'alias', 'banner', 'footer', 'supported', 'logOverride']);
+// Flags whose comma-separated value must be passed as an array
+const COMMA_LIST_FLAGS = new Set(['mainFields', 'conditions', 'resolveExtensions']);
+// Flags whose value is a regular expression
+const REGEX_FLAGS = new Set(['mangleProps', 'reserveProps']);
+
+const PASSTHROUGH = 'packr-side-effects';
+
+function camel(name) {
+	return name.replace(/-([a-z])/g, (_, c) => c.toUpperCase());
+}
+
+function parseArgs(args) {
+	const options = { entryPoints: [] };
+	let metafile = null;
+	let watch = false;
+
+	for (const arg of args) {
+		if (!arg.startsWith('--')) {
+			const eq = arg.indexOf('=');
+			options.entryPoints.push(eq > 0 ? { out: arg.slice(0, eq), in: arg.slice(eq + 1) } : arg);
+			continue;
+		}
+
+		const body = arg.slice(2);
+		const colon = body.indexOf(':');
+		const eq = body.indexOf('=');
+
+		if (colon > 0 && (eq < 0 || colon < eq)) {
+			const key = camel(body.slice(0, colon));
+			const rest = body.slice(colon + 1);
+			if (MAP_FLAGS.has(key)) {
+				const split = rest.indexOf('=');
+				options[key] = options[key] || {};
+				options[key][rest.slice(0, split)] = rest.slice(split + 1);
+			} else {
+				options[key] = options[key] || [];
+				options[key].push(rest);
+			}
+			continue;
+		}
+
+		const key = camel(eq < 0 ? body : body.slice(0, eq));
+		const value = eq < 0 ? true : body.slice(eq + 1);
+
+		if (key === 'watch') {
+			watch = true;
+		} else if (key === 'metafile') {
+			metafile = value;
+			options.metafile = true;
+		} else if (REGEX_FLAGS.has(key)) {
+			options[key] = new RegExp(value);
+		} else if (COMMA_LIST_FLAGS.has(key)) {
+			options[key] = value.split(',');
+		} else if (value === 'true' || value === 'false') {
+			options[key] = value === 'true';
+		} else {
+			options[key] = value;
+		}
+	}
+
+	return { options, metafile, watch };
+}
+
+// Package name of a bare import specifier, or null for relative/absolute paths
+function packageName(specifier) {
+	if (specifier.startsWith('.') || path.isAbsolute(specifier)) return null;
+	const parts = specifier.split('/');
+	return specifier.startsWith('@') ? parts.slice(0, 2).join('/') : parts[0];
+}
+
+// Overridden package that a resolved file belongs to, if any
+function owningPackage(file, names) {
+	const normalized = file.split(path.sep).join('/');
+	return names.find((name) => normalized.includes(`/node_modules/${name}/`));
+}
+
+// Force the `sideEffects` flag for every file of the configured packages
+function sideEffectsPlugin(overrides) {
+	const names = Object.keys(overrides);
+
+	return {
+		name: 'packr-side-effects',
+		setup(build) {
+			if (names.length === 0) return;
+
+			build.onResolve({ filter: /.*/ }, async (args) => {
+				if (args.pluginData === PASSTHROUGH) return undefined;
+
+				const direct = packageName(args.path);
+				if (!names.includes(direct) && !owningPackage(args.importer || '', names)) return undefined;
+
+				const result = await build.resolve(args.path, {
+					kind: args.kind,
+					importer: args.importer,
+					namespace: args.namespace,
+					resolveDir: args.resolveDir,
+					pluginData: PASSTHROUGH,
+				});
+				if (result.errors.length > 0) return { errors: result.errors };
+
+				const owner = owningPackage(result.path, names);
+				return {
+					path: result.path,
+					namespace: result.namespace,
+					external: result.external,
+					pluginData: result.pluginData,
+					sideEffects: owner ? overrides[owner] : result.sideEffects,
+				};
+			});
+		},
+	};
+}
+
+// Load a user plugin: a module exporting a plugin object or a factory taking options
+function loadPlugin({ module, options }, config) {
+	const resolved = require.resolve(module, { paths: [config.projectDir || process.cwd()] });
+	const exported = require(resolved);
+	const plugin = exported && exported.__esModule && exported.default ? exported.default : exported;
+	return typeof plugin === 'function' ? plugin(options || {}) : plugin;
+}
+
+function loadPlugins(config) {
+	return [sideEffectsPlugin(config.sideEffects || {}), ...(config.plugins || []).map((spec) => loadPlugin(spec, config))];
+}
+
+// With PACKR_ESBUILD_MESSAGES set, the errors and warnings of a build are printed as one
+// JSON line on stdout for packr to render, instead of esbuild logging them as text
+const MESSAGES = process.env.PACKR_ESBUILD_MESSAGES === '1';
+
+function reportMessages({ errors, warnings }) {
+	if (MESSAGES) {
+		process.stdout.write(`packr-messages ${JSON.stringify({ errors: errors || [], warnings: warnings || [] })}\n`);
+	}
+}
+
+// With PACKR_ESBUILD_SERVICE set, the shim stays running and builds what arrives on stdin, one
+// `{ id, args, shim }` request per line, by rebuilding an esbuild context kept for each set of
+// arguments, so modules that didn't change aren't parsed again. Every request is answered with
+// a `packr-reply` line on stdout carrying its messages.
+const SERVICE = process.env.PACKR_ESBUILD_SERVICE === '1';
+// Contexts kept for the most recently built sets of arguments
+const SERVICE_CONTEXTS = 8;
+
+function serve() {
+	const contexts = new Map();
+	const reply = (message) => process.stdout.write(`packr-reply ${JSON.stringify(message)}\n`);
+
+	function context(args, config) {
+		const key = JSON.stringify([args, config]);
+		let entry = contexts.get(key);
+		if (entry) {
+			contexts.delete(key);
+		} else {
+			const { options, metafile } = parseArgs(args);
+			options.logLevel = 'silent';
+			options.plugins = loadPlugins(config);
+			entry = { ctx: esbuild.context(options), metafile, queue: Promise.resolve() };
+			// Options esbuild refused are tried afresh next time
+			entry.ctx.catch(() => contexts.get(key) === entry && contexts.delete(key));
+		}
+		contexts.set(key, entry);
+		if (contexts.size > SERVICE_CONTEXTS) {
+			const [oldest, stale] = contexts.entries().next().value;
+			contexts.delete(oldest);
+			stale.queue.then(() => stale.ctx).then((ctx) => ctx.dispose(), () => {});
+		}
+		return entry;
+	}
+
+	async function build({ id, args, shim: config }) {
+		try {
+			const entry = context(args, config || {});
+			// A context builds one request at a time
+			const run = entry.queue.then(() => entry.ctx).then((ctx) => ctx.rebuild());
+			entry.queue = run.catch(() => {});
+			const result = await run;
+			if (entry.metafile) {
+				fs.writeFileSync(entry.metafile, JSON.stringify(result.metafile));
+			}
+			reply({ id, success: true, errors: result.errors, warnings: result.warnings });
+		} catch (err) {
+			const errors = err && Array.isArray(err.errors) ? err.errors : [{ text: err && err.message ? err.message : String(err) }];
+			reply({ id, success: false, errors, warnings: (err && err.warnings) || [] });
+		}
+	}
+
+	require('readline')
+		.createInterface({ input: process.stdin })
+		.on('line', (line) => line.trim() && build(JSON.parse(line)))
+		// Packr went away, and the contexts with it
+		.on('close', () => process.exit(0));
+}
+
+(async () => {
+	if (SERVICE) {
+		serve();
+		return;
+	}
+
+	const { options, metafile, watch } = parseArgs(process.argv.slice(2));
+	options.logLevel = MESSAGES && !watch ? 'silent' : options.logLevel || 'info';
+
+	try {
+		options.plugins = loadPlugins(shim);
+	} catch (err) {
+		console.error(`packr: failed to load esbuild plugin: ${err.message}`);
+		process.exit(1);
+	}
+
+	try {
+		if (watch) {
+			const ctx = await esbuild.context(options);
+			await ctx.watch();
+			return;
+		}
+
+		const result = await esbuild.build(options);
+		reportMessages(result);
+		if (metafile) {
+			fs.writeFileSync(metafile, JSON.stringify(result.metafile));
+		}
+	} catch (err) {
+		// A failed build carries its messages; anything else is reported as it is
+		if (err && Array.isArray(err.errors)) {
+			reportMessages(err);
+		} else if (MESSAGES) {
+			console.error(err && err.stack ? err.stack : String(err));
+		}
+		// Leaving with exitCode lets the messages above reach a piped stdout first
+		process.exitCode = 1;
+	}
+})();
+"#;
+
+// * An esbuild plugin module loaded by the shim, as a module name or with options
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PluginSpec {
+    pub module: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<serde_json::Value>,
+}
+
+impl<'de> serde::Deserialize<'de> for PluginSpec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Module(String),
+            Full {
+                module: String,
+                #[serde(default)]
+                options: Option<serde_json::Value>,
+            },
+        }
+
+        Ok(match Value::deserialize(deserializer)? {
+            Value::Module(module) => PluginSpec {
+                module,
+                options: None,
+            },
+            Value::Full { module, options } => PluginSpec { module, options },
+        })
+    }
+}
+
+// * Whether the project installs the esbuild package, whose JS API the shim runs builds through
+pub fn api_installed(config_dir: &Path) -> bool {
+    config_dir
+        .join("node_modules")
+        .join("esbuild")
+        .join("package.json")
+        .is_file()
+}
+
+// * Write the shim into the project's `.packr` directory so `require('esbuild')`
+// * resolves against the project's node_modules (`.cjs` keeps Deno and Bun in CommonJS mode)
+pub fn write_shim(config_dir: &Path) -> Result<PathBuf, String> {
+    let dir = config_dir.join(".packr");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create .packr directory: {}", e))?;
+    let path = dir.join("esbuild-shim.cjs");
+
+    // Skip the write when unchanged so watchers on the project don't fire
+    if fs::read_to_string(&path).ok().as_deref() != Some(SHIM_SOURCE) {
+        fs::write(&path, SHIM_SOURCE)
+            .map_err(|e| format!("Failed to write esbuild shim: {}", e))?;
+    }
+
+    Ok(path)
+}
+
+// * Whether shim builds go to a resident service rather than a process each, as in a daemon or
+// * watch session, where the same builds run again and again
+static SERVICE_ON: AtomicBool = AtomicBool::new(false);
+
+// * The running service, started by the first build that needs it
+static SERVICE: Mutex<Option<Arc<Service>>> = Mutex::new(None);
+
+// * Requests waiting for their reply, by id, and whether the service has exited, after which
+// * none is answered
+#[derive(Default)]
+struct Waiting {
+    closed: bool,
+    replies: BTreeMap<u64, mpsc::Sender<serde_json::Value>>,
+}
+
+// * The shim running in service mode, answering build requests over its stdin and stdout
+struct Service {
+    // The program and arguments that run the shim, which a new runtime or shim path changes
+    program: Vec<OsString>,
+    stdin: Mutex<ChildStdin>,
+    waiting: Arc<Mutex<Waiting>>,
+    next_id: AtomicU64,
+    resident: Mutex<Resident>,
+}
+
+// * How a build in the service went, with its messages as `diagnostics::parse_esbuild_messages`
+// * reads them
+pub struct Reply {
+    pub success: bool,
+    pub messages: String,
+}
+
+// * Send shim builds to a resident service from now on
+pub fn start_service() {
+    SERVICE_ON.store(true, Ordering::SeqCst);
+}
+
+// * The shim program, its esbuild arguments and its `PACKR_ESBUILD_SHIM` config, when `cmd`
+// * runs the shim; requests are read off the command built for a one-off run
+fn shim_run(cmd: &Command) -> Option<(Vec<OsString>, Vec<String>, serde_json::Value)> {
+    let config = cmd
+        .get_envs()
+        .find_map(|(key, value)| (key == "PACKR_ESBUILD_SHIM").then_some(value)?)?;
+    let config = serde_json::from_str(&config.to_string_lossy()).ok()?;
+    let args: Vec<&std::ffi::OsStr> = cmd.get_args().collect();
+    let script = args
+        .iter()
+        .position(|arg| Path::new(arg).file_name() == Some("esbuild-shim.cjs".as_ref()))?;
+    let program = std::iter::once(cmd.get_program())
+        .chain(args[..=script].iter().copied())
+        .map(OsString::from)
+        .collect();
+    let args = args[script + 1..]
+        .iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect();
+    Some((program, args, config))
+}
+
+// * Run the build `cmd` describes in the service, when one is on and `cmd` runs the shim;
+// * `None` when it should run as a process of its own
+pub fn service_build(cmd: &Command) -> Option<io::Result<Reply>> {
+    if !SERVICE_ON.load(Ordering::SeqCst) {
+        return None;
+    }
+    let (program, args, config) = shim_run(cmd)?;
+    Some(service(&program).and_then(|service| service.build(args, config)))
+}
+
+// * The running service for `program`, started or restarted as needed
+fn service(program: &[OsString]) -> io::Result<Arc<Service>> {
+    let mut running = SERVICE.lock().unwrap_or_else(|e| e.into_inner());
+    let usable = |service: &&Arc<Service>| {
+        service.program == program && service.waiting.lock().is_ok_and(|w| !w.closed)
+    };
+    if let Some(service) = running.as_ref().filter(usable) {
+        return Ok(service.clone());
+    }
+    let mut cmd = Command::new(&program[0]);
+    cmd.args(&program[1..]).env("PACKR_ESBUILD_SERVICE", "1");
+    let mut resident = children::resident(&mut cmd)?;
+    let (Some(stdin), Some(stdout)) = (resident.child.stdin.take(), resident.child.stdout.take())
+    else {
+        return Err(io::Error::other("the esbuild service has no pipes"));
+    };
+
+    let waiting = Arc::new(Mutex::new(Waiting::default()));
+    let replies = waiting.clone();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let Some(reply) = line.strip_prefix("packr-reply ") else {
+                // Plugins logging to stdout
+                eprintln!("{}", line);
+                continue;
+            };
+            let Ok(reply) = serde_json::from_str::<serde_json::Value>(reply) else {
+                continue;
+            };
+            let sender = reply["id"]
+                .as_u64()
+                .and_then(|id| replies.lock().ok()?.replies.remove(&id));
+            if let Some(sender) = sender {
+                let _ = sender.send(reply);
+            }
+        }
+        // The service exited: requests still waiting fail, and the next one starts it again
+        if let Ok(mut waiting) = replies.lock() {
+            waiting.closed = true;
+            waiting.replies.clear();
+        }
+    });
+
+    let service = Arc::new(Service {
+        program: program.to_vec(),
+        stdin: Mutex::new(stdin),
+        waiting,
+        next_id: AtomicU64::new(0),
+        resident: Mutex::new(resident),
+    });
+    *running = Some(service.clone());
+    Ok(service)
+}
+
+impl Service {
+    fn build(&self, args: Vec<String>, config: serde_json::Value) -> io::Result<Reply> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, reply) = mpsc::channel();
+        match self.waiting.lock() {
+            Ok(mut waiting) if !waiting.closed => {
+                waiting.replies.insert(id, sender);
+            }
+            _ => return Err(io::Error::other("the esbuild service exited")),
+        }
+        let request = serde_json::json!({ "id": id, "args": args, "shim": config });
+        let sent = self
+            .stdin
+            .lock()
+            .map_err(|_| io::Error::other("the esbuild service is unavailable"))
+            .and_then(|mut stdin| writeln!(stdin, "{}", request).and_then(|()| stdin.flush()));
+        if let Err(e) = sent {
+            self.stop();
+            return Err(e);
+        }
+
+        let timeout = children::timeout("esbuild");
+        let received = match timeout {
+            Some(secs) => reply.recv_timeout(Duration::from_secs(secs)),
+            None => reply.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(reply) => Ok(Reply {
+                success: reply["success"].as_bool().unwrap_or(false),
+                messages: reply.to_string(),
+            }),
+            Err(RecvTimeoutError::Timeout) => {
+                self.stop();
+                Err(children::timed_out("esbuild", timeout.unwrap_or_default()))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.stop();
+                Err(io::Error::other("the esbuild service exited"))
+            }
+        }
+    }
+
+    // * Kill the service, so the next build starts a new one
+    fn stop(&self) {
+        let mut running = SERVICE.lock().unwrap_or_else(|e| e.into_inner());
+        if running
+            .as_ref()
+            .is_some_and(|s| std::ptr::eq(s.as_ref(), self))
+        {
+            *running = None;
+        }
+        if let Ok(mut resident) = self.resident.lock() {
+            let _ = resident.child.kill();
+        }
+    }
+}
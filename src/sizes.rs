@@ -0,0 +1,109 @@
+// * ! ==================================================
+// * ! Output size tracking for Packr
+// * ! ==================================================
+
+use crate::build::log_line;
+use crate::cache::SIZES_FILE;
+use crate::manifest::relative_to;
+use colored::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * `300 B` below a kilobyte, `2.1 KB` above
+pub fn size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+// * `+2.1 KB` / `−300 B`
+pub fn change(previous: u64, current: u64) -> String {
+    if current >= previous {
+        format!("+{}", size(current - previous))
+    } else {
+        format!("\u{2212}{}", size(previous - current))
+    }
+}
+
+// * `change`, red for growth and green for savings
+fn delta(previous: u64, current: u64) -> ColoredString {
+    if current >= previous {
+        change(previous, current).red()
+    } else {
+        change(previous, current).green()
+    }
+}
+
+// * Output sizes the last build recorded in the cache, keyed by logical path
+pub fn recorded(cache_dir: &Path) -> Option<BTreeMap<String, u64>> {
+    let content = fs::read_to_string(cache_dir.join(SIZES_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// * Print how each output's size changed since the last build, then record the new sizes.
+// * Outputs are keyed by their logical path, so hashed file names still compare.
+pub fn report(
+    cache_dir: &Path,
+    config_dir: &Path,
+    outputs: &[(PathBuf, PathBuf)],
+) -> Result<(), String> {
+    let path = cache_dir.join(SIZES_FILE);
+    let previous = recorded(cache_dir);
+
+    let current: BTreeMap<String, u64> = outputs
+        .iter()
+        .filter_map(|(logical, actual)| {
+            let bytes = fs::metadata(actual).ok()?.len();
+            Some((relative_to(logical, config_dir), bytes))
+        })
+        .collect();
+
+    if let Some(ref previous) = previous {
+        let mut changed = 0;
+        for (name, &bytes) in &current {
+            match previous.get(name) {
+                Some(&before) if before == bytes => continue,
+                Some(&before) => log_line(format!(
+                    "{} {} {} ({})",
+                    "Size".cyan().bold(),
+                    delta(before, bytes),
+                    name,
+                    size(bytes)
+                )),
+                None => log_line(format!(
+                    "{} {} {} (new)",
+                    "Size".cyan().bold(),
+                    delta(0, bytes),
+                    name
+                )),
+            }
+            changed += 1;
+        }
+        for (name, &before) in previous {
+            if !current.contains_key(name) {
+                log_line(format!(
+                    "{} {} {} (removed)",
+                    "Size".cyan().bold(),
+                    delta(before, 0),
+                    name
+                ));
+                changed += 1;
+            }
+        }
+        if changed == 0 {
+            log_line(format!(
+                "{} no changes since the last build",
+                "Size".cyan().bold()
+            ));
+        }
+    }
+
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+    let json = serde_json::to_string_pretty(&current)
+        .map_err(|e| format!("Failed to serialize output sizes: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
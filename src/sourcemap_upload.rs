@@ -0,0 +1,344 @@
+// * ! ==================================================
+// * ! Source map uploads for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, Config};
+use crate::cache;
+use crate::copy::expand_env;
+use crate::curl::{self, Field, Request};
+use crate::manifest::{relative_to, Manifest};
+use crate::plugin;
+use crate::prune;
+use crate::retry;
+use colored::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Where source maps can be uploaded to
+pub const PROVIDERS: [&str; 3] = ["sentry", "http", "plugin"];
+
+// * How long one file may take to upload
+const TIMEOUT_SECS: u64 = 120;
+
+// * Upload the source maps of every full build, e.g. to an error tracker that un-minifies
+// * stack traces with them
+#[derive(Clone, serde::Deserialize)]
+pub struct SourcemapUpload {
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    // What the maps are tagged with; a build that expands it to nothing uploads nothing
+    pub release: String,
+    // Sentry's server (https://sentry.io by default), or the endpoint of `http`
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub org: Option<String>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default = "default_auth_token")]
+    pub auth_token: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // Prefix of the uploaded names, which match the URLs the files are served from
+    #[serde(default = "default_url_prefix")]
+    pub url_prefix: String,
+    // Delete the maps and their `sourceMappingURL` comments once uploaded
+    #[serde(default)]
+    pub strip: bool,
+}
+
+// * The token and header values are secrets, so the logged config leaves them out
+impl fmt::Debug for SourcemapUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourcemapUpload")
+            .field("provider", &self.provider)
+            .field("release", &self.release)
+            .field("url", &self.url)
+            .field("org", &self.org)
+            .field("project", &self.project)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("url_prefix", &self.url_prefix)
+            .field("strip", &self.strip)
+            .finish()
+    }
+}
+
+// * Likewise for the resolved config in the build record
+impl serde::Serialize for SourcemapUpload {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = serde_json::json!({
+            "provider": self.provider,
+            "release": self.release,
+            "url": self.url,
+            "org": self.org,
+            "project": self.project,
+            "headers": self.headers.keys().collect::<Vec<_>>(),
+            "url_prefix": self.url_prefix,
+            "strip": self.strip,
+        });
+        serde::Serialize::serialize(&value, serializer)
+    }
+}
+
+fn default_provider() -> String {
+    "sentry".to_string()
+}
+
+fn default_auth_token() -> String {
+    "[env:SENTRY_AUTH_TOKEN]".to_string()
+}
+
+fn default_url_prefix() -> String {
+    "~/".to_string()
+}
+
+const SENTRY_URL: &str = "https://sentry.io";
+
+// * Upload the maps the build wrote, with the files they map, then strip them from the outputs
+// * if the config asks to. Runs before the manifest is written, so its integrity hashes are
+// * those of the stripped files.
+pub fn run(config: &Config, config_dir: &Path, manifest: &mut Manifest) -> Result<(), String> {
+    let Some(ref upload) = config.sourcemap_upload else {
+        return Ok(());
+    };
+    let release = expand_env(&upload.release);
+    if release.is_empty() {
+        log_line(format!(
+            "{} not uploaded, as the release \"{}\" is empty",
+            "Source maps".yellow().bold(),
+            upload.release
+        ));
+        return Ok(());
+    }
+
+    let maps: Vec<PathBuf> = prune::emitted(config, config_dir, &manifest.files())
+        .into_iter()
+        .filter(|file| file.ends_with(".map"))
+        .map(|file| config_dir.join(file))
+        .filter(|path| path.is_file())
+        .collect();
+    let files = artifacts(config, config_dir, upload, &maps);
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let cache_dir = cache::dir(config, config_dir);
+    match upload.provider.as_str() {
+        "sentry" => sentry(upload, &cache_dir, &release, &files)?,
+        "http" => http(upload, &cache_dir, &release, &files)?,
+        _ => plugin::upload_source_maps(&release, &files)?,
+    }
+    log_line(format!(
+        "{} {} file(s) for release {}",
+        "Uploaded".green().bold(),
+        files.len(),
+        release
+    ));
+
+    if upload.strip {
+        for map in &maps {
+            let code = PathBuf::from(map.to_string_lossy().trim_end_matches(".map"));
+            strip_reference(&code)?;
+            fs::remove_file(map)
+                .map_err(|e| format!("Failed to remove {}: {}", map.display(), e))?;
+        }
+        manifest.refresh_integrity()?;
+    }
+    Ok(())
+}
+
+// * The maps with the files they map, named by their path below the CSS or JavaScript output
+// * directory. Copies in `css_destination` and `js_destination` would repeat those names, so
+// * they aren't uploaded.
+fn artifacts(
+    config: &Config,
+    config_dir: &Path,
+    upload: &SourcemapUpload,
+    maps: &[PathBuf],
+) -> Vec<(String, PathBuf)> {
+    let output_dir = |output: &str| {
+        let path = config_dir.join(output);
+        path.parent().unwrap_or(config_dir).to_path_buf()
+    };
+    let dirs = [
+        output_dir(&config.js_output),
+        output_dir(&config.scss_output),
+    ];
+    let prefix = upload.url_prefix.trim_end_matches('/');
+
+    let mut files = BTreeMap::new();
+    for map in maps {
+        let code = PathBuf::from(map.to_string_lossy().trim_end_matches(".map"));
+        for path in [code, map.clone()]
+            .into_iter()
+            .filter(|path| path.is_file())
+        {
+            let Some(dir) = dirs.iter().find(|dir| path.starts_with(dir)) else {
+                continue;
+            };
+            let name = format!("{}/{}", prefix, relative_to(&path, dir));
+            files.entry(name).or_insert(path);
+        }
+    }
+    files.into_iter().collect()
+}
+
+// * `value` as one URL path segment
+fn encode_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// * Create the release in Sentry, then add each file to it as a release artifact
+fn sentry(
+    upload: &SourcemapUpload,
+    cache_dir: &Path,
+    release: &str,
+    files: &[(String, PathBuf)],
+) -> Result<(), String> {
+    let base = upload.url.as_deref().unwrap_or(SENTRY_URL);
+    let base = expand_env(base).trim_end_matches('/').to_string();
+    let org = encode_segment(upload.org.as_deref().unwrap_or_default());
+    let project = upload.project.as_deref().unwrap_or_default();
+    let mut headers = headers(upload);
+    headers.push(format!(
+        "Authorization: Bearer {}",
+        expand_env(&upload.auth_token)
+    ));
+
+    let body = serde_json::json!({ "version": release, "projects": [project] });
+    let (status, response) = curl::send(
+        cache_dir,
+        "sourcemaps",
+        Request {
+            method: "POST",
+            url: format!("{}/api/0/organizations/{}/releases/", base, org),
+            headers: headers.clone(),
+            json: Some(&body),
+            form: Vec::new(),
+            timeout_secs: TIMEOUT_SECS,
+        },
+    )
+    .map_err(|e| format!("Failed to create release {} in Sentry: {}", release, e))?;
+    // 208 is an existing release
+    if !(200..300).contains(&status) {
+        return Err(format!(
+            "Sentry answered HTTP {} to creating release {}: {}",
+            status,
+            release,
+            response.trim()
+        ));
+    }
+
+    let url = format!(
+        "{}/api/0/projects/{}/{}/releases/{}/files/",
+        base,
+        org,
+        encode_segment(project),
+        encode_segment(release)
+    );
+    for (name, path) in files {
+        let (status, response) = curl::send(
+            cache_dir,
+            "sourcemaps",
+            Request {
+                method: "POST",
+                url: url.clone(),
+                headers: headers.clone(),
+                json: None,
+                form: vec![
+                    ("file".to_string(), Field::File(path.clone())),
+                    ("name".to_string(), Field::Text(name.clone())),
+                ],
+                timeout_secs: TIMEOUT_SECS,
+            },
+        )
+        .map_err(|e| format!("Failed to upload {}: {}", path.display(), e))?;
+        // 409 is a file the release already has, e.g. from a retried job
+        if !(200..300).contains(&status) && status != 409 {
+            return Err(format!(
+                "Sentry answered HTTP {} to uploading {}: {}",
+                status,
+                name,
+                response.trim()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// * POST each file as a multipart form with `file`, `name` and `release` fields
+fn http(
+    upload: &SourcemapUpload,
+    cache_dir: &Path,
+    release: &str,
+    files: &[(String, PathBuf)],
+) -> Result<(), String> {
+    let url = expand_env(upload.url.as_deref().unwrap_or_default());
+    for (name, path) in files {
+        let (status, response) = curl::send(
+            cache_dir,
+            "sourcemaps",
+            Request {
+                method: "POST",
+                url: url.clone(),
+                headers: headers(upload),
+                json: None,
+                form: vec![
+                    ("file".to_string(), Field::File(path.clone())),
+                    ("name".to_string(), Field::Text(name.clone())),
+                    ("release".to_string(), Field::Text(release.to_string())),
+                ],
+                timeout_secs: TIMEOUT_SECS,
+            },
+        )
+        .map_err(|e| format!("Failed to upload {}: {}", path.display(), e))?;
+        if !(200..300).contains(&status) {
+            return Err(format!(
+                "{} answered HTTP {} to uploading {}: {}",
+                upload.url.as_deref().unwrap_or_default(),
+                status,
+                name,
+                response.trim()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn headers(upload: &SourcemapUpload) -> Vec<String> {
+    upload
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, expand_env(value)))
+        .collect()
+}
+
+// * Remove the `sourceMappingURL` comment that ends a CSS or JavaScript file, leaving other
+// * mentions of it, e.g. in a string, alone
+fn strip_reference(path: &Path) -> Result<(), String> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let Some(at) = content.rfind("sourceMappingURL=") else {
+        return Ok(());
+    };
+    let start = content[..at].rfind('\n').map_or(0, |i| i + 1);
+    if !matches!(content[start..at].trim(), "//#" | "//@" | "/*#" | "/*@") {
+        return Ok(());
+    }
+    let end = content[at..]
+        .find('\n')
+        .map_or(content.len(), |i| at + i + 1);
+    let stripped = format!("{}{}", &content[..start], &content[end..]);
+    retry::write(path, stripped).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
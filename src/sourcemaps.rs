@@ -0,0 +1,181 @@
+// * ! ==================================================
+// * ! Source map path normalization for Packr
+// * ! ==================================================
+
+use crate::build::{normalize_path, Config};
+use crate::manifest::relative_to;
+use crate::prune;
+use crate::retry;
+use base64::Engine;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * Start of an inline source map comment, as esbuild and lightningcss write it
+const INLINE_PREFIX: &str = "sourceMappingURL=data:application/json;base64,";
+
+// * Where the `sources` of a map are resolved from and written relative to
+struct Layout<'a> {
+    // Directory of the map, or of the file embedding it
+    map_dir: PathBuf,
+    // Config directory, when `sourcemap_root` makes sources project-relative
+    project: Option<(PathBuf, &'a str)>,
+}
+
+// * A source path as the map should list it, or `None` to leave it alone: URLs such as
+// * `webpack://`, and esbuild namespaces such as `<stdin>` or `packr-shim:...`
+fn rewrite_source(source: &str, root: Option<&Path>, layout: &Layout) -> Option<String> {
+    let source = source.strip_prefix("file://").unwrap_or(source);
+    let path = Path::new(source);
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else if source.contains(':') || source.starts_with('<') {
+        return None;
+    } else {
+        root.unwrap_or(&layout.map_dir)
+            .join(source.replace('\\', "/"))
+    };
+    let absolute = normalize_path(&absolute);
+
+    let base = match layout.project {
+        Some((ref config_dir, _)) => config_dir,
+        None => &layout.map_dir,
+    };
+    // A source reached through a symlinked path is compared by its real location
+    let absolute = if absolute.starts_with(base) {
+        absolute
+    } else {
+        absolute.canonicalize().unwrap_or(absolute)
+    };
+    Some(relative_to(&absolute, base))
+}
+
+// * Rewrite the `sources` of a parsed map; true when anything changed
+fn normalize_map(map: &mut Value, layout: &Layout) -> bool {
+    let before = map.clone();
+
+    // A local `sourceRoot` is folded into the sources; a URL is kept as it is
+    let source_root = map["sourceRoot"].as_str().unwrap_or("").to_string();
+    let root = source_root.strip_prefix("file://").unwrap_or(&source_root);
+    let root = if root.is_empty() {
+        None
+    } else if Path::new(root).is_absolute() {
+        Some(PathBuf::from(root))
+    } else if root.contains(':') {
+        return false;
+    } else {
+        Some(layout.map_dir.join(root))
+    };
+
+    for source in map["sources"].as_array_mut().into_iter().flatten() {
+        let rewritten = source
+            .as_str()
+            .and_then(|s| rewrite_source(s, root.as_deref(), layout));
+        if let Some(rewritten) = rewritten {
+            *source = Value::from(rewritten);
+        }
+    }
+
+    if let Some(fields) = map.as_object_mut() {
+        match layout.project {
+            Some((_, url)) => {
+                fields.insert("sourceRoot".to_string(), Value::from(url));
+            }
+            None => {
+                fields.remove("sourceRoot");
+            }
+        }
+    }
+    *map != before
+}
+
+// * Normalize a `.map` file in place
+fn normalize_file(path: &Path, layout: &Layout) -> Result<bool, String> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(false);
+    };
+    let Ok(mut map) = serde_json::from_str::<Value>(&content) else {
+        return Ok(false);
+    };
+    if !normalize_map(&mut map, layout) {
+        return Ok(false);
+    }
+    retry::write(path, map.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(true)
+}
+
+// * Normalize the map embedded as a data URL at the end of a CSS or JavaScript file
+fn normalize_inline(path: &Path, layout: &Layout) -> Result<bool, String> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(false);
+    };
+    let Some(start) = content
+        .rfind(INLINE_PREFIX)
+        .map(|i| i + INLINE_PREFIX.len())
+    else {
+        return Ok(false);
+    };
+    let end = content[start..]
+        .find(|c: char| c.is_whitespace() || c == '*')
+        .map_or(content.len(), |i| start + i);
+    let engine = base64::engine::general_purpose::STANDARD;
+    let Some(mut map) = engine
+        .decode(&content[start..end])
+        .ok()
+        .and_then(|json| serde_json::from_slice::<Value>(&json).ok())
+    else {
+        return Ok(false);
+    };
+    if !normalize_map(&mut map, layout) {
+        return Ok(false);
+    }
+
+    let encoded = engine.encode(map.to_string());
+    let updated = format!("{}{}{}", &content[..start], encoded, &content[end..]);
+    retry::write(path, updated)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(true)
+}
+
+// * Rewrite the `sources` of every source map the build wrote, in `.map` files and inline,
+// * so they don't depend on where the project was built: relative to the map, or relative to
+// * the config directory under `sourcemap_root` when that is set
+pub fn normalize(config: &Config, config_dir: &Path, files: &[PathBuf]) -> Result<(), String> {
+    if !config.sourcemap.is_enabled() {
+        return Ok(());
+    }
+    // The config directory is empty for a config in the working directory
+    let project_dir = Path::new(".")
+        .join(config_dir)
+        .canonicalize()
+        .unwrap_or_else(|_| config_dir.to_path_buf());
+
+    for file in prune::emitted(config, config_dir, files) {
+        let path = config_dir.join(&file);
+        let is_map = file.ends_with(".map");
+        let is_code = [".css", ".js", ".mjs", ".cjs"]
+            .iter()
+            .any(|ext| file.ends_with(ext));
+        if !(is_map || is_code) || !path.is_file() {
+            continue;
+        }
+
+        let map_dir = path.parent().unwrap_or(config_dir);
+        let layout = Layout {
+            map_dir: map_dir
+                .canonicalize()
+                .unwrap_or_else(|_| map_dir.to_path_buf()),
+            project: config
+                .sourcemap_root
+                .as_deref()
+                .map(|url| (project_dir.clone(), url)),
+        };
+        if is_map {
+            normalize_file(&path, &layout)?;
+        } else {
+            normalize_inline(&path, &layout)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+// * ! ==================================================
+// * ! SVG minification for Packr
+// * ! ==================================================
+
+// * Namespace prefixes written by editors that browsers ignore
+const EDITOR_PREFIXES: [&str; 4] = ["sodipodi:", "inkscape:", "sketch:", "serif:"];
+
+// * Elements removed together with their content
+const DROPPED_ELEMENTS: [&str; 1] = ["metadata"];
+
+// * Elements whose text content, including whitespace, is rendered or parsed
+const TEXT_ELEMENTS: [&str; 7] = [
+    "text", "tspan", "textPath", "title", "desc", "style", "script",
+];
+
+fn is_editor_name(name: &str) -> bool {
+    EDITOR_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+        || EDITOR_PREFIXES
+            .iter()
+            .any(|prefix| name.strip_prefix("xmlns:") == Some(prefix.trim_end_matches(':')))
+}
+
+// * Index of the `>` closing a tag, skipping over quoted attribute values. Shared with the
+// * HTML minifier, whose tags quote their values the same way.
+pub fn tag_end(tag: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return i,
+            _ => {}
+        }
+    }
+    tag.len()
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// * Split the inside of a tag into its name, attributes and self-closing flag
+fn parse_tag(inner: &str) -> (String, Vec<(String, String)>, bool) {
+    let self_closing = inner.ends_with('/');
+    let inner = inner.trim_end_matches('/');
+    let name_end = inner
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(inner.len());
+    let name = inner[..name_end].to_string();
+
+    let mut attributes = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let Some(eq) = rest.find('=') else {
+            break;
+        };
+        let key = rest[..eq].trim().to_string();
+        let after = rest[eq + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(close) = after[1..].find(quote) else {
+            break;
+        };
+        attributes.push((key, after[1..close + 1].to_string()));
+        rest = after[close + 2..].trim_start();
+    }
+
+    (name, attributes, self_closing)
+}
+
+fn write_tag(out: &mut String, name: &str, attributes: &[(String, String)], self_closing: bool) {
+    out.push('<');
+    out.push_str(name);
+    for (key, value) in attributes {
+        if is_editor_name(key) {
+            continue;
+        }
+        // Values keep their original escaping; only double quotes need re-escaping
+        let value = collapse_whitespace(value).replace('"', "&quot;");
+        out.push_str(&format!(" {}=\"{}\"", key, value));
+    }
+    out.push_str(if self_closing { "/>" } else { ">" });
+}
+
+// * Remove comments, metadata and editor data, and collapse insignificant whitespace
+pub fn minify(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut stack: Vec<String> = Vec::new();
+    // Depth in `stack` at which a dropped element started
+    let mut dropping: Option<usize> = None;
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            if dropping.is_none() && !rest.trim().is_empty() {
+                out.push_str(rest);
+            }
+            break;
+        };
+
+        // Text before the next tag
+        let text = &rest[..start];
+        if dropping.is_none() && !text.is_empty() {
+            let in_text = stack
+                .last()
+                .is_some_and(|name| TEXT_ELEMENTS.contains(&name.as_str()));
+            if in_text {
+                out.push_str(text);
+            } else if !text.trim().is_empty() {
+                out.push_str(text.trim());
+            }
+        }
+        rest = &rest[start..];
+
+        if let Some(body) = rest.strip_prefix("<!--") {
+            rest = body.find("-->").map_or("", |end| &body[end + 3..]);
+        } else if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").map_or(rest.len(), |end| end + 3);
+            if dropping.is_none() {
+                out.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            // XML declarations and doctypes aren't needed by browsers
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(body) = rest.strip_prefix("</") {
+            let end = body.find('>').unwrap_or(body.len());
+            let name = body[..end].trim();
+            if dropping.is_none() {
+                out.push_str(&format!("</{}>", name));
+            }
+            stack.pop();
+            if dropping.is_some_and(|depth| stack.len() <= depth) {
+                dropping = None;
+            }
+            rest = body.get(end + 1..).unwrap_or("");
+        } else {
+            let end = tag_end(rest);
+            let (name, attributes, self_closing) = parse_tag(rest[1..end].trim());
+
+            let dropped = DROPPED_ELEMENTS.contains(&name.as_str()) || is_editor_name(&name);
+            if dropping.is_none() && dropped && !self_closing {
+                dropping = Some(stack.len());
+            } else if dropping.is_none() && !dropped {
+                write_tag(&mut out, &name, &attributes, self_closing);
+            }
+
+            if !self_closing {
+                stack.push(name);
+            }
+            rest = rest.get(end + 1..).unwrap_or("");
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_end_at_the_first_unquoted_bracket() {
+        assert_eq!(tag_end("<path d=\"M0 0\">"), 14);
+        assert_eq!(tag_end("<a title='a > b' href=\"x\">rest"), 25);
+        assert_eq!(tag_end("<g fill=\"red"), 12);
+    }
+}
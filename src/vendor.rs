@@ -0,0 +1,112 @@
+// * ! ==================================================
+// * ! Vendor chunk entry generation for Packr
+// * ! ==================================================
+
+use std::fs;
+use std::path::Path;
+
+// * Vendor chunk settings (`"vendor_chunk": true` uses the defaults)
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct VendorChunk {
+    // Packages forced into the vendor chunk; empty means every package.json dependency
+    #[serde(default)]
+    pub packages: Vec<String>,
+    // Packages whose JavaScript on disk is smaller than this (in bytes) stay in the app chunk
+    #[serde(default)]
+    pub min_size: u64,
+}
+
+// * Accept either a boolean or a settings object for `vendor_chunk`
+pub fn deserialize_vendor_chunk<'de, D>(deserializer: D) -> Result<Option<VendorChunk>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Bool(bool),
+        Settings(VendorChunk),
+    }
+
+    Ok(
+        match <Value as serde::Deserialize>::deserialize(deserializer)? {
+            Value::Bool(true) => Some(VendorChunk::default()),
+            Value::Bool(false) => None,
+            Value::Settings(settings) => Some(settings),
+        },
+    )
+}
+
+// * Read dependency names from the project's package.json
+fn package_dependencies(config_dir: &Path) -> Result<Vec<String>, String> {
+    let manifest = config_dir.join("package.json");
+    if !manifest.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&manifest)
+        .map_err(|e| format!("Failed to read {}: {}", manifest.display(), e))?;
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse {}: {}", manifest.display(), e))?;
+
+    Ok(json
+        .get("dependencies")
+        .and_then(|d| d.as_object())
+        .map(|deps| deps.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+// * Approximate a package's weight by the JavaScript files it ships
+fn package_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                // Nested dependencies are counted under their own package
+                if path.file_name().is_some_and(|name| name == "node_modules") {
+                    0
+                } else {
+                    package_size(&path)
+                }
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext == "js" || ext == "mjs" || ext == "cjs")
+            {
+                entry.metadata().map(|m| m.len()).unwrap_or(0)
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+// * Resolve which packages belong in the vendor chunk
+pub fn vendor_packages(settings: &VendorChunk, config_dir: &Path) -> Result<Vec<String>, String> {
+    let candidates = if settings.packages.is_empty() {
+        package_dependencies(config_dir)?
+    } else {
+        settings.packages.clone()
+    };
+
+    let node_modules = config_dir.join("node_modules");
+    Ok(candidates
+        .into_iter()
+        .filter(|name| {
+            settings.min_size == 0 || package_size(&node_modules.join(name)) >= settings.min_size
+        })
+        .collect())
+}
+
+// * Render the synthetic entry whose code shared with the app becomes the vendor chunk
+pub fn entry_source(packages: &[String]) -> String {
+    packages
+        .iter()
+        .enumerate()
+        .map(|(i, name)| format!("export * as __vendor_{} from \"{}\";\n", i, name))
+        .collect()
+}
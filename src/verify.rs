@@ -0,0 +1,134 @@
+// * ! ==================================================
+// * ! Post-build output verification for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, script_emitted_files, Config};
+use crate::children;
+use crate::manifest::relative_to;
+use crate::runtime::program;
+use colored::*;
+use lightningcss::stylesheet::{ParserOptions, StyleSheet};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// * Whether Node has to parse a bundle as an ES module. The legacy bundle is always iife.
+fn is_module(config: &Config, path: &Path) -> bool {
+    let legacy = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(".legacy."));
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("mjs") => true,
+        Some("cjs") => false,
+        _ => config.format == "esm" && !legacy,
+    }
+}
+
+// * Syntax error in a JavaScript output according to `node --check`, if it has one. The file
+// * is piped in so `--input-type` can pick the module system regardless of package.json;
+// * `Err` means Node itself couldn't be run.
+fn check_js(config: &Config, path: &Path, name: &str) -> io::Result<Option<String>> {
+    let input_type = if is_module(config, path) {
+        "module"
+    } else {
+        "commonjs"
+    };
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Ok(Some(format!("{}: {}", name, e))),
+    };
+    let output = children::output_from(
+        "node",
+        program("node")
+            .arg(format!("--input-type={}", input_type))
+            .arg("--check"),
+        file,
+    )?;
+    if output.status.success() {
+        return Ok(None);
+    }
+
+    // Node prints `[stdin]:<line>`, the offending source line, and later the error itself
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("[stdin]:"))
+        .map(|number| format!(":{}", number.trim()))
+        .unwrap_or_default();
+    let error = stderr
+        .lines()
+        .find(|line| line.contains("Error:"))
+        .unwrap_or("node --check failed")
+        .trim();
+    Ok(Some(format!("{}{}: {}", name, line, error)))
+}
+
+// * Parse error in a CSS output, if lightningcss can't read it back
+fn check_css(path: &Path, name: &str) -> Option<String> {
+    let css = match fs::read_to_string(path) {
+        Ok(css) => css,
+        Err(e) => return Some(format!("{}: {}", name, e)),
+    };
+    let options = ParserOptions {
+        filename: name.to_string(),
+        ..Default::default()
+    };
+    StyleSheet::parse(&css, options)
+        .err()
+        .map(|e| format!("{}: {}", name, e))
+}
+
+// * Re-read every CSS and JavaScript file the build emitted, including esbuild's chunks, so a
+// * truncated write or broken wrapper fails the build instead of reaching production
+pub fn verify(config: &Config, config_dir: &Path, files: &[PathBuf]) -> Result<(), String> {
+    let outputs: BTreeSet<PathBuf> = files
+        .iter()
+        .cloned()
+        .chain(script_emitted_files(config, config_dir))
+        .filter(|file| file.is_file())
+        .collect();
+
+    let mut checked = 0;
+    let mut failures = Vec::new();
+    let mut node_missing = false;
+    for file in &outputs {
+        let name = relative_to(file, config_dir);
+        let failure = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("css") => check_css(file, &name),
+            Some("js" | "mjs" | "cjs") if !node_missing => match check_js(config, file, &name) {
+                Ok(failure) => failure,
+                Err(_) => {
+                    node_missing = true;
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+        checked += 1;
+        failures.extend(failure);
+    }
+
+    if node_missing {
+        log_line(format!(
+            "{} node could not be run, JavaScript outputs were not checked",
+            "Verify".yellow().bold()
+        ));
+    }
+    for failure in &failures {
+        log_line(format!("{} {}", "Verify".red().bold(), failure));
+    }
+    if !failures.is_empty() {
+        return Err(format!(
+            "{} of {} output(s) failed verification",
+            failures.len(),
+            checked
+        ));
+    }
+    log_line(format!(
+        "{} {} output(s)",
+        "Verified".green().bold(),
+        checked
+    ));
+    Ok(())
+}
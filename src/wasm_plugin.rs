@@ -0,0 +1,193 @@
+// * ! ==================================================
+// * ! WebAssembly plugins for Packr
+// * ! ==================================================
+
+use crate::plugin::{self, PackrPlugin};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use wasmi::{Caller, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+// * Hooks a module may export, each taking a UTF-8 string and returning one
+pub const HOOKS: [&str; 2] = ["transform_css", "rename_output"];
+
+// * Instructions one hook call may run before it's stopped, so a plugin stuck in a loop
+// * fails the build instead of hanging it
+const FUEL: u64 = 1_000_000_000;
+
+// * Linear memory one hook call may grow to
+const MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+
+// * What a running hook can reach: its limits and the error it reported, nothing else
+struct State {
+    limits: StoreLimits,
+    error: Option<String>,
+}
+
+// * A module from `wasm_plugins`, instantiated afresh for every hook call so calls share no
+// * state and can run on the style and script threads at once
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    hooks: Vec<&'static str>,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes)
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+
+        // * The only imports on offer are Packr's own, so a plugin can't reach the file
+        // * system, the network or the environment
+        if let Some(import) = module
+            .imports()
+            .find(|import| import.module() != "packr" || import.name() != "error")
+        {
+            return Err(format!(
+                "{} imports {}.{}, but WebAssembly plugins may only import packr.error",
+                path.display(),
+                import.module(),
+                import.name()
+            ));
+        }
+        for export in ["memory", "alloc"] {
+            if module.get_export(export).is_none() {
+                return Err(format!("{} does not export `{}`", path.display(), export));
+            }
+        }
+        let hooks: Vec<&'static str> = HOOKS
+            .into_iter()
+            .filter(|hook| module.get_export(hook).is_some())
+            .collect();
+        if hooks.is_empty() {
+            return Err(format!(
+                "{} exports none of the plugin hooks ({})",
+                path.display(),
+                HOOKS.join(", ")
+            ));
+        }
+
+        Ok(Self {
+            name: path.display().to_string(),
+            engine,
+            module,
+            hooks,
+        })
+    }
+
+    // * Call `hook` with `input`: the input is copied into memory from `alloc(len)`, and the
+    // * hook returns its output's pointer and length packed as `(ptr << 32) | len`. A hook
+    // * reports failure by calling `packr.error(ptr, len)` with a message.
+    fn call(&self, hook: &str, input: &str) -> Result<String, String> {
+        let state = State {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MEMORY_LIMIT)
+                .instances(1)
+                .build(),
+            error: None,
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL).map_err(|e| e.to_string())?;
+
+        let mut linker = Linker::<State>::new(&self.engine);
+        linker
+            .func_wrap(
+                "packr",
+                "error",
+                |mut caller: Caller<'_, State>, ptr: i32, len: i32| {
+                    let message = caller
+                        .get_export("memory")
+                        .and_then(Extern::into_memory)
+                        .and_then(|memory| read_string(&memory, &caller, ptr as u32, len as u32))
+                        .unwrap_or_else(|| "failed without a readable message".to_string());
+                    caller.data_mut().error = Some(message);
+                },
+            )
+            .map_err(|e| e.to_string())?;
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| format!("Failed to instantiate: {}", e))?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("`memory` is not a memory")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")
+            .map_err(|e| format!("`alloc` must take and return an i32: {}", e))?;
+        let function = instance
+            .get_typed_func::<(i32, i32), i64>(&store, hook)
+            .map_err(|e| format!("`{}` must take (i32, i32) and return an i64: {}", hook, e))?;
+
+        let len = i32::try_from(input.len()).map_err(|_| "Input too large".to_string())?;
+        let ptr = alloc.call(&mut store, len).map_err(|e| trap(hook, e))?;
+        memory
+            .write(&mut store, ptr as u32 as usize, input.as_bytes())
+            .map_err(|e| format!("`alloc` returned memory out of bounds: {}", e))?;
+        let packed = function
+            .call(&mut store, (ptr, len))
+            .map_err(|e| trap(hook, e))?;
+
+        if let Some(error) = store.data_mut().error.take() {
+            return Err(error);
+        }
+        read_string(&memory, &store, (packed >> 32) as u32, packed as u32)
+            .ok_or_else(|| format!("`{}` returned an output out of bounds or not UTF-8", hook))
+    }
+}
+
+fn read_string(
+    memory: &wasmi::Memory,
+    store: impl wasmi::AsContext,
+    ptr: u32,
+    len: u32,
+) -> Option<String> {
+    let mut buffer = vec![0; len as usize];
+    memory.read(store, ptr as usize, &mut buffer).ok()?;
+    String::from_utf8(buffer).ok()
+}
+
+fn trap(hook: &str, error: wasmi::Error) -> String {
+    format!("`{}` trapped: {}", hook, error)
+}
+
+impl PackrPlugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform_css(&self, css: String, _input: &Path) -> Result<String, String> {
+        if !self.hooks.contains(&"transform_css") {
+            return Ok(css);
+        }
+        self.call("transform_css", &css)
+    }
+
+    fn rename_output(&self, file_name: String) -> Result<String, String> {
+        if !self.hooks.contains(&"rename_output") {
+            return Ok(file_name);
+        }
+        self.call("rename_output", &file_name)
+    }
+}
+
+// * Load the modules listed in `wasm_plugins`, relative to the config directory, in place of
+// * those of a previously loaded config
+pub fn configure(paths: &[String], config_dir: &Path) -> Result<(), String> {
+    let plugins = paths
+        .iter()
+        .map(|path| {
+            WasmPlugin::load(&config_dir.join(path))
+                .map(|plugin| Arc::new(plugin) as Arc<dyn PackrPlugin>)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    plugin::declare(plugins);
+    Ok(())
+}
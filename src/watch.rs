@@ -0,0 +1,336 @@
+// * ! ==================================================
+// * ! Stylesheet and bundle watching for Packr
+// * ! ==================================================
+
+use crate::build::{
+    self, build_scripts, build_styles, log_line, script_dependencies, suffixed_path, Config,
+};
+use crate::bundle::{self, Step};
+use crate::cache::{self, WATCH_FILE};
+use crate::error::PackrError;
+use crate::events::{self, Event};
+use crate::manifest::Manifest;
+use crate::notify;
+use crate::profile;
+use crate::prune;
+use colored::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// * How often dependency timestamps are checked
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// * Rebuilds between printed latency summaries
+const REPORT_EVERY: usize = 10;
+
+// * Phases and changed files listed in latency summaries
+const SLOWEST: usize = 3;
+
+// * Set by the control API to have the entries rebuilt without a change
+static REBUILD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// * Rebuild the watched entries of the session at its next poll
+pub fn request_rebuild() {
+    REBUILD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+// * Modification time per dependency; `None` for paths that don't exist (yet)
+type Stamps = BTreeMap<PathBuf, Option<SystemTime>>;
+
+// * A watched entry, with the config it builds from and the state of the files it was
+// * built from
+struct Entry {
+    name: String,
+    failed: String,
+    step: Step,
+    config: Config,
+    input: PathBuf,
+    stamps: Stamps,
+}
+
+impl Entry {
+    fn new(task: bundle::Task, config_dir: &Path) -> Self {
+        let input = match task.step {
+            Step::Styles => &task.config.scss_input,
+            Step::Scripts => &task.config.js_input,
+        };
+        Self {
+            input: config_dir.join(input),
+            name: task.name,
+            failed: task.failed,
+            step: task.step,
+            config: task.config.into_owned(),
+            stamps: Stamps::new(),
+        }
+    }
+
+    fn build(&self, config_dir: &Path, manifest: &mut Manifest) -> Result<(), PackrError> {
+        build::set_log_scope(Some(&self.name));
+        let result = match self.step {
+            Step::Styles => build_styles(&self.config, config_dir, manifest),
+            Step::Scripts => build_scripts(&self.config, config_dir, false, manifest),
+        };
+        build::set_log_scope(None);
+        result.map_err(|e| PackrError::context(&self.failed, e))
+    }
+}
+
+// * Symlinked sources are followed: a dependency counts as modified when its target changes
+// * and when the link itself is pointed somewhere else
+fn stamp(paths: impl IntoIterator<Item = PathBuf>) -> Stamps {
+    paths
+        .into_iter()
+        .map(|path| {
+            let target = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            let link = fs::symlink_metadata(&path).and_then(|m| m.modified()).ok();
+            (path, target.max(link).filter(|_| target.is_some()))
+        })
+        .collect()
+}
+
+// * Dependencies of an entry from its last build, keeping the old set if that build failed:
+// * the files Sass read for a stylesheet, the modules in esbuild's graph for a script
+fn dependencies(config_dir: &Path, entry: &Entry) -> Vec<PathBuf> {
+    let mut paths = match entry.step {
+        Step::Styles => {
+            cache::scss_dependencies(&cache::dir(&entry.config, config_dir), &entry.input)
+        }
+        Step::Scripts => script_dependencies(&entry.config, config_dir).unwrap_or_default(),
+    };
+    if paths.is_empty() {
+        paths = entry.stamps.keys().cloned().collect();
+    }
+    if !paths.contains(&entry.input) {
+        paths.push(entry.input.clone());
+    }
+    paths
+}
+
+// * Every existing file the build writes, including variants, destination copies and esbuild's
+// * chunks, canonicalized so that differently spelled dependency paths match
+fn output_files(config: &Config, config_dir: &Path) -> BTreeSet<PathBuf> {
+    let outputs: Vec<PathBuf> = bundle::outputs(config)
+        .into_iter()
+        .flat_map(|(_, output)| {
+            let output = config_dir.join(output);
+            [
+                suffixed_path(&output, ".min"),
+                suffixed_path(&output, ".legacy"),
+                output,
+            ]
+        })
+        .collect();
+    prune::emitted(config, config_dir, &outputs)
+        .iter()
+        .filter_map(|file| config_dir.join(file).canonicalize().ok())
+        .collect()
+}
+
+// * Dependencies to watch, leaving out files the build writes itself: a stylesheet that reads
+// * an output would otherwise rebuild after every write of it, forever. Each overlap found is
+// * reported once, as it's a configuration the user should fix.
+fn watched(
+    config_dir: &Path,
+    entry: &Entry,
+    outputs: &BTreeSet<PathBuf>,
+    reported: &mut BTreeSet<PathBuf>,
+) -> Vec<PathBuf> {
+    let (own, watched): (Vec<PathBuf>, Vec<PathBuf>) = dependencies(config_dir, entry)
+        .into_iter()
+        .partition(|path| {
+            path.canonicalize()
+                .is_ok_and(|path| outputs.contains(&path))
+        });
+    for path in own {
+        if reported.insert(path.clone()) {
+            log_line(format!(
+                "{} {} is written by the build and read by {}; writes to it are ignored rather than rebuilding in a loop, import its source instead",
+                "Watch".yellow().bold(),
+                path.display(),
+                entry.input.display()
+            ));
+        }
+    }
+    watched
+}
+
+// * First dependency whose timestamp or existence changed
+fn changed(entry: &Entry) -> Option<PathBuf> {
+    let current = stamp(entry.stamps.keys().cloned());
+    current
+        .into_iter()
+        .find(|(path, modified)| entry.stamps.get(path) != Some(modified))
+        .map(|(path, _)| path)
+}
+
+// * Rebuild latency of a watch session, in milliseconds
+#[derive(Default)]
+struct Metrics {
+    rebuilds: Vec<u64>,
+    // Slowest time seen per build phase, e.g. `grass compile`
+    phases: BTreeMap<String, u64>,
+    // Slowest rebuild seen per changed file, to spot expensive partials
+    files: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    fn record(&mut self, file: String, duration: u64, phases: Vec<(String, u128)>) {
+        self.rebuilds.push(duration);
+        let slowest = self.files.entry(file).or_default();
+        *slowest = (*slowest).max(duration);
+        for (name, micros) in phases {
+            let slowest = self.phases.entry(name).or_default();
+            *slowest = (*slowest).max((micros / 1000) as u64);
+        }
+    }
+
+    // * Nearest-rank percentile of the rebuild durations
+    fn percentile(&self, p: f64) -> u64 {
+        let mut sorted = self.rebuilds.clone();
+        sorted.sort_unstable();
+        let rank = ((p * sorted.len() as f64).ceil() as usize).max(1);
+        sorted.get(rank - 1).copied().unwrap_or(0)
+    }
+
+    fn slowest(map: &BTreeMap<String, u64>) -> Vec<(&String, u64)> {
+        let mut slowest: Vec<(&String, u64)> = map.iter().map(|(name, &ms)| (name, ms)).collect();
+        slowest.sort_by_key(|&(_, ms)| Reverse(ms));
+        slowest.truncate(SLOWEST);
+        slowest
+    }
+
+    fn print(&self) {
+        let list = |map| {
+            Self::slowest(map)
+                .iter()
+                .map(|(name, ms)| format!("{} {} ms", name, ms))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        log_line(format!(
+            "{} {} rebuilds, p50 {} ms, p95 {} ms; slowest phases: {}; slowest files: {}",
+            "Watch".cyan().bold(),
+            self.rebuilds.len(),
+            self.percentile(0.5),
+            self.percentile(0.95),
+            list(&self.phases),
+            list(&self.files)
+        ));
+    }
+
+    // * Status file for editors and scripts watching the session
+    fn write(&self, cache_dir: &Path) {
+        let list = |map| {
+            Self::slowest(map)
+                .iter()
+                .map(|(name, ms)| serde_json::json!({ "name": name, "ms": ms }))
+                .collect::<Vec<_>>()
+        };
+        let status = serde_json::json!({
+            "rebuilds": self.rebuilds.len(),
+            "last_ms": self.rebuilds.last(),
+            "p50_ms": self.percentile(0.5),
+            "p95_ms": self.percentile(0.95),
+            "slowest_phases": list(&self.phases),
+            "slowest_files": list(&self.files),
+        });
+        // Losing the status file costs nothing but the numbers in it
+        if fs::create_dir_all(cache_dir).is_ok() {
+            let _ = fs::write(cache_dir.join(WATCH_FILE), status.to_string());
+        }
+    }
+}
+
+/// Rebuild the stylesheets, and the scripts of `bundles`, whose dependencies contain a
+/// changed file, until `stop` is set. A change only rebuilds the entries that depend on it;
+/// the script of `js_input` is left to esbuild's own watcher.
+pub fn watch_styles(config: &Config, config_dir: &Path, manifest_path: PathBuf, stop: &AtomicBool) {
+    let cache_dir = cache::dir(config, config_dir);
+    let mut entries: Vec<Entry> = bundle::tasks(config)
+        .into_iter()
+        .filter(|task| task.step == Step::Styles || task.bundle.is_some())
+        .map(|task| Entry::new(task, config_dir))
+        .collect();
+    let mut outputs = output_files(config, config_dir);
+    let mut reported = BTreeSet::new();
+    for entry in &mut entries {
+        entry.stamps = stamp(watched(config_dir, entry, &outputs, &mut reported));
+    }
+
+    // Phases are timed with profiling spans, which only this thread's rebuilds add to from here
+    profile::enable();
+    profile::take_thread_spans();
+    let mut metrics = Metrics::default();
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+
+        let requested = REBUILD_REQUESTED.swap(false, Ordering::Relaxed);
+        for entry in &mut entries {
+            let path = match changed(entry) {
+                Some(path) => {
+                    log_line(format!(
+                        "{} {} changed, rebuilding {}",
+                        "Watch".cyan().bold(),
+                        path.display(),
+                        entry.input.display()
+                    ));
+                    path
+                }
+                None if requested => {
+                    log_line(format!(
+                        "{} rebuild requested, rebuilding {}",
+                        "Watch".cyan().bold(),
+                        entry.input.display()
+                    ));
+                    entry.input.clone()
+                }
+                None => continue,
+            };
+
+            // Watch builds never hash, so the manifest only tracks the plain names
+            let kind = match entry.step {
+                Step::Styles => "styles",
+                Step::Scripts => "scripts",
+            };
+            let mut manifest = Manifest::new(config, true, manifest_path.clone());
+            let start = Instant::now();
+            let recorder = notify::start(config, config_dir);
+            events::emit(|| Event::BuildStarted { kind });
+            let result = entry.build(config_dir, &mut manifest);
+            if let Err(ref e) = result {
+                eprintln!("\u{274C} {e}");
+            }
+            events::finished(kind, start, &result);
+            if let Some(recorder) = recorder {
+                recorder.finish(config, config_dir, kind, &result, true);
+            }
+            let duration = start.elapsed().as_millis() as u64;
+            log_line(format!(
+                "{} rebuilt {} in {} ms",
+                "Watch".cyan().bold(),
+                entry.input.display(),
+                duration
+            ));
+
+            let phases = profile::take_thread_spans()
+                .into_iter()
+                .filter(|(name, _)| name != "build styles" && name != "build scripts")
+                .collect();
+            let file = path.strip_prefix(config_dir).unwrap_or(&path);
+            metrics.record(file.display().to_string(), duration, phases);
+            metrics.write(&cache_dir);
+            if metrics.rebuilds.len() % REPORT_EVERY == 0 {
+                metrics.print();
+            }
+            // The outputs may have moved, e.g. with new esbuild chunks
+            outputs = output_files(config, config_dir);
+            entry.stamps = stamp(watched(config_dir, entry, &outputs, &mut reported));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// * ! ==================================================
+// * ! WOFF2 encoding for Packr font subsets
+// * ! ==================================================
+
+use std::io::Write;
+
+// * Tags with a one-byte index in the WOFF2 table directory
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+// * Transform version marking glyf/loca as stored untransformed
+const NULL_TRANSFORM_GLYF: u8 = 3 << 6;
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+// * Variable-length integer used for table lengths
+fn write_base128(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+// * Wrap an OpenType font in WOFF2, with glyf/loca stored untransformed
+pub fn encode(sfnt: &[u8]) -> Result<Vec<u8>, String> {
+    let invalid = || "Invalid OpenType font data".to_string();
+
+    let flavor = read_u32(sfnt, 0).ok_or_else(invalid)?;
+    let num_tables = read_u16(sfnt, 4).ok_or_else(invalid)?;
+
+    let mut tables: Vec<([u8; 4], &[u8])> = Vec::with_capacity(num_tables as usize);
+    for i in 0..num_tables as usize {
+        let record = 12 + i * 16;
+        let tag: [u8; 4] = sfnt
+            .get(record..record + 4)
+            .and_then(|t| t.try_into().ok())
+            .ok_or_else(invalid)?;
+        let offset = read_u32(sfnt, record + 8).ok_or_else(invalid)? as usize;
+        let length = read_u32(sfnt, record + 12).ok_or_else(invalid)? as usize;
+        tables.push((tag, sfnt.get(offset..offset + length).ok_or_else(invalid)?));
+    }
+
+    // Table directory and the uncompressed stream of table data
+    let mut directory = Vec::new();
+    let mut stream = Vec::new();
+    let mut sfnt_size = 12 + 16 * tables.len() as u32;
+    for (tag, data) in &tables {
+        let transform = if tag == b"glyf" || tag == b"loca" {
+            NULL_TRANSFORM_GLYF
+        } else {
+            0
+        };
+        match KNOWN_TAGS.iter().position(|known| *known == tag) {
+            Some(index) => directory.push(index as u8 | transform),
+            None => {
+                directory.push(63 | transform);
+                directory.extend_from_slice(tag);
+            }
+        }
+        write_base128(&mut directory, data.len() as u32);
+
+        stream.extend_from_slice(data);
+        sfnt_size += (data.len() as u32).div_ceil(4) * 4;
+    }
+
+    let mut compressed = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+        writer
+            .write_all(&stream)
+            .map_err(|e| format!("Failed to compress font: {}", e))?;
+    }
+
+    let header_size = 48;
+    let unpadded = header_size + directory.len() + compressed.len();
+    let length = unpadded.div_ceil(4) * 4;
+
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(b"wOF2");
+    out.extend_from_slice(&flavor.to_be_bytes());
+    out.extend_from_slice(&(length as u32).to_be_bytes());
+    out.extend_from_slice(&num_tables.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&sfnt_size.to_be_bytes());
+    out.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    // No metadata or private blocks
+    out.extend_from_slice(&[0; 20]);
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&compressed);
+    out.resize(length, 0);
+
+    Ok(out)
+}
@@ -0,0 +1,132 @@
+// * ! ==================================================
+// * ! WordPress asset manifest for Packr
+// * ! ==================================================
+
+use crate::build::{log_line, suffixed_path, Config};
+use crate::manifest::{content_hash, relative_to, Manifest};
+use crate::retry;
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// * WordPress asset file settings
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct WordPress {
+    // Handle the script and stylesheet are registered under
+    pub handle: String,
+    // PHP file to write, relative to the config file
+    #[serde(default = "default_path")]
+    pub path: String,
+    // Handles the script depends on, e.g. `jquery`
+    #[serde(default)]
+    pub script_deps: Vec<String>,
+    // Handles the stylesheet depends on
+    #[serde(default)]
+    pub style_deps: Vec<String>,
+}
+
+fn default_path() -> String {
+    "packr-assets.php".to_string()
+}
+
+// * Single-quoted PHP string literal
+fn php_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn php_list(values: &[String]) -> String {
+    let items: Vec<String> = values.iter().map(|v| php_string(v)).collect();
+    format!("array({})", items.join(", "))
+}
+
+// * One registered asset: its handle, PHP array fields and logical output path
+struct Asset<'a> {
+    handle: String,
+    logical: PathBuf,
+    deps: &'a [String],
+    flags: Vec<(&'static str, bool)>,
+}
+
+// * PHP array entry for an asset, with `src` relative to the PHP file
+fn php_entry(asset: &Asset, manifest: &Manifest, base: &Path) -> String {
+    let file = manifest.resolve(&asset.logical);
+    // Unbuilt files (e.g. scripts before esbuild's first watch build) get no version
+    let version = fs::read(&file)
+        .map(|content| php_string(&content_hash(&content)))
+        .unwrap_or_else(|_| "null".to_string());
+
+    let mut fields = vec![
+        format!("'src' => {}", php_string(&relative_to(&file, base))),
+        format!("'version' => {}", version),
+        format!("'deps' => {}", php_list(asset.deps)),
+    ];
+    fields.extend(
+        asset
+            .flags
+            .iter()
+            .map(|(key, value)| format!("'{}' => {}", key, value)),
+    );
+
+    format!(
+        "\t\t{} => array(\n\t\t\t{},\n\t\t),\n",
+        php_string(&asset.handle),
+        fields.join(",\n\t\t\t")
+    )
+}
+
+// * Write the PHP file mapping handles to the built files, versions and dependencies
+pub fn write_assets(config: &Config, config_dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let Some(ref settings) = config.wordpress else {
+        return Ok(());
+    };
+
+    let path = config_dir.join(&settings.path);
+    let base = path.parent().unwrap_or(config_dir);
+    let minified = |logical: PathBuf| {
+        if config.minify {
+            suffixed_path(&logical, ".min")
+        } else {
+            logical
+        }
+    };
+
+    let js = config_dir.join(&config.js_output);
+    let mut scripts = vec![Asset {
+        handle: settings.handle.clone(),
+        logical: minified(js.clone()),
+        deps: &settings.script_deps,
+        flags: vec![("module", config.format == "esm")],
+    }];
+    if config.legacy_target.is_some() {
+        scripts.push(Asset {
+            handle: format!("{}-legacy", settings.handle),
+            logical: suffixed_path(&js, ".legacy"),
+            deps: &settings.script_deps,
+            flags: vec![("nomodule", true)],
+        });
+    }
+    let styles = [Asset {
+        handle: settings.handle.clone(),
+        logical: minified(config_dir.join(&config.scss_output)),
+        deps: &settings.style_deps,
+        flags: Vec::new(),
+    }];
+
+    let mut php = String::from("<?php\n// Generated by Packr. Do not edit.\n\nreturn array(\n");
+    for (group, assets) in [("scripts", &scripts[..]), ("styles", &styles[..])] {
+        php.push_str(&format!("\t'{}' => array(\n", group));
+        for asset in assets {
+            php.push_str(&php_entry(asset, manifest, base));
+        }
+        php.push_str("\t),\n");
+    }
+    php.push_str(");\n");
+
+    retry::write(&path, php).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    log_line(format!(
+        "{} assets written to {}",
+        "WordPress".green().bold(),
+        settings.path
+    ));
+    Ok(())
+}
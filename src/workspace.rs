@@ -0,0 +1,484 @@
+// * ! ==================================================
+// * ! Workspaces of several Packr projects
+// * ! ==================================================
+
+use crate::build::{load_config, log_line, normalize_path, read_config, Config};
+use crate::cache;
+use crate::children;
+use crate::error::PackrError;
+use crate::run_build;
+use crate::runtime;
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// * Config file a project directory is built from
+const PROJECT_CONFIG: &str = ".packr.json";
+
+// * Build settings a workspace config can't have, as its projects configure their own builds
+const PROJECT_KEYS: [&str; 4] = ["scss_input", "scss_output", "js_input", "js_output"];
+
+// * The projects of a workspace: names mapped to project directories or config files, or glob
+// * patterns matching them
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Projects {
+    Named(BTreeMap<String, String>),
+    Discovered(Vec<String>),
+}
+
+// * A project of the workspace and the config it's built from
+pub struct Project {
+    pub name: String,
+    pub config_path: PathBuf,
+}
+
+// * The config file of a project directory, or `path` itself when it's a file
+fn project_config(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join(PROJECT_CONFIG)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+// * `path` relative to `base`, with `/` between the parts on every platform
+fn display_name(path: &Path, base: &Path) -> String {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    parts.join("/")
+}
+
+/// The projects of the workspace config at `config_path`, or `None` when it configures a build
+/// of its own. A missing or unreadable file is left to [`load_config`] to report.
+pub fn load(config_path: &str) -> Result<Option<Vec<Project>>, String> {
+    let Ok(content) = fs::read_to_string(config_path) else {
+        return Ok(None);
+    };
+    let Ok(serde_json::Value::Object(mut fields)) = serde_json::from_str(&content) else {
+        return Ok(None);
+    };
+    let Some(projects) = fields.remove("projects") else {
+        return Ok(None);
+    };
+    if let Some(key) = PROJECT_KEYS.iter().find(|key| fields.contains_key(**key)) {
+        return Err(format!(
+            "\"{}\" belongs in a project's config; a workspace config only lists projects",
+            key
+        ));
+    }
+    let projects: Projects = serde_json::from_value(projects).map_err(|_| {
+        "\"projects\" must map names to project paths, or list glob patterns".to_string()
+    })?;
+
+    let base = Path::new(config_path).parent().unwrap_or(Path::new(""));
+    let mut found = Vec::new();
+    match projects {
+        Projects::Named(named) => {
+            for (name, path) in named {
+                found.push(Project {
+                    name,
+                    config_path: project_config(&base.join(path)),
+                });
+            }
+        }
+        Projects::Discovered(patterns) => {
+            for pattern in patterns {
+                let full = base.join(&pattern);
+                let matches = glob::glob(&full.to_string_lossy())
+                    .map_err(|e| format!("Invalid projects pattern \"{}\": {}", pattern, e))?;
+                let mut matched = false;
+                for path in matches.flatten() {
+                    let config_path = project_config(&path);
+                    // Directories without a config, e.g. shared packages, aren't projects
+                    if !config_path.is_file()
+                        || found.iter().any(|p: &Project| p.config_path == config_path)
+                    {
+                        continue;
+                    }
+                    matched = true;
+                    let dir = config_path.parent().unwrap_or(base);
+                    found.push(Project {
+                        name: display_name(dir, base),
+                        config_path,
+                    });
+                }
+                if !matched {
+                    return Err(format!(
+                        "projects pattern \"{}\" matches no project",
+                        pattern
+                    ));
+                }
+            }
+        }
+    }
+
+    if found.is_empty() {
+        return Err("The workspace lists no projects".to_string());
+    }
+    if let Some(missing) = found.iter().find(|p| !p.config_path.is_file()) {
+        return Err(format!(
+            "Project \"{}\" has no config at {}",
+            missing.name,
+            missing.config_path.display()
+        ));
+    }
+    Ok(Some(found))
+}
+
+// * Output of a git command run in `dir`
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let mut cmd = runtime::program("git");
+    cmd.current_dir(dir).args(args);
+    let output =
+        children::output("git", &mut cmd).map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// * Files changed since the branch left `git_ref`, committed or not, and untracked files
+fn changed_files(dir: &Path, git_ref: &str) -> Result<BTreeSet<PathBuf>, String> {
+    let root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim());
+    let root = root.canonicalize().unwrap_or(root);
+    let base = git(dir, &["merge-base", git_ref, "HEAD"])?;
+    let mut names = git(dir, &["diff", "--name-only", "-z", base.trim()])?;
+    names.push_str(&git(
+        dir,
+        &[
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+        ],
+    )?);
+    Ok(names
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| root.join(name))
+        .collect())
+}
+
+// * `path` with symlinks resolved where it exists, as deleted files can't be
+fn resolved(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .unwrap_or_else(|_| normalize_path(&Path::new(".").join(path)))
+}
+
+// * Whether a changed file is inside the project's directory, or one of the files its last
+// * build read from elsewhere, such as a partial of a shared package
+fn is_affected(project: &Project, changed: &BTreeSet<PathBuf>) -> bool {
+    let dir = resolved(project.config_path.parent().unwrap_or(Path::new("")));
+    if changed.iter().any(|file| file.starts_with(&dir)) {
+        return true;
+    }
+    // A project whose config can't be read is built, so its build reports why
+    let Ok((config, config_dir)) = read_config(&project.config_path.to_string_lossy()) else {
+        return true;
+    };
+    // Without a record of what its last build read, a change elsewhere may still be one of
+    // its sources
+    if !cache::dependencies_recorded(&config, &config_dir) {
+        return true;
+    }
+    cache::input_files(&config, &config_dir, &project.config_path)
+        .iter()
+        .any(|input| changed.contains(&resolved(input)))
+}
+
+/// The projects the command line asks for: those matching one of the `--project` glob
+/// patterns, if any, and of those the ones with changes since `--changed-since`, if given
+pub fn select(
+    projects: Vec<Project>,
+    patterns: &[String],
+    changed_since: Option<&str>,
+    workspace_dir: &Path,
+) -> Result<Vec<Project>, String> {
+    let mut selected = projects;
+    if !patterns.is_empty() {
+        let mut matched = BTreeSet::new();
+        for pattern in patterns {
+            let glob = glob::Pattern::new(pattern)
+                .map_err(|e| format!("Invalid --project pattern \"{}\": {}", pattern, e))?;
+            let before = matched.len();
+            matched.extend(
+                selected
+                    .iter()
+                    .filter(|project| glob.matches(&project.name))
+                    .map(|project| project.name.clone()),
+            );
+            if matched.len() == before {
+                let names: Vec<&str> = selected.iter().map(|p| p.name.as_str()).collect();
+                return Err(format!(
+                    "--project \"{}\" matches no project (projects: {})",
+                    pattern,
+                    names.join(", ")
+                ));
+            }
+        }
+        selected.retain(|project| matched.contains(&project.name));
+    }
+
+    if let Some(git_ref) = changed_since {
+        let changed = changed_files(workspace_dir, git_ref)?;
+        let total = selected.len();
+        selected.retain(|project| is_affected(project, &changed));
+        let names: Vec<&str> = selected.iter().map(|p| p.name.as_str()).collect();
+        let list = if names.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", names.join(", "))
+        };
+        log_line(format!(
+            "{} {} of {} project(s) changed since {}{}",
+            "Workspace".magenta().bold(),
+            selected.len(),
+            total,
+            git_ref,
+            list
+        ));
+    }
+    Ok(selected)
+}
+
+// * How a project's build ended
+enum Outcome {
+    Built,
+    UpToDate,
+    Failed(PackrError),
+}
+
+/// Build every project in turn, each from its own config after `adjust` applied the command
+/// line's overrides, then print a summary of them all. A failed project doesn't stop the
+/// others; the result is an error when any failed.
+pub fn build(
+    projects: &[Project],
+    force: bool,
+    adjust: impl Fn(&mut Config),
+) -> Result<bool, PackrError> {
+    let start = Instant::now();
+    let mut outcomes: Vec<(&str, Outcome, Duration)> = Vec::new();
+    for project in projects {
+        log_line(format!(
+            "\n{} {} ({})",
+            "Project".magenta().bold(),
+            project.name,
+            project.config_path.display()
+        ));
+        let started = Instant::now();
+        let result = load_config(&project.config_path.to_string_lossy())
+            .map_err(|e| PackrError::context("Failed to load configuration", e))
+            .and_then(|(mut config, config_dir)| {
+                adjust(&mut config);
+                run_build(&config, &config_dir, &project.config_path, force)
+            });
+        let outcome = match result {
+            Ok(true) => Outcome::Built,
+            Ok(false) => Outcome::UpToDate,
+            Err(e) => {
+                eprintln!("\u{274C} {}: {e}", project.name);
+                Outcome::Failed(e)
+            }
+        };
+        outcomes.push((&project.name, outcome, started.elapsed()));
+    }
+
+    let count =
+        |wanted: fn(&Outcome) -> bool| outcomes.iter().filter(|(_, o, _)| wanted(o)).count();
+    let built = count(|o| matches!(o, Outcome::Built));
+    let up_to_date = count(|o| matches!(o, Outcome::UpToDate));
+    let failed = count(|o| matches!(o, Outcome::Failed(_)));
+    log_line(format!(
+        "\n{} {} project(s) in {:.2} s: {} built, {} up to date, {} failed",
+        "Workspace".magenta().bold(),
+        outcomes.len(),
+        start.elapsed().as_secs_f64(),
+        built,
+        up_to_date,
+        failed
+    ));
+    let width = outcomes
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, outcome, duration) in &outcomes {
+        let seconds = duration.as_secs_f64();
+        match outcome {
+            Outcome::Built => log_line(format!(
+                "  \u{2705} {:<width$}  built in {:.2} s",
+                name, seconds
+            )),
+            Outcome::UpToDate => log_line(format!("  \u{2705} {:<width$}  up to date", name)),
+            Outcome::Failed(e) => log_line(format!(
+                "  \u{274C} {:<width$}  {}",
+                name,
+                e.to_string().lines().next().unwrap_or_default()
+            )),
+        }
+    }
+
+    if failed > 0 {
+        return Err(PackrError::context(
+            "Workspace build failed",
+            format!("{} of {} project(s) failed", failed, outcomes.len()),
+        ));
+    }
+    Ok(built > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projects(names: &[&str]) -> Vec<Project> {
+        names
+            .iter()
+            .map(|name| Project {
+                name: name.to_string(),
+                config_path: Path::new(name).join(PROJECT_CONFIG),
+            })
+            .collect()
+    }
+
+    fn names(projects: &[Project]) -> Vec<&str> {
+        projects
+            .iter()
+            .map(|project| project.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn projects_are_selected_by_glob() {
+        let all = ["apps/admin", "apps/site", "packages/ui"];
+        let patterns = ["apps/*".to_string()];
+        let selected = select(projects(&all), &patterns, None, Path::new(".")).unwrap();
+        assert_eq!(names(&selected), ["apps/admin", "apps/site"]);
+
+        // Every pattern adds its matches, in workspace order
+        let patterns = ["packages/ui".to_string(), "*/admin".to_string()];
+        let selected = select(projects(&all), &patterns, None, Path::new(".")).unwrap();
+        assert_eq!(names(&selected), ["apps/admin", "packages/ui"]);
+
+        let selected = select(projects(&all), &[], None, Path::new(".")).unwrap();
+        assert_eq!(selected.len(), 3);
+    }
+
+    #[test]
+    fn patterns_matching_nothing_are_rejected() {
+        let all = ["apps/admin", "apps/site"];
+        let error = select(projects(&all), &["docs".to_string()], None, Path::new("."))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            "--project \"docs\" matches no project (projects: apps/admin, apps/site)"
+        );
+        assert!(
+            select(projects(&all), &["[".to_string()], None, Path::new("."))
+                .err()
+                .unwrap()
+                .starts_with("Invalid --project pattern \"[\"")
+        );
+    }
+
+    #[test]
+    fn changes_affect_projects_by_directory_and_by_what_they_read() {
+        let dir = std::env::temp_dir().join(format!("packr-workspace-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (file, text) in [
+            ("site/src/main.scss", "@use \"../../shared/colors\";\n"),
+            ("site/src/main.js", "console.log(1);\n"),
+            ("admin/src/main.scss", "a { color: red; }\n"),
+            ("admin/src/print.scss", "@use \"../../shared/print\";\n"),
+            ("admin/src/main.js", "console.log(2);\n"),
+            ("shared/_colors.scss", "$red: red;\n"),
+            ("shared/_print.scss", "$black: black;\n"),
+            ("unrelated/notes.txt", "\n"),
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let config = r#"{"scss_input": "src/main.scss", "scss_output": "dist/main.css",
+                         "js_input": "src/main.js", "js_output": "dist/main.js"}"#;
+        fs::write(dir.join("site").join(PROJECT_CONFIG), config).unwrap();
+        let with_bundle = config.replace(
+            "}",
+            r#", "bundles": [{"scss_input": "src/print.scss", "scss_output": "dist/print.css"}]}"#,
+        );
+        fs::write(dir.join("admin").join(PROJECT_CONFIG), with_bundle).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let project = |name: &str| Project {
+            name: name.to_string(),
+            config_path: dir.join(name).join(PROJECT_CONFIG),
+        };
+        let changed = |file: &str| BTreeSet::from([dir.join(file)]);
+
+        // Before a build recorded what the project read, any change may be one of its sources
+        assert!(is_affected(
+            &project("site"),
+            &changed("unrelated/notes.txt")
+        ));
+
+        // What a build records: the files each stylesheet read, and the script's metafile
+        for (project, cache, input) in [
+            ("site", ".packr/cache", "src/main.scss"),
+            ("admin", ".packr/cache", "src/main.scss"),
+            ("admin", ".packr/cache/bundles/0", "src/print.scss"),
+        ] {
+            let cache = dir.join(project).join(cache);
+            cache::compile_scss(&cache, &dir.join(project).join(input)).unwrap();
+            fs::write(cache.join("metafile.json"), r#"{"inputs": {}}"#).unwrap();
+        }
+
+        assert!(is_affected(
+            &project("admin"),
+            &changed("admin/src/main.js")
+        ));
+        assert!(!is_affected(
+            &project("admin"),
+            &changed("site/src/main.js")
+        ));
+        assert!(!is_affected(
+            &project("site"),
+            &changed("unrelated/notes.txt")
+        ));
+        assert!(is_affected(
+            &project("site"),
+            &changed("shared/_colors.scss")
+        ));
+        assert!(!is_affected(
+            &project("admin"),
+            &changed("shared/_colors.scss")
+        ));
+        // Partials of bundle stylesheets count too
+        assert!(is_affected(
+            &project("admin"),
+            &changed("shared/_print.scss")
+        ));
+        assert!(!is_affected(
+            &project("site"),
+            &changed("shared/_print.scss")
+        ));
+
+        // A project whose config can't be read is built, so its build reports why
+        fs::write(dir.join("admin").join(PROJECT_CONFIG), "{").unwrap();
+        assert!(is_affected(
+            &project("admin"),
+            &changed("shared/_colors.scss")
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
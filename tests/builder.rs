@@ -0,0 +1,72 @@
+// * ! ==================================================
+// * ! Builds run through `PackrBuilder`
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::project;
+use packr::{Event, PackrBuilder};
+use std::error::Error;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+// * Events go to every handler in the process, so builds here run one at a time
+static BUILDS: Mutex<()> = Mutex::new(());
+
+// * Run the project's build, returning its result and the kinds of the events it streamed
+fn build(dir: &std::path::Path) -> (Result<bool, packr::PackrError>, Vec<String>) {
+    let _build = BUILDS.lock().unwrap_or_else(|e| e.into_inner());
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let result = PackrBuilder::from_file(dir.join(".packr.json"))
+        .unwrap()
+        .on_event(move |event| {
+            let kind = match event {
+                Event::BuildStarted { .. } => "build_started",
+                Event::BuildFinished { success: true, .. } => "build_succeeded",
+                Event::BuildFinished { success: false, .. } => "build_failed",
+                Event::FileWritten { .. } => "file_written",
+                Event::Diagnostic { .. } => "diagnostic",
+                Event::Error { .. } => "error",
+                _ => return,
+            };
+            recorded.lock().unwrap().push(kind.to_string());
+        })
+        .build();
+    let events = events.lock().unwrap().clone();
+    (result, events)
+}
+
+#[test]
+fn successful_build_streams_its_events() {
+    let dir = project("builder-success");
+    let (result, events) = build(&dir);
+    assert!(matches!(result, Ok(true)), "{result:?}");
+    assert_eq!(events.first().map(String::as_str), Some("build_started"));
+    assert!(events.iter().any(|kind| kind == "file_written"));
+    assert_eq!(events.last().map(String::as_str), Some("build_succeeded"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_build_returns_a_typed_error() {
+    let dir = project("builder-failure");
+    fs::write(dir.join("src/main.scss"), "a { color: $missing; }\n").unwrap();
+    let (result, events) = build(&dir);
+
+    let error = result.unwrap_err();
+    assert_eq!(error.code(), "E_SCSS");
+    assert!(
+        error.to_string().starts_with("Styles failed [E_SCSS]: "),
+        "{error}"
+    );
+    let cause = error.source().expect("the Sass error");
+    assert!(cause.to_string().contains("Undefined variable"), "{cause}");
+
+    assert!(events.iter().any(|kind| kind == "diagnostic"));
+    let tail: Vec<&str> = events.iter().rev().take(2).map(String::as_str).collect();
+    assert_eq!(tail, ["build_failed", "error"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,295 @@
+// * ! ==================================================
+// * ! `bundles` built by the Packr binary
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{command, executable, packr, project_with, wait_for};
+use std::collections::BTreeSet;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+const CONFIG: &str = r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "bundles": [
+    {"scss_input": "src/admin.scss", "scss_output": "dist/admin.css",
+     "js_input": "src/admin.js", "js_output": "dist/admin.js"},
+    {"scss_input": "src/print.scss", "scss_output": "dist/print.css"}
+  ],
+  "jobs": 2,
+  "minify": false,
+  "eslint": false
+}"#;
+
+fn bundles_project(name: &str) -> std::path::PathBuf {
+    let dir = project_with(name, CONFIG);
+    fs::write(dir.join("src/admin.scss"), ".admin { color: blue; }\n").unwrap();
+    fs::write(dir.join("src/admin.js"), "console.log(2);\n").unwrap();
+    fs::write(
+        dir.join("src/print.scss"),
+        "@media print { a { color: black; } }\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn every_bundle_is_built_under_its_own_prefix() {
+    let dir = bundles_project("bundles-built");
+    let output = packr(&dir, &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let admin = fs::read_to_string(dir.join("dist/admin.css")).unwrap();
+    assert!(admin.contains(".admin"), "{admin}");
+    let print = fs::read_to_string(dir.join("dist/print.css")).unwrap();
+    assert!(print.contains("@media print"), "{print}");
+    assert!(fs::read_to_string(dir.join("dist/main.css"))
+        .unwrap()
+        .contains("red"));
+    assert!(dir.join("dist/admin.js").is_file());
+    assert!(dir.join("dist/main.js").is_file());
+
+    for prefix in [
+        "[styles]",
+        "[scripts]",
+        "[admin.css]",
+        "[admin.js]",
+        "[print.css]",
+    ] {
+        assert!(stdout.contains(prefix), "no {prefix} lines in:\n{stdout}");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_failing_bundle_is_named_in_the_error() {
+    let dir = bundles_project("bundles-failure");
+    fs::write(dir.join("src/print.scss"), "a { color: $missing; }\n").unwrap();
+    let output = packr(&dir, &["--json"]);
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("stdout is not JSON ({e})"));
+    assert_eq!(report["code"], "E_SCSS");
+    let error = report["error"].as_str().unwrap();
+    assert!(error.starts_with("Styles failed (print.css)"), "{error}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bundles_may_not_share_an_output() {
+    let config = CONFIG.replace("dist/print.css", "dist/main.css");
+    let dir = project_with("bundles-shared-output", &config);
+    let output = packr(&dir, &[]);
+    assert!(!output.status.success());
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        text.contains("bundles[1].scss_output \"dist/main.css\" is written by another entry"),
+        "{text}"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unchanged_stylesheets_are_not_printed_again() {
+    let dir = bundles_project("bundles-printed-cache");
+    let first = packr(&dir, &[]);
+    assert!(first.status.success());
+    assert!(!String::from_utf8_lossy(&first.stdout).contains("CSS unchanged"));
+
+    fs::write(dir.join("src/admin.scss"), ".admin { color: green; }\n").unwrap();
+    fs::remove_file(dir.join("dist/print.css")).unwrap();
+    let second = packr(&dir, &["--force"]);
+    let stdout = String::from_utf8_lossy(&second.stdout);
+    assert!(second.status.success(), "{stdout}");
+
+    let reused: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains("CSS unchanged since the last build"))
+        .collect();
+    assert_eq!(reused.len(), 2, "{stdout}");
+    assert!(reused.iter().any(|line| line.contains("[print.css]")));
+    assert!(fs::read_to_string(dir.join("dist/print.css"))
+        .unwrap()
+        .contains("@media print"));
+    assert!(fs::read_to_string(dir.join("dist/admin.css"))
+        .unwrap()
+        .contains("green"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_bundle_may_not_write_over_an_input() {
+    let config = CONFIG.replace("dist/print.css", "src/admin.scss");
+    let dir = bundles_project("bundles-over-input");
+    fs::write(dir.join(".packr.json"), config).unwrap();
+    let output = packr(&dir, &[]);
+    assert!(!output.status.success());
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        text.contains("bundles[1].scss_output would overwrite bundles[0].scss_input"),
+        "{text}"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("src/admin.scss")).unwrap(),
+        ".admin { color: blue; }\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// * Stand-in for esbuild that keeps watching, like the real one, until the session is killed
+const WATCHING_ESBUILD: &str = r#"#!/bin/sh
+case "$1" in --version) echo 0.25.2; exit 0;; esac
+for arg in "$@"; do
+  case "$arg" in
+    --outfile=*) out="${arg#--outfile=}"; mkdir -p "$(dirname "$out")"; echo "console.log(1);" > "$out";;
+    --watch) watch=1;;
+  esac
+done
+[ -n "$watch" ] && sleep 20
+exit 0
+"#;
+
+#[test]
+fn watch_rebuilds_only_the_affected_bundles() {
+    let dir = bundles_project("bundles-watch");
+    executable(&dir.join("node_modules/.bin/esbuild"), WATCHING_ESBUILD);
+    let log = dir.join("watch.log");
+    let mut session = command(&dir, &["--watch"])
+        .stdout(fs::File::create(&log).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // The three stylesheets and the script of the admin bundle
+    wait_for(&log, "Styles built successfully", 3);
+    wait_for(&log, "Scripts built successfully", 1);
+    // Let the session take its first timestamps before anything changes
+    thread::sleep(Duration::from_secs(1));
+    fs::write(dir.join("src/admin.scss"), ".admin { color: green; }\n").unwrap();
+    wait_for(&log, "Styles built successfully", 4);
+    fs::write(dir.join("src/admin.js"), "console.log(3);\n").unwrap();
+    wait_for(&log, "[admin.js] Scripts built successfully", 2);
+    thread::sleep(Duration::from_secs(1));
+    let output = fs::read_to_string(&log).unwrap();
+    session.kill().unwrap();
+    session.wait().unwrap();
+
+    let rebuilt: Vec<&str> = output
+        .lines()
+        .filter(|line| line.contains("changed, rebuilding"))
+        .collect();
+    assert_eq!(rebuilt.len(), 2, "{output}");
+    assert!(rebuilt[0].ends_with("src/admin.scss"), "{output}");
+    assert!(rebuilt[1].ends_with("src/admin.js"), "{output}");
+    assert!(fs::read_to_string(dir.join("dist/admin.css"))
+        .unwrap()
+        .contains("green"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// * Stand-in for the esbuild package that logs each context it creates and each build, with
+// * the process that ran it
+const ESBUILD_PACKAGE: &str = r#"const fs = require('fs');
+const path = require('path');
+const log = (line) => fs.appendFileSync(path.join(__dirname, 'calls.log'), `${line} ${process.pid}\n`);
+const write = (options) => {
+  if (options.outfile) {
+    fs.mkdirSync(path.dirname(options.outfile), { recursive: true });
+    fs.writeFileSync(options.outfile, 'console.log(1);\n');
+  }
+  return { errors: [], warnings: [], metafile: { inputs: {}, outputs: {} } };
+};
+exports.context = async (options) => {
+  log('context');
+  return {
+    rebuild: async () => (log('rebuild'), write(options)),
+    watch: async () => (write(options), setInterval(() => {}, 1000)),
+    dispose: async () => {},
+  };
+};
+exports.build = async (options) => (log('build'), write(options));
+exports.formatMessages = async (messages) => messages.map((message) => `${message.text}\n`);
+"#;
+
+#[test]
+fn watch_builds_bundle_scripts_in_one_esbuild_service() {
+    if Command::new("node").arg("--version").output().is_err() {
+        eprintln!("node is not installed, skipping");
+        return;
+    }
+    // Side-effect overrides send esbuild builds through the JS API shim
+    let config = CONFIG.replace(
+        r#""eslint": false"#,
+        r#""eslint": false, "side_effects_overrides": {"lib": false}"#,
+    );
+    let dir = project_with("bundles-service", &config);
+    fs::write(dir.join("src/admin.scss"), ".admin { color: blue; }\n").unwrap();
+    fs::write(dir.join("src/admin.js"), "console.log(2);\n").unwrap();
+    fs::write(dir.join("src/print.scss"), "a { color: black; }\n").unwrap();
+    let package = dir.join("node_modules/esbuild");
+    fs::create_dir_all(&package).unwrap();
+    fs::write(
+        package.join("package.json"),
+        r#"{"name": "esbuild", "version": "0.25.2", "main": "index.js"}"#,
+    )
+    .unwrap();
+    fs::write(package.join("index.js"), ESBUILD_PACKAGE).unwrap();
+    let log = dir.join("watch.log");
+    let config_path = dir.join(".packr.json");
+    let mut session = command(
+        &dir,
+        &["--watch", "--config", config_path.to_str().unwrap()],
+    )
+    .stdout(fs::File::create(&log).unwrap())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+
+    wait_for(&log, "Scripts built successfully", 1);
+    thread::sleep(Duration::from_secs(1));
+    fs::write(dir.join("src/admin.js"), "console.log(3);\n").unwrap();
+    wait_for(&log, "Scripts built successfully", 2);
+    thread::sleep(Duration::from_secs(1));
+    session.kill().unwrap();
+    session.wait().unwrap();
+
+    // Every build of the bundle script, its first one included, is a rebuild in one process
+    let calls = fs::read_to_string(package.join("calls.log")).unwrap();
+    let rebuilds: Vec<&str> = calls
+        .lines()
+        .filter(|line| line.starts_with("rebuild"))
+        .collect();
+    let processes: BTreeSet<&str> = rebuilds
+        .iter()
+        .filter_map(|line| line.split(' ').nth(1))
+        .collect();
+    assert!(rebuilds.len() >= 2, "{calls}");
+    assert_eq!(processes.len(), 1, "{calls}");
+    assert!(
+        !calls.lines().any(|line| line.starts_with("build")),
+        "{calls}"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,85 @@
+// * ! ==================================================
+// * ! Shared setup for the Packr binary tests
+// * ! ==================================================
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// * Stand-in for esbuild that writes the files it's asked for, so the build needs no Node
+const FAKE_ESBUILD: &str = r#"#!/bin/sh
+case "$1" in --version) echo 0.25.2; exit 0;; esac
+for arg in "$@"; do
+  case "$arg" in
+    --outfile=*) out="${arg#--outfile=}"; mkdir -p "$(dirname "$out")"; echo "console.log(1);" > "$out";;
+  esac
+done
+echo "progress from esbuild"
+exit 0
+"#;
+
+// * The config `project` writes: one stylesheet and one script, without ESLint
+pub const CONFIG: &str = r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "eslint": false,
+  "verbose": true
+}"#;
+
+// * A project with one stylesheet and one script in a fresh directory named after the test
+pub fn project(name: &str) -> PathBuf {
+    project_with(name, CONFIG)
+}
+
+// * `project` with its own `.packr.json`
+pub fn project_with(name: &str, config: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("packr-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::create_dir_all(dir.join("node_modules/.bin")).unwrap();
+    fs::write(dir.join("src/main.scss"), "$c: red;\na { color: $c; }\n").unwrap();
+    fs::write(dir.join("src/main.js"), "console.log(1);\n").unwrap();
+    executable(&dir.join("node_modules/.bin/esbuild"), FAKE_ESBUILD);
+    fs::write(dir.join(".packr.json"), config).unwrap();
+    dir
+}
+
+// * Write a script and make it executable
+pub fn executable(path: &Path, script: &str) {
+    fs::write(path, script).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+pub fn packr(dir: &Path, args: &[&str]) -> std::process::Output {
+    command(dir, args).output().unwrap()
+}
+
+// * The Packr binary run in `dir`, for tests that change its environment
+pub fn command(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_asset-pipeline"));
+    command
+        .args(args)
+        .current_dir(dir)
+        .env_remove("PACKR_VERBOSE");
+    command
+}
+
+// * Wait until a running session's output, logged to `log`, contains `text` `count` times
+pub fn wait_for(log: &Path, text: &str, count: usize) -> String {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        let output = fs::read_to_string(log).unwrap_or_default();
+        if output.matches(text).count() >= count {
+            return output;
+        }
+        assert!(Instant::now() < deadline, "no {text:?} in:\n{output}");
+        thread::sleep(Duration::from_millis(100));
+    }
+}
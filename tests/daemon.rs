@@ -0,0 +1,136 @@
+// * ! ==================================================
+// * ! `packr daemon` serving builds over its socket
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{command, packr, project, wait_for, CONFIG};
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[test]
+fn daemon_keeps_answering_while_a_client_stays_silent() {
+    let dir = project("daemon-clients");
+    let log = dir.join("daemon.log");
+    let mut daemon = command(&dir, &["daemon"])
+        .stdout(fs::File::create(&log).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for(&log, "listening on", 1);
+
+    // Connected, but never sends its request
+    let _silent = UnixStream::connect(dir.join(".packr/cache/daemon.sock")).unwrap();
+    let started = Instant::now();
+    let built = packr(&dir, &["--daemon"]);
+    assert!(
+        String::from_utf8_lossy(&built.stdout).contains("Build (daemon) complete"),
+        "{}",
+        String::from_utf8_lossy(&built.stderr)
+    );
+    assert!(started.elapsed() < Duration::from_secs(4));
+
+    let stopped = packr(&dir, &["daemon", "--stop"]);
+    assert!(stopped.status.success());
+    assert!(daemon.wait().unwrap().success());
+    assert!(!dir.join(".packr/cache/daemon.sock").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn daemon_loads_the_config_again_only_when_it_changed() {
+    let dir = project("daemon-config");
+    let log = dir.join("daemon.log");
+    let mut daemon = command(&dir, &["daemon"])
+        .stdout(fs::File::create(&log).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for(&log, "listening on", 1);
+
+    for args in [&["--daemon"][..], &["--daemon", "--force"]] {
+        assert!(packr(&dir, args).status.success());
+    }
+    fs::write(
+        dir.join(".packr.json"),
+        CONFIG.replace("\"verbose\": true", "\"verbose\": false"),
+    )
+    .unwrap();
+    assert!(packr(&dir, &["--daemon", "--force"]).status.success());
+    assert!(packr(&dir, &["daemon", "--stop"]).status.success());
+    daemon.wait().unwrap();
+
+    // Once on startup, then for the first build and after the edit
+    let output = fs::read_to_string(&log).unwrap();
+    assert_eq!(output.matches("Loading config").count(), 3, "{output}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// * Stand-in for the esbuild package that logs each context it creates and each build, with
+// * the process that ran it
+const ESBUILD_PACKAGE: &str = r#"const fs = require('fs');
+const path = require('path');
+const log = (line) => fs.appendFileSync(path.join(__dirname, 'calls.log'), `${line} ${process.pid}\n`);
+const write = (options) => {
+  if (options.outfile) {
+    fs.mkdirSync(path.dirname(options.outfile), { recursive: true });
+    fs.writeFileSync(options.outfile, 'console.log(1);\n');
+  }
+  return { errors: [], warnings: [], metafile: { inputs: {}, outputs: {} } };
+};
+exports.context = async (options) => {
+  log('context');
+  return { rebuild: async () => (log('rebuild'), write(options)), dispose: async () => {} };
+};
+exports.build = async (options) => (log('build'), write(options));
+"#;
+
+#[test]
+fn daemon_rebuilds_scripts_in_one_esbuild_service() {
+    if Command::new("node").arg("--version").output().is_err() {
+        eprintln!("node is not installed, skipping");
+        return;
+    }
+    let dir = project("daemon-service");
+    let package = dir.join("node_modules/esbuild");
+    fs::create_dir_all(&package).unwrap();
+    fs::write(
+        package.join("package.json"),
+        r#"{"name": "esbuild", "version": "0.25.2", "main": "index.js"}"#,
+    )
+    .unwrap();
+    fs::write(package.join("index.js"), ESBUILD_PACKAGE).unwrap();
+    let log = dir.join("daemon.log");
+    let mut daemon = command(&dir, &["daemon"])
+        .stdout(fs::File::create(&log).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for(&log, "listening on", 1);
+
+    for _ in 0..3 {
+        let built = packr(&dir, &["--daemon", "--force"]);
+        assert!(
+            String::from_utf8_lossy(&built.stdout).contains("Build (daemon) complete"),
+            "{}",
+            fs::read_to_string(&log).unwrap()
+        );
+    }
+    assert!(packr(&dir, &["daemon", "--stop"]).status.success());
+    daemon.wait().unwrap();
+
+    let calls = fs::read_to_string(package.join("calls.log")).unwrap();
+    let count = |kind: &str| calls.lines().filter(|l| l.starts_with(kind)).count();
+    let processes: BTreeSet<&str> = calls.lines().filter_map(|l| l.split(' ').nth(1)).collect();
+    assert_eq!(processes.len(), 1, "{calls}");
+    assert_eq!(count("build"), 0, "{calls}");
+    assert!(count("context") > 0);
+    assert_eq!(count("rebuild"), 3 * count("context"), "{calls}");
+    assert!(dir.join("dist/main.js").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,65 @@
+// * ! ==================================================
+// * ! `--deploy` with `skip_unchanged`
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{command, executable, project_with};
+use std::fs;
+use std::path::Path;
+
+// * Stand-in for rsync that records each call next to the project
+const FAKE_RSYNC: &str = "#!/bin/sh\necho \"$@\" >> \"$PACKR_TEST_LOG\"\n";
+
+fn deploys(dir: &Path) -> usize {
+    fs::read_to_string(dir.join("rsync.log"))
+        .map(|log| log.lines().count())
+        .unwrap_or(0)
+}
+
+#[test]
+fn deploy_runs_after_an_unchanged_build() {
+    let dir = project_with(
+        "deploy-unchanged",
+        r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "eslint": false,
+  "skip_unchanged": true,
+  "deploy": { "provider": "rsync", "target": "/tmp/packr-deploy-target" }
+}"#,
+    );
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    executable(&dir.join("bin/rsync"), FAKE_RSYNC);
+    let path = format!(
+        "{}:{}",
+        dir.join("bin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let run = |args: &[&str]| {
+        let output = command(&dir, args)
+            .env("PATH", &path)
+            .env("PACKR_TEST_LOG", dir.join("rsync.log"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+
+    run(&[]);
+    assert_eq!(deploys(&dir), 0);
+    // Nothing changed, but this build was asked to deploy
+    run(&["--deploy"]);
+    assert_eq!(deploys(&dir), 1);
+    // Deployed already, and nothing changed since
+    run(&["--deploy"]);
+    assert_eq!(deploys(&dir), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
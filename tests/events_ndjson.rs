@@ -0,0 +1,101 @@
+// * ! ==================================================
+// * ! `--events-ndjson` output of the Packr binary
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{packr, project};
+use std::fs;
+
+// * Every stdout line is one JSON event, as an editor reading the stream expects
+fn events(stdout: &[u8]) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .map(|line| {
+            serde_json::from_str(line)
+                .unwrap_or_else(|e| panic!("stdout line is not JSON ({e}): {line:?}"))
+        })
+        .collect()
+}
+
+#[test]
+fn stdout_carries_only_events() {
+    let dir = project("ndjson-stdout");
+    let output = packr(&dir, &["--events-ndjson"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let events = events(&output.stdout);
+    let kinds: Vec<&str> = events.iter().filter_map(|e| e["event"].as_str()).collect();
+    assert!(kinds.contains(&"build_started"));
+    assert!(kinds.contains(&"file_written"));
+    assert_eq!(kinds.last(), Some(&"build_finished"));
+
+    // The progress lines still reach the terminal, on stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Build (single) complete"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_build_keeps_stdout_json() {
+    let dir = project("ndjson-failure");
+    fs::write(dir.join("src/main.scss"), "a { color: $missing; }\n").unwrap();
+    let output = packr(&dir, &["--events-ndjson"]);
+    assert!(!output.status.success());
+
+    let events = events(&output.stdout);
+    let finished = events
+        .iter()
+        .find(|e| e["event"] == "build_finished")
+        .expect("build_finished event");
+    assert_eq!(finished["success"], false);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn file_target_leaves_stdout_to_progress() {
+    let dir = project("ndjson-file");
+    let output = packr(&dir, &["--events-ndjson=events.ndjson"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Build (single) complete"));
+    let written = fs::read(dir.join("events.ndjson")).unwrap();
+    assert!(!events(&written).is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_report_is_the_whole_stdout() {
+    let dir = project("json-failure");
+    fs::write(dir.join("src/main.scss"), "a { color: $missing; }\n").unwrap();
+    let output = packr(&dir, &["--json"]);
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("stdout is not JSON ({e})"));
+    assert_eq!(report["status"], "failure");
+    assert_eq!(report["code"], "E_SCSS");
+    let errors = report["errors"].as_array().unwrap();
+    assert!(errors.iter().any(|e| e["source"] == "Sass"), "{report}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_report_refuses_events_on_stdout() {
+    let dir = project("json-events");
+    let output = packr(&dir, &["--json", "--events-ndjson"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
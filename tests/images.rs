@@ -0,0 +1,133 @@
+// * ! ==================================================
+// * ! `images` variants written by the Packr binary
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{command, executable, project_with};
+use std::fs;
+use std::path::Path;
+
+// * Stand-ins for the encoders that record each call and write the image they were given
+const FAKE_CWEBP: &str = r#"#!/bin/sh
+echo "cwebp $*" >> "$PACKR_TEST_LOG"
+cp "$4" "$6"
+"#;
+const FAKE_AVIFENC: &str = r#"#!/bin/sh
+echo "avifenc $*" >> "$PACKR_TEST_LOG"
+cp "$3" "$4"
+"#;
+
+const CONFIG: &str = r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "eslint": false,
+  "copy": [{ "from": "src/images", "to": "dist/images" }],
+  "images": { "quality": { "webp": 70 }, "manifest": true }
+}"#;
+
+fn encoded(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("encoders.log"))
+        .map(|log| log.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[test]
+fn variants_are_written_once_and_listed_in_the_manifest() {
+    let dir = project_with("images-variants", CONFIG);
+    fs::create_dir_all(dir.join("src/images")).unwrap();
+    fs::write(dir.join("src/images/hero.png"), "png").unwrap();
+    fs::write(dir.join("src/images/photo.jpg"), "jpeg").unwrap();
+    fs::write(dir.join("src/images/logo.svg"), "<svg/>").unwrap();
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    executable(&dir.join("bin/cwebp"), FAKE_CWEBP);
+    executable(&dir.join("bin/avifenc"), FAKE_AVIFENC);
+    let path = format!(
+        "{}:{}",
+        dir.join("bin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let run = || {
+        let output = command(&dir, &["--force"])
+            .env("PATH", &path)
+            .env("PACKR_TEST_LOG", dir.join("encoders.log"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+
+    run();
+    let calls = encoded(&dir);
+    assert_eq!(calls.len(), 4, "{calls:?}");
+    assert!(calls
+        .iter()
+        .any(|call| call.starts_with("cwebp -quiet -q 70 ")));
+    assert!(calls.iter().any(|call| call.starts_with("avifenc -q 60 ")));
+    for variant in [
+        "hero.png.webp",
+        "hero.png.avif",
+        "photo.jpg.webp",
+        "photo.jpg.avif",
+    ] {
+        assert!(dir.join("dist/images").join(variant).is_file(), "{variant}");
+    }
+    assert!(!dir.join("dist/images/logo.svg.webp").exists());
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("dist/manifest.json")).unwrap()).unwrap();
+    assert_eq!(
+        manifest["images/hero.png"],
+        serde_json::json!({
+            "file": "images/hero.png",
+            "variants": {
+                "avif": "images/hero.png.avif",
+                "webp": "images/hero.png.webp"
+            }
+        })
+    );
+
+    // Unchanged images keep their variants; a changed one is encoded again
+    run();
+    assert_eq!(encoded(&dir).len(), 4);
+    fs::write(dir.join("src/images/hero.png"), "new png").unwrap();
+    run();
+    let calls = encoded(&dir);
+    assert_eq!(calls.len(), 6, "{calls:?}");
+    assert!(calls[4..].iter().all(|call| call.contains("hero.png")));
+    assert_eq!(
+        fs::read_to_string(dir.join("dist/images/hero.png.webp")).unwrap(),
+        "new png"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_missing_encoder_fails_the_build() {
+    let config = CONFIG.replace(r#""quality""#, r#""formats": ["avif"], "quality""#);
+    let dir = project_with("images-missing-encoder", &config);
+    fs::create_dir_all(dir.join("src/images")).unwrap();
+    fs::write(dir.join("src/images/hero.png"), "png").unwrap();
+    let output = command(&dir, &["--json"])
+        .env("PATH", "/nonexistent")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("stdout is not JSON ({e})"));
+    let error = report["error"].as_str().unwrap();
+    assert!(
+        error.starts_with("Images failed: avifenc is not installed"),
+        "{error}"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,57 @@
+// * ! ==================================================
+// * ! `skip_unchanged` with sources outside the project
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{packr, project_with};
+use std::fs;
+
+#[test]
+fn a_shared_partial_outside_the_project_is_hashed() {
+    let root = project_with(
+        "skip-unchanged-shared",
+        r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "skip_unchanged": true,
+  "eslint": false
+}"#,
+    );
+    // The project lives in `site`, next to the package its stylesheet uses
+    let site = root.join("site");
+    fs::create_dir_all(&site).unwrap();
+    for entry in ["src", "node_modules", ".packr.json"] {
+        fs::rename(root.join(entry), site.join(entry)).unwrap();
+    }
+    fs::create_dir_all(root.join("shared")).unwrap();
+    fs::write(root.join("shared/_colors.scss"), "$brand: red;\n").unwrap();
+    fs::write(
+        site.join("src/main.scss"),
+        "@use \"../../shared/colors\";\na { color: colors.$brand; }\n",
+    )
+    .unwrap();
+
+    let build = || {
+        let output = packr(&site, &[]);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(output.status.success(), "{stdout}");
+        stdout
+    };
+    let first = build();
+    assert!(first.contains("Build (single) complete"), "{first}");
+    let second = build();
+    assert!(second.contains("Build (single) up to date"), "{second}");
+
+    fs::write(root.join("shared/_colors.scss"), "$brand: green;\n").unwrap();
+    let third = build();
+    assert!(third.contains("Build (single) complete"), "{third}");
+    assert!(fs::read_to_string(site.join("dist/main.css"))
+        .unwrap()
+        .contains("green"));
+    fs::remove_dir_all(&root).unwrap();
+}
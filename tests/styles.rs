@@ -0,0 +1,51 @@
+// * ! ==================================================
+// * ! Stylesheets built by the Packr binary
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{packr, project_with};
+use std::fs;
+
+#[test]
+fn css_destination_gets_the_source_map_of_each_stylesheet() {
+    let dir = project_with(
+        "styles-destination-maps",
+        r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "css_destination": "theme/css",
+  "minify": true,
+  "sourcemap": "linked",
+  "eslint": false
+}"#,
+    );
+    let output = packr(&dir, &[]);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    for file in [
+        "main.css",
+        "main.css.map",
+        "main.min.css",
+        "main.min.css.map",
+    ] {
+        assert_eq!(
+            fs::read(dir.join("theme/css").join(file)).unwrap(),
+            fs::read(dir.join("dist").join(file)).unwrap(),
+            "{file}"
+        );
+    }
+    let map: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join("dist/main.min.css.map")).unwrap()).unwrap();
+    assert_eq!(map["sources"][0], "../src/main.scss (compiled)");
+    fs::remove_dir_all(&dir).unwrap();
+}
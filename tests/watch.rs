@@ -0,0 +1,49 @@
+// * ! ==================================================
+// * ! `--watch` sessions run by the Packr binary
+// * ! ==================================================
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{command, packr, project_with, wait_for};
+use std::fs;
+use std::process::Stdio;
+
+const CONFIG: &str = r#"{
+  "scss_input": "src/main.scss",
+  "scss_output": "dist/main.css",
+  "js_input": "src/main.js",
+  "js_output": "dist/main.js",
+  "skip_unchanged": true,
+  "clean_output": true,
+  "tool_lock": "error",
+  "eslint": false
+}"#;
+
+#[test]
+fn watch_runs_the_checks_of_a_build_before_building() {
+    let dir = project_with("watch-prepare", CONFIG);
+    fs::write(dir.join(".packr.lock"), r#"{"packr": "0.0.1"}"#).unwrap();
+    let output = packr(&dir, &["--watch"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Tool lock failed"), "{stderr}");
+
+    // Up to date for a build, but a session builds anyway, once stale outputs are gone
+    fs::remove_file(dir.join(".packr.lock")).unwrap();
+    assert!(packr(&dir, &[]).status.success());
+    fs::write(dir.join("dist/stale.css"), "a {}\n").unwrap();
+    let log = dir.join("watch.log");
+    let mut session = command(&dir, &["--watch"])
+        .stdout(fs::File::create(&log).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for(&log, "Styles built successfully", 1);
+    session.kill().unwrap();
+    session.wait().unwrap();
+    assert!(!dir.join("dist/stale.css").exists());
+    assert!(dir.join("dist/main.css").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}